@@ -0,0 +1,366 @@
+//! An HTTP/1.1 client built on the same codec as the server.
+//!
+//! [`HttpClient`] sends requests over a TCP connection with the [`RequestEncoder`] and reads the
+//! responses with the [`ResponseDecoder`]. The connection is kept alive between requests, unless the
+//! server or the request asked to close it, in which case the next request opens a new one.
+//!
+//! Request and response bodies are sent and received as a whole:
+//!
+//! ```no_run
+//! use bytes::Bytes;
+//! use http::Request;
+//! use micro_http::client::HttpClient;
+//! use std::time::Duration;
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//!     let mut client = HttpClient::with_timeout(Duration::from_secs(5), Duration::from_secs(30))
+//!         .connect("127.0.0.1:8080")
+//!         .await?;
+//!
+//!     let request = Request::post("/echo").body(Bytes::from_static(b"hello"))?;
+//!     let response = client.send(request).await?;
+//!     println!("{}: {:?}", response.status(), response.body());
+//!
+//!     // the same connection is used
+//!     let response = client.send(Request::get("/").body(Bytes::new())?).await?;
+//!     println!("{}", response.status());
+//!     Ok(())
+//! }
+//! ```
+
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use bytes::{Bytes, BytesMut};
+use futures::{SinkExt, StreamExt};
+use http::header::{CONTENT_LENGTH, HOST, TRANSFER_ENCODING};
+use http::{HeaderValue, Method, Request, Response, StatusCode, Version};
+use thiserror::Error;
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpStream, ToSocketAddrs};
+use tokio_util::codec::{FramedRead, FramedWrite};
+use tracing::{debug, trace};
+
+use crate::codec::{RequestEncoder, ResponseDecoder};
+use crate::protocol::{Message, ParseError, PayloadItem, PayloadSize, RequestHead, ResponseHead, SendError};
+use crate::utils::has_connection_option;
+
+/// Errors of an [`HttpClient`].
+#[derive(Error, Debug)]
+pub enum ClientError {
+    /// The server could not be connected to
+    #[error("connect error: {source}")]
+    Connect {
+        #[from]
+        source: io::Error,
+    },
+
+    /// The connection was not established within the connect timeout
+    #[error("connect timeout after {timeout:?}")]
+    ConnectTimeout { timeout: Duration },
+
+    /// The request could not be sent
+    #[error("request error: {source}")]
+    Request {
+        #[from]
+        source: SendError,
+    },
+
+    /// The response could not be read, including when it's not received within the read timeout
+    #[error("response error: {source}")]
+    Response {
+        #[from]
+        source: ParseError,
+    },
+
+    /// The server closed the connection before the response was complete
+    #[error("connection closed before the response was received")]
+    Closed,
+}
+
+/// Configures and connects an [`HttpClient`], see [`HttpClient::with_timeout`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ClientBuilder {
+    connect_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+}
+
+impl ClientBuilder {
+    /// Connects to `addr`, the first of its resolved addresses is used.
+    pub async fn connect(self, addr: impl ToSocketAddrs) -> Result<HttpClient, ClientError> {
+        let addr = tokio::net::lookup_host(addr)
+            .await?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "could not resolve to any address"))?;
+
+        let mut client = HttpClient { addr, config: self, connection: None };
+        client.connection = Some(client.open().await?);
+        Ok(client)
+    }
+}
+
+/// An HTTP/1.1 client sending requests one after another to a single server.
+///
+/// See the [module documentation](self).
+pub struct HttpClient {
+    addr: SocketAddr,
+    config: ClientBuilder,
+    /// The open connection, `None` once the server or a request asked to close it
+    connection: Option<Connection>,
+}
+
+/// A connection to the server.
+struct Connection {
+    framed_read: FramedRead<OwnedReadHalf, ResponseDecoder>,
+    framed_write: FramedWrite<OwnedWriteHalf, RequestEncoder>,
+}
+
+impl HttpClient {
+    /// Connects to `addr` without timeouts.
+    pub async fn connect(addr: impl ToSocketAddrs) -> Result<HttpClient, ClientError> {
+        ClientBuilder::default().connect(addr).await
+    }
+
+    /// Creates a client that fails to connect after `connect`, and fails to receive a response if
+    /// the server is silent for longer than `read`.
+    pub fn with_timeout(connect: Duration, read: Duration) -> ClientBuilder {
+        ClientBuilder { connect_timeout: Some(connect), read_timeout: Some(read) }
+    }
+
+    /// Returns the address of the server.
+    pub fn remote_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Sends `request` and receives its response.
+    ///
+    /// The `Host` header is set to the address of the server if neither the URI nor the headers
+    /// name the host. Informational responses, such as `100 Continue`, are skipped.
+    pub async fn send(&mut self, request: Request<Bytes>) -> Result<Response<Bytes>, ClientError> {
+        let mut connection = match self.connection.take() {
+            Some(connection) => connection,
+            None => self.open().await?,
+        };
+
+        let (parts, body) = request.into_parts();
+        let mut head = RequestHead::from_parts(parts, ());
+        if head.uri().authority().is_none() && !head.headers().contains_key(HOST) {
+            // made of an ip address and a port only
+            head.headers_mut().insert(HOST, HeaderValue::try_from(self.addr.to_string()).unwrap());
+        }
+
+        let head_request = head.method() == Method::HEAD;
+        let keep_alive = !has_connection_option(head.headers(), "close");
+        let payload_size = match body.len() {
+            0 => PayloadSize::Empty,
+            length => PayloadSize::Length(length as u64),
+        };
+
+        connection.framed_read.decoder_mut().set_head_request(head_request);
+        connection.framed_write.feed(Message::<_, Bytes>::Header((head, payload_size))).await?;
+        if !body.is_empty() {
+            connection.framed_write.feed(Message::Payload(PayloadItem::Chunk(body))).await?;
+        }
+        connection.framed_write.send(Message::Payload(PayloadItem::<Bytes>::Eof)).await?;
+
+        let response = connection.receive(self.config.read_timeout).await?;
+
+        if keep_alive && is_reusable(&response, head_request) {
+            self.connection = Some(connection);
+        } else {
+            debug!(addr = %self.addr, "the connection can't be reused, close it");
+        }
+        Ok(response)
+    }
+
+    /// Opens a new connection to the server, within the connect timeout.
+    async fn open(&self) -> Result<Connection, ClientError> {
+        let stream = match self.config.connect_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, TcpStream::connect(self.addr))
+                .await
+                .map_err(|_| ClientError::ConnectTimeout { timeout })??,
+            None => TcpStream::connect(self.addr).await?,
+        };
+        trace!(addr = %self.addr, "connected");
+
+        let (reader, writer) = stream.into_split();
+        Ok(Connection {
+            framed_read: FramedRead::with_capacity(reader, ResponseDecoder::new(), 8 * 1024),
+            framed_write: FramedWrite::new(writer, RequestEncoder::new()),
+        })
+    }
+}
+
+impl Connection {
+    /// Receives the final response to the request that was sent.
+    async fn receive(&mut self, read_timeout: Option<Duration>) -> Result<Response<Bytes>, ClientError> {
+        loop {
+            let head = match self.next(read_timeout).await? {
+                Message::Header(head) => head,
+                Message::Payload(_) => return Err(ParseError::invalid_body("receive payload before header").into()),
+            };
+
+            let mut body = BytesMut::new();
+            loop {
+                match self.next(read_timeout).await? {
+                    Message::Payload(PayloadItem::Chunk(bytes)) => body.extend_from_slice(&bytes),
+                    Message::Payload(PayloadItem::Trailer(_)) => (),
+                    Message::Payload(PayloadItem::Eof) => break,
+                    Message::Header(_) => return Err(ParseError::invalid_body("receive header before eof").into()),
+                }
+            }
+
+            // the final response follows the interim ones
+            if head.status().is_informational() && head.status() != StatusCode::SWITCHING_PROTOCOLS {
+                trace!(status = %head.status(), "skip interim response");
+                continue;
+            }
+
+            return Ok(head.map(|_| body.freeze()));
+        }
+    }
+
+    /// Reads the next message, within the read timeout.
+    async fn next(&mut self, read_timeout: Option<Duration>) -> Result<Message<ResponseHead>, ClientError> {
+        let next = match read_timeout {
+            Some(read_timeout) => tokio::time::timeout(read_timeout, self.framed_read.next())
+                .await
+                .map_err(|_| ParseError::read_timeout(read_timeout))?,
+            None => self.framed_read.next().await,
+        };
+        next.ok_or(ClientError::Closed)?.map_err(ClientError::from)
+    }
+}
+
+/// Returns true if the connection can be reused once the response is received.
+fn is_reusable<T>(response: &Response<T>, head_request: bool) -> bool {
+    let status = response.status();
+    if status == StatusCode::SWITCHING_PROTOCOLS {
+        return false;
+    }
+
+    // a body delimited by closing the connection ends it
+    let has_body = !(head_request || status == StatusCode::NO_CONTENT || status == StatusCode::NOT_MODIFIED);
+    let headers = response.headers();
+    if has_body && !headers.contains_key(CONTENT_LENGTH) && !headers.contains_key(TRANSFER_ENCODING) {
+        return false;
+    }
+
+    match response.version() {
+        Version::HTTP_10 => has_connection_option(headers, "keep-alive"),
+        _ => !has_connection_option(headers, "close"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::HttpConnection;
+    use crate::handler::make_handler;
+    use crate::protocol::body::ReqBody;
+    use http_body_util::BodyExt;
+    use std::error::Error;
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    async fn echo(req: Request<ReqBody>) -> Result<Response<String>, Box<dyn Error + Send + Sync>> {
+        let (parts, body) = req.into_parts();
+        let body = body.collect().await?.to_bytes();
+        let host = parts.headers.get(HOST).and_then(|host| host.to_str().ok()).unwrap_or_default().to_string();
+        Ok(Response::new(format!("{} {} {} {}", parts.method, parts.uri, host, String::from_utf8_lossy(&body))))
+    }
+
+    /// Serves `echo` with `HttpConnection`, returns the address and the number of accepted connections.
+    async fn serve() -> (SocketAddr, Arc<std::sync::atomic::AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let accepted = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = accepted.clone();
+        tokio::spawn(async move {
+            let handler = Arc::new(make_handler(echo));
+            while let Ok((stream, _)) = listener.accept().await {
+                counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                let (reader, writer) = stream.into_split();
+                tokio::spawn(HttpConnection::new(reader, writer).process(handler.clone()));
+            }
+        });
+        (addr, accepted)
+    }
+
+    #[tokio::test]
+    async fn test_keep_alive() {
+        let (addr, accepted) = serve().await;
+        let mut client = HttpClient::connect(addr).await.unwrap();
+
+        let response = client.send(Request::post("/echo").body(Bytes::from_static(b"hello")).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.body(), &format!("POST /echo {addr} hello"));
+
+        let request = Request::get("/").header(HOST, "example.com").body(Bytes::new()).unwrap();
+        let response = client.send(request).await.unwrap();
+        assert_eq!(response.body(), "GET / example.com ");
+        assert_eq!(response.headers().get("keep-alive").unwrap(), "timeout=75, max=998");
+
+        let response = client.send(Request::head("/").body(Bytes::new()).unwrap()).await.unwrap();
+        assert!(response.body().is_empty());
+        assert!(response.headers().contains_key(CONTENT_LENGTH));
+        assert_eq!(accepted.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        // a new connection is opened once the request closed the previous one
+        let request = Request::get("/").header("connection", "close").body(Bytes::new()).unwrap();
+        client.send(request).await.unwrap();
+        client.send(Request::get("/").body(Bytes::new()).unwrap()).await.unwrap();
+        assert_eq!(accepted.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    /// Accepts a single connection, answers its first request with `response` and closes it.
+    async fn serve_once(response: &'static [u8]) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0; 1024];
+            let _ = stream.read(&mut buf).await.unwrap();
+            stream.write_all(response).await.unwrap();
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_interim_and_close_delimited_response() {
+        let addr = serve_once(b"HTTP/1.1 100 Continue\r\n\r\nHTTP/1.0 200 OK\r\nx-id: 1\r\n\r\nhello world").await;
+        let mut client = HttpClient::connect(addr).await.unwrap();
+
+        let response = client.send(Request::get("/").body(Bytes::new()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.version(), Version::HTTP_10);
+        assert_eq!(response.headers().get("x-id").unwrap(), "1");
+        assert_eq!(response.body(), "hello world");
+    }
+
+    #[tokio::test]
+    async fn test_incomplete_response() {
+        let addr = serve_once(b"HTTP/1.1 200 OK\r\ncontent-length: 10\r\n\r\nhello").await;
+        let mut client = HttpClient::connect(addr).await.unwrap();
+
+        let result = client.send(Request::get("/").body(Bytes::new()).unwrap()).await;
+        assert!(matches!(result, Err(ClientError::Closed)), "{result:?}");
+    }
+
+    #[tokio::test]
+    async fn test_read_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        // the connection is accepted, but never answered
+        let server = tokio::spawn(async move { listener.accept().await });
+
+        let builder = HttpClient::with_timeout(Duration::from_secs(1), Duration::from_millis(100));
+        let mut client = builder.connect(addr).await.unwrap();
+        let result = client.send(Request::get("/").body(Bytes::new()).unwrap()).await;
+        assert!(matches!(result, Err(ClientError::Response { source: ParseError::ReadTimeout { .. } })), "{result:?}");
+        drop(server);
+    }
+}
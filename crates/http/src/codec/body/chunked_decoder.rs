@@ -7,7 +7,8 @@
 //! indicating the size of each chunk before its data.
 
 use crate::protocol::{ParseError, PayloadItem};
-use bytes::{Buf, Bytes, BytesMut};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use http::{HeaderMap, HeaderName, HeaderValue};
 use std::io;
 use std::io::ErrorKind;
 use std::task::Poll;
@@ -15,6 +16,12 @@ use tokio_util::codec::Decoder;
 use tracing::trace;
 use ChunkedState::*;
 
+/// Maximum number of trailer fields allowed after the last chunk
+const MAX_TRAILER_NUM: usize = 64;
+
+/// Maximum size in bytes allowed for the trailer section
+const MAX_TRAILER_BYTES: usize = 8 * 1024;
+
 /// A decoder for handling HTTP chunked transfer encoding.
 ///
 /// The decoder processes incoming bytes according to the chunked format:
@@ -22,11 +29,14 @@ use ChunkedState::*;
 /// - Followed by optional extensions and CRLF
 /// - Then the chunk data and CRLF
 /// - A zero-sized chunk indicates the end of the message
+/// - Trailer fields may follow the last chunk, they are decoded as a [`PayloadItem::Trailer`] before the EOF
 ///
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ChunkedDecoder {
     state: ChunkedState,
     remaining_size: u64,
+    /// The raw trailer section read so far, each field ends with CRLF
+    trailers: BytesMut,
 }
 
 impl ChunkedDecoder {
@@ -34,7 +44,7 @@ impl ChunkedDecoder {
     ///
     /// The decoder starts in the Size state, ready to read the size of the first chunk.
     pub fn new() -> Self {
-        Self { state: Size, remaining_size: 0 }
+        Self { state: Size, remaining_size: 0, trailers: BytesMut::new() }
     }
 }
 
@@ -74,12 +84,18 @@ impl Decoder for ChunkedDecoder {
     ///
     /// # Returns
     /// - `Ok(Some(PayloadItem::Chunk(bytes)))` when a chunk is successfully decoded
-    /// - `Ok(Some(PayloadItem::Eof))` when the final chunk is processed
+    /// - `Ok(Some(PayloadItem::Trailer(headers)))` when the final chunk is followed by trailer fields
+    /// - `Ok(Some(PayloadItem::Eof))` when the final chunk and its trailer fields are processed
     /// - `Ok(None)` when more data is needed
     /// - `Err(ParseError)` if the chunked encoding is invalid
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
         loop {
             if self.state == End {
+                if !self.trailers.is_empty() {
+                    let trailers = parse_trailers(&mut self.trailers)?;
+                    trace!(count = trailers.len(), "read chunked trailers");
+                    return Ok(Some(PayloadItem::Trailer(trailers)));
+                }
                 trace!("finished reading chunked data");
                 return Ok(Some(PayloadItem::Eof));
             }
@@ -91,12 +107,16 @@ impl Decoder for ChunkedDecoder {
 
             let mut buf = None;
 
-            self.state = match self.state.step(src, &mut self.remaining_size, &mut buf) {
+            self.state = match self.state.step(src, &mut self.remaining_size, &mut buf, &mut self.trailers) {
                 Poll::Pending => return Ok(None),
                 Poll::Ready(Ok(new_state)) => new_state,
                 Poll::Ready(Err(e)) => return Err(ParseError::io(e)),
             };
 
+            if self.trailers.len() > MAX_TRAILER_BYTES {
+                return Err(ParseError::too_large_header(self.trailers.len(), MAX_TRAILER_BYTES));
+            }
+
             if let Some(bytes) = buf {
                 trace!(len = bytes.len(), "read chunked bytes");
                 return Ok(Some(PayloadItem::Chunk(bytes)));
@@ -126,6 +146,7 @@ impl ChunkedState {
     /// * `src` - Source buffer containing the chunked data
     /// * `remaining_size` - Tracks remaining bytes in current chunk
     /// * `buf` - Buffer to store decoded chunk data
+    /// * `trailers` - Buffer collecting the raw trailer fields
     ///
     /// # Returns
     /// The next state in the decoding process or an error if invalid encoding is detected
@@ -134,6 +155,7 @@ impl ChunkedState {
         src: &mut BytesMut,
         remaining_size: &mut u64,
         buf: &mut Option<Bytes>,
+        trailers: &mut BytesMut,
     ) -> Poll<Result<ChunkedState, io::Error>> {
         match self {
             Size => ChunkedState::read_size(src, remaining_size),
@@ -143,9 +165,9 @@ impl ChunkedState {
             Body => ChunkedState::read_body(src, remaining_size, buf),
             BodyCr => ChunkedState::read_body_cr(src),
             BodyLf => ChunkedState::read_body_lf(src),
-            Trailer => ChunkedState::read_trailer(src),
-            TrailerLf => ChunkedState::read_trailer_lf(src),
-            EndCr => ChunkedState::read_end_cr(src),
+            Trailer => ChunkedState::read_trailer(src, trailers),
+            TrailerLf => ChunkedState::read_trailer_lf(src, trailers),
+            EndCr => ChunkedState::read_end_cr(src, trailers),
             EndLf => ChunkedState::read_end_lf(src),
             End => Poll::Ready(Ok(End)),
         }
@@ -169,7 +191,7 @@ impl ChunkedState {
                     Some(val) => val,
                     None => {
                         return Poll::Ready(Err(io::Error::new(
-                            ErrorKind::InvalidInput,
+                            ErrorKind::InvalidData,
                             "invalid overflow chunked length",
                         )))
                     }
@@ -198,7 +220,7 @@ impl ChunkedState {
 
             _ => {
                 return Poll::Ready(Err(io::Error::new(
-                    ErrorKind::InvalidInput,
+                    ErrorKind::InvalidData,
                     "invalid chunk size line: Invalid Size",
                 )))
             }
@@ -226,7 +248,7 @@ impl ChunkedState {
             b'\t' | b' ' => Poll::Ready(Ok(SizeLws)),
             b';' => Poll::Ready(Ok(Extension)), 
             b'\r' => Poll::Ready(Ok(SizeLf)),
-            _ => Poll::Ready(Err(io::Error::new(ErrorKind::InvalidInput, "invalid chunk size linear white space"))),
+            _ => Poll::Ready(Err(io::Error::new(ErrorKind::InvalidData, "invalid chunk size linear white space"))),
         }
     }
 
@@ -253,7 +275,7 @@ impl ChunkedState {
         match try_next_byte!(src) {
             b'\r' => Poll::Ready(Ok(SizeLf)),
             b'\n' => {
-                Poll::Ready(Err(io::Error::new(ErrorKind::InvalidInput, "invalid chunk extension contains newline")))
+                Poll::Ready(Err(io::Error::new(ErrorKind::InvalidData, "invalid chunk extension contains newline")))
             }
             _ => Poll::Ready(Ok(Extension)), // no supported extensions
         }
@@ -279,7 +301,7 @@ impl ChunkedState {
                 }
             }
 
-            _ => Poll::Ready(Err(io::Error::new(ErrorKind::InvalidInput, "invalid chunk size LF"))),
+            _ => Poll::Ready(Err(io::Error::new(ErrorKind::InvalidData, "invalid chunk size LF"))),
         }
     }
 
@@ -339,7 +361,7 @@ impl ChunkedState {
     fn read_body_cr(src: &mut BytesMut) -> Poll<Result<ChunkedState, io::Error>> {
         match try_next_byte!(src) {
             b'\r' => Poll::Ready(Ok(BodyLf)),
-            _ => Poll::Ready(Err(io::Error::new(ErrorKind::InvalidInput, "invalid chunk body CR"))),
+            _ => Poll::Ready(Err(io::Error::new(ErrorKind::InvalidData, "invalid chunk body CR"))),
         }
     }
 
@@ -354,22 +376,26 @@ impl ChunkedState {
     fn read_body_lf(src: &mut BytesMut) -> Poll<Result<ChunkedState, io::Error>> {
         match try_next_byte!(src) {
             b'\n' => Poll::Ready(Ok(Size)),
-            _ => Poll::Ready(Err(io::Error::new(ErrorKind::InvalidInput, "invalid chunk body LF"))),
+            _ => Poll::Ready(Err(io::Error::new(ErrorKind::InvalidData, "invalid chunk body LF"))),
         }
     }
 
     /// Processes optional trailer fields after the last chunk.
     ///
     /// The chunked encoding format allows for trailer fields after the
-    /// zero-length chunk. This implementation reads but ignores them.
+    /// zero-length chunk. Their bytes are collected, to be parsed once the
+    /// chunked message ends.
     ///
     /// # State Transitions
     /// - On CR: Move to TrailerLf state
     /// - On any other byte: Stay in Trailer state
-    fn read_trailer(src: &mut BytesMut) -> Poll<Result<ChunkedState, io::Error>> {
+    fn read_trailer(src: &mut BytesMut, trailers: &mut BytesMut) -> Poll<Result<ChunkedState, io::Error>> {
         match try_next_byte!(src) {
             b'\r' => Poll::Ready(Ok(TrailerLf)),
-            _ => Poll::Ready(Ok(Trailer)),
+            b => {
+                trailers.put_u8(b);
+                Poll::Ready(Ok(Trailer))
+            }
         }
     }
 
@@ -380,10 +406,13 @@ impl ChunkedState {
     /// # State Transitions
     /// - On LF: Move to EndCr state
     /// - On any other byte: Return error
-    fn read_trailer_lf(src: &mut BytesMut) -> Poll<Result<ChunkedState, io::Error>> {
+    fn read_trailer_lf(src: &mut BytesMut, trailers: &mut BytesMut) -> Poll<Result<ChunkedState, io::Error>> {
         match try_next_byte!(src) {
-            b'\n' => Poll::Ready(Ok(EndCr)),
-            _ => Poll::Ready(Err(io::Error::new(ErrorKind::InvalidInput, "invalid trailer end LF"))),
+            b'\n' => {
+                trailers.extend_from_slice(b"\r\n");
+                Poll::Ready(Ok(EndCr))
+            }
+            _ => Poll::Ready(Err(io::Error::new(ErrorKind::InvalidData, "invalid trailer end LF"))),
         }
     }
 
@@ -395,10 +424,13 @@ impl ChunkedState {
     /// # State Transitions
     /// - On CR: Move to EndLf state
     /// - On any other byte: Move to Trailer state to handle as trailer field
-    fn read_end_cr(src: &mut BytesMut) -> Poll<Result<ChunkedState, io::Error>> {
+    fn read_end_cr(src: &mut BytesMut, trailers: &mut BytesMut) -> Poll<Result<ChunkedState, io::Error>> {
         match try_next_byte!(src) {
             b'\r' => Poll::Ready(Ok(EndLf)),
-            _ => Poll::Ready(Ok(Trailer)),
+            b => {
+                trailers.put_u8(b);
+                Poll::Ready(Ok(Trailer))
+            }
         }
     }
 
@@ -413,11 +445,34 @@ impl ChunkedState {
     fn read_end_lf(src: &mut BytesMut) -> Poll<Result<ChunkedState, io::Error>> {
         match try_next_byte!(src) {
             b'\n' => Poll::Ready(Ok(End)),
-            _ => Poll::Ready(Err(io::Error::new(ErrorKind::InvalidInput, "invalid chunk end LF"))),
+            _ => Poll::Ready(Err(io::Error::new(ErrorKind::InvalidData, "invalid chunk end LF"))),
         }
     }
 }
 
+/// Parses the trailer fields collected by the decoder, the buffer is left empty.
+fn parse_trailers(raw: &mut BytesMut) -> Result<HeaderMap, ParseError> {
+    // httparse expects the empty line ending the fields
+    raw.extend_from_slice(b"\r\n");
+    let raw = raw.split();
+
+    let mut fields = [httparse::EMPTY_HEADER; MAX_TRAILER_NUM];
+    let fields = match httparse::parse_headers(&raw, &mut fields) {
+        Ok(httparse::Status::Complete((_, fields))) => fields,
+        Ok(httparse::Status::Partial) => return Err(ParseError::invalid_header("incomplete trailer fields")),
+        Err(httparse::Error::TooManyHeaders) => return Err(ParseError::too_many_headers(MAX_TRAILER_NUM)),
+        Err(e) => return Err(ParseError::invalid_header(e)),
+    };
+
+    let mut trailers = HeaderMap::with_capacity(fields.len());
+    for field in fields {
+        let name = HeaderName::from_bytes(field.name.as_bytes()).map_err(ParseError::invalid_header)?;
+        let value = HeaderValue::from_bytes(field.value).map_err(ParseError::invalid_header)?;
+        trailers.append(name, value);
+    }
+    Ok(trailers)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -496,11 +551,44 @@ mod tests {
         
         let chunk = decoder.decode(&mut buffer).unwrap().unwrap();
         assert_eq!(chunk.as_bytes().unwrap(), &Bytes::copy_from_slice(b"hello"));
+
+        let trailers = decoder.decode(&mut buffer).unwrap().unwrap().into_trailers().unwrap();
+        assert_eq!(trailers.len(), 1);
+        assert_eq!(trailers.get("trailer").unwrap(), "value");
         
         let eof = decoder.decode(&mut buffer).unwrap().unwrap();
         assert!(eof.is_eof());
     }
 
+    #[test]
+    fn test_trailers_split_across_buffers() {
+        let mut buffer: BytesMut = BytesMut::from(&b"0\r\ngrpc-status: 0\r"[..]);
+        let mut decoder = ChunkedDecoder::new();
+        assert!(decoder.decode(&mut buffer).unwrap().is_none());
+
+        buffer.extend_from_slice(b"\ngrpc-message: ok\r\n");
+        assert!(decoder.decode(&mut buffer).unwrap().is_none());
+
+        buffer.extend_from_slice(b"\r\n");
+        let trailers = decoder.decode(&mut buffer).unwrap().unwrap().into_trailers().unwrap();
+        assert_eq!(trailers.get("grpc-status").unwrap(), "0");
+        assert_eq!(trailers.get("grpc-message").unwrap(), "ok");
+        assert!(decoder.decode(&mut buffer).unwrap().unwrap().is_eof());
+    }
+
+    #[test]
+    fn test_invalid_trailers() {
+        let mut buffer: BytesMut = BytesMut::from(&b"0\r\nno colon\r\n\r\n"[..]);
+        let result = ChunkedDecoder::new().decode(&mut buffer);
+        assert!(matches!(result, Err(ParseError::InvalidHeader { .. })));
+
+        let mut data = b"0\r\nx: ".to_vec();
+        data.extend(vec![b'a'; MAX_TRAILER_BYTES]);
+        let mut buffer = BytesMut::from(&data[..]);
+        let result = ChunkedDecoder::new().decode(&mut buffer);
+        assert!(matches!(result, Err(ParseError::TooLargeHeader { .. })));
+    }
+
     #[test]
     fn test_incomplete_chunk() {
         let mut buffer: BytesMut = BytesMut::from(&b"5\r\nhel"[..]);
@@ -563,6 +651,51 @@ mod tests {
         assert!(eof.is_eof());
     }
 
+    #[test]
+    fn test_size_line_split_across_buffers() {
+        let mut buffer: BytesMut = BytesMut::from(&b"1"[..]);
+        let mut decoder = ChunkedDecoder::new();
+
+        assert!(decoder.decode(&mut buffer).unwrap().is_none());
+
+        buffer.extend_from_slice(b"0;name=val");
+        assert!(decoder.decode(&mut buffer).unwrap().is_none());
+
+        buffer.extend_from_slice(b"ue\r");
+        assert!(decoder.decode(&mut buffer).unwrap().is_none());
+
+        buffer.extend_from_slice(b"\n1234567890abcdef\r");
+        let chunk = decoder.decode(&mut buffer).unwrap().unwrap();
+        assert_eq!(chunk.as_bytes().unwrap(), &Bytes::copy_from_slice(b"1234567890abcdef"));
+        assert!(decoder.decode(&mut buffer).unwrap().is_none());
+
+        buffer.extend_from_slice(b"\n0\r\n");
+        assert!(decoder.decode(&mut buffer).unwrap().is_none());
+
+        buffer.extend_from_slice(b"\r\n");
+        assert!(decoder.decode(&mut buffer).unwrap().unwrap().is_eof());
+    }
+
+    #[test]
+    fn test_invalid_data_error_kind() {
+        fn error_kind(input: &'static [u8]) -> ErrorKind {
+            let mut buffer = BytesMut::from(input);
+            let mut decoder = ChunkedDecoder::new();
+            loop {
+                match decoder.decode(&mut buffer) {
+                    Ok(Some(item)) if item.is_chunk() => continue,
+                    Err(ParseError::Io { source }) => return source.kind(),
+                    other => panic!("expect io error, but got {:?}", other),
+                }
+            }
+        }
+
+        assert_eq!(error_kind(b"xyz\r\n"), ErrorKind::InvalidData);
+        assert_eq!(error_kind(b"5\rhello"), ErrorKind::InvalidData);
+        assert_eq!(error_kind(b"5\r\nhelloXX"), ErrorKind::InvalidData);
+        assert_eq!(error_kind(b"5;ext\nhello"), ErrorKind::InvalidData);
+    }
+
     #[test]
     fn test_zero_size_chunk() {
         let mut buffer: BytesMut = BytesMut::from(&b"0\r\n\r\n"[..]);
@@ -8,6 +8,7 @@
 
 use crate::protocol::{PayloadItem, SendError};
 use bytes::{Buf, BytesMut};
+use http::HeaderMap;
 use std::io::Write;
 use tokio_util::codec::Encoder;
 
@@ -17,28 +18,36 @@ use tokio_util::codec::Encoder;
 /// - Each chunk starts with its size in hexadecimal
 /// - Followed by CRLF
 /// - Then the chunk data and CRLF
-/// - A zero-sized chunk indicates the end of the message
+/// - A zero-sized chunk indicates the end of the message, followed by the trailer fields if any
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ChunkedEncoder {
     /// Indicates if the final zero-length chunk has been sent
     eof: bool,
     /// Size of the current chunk being sent
     send_size: usize,
+    /// Maximum size of a buffered chunk, `None` writes one chunk per item and `Some(0)` buffers until EOF
+    max_chunk_size: Option<usize>,
+    /// Data waiting to be written as a chunk
+    buf: BytesMut,
+    /// Trailer fields written after the last chunk
+    trailers: Option<HeaderMap>,
 }
 
 impl ChunkedEncoder {
     /// Creates a new ChunkedEncoder instance.
     ///
     /// The encoder starts in a non-EOF state, ready to encode chunks.
+    /// Each payload item is written as its own chunk.
     pub fn new() -> Self {
-        Self { eof: false, send_size: 0 }
+        Self { eof: false, send_size: 0, max_chunk_size: None, buf: BytesMut::new(), trailers: None }
     }
 
-    /// Returns whether the encoder has finished sending all chunks.
+    /// Creates a ChunkedEncoder buffering small payload items into chunks of `max` bytes.
     ///
-    /// Returns true if the final zero-length chunk has been sent.
-    pub fn is_finish(&self) -> bool {
-        self.eof
+    /// A chunk is written once `max` bytes are buffered, the remaining data is written on EOF.
+    /// A `max` of 0 buffers the whole payload until EOF, which suits small bodies.
+    pub fn with_max_chunk_size(max: usize) -> Self {
+        Self { max_chunk_size: Some(max), ..Self::new() }
     }
 }
 
@@ -46,14 +55,15 @@ impl ChunkedEncoder {
 ///
 /// This implementation handles encoding of PayloadItems into chunked format:
 /// - For PayloadItem::Chunk, writes the chunk size, data and terminating CRLF
-/// - For PayloadItem::Eof, writes the final zero-length chunk
+/// - For PayloadItem::Trailer, keeps the fields until EOF, the ones of several items are merged
+/// - For PayloadItem::Eof, writes the final zero-length chunk and the trailer fields
 impl<D: Buf> Encoder<PayloadItem<D>> for ChunkedEncoder {
     type Error = SendError;
 
     /// Encodes a PayloadItem into chunked transfer encoding format.
     ///
     /// # Arguments
-    /// * `item` - The PayloadItem to encode (either Chunk or Eof)
+    /// * `item` - The PayloadItem to encode
     /// * `dst` - The output buffer to write the encoded data to
     ///
     /// # Returns
@@ -64,27 +74,70 @@ impl<D: Buf> Encoder<PayloadItem<D>> for ChunkedEncoder {
             return Ok(());
         }
 
-        match item {
-            PayloadItem::Chunk(bytes) => {
-                // Write chunk size in hex followed by CRLF
-                write!(helper::Writer(dst), "{:X}\r\n", bytes.remaining())?;
-                dst.reserve(bytes.remaining() + 2);
-                // Write chunk data
-                dst.extend_from_slice(bytes.chunk());
-                // Write chunk terminating CRLF
-                dst.extend_from_slice(b"\r\n");
+        match (item, self.max_chunk_size) {
+            (PayloadItem::Chunk(bytes), None) => write_chunk(bytes.chunk(), dst),
+            (PayloadItem::Chunk(bytes), Some(max)) => {
+                self.buf.extend_from_slice(bytes.chunk());
+                if max > 0 {
+                    while self.buf.len() >= max {
+                        let chunk = self.buf.split_to(max);
+                        write_chunk(&chunk, dst)?;
+                    }
+                }
+                Ok(())
+            }
+            (PayloadItem::Trailer(trailers), _) => {
+                match &mut self.trailers {
+                    Some(existing) => existing.extend(trailers),
+                    None => self.trailers = Some(trailers),
+                }
                 Ok(())
             }
-            PayloadItem::Eof => {
+            (PayloadItem::Eof, _) => {
+                // Write the buffered data
+                let chunk = self.buf.split();
+                write_chunk(&chunk, dst)?;
+
                 self.eof = true;
                 // Write final zero-length chunk
-                dst.extend_from_slice(b"0\r\n\r\n");
+                dst.extend_from_slice(b"0\r\n");
+                if let Some(trailers) = self.trailers.take() {
+                    write_trailers(&trailers, dst);
+                }
+                dst.extend_from_slice(b"\r\n");
                 Ok(())
             }
         }
     }
 }
 
+/// Writes each trailer field as a `name: value` line.
+fn write_trailers(trailers: &HeaderMap, dst: &mut BytesMut) {
+    for (name, value) in trailers {
+        dst.reserve(name.as_str().len() + value.len() + 4);
+        dst.extend_from_slice(name.as_str().as_bytes());
+        dst.extend_from_slice(b": ");
+        dst.extend_from_slice(value.as_bytes());
+        dst.extend_from_slice(b"\r\n");
+    }
+}
+
+/// Writes `data` as a single chunk, an empty chunk would end the payload so it's skipped.
+fn write_chunk(data: &[u8], dst: &mut BytesMut) -> Result<(), SendError> {
+    if data.is_empty() {
+        return Ok(());
+    }
+
+    // Write chunk size in hex followed by CRLF
+    write!(helper::Writer(dst), "{:X}\r\n", data.len())?;
+    dst.reserve(data.len() + 2);
+    // Write chunk data
+    dst.extend_from_slice(data);
+    // Write chunk terminating CRLF
+    dst.extend_from_slice(b"\r\n");
+    Ok(())
+}
+
 /// Helper module providing a Writer implementation for BytesMut.
 ///
 /// This allows using std::fmt::Write with BytesMut for writing
@@ -106,3 +159,104 @@ mod helper {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+
+    fn encode_one_byte_items(mut encoder: ChunkedEncoder, count: usize) -> BytesMut {
+        let mut dst = BytesMut::new();
+        for _ in 0..count {
+            encoder.encode(PayloadItem::Chunk(Bytes::from_static(b"a")), &mut dst).unwrap();
+        }
+        encoder.encode(PayloadItem::<Bytes>::Eof, &mut dst).unwrap();
+        assert!(encoder.eof);
+        dst
+    }
+
+    #[test]
+    fn test_chunk_per_item() {
+        let dst = encode_one_byte_items(ChunkedEncoder::new(), 3);
+        assert_eq!(&dst[..], b"1\r\na\r\n1\r\na\r\n1\r\na\r\n0\r\n\r\n");
+        assert_eq!(encode_one_byte_items(ChunkedEncoder::new(), 100).len(), 100 * 6 + 5);
+    }
+
+    #[test]
+    fn test_max_chunk_size() {
+        let dst = encode_one_byte_items(ChunkedEncoder::with_max_chunk_size(64), 100);
+        let expected = format!("40\r\n{}\r\n24\r\n{}\r\n0\r\n\r\n", "a".repeat(64), "a".repeat(36));
+        assert_eq!(&dst[..], expected.as_bytes());
+        assert_eq!(dst.len(), 117);
+
+        // unlimited buffering writes a single chunk
+        let dst = encode_one_byte_items(ChunkedEncoder::with_max_chunk_size(0), 100);
+        let expected = format!("64\r\n{}\r\n0\r\n\r\n", "a".repeat(100));
+        assert_eq!(&dst[..], expected.as_bytes());
+        assert_eq!(dst.len(), 111);
+    }
+
+    #[test]
+    fn test_trailers() {
+        let mut encoder = ChunkedEncoder::new();
+        let mut dst = BytesMut::new();
+        let mut trailers = HeaderMap::new();
+        trailers.insert("grpc-status", "0".parse().unwrap());
+        trailers.append("server-timing", "db;dur=53".parse().unwrap());
+
+        encoder.encode(PayloadItem::Chunk(Bytes::from_static(b"hello")), &mut dst).unwrap();
+        encoder.encode(PayloadItem::<Bytes>::Trailer(trailers), &mut dst).unwrap();
+        encoder.encode(PayloadItem::<Bytes>::Eof, &mut dst).unwrap();
+
+        assert_eq!(&dst[..], b"5\r\nhello\r\n0\r\ngrpc-status: 0\r\nserver-timing: db;dur=53\r\n\r\n");
+    }
+
+    #[test]
+    fn test_empty_chunk_does_not_end_payload() {
+        let mut encoder = ChunkedEncoder::new();
+        let mut dst = BytesMut::new();
+        encoder.encode(PayloadItem::Chunk(Bytes::new()), &mut dst).unwrap();
+        assert!(dst.is_empty());
+        assert!(!encoder.eof);
+    }
+
+    /// Formats `bytes` as hex pairs, sixteen per line, so a framing mismatch shows which byte differs.
+    fn hexdump(bytes: &[u8]) -> String {
+        bytes
+            .chunks(16)
+            .map(|line| line.iter().map(|b| format!("{b:02x}")).collect::<Vec<_>>().join(" "))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn test_wire_format() {
+        let mut encoder = ChunkedEncoder::new();
+        let mut dst = BytesMut::new();
+        for chunk in [&b"hello"[..], b" ", b"abcdefghijklmnopqrstuvwxyz"] {
+            encoder.encode(PayloadItem::Chunk(Bytes::from_static(chunk)), &mut dst).unwrap();
+        }
+        encoder.encode(PayloadItem::<Bytes>::Eof, &mut dst).unwrap();
+
+        let expected: &[u8] = b"5\r\nhello\r\n1\r\n \r\n1A\r\nabcdefghijklmnopqrstuvwxyz\r\n0\r\n\r\n";
+        assert!(dst[..] == *expected, "encoded:\n{}\nexpected:\n{}", hexdump(&dst), hexdump(expected));
+        // the last chunk and the end of the trailer section, written once
+        assert_eq!(dst.windows(5).filter(|window| window == b"0\r\n\r\n").count(), 1, "{}", hexdump(&dst));
+    }
+
+    #[test]
+    fn test_encode_after_eof_is_noop() {
+        let mut encoder = ChunkedEncoder::new();
+        let mut dst = BytesMut::new();
+        encoder.encode(PayloadItem::Chunk(Bytes::from_static(b"hello")), &mut dst).unwrap();
+        encoder.encode(PayloadItem::<Bytes>::Eof, &mut dst).unwrap();
+        let len = dst.len();
+
+        encoder.encode(PayloadItem::<Bytes>::Eof, &mut dst).unwrap();
+        assert_eq!(dst.len(), len, "{}", hexdump(&dst));
+        encoder.encode(PayloadItem::Chunk(Bytes::from_static(b"late")), &mut dst).unwrap();
+        assert_eq!(dst.len(), len, "{}", hexdump(&dst));
+        assert_eq!(&dst[..], b"5\r\nhello\r\n0\r\n\r\n");
+        assert!(encoder.eof);
+    }
+}
@@ -6,8 +6,9 @@
 
 use crate::protocol::{PayloadItem, SendError};
 use bytes::{Buf, BytesMut};
+use std::io;
+use std::io::ErrorKind;
 use tokio_util::codec::Encoder;
-use tracing::warn;
 
 /// An encoder for handling HTTP messages with a known content length.
 ///
@@ -19,6 +20,8 @@ pub struct LengthEncoder {
     received_eof: bool,
     /// The number of bytes remaining to be sent
     length: u64,
+    /// The number of bytes already sent
+    written: u64,
 }
 
 impl LengthEncoder {
@@ -27,14 +30,7 @@ impl LengthEncoder {
     /// # Arguments
     /// * `length` - The total content length to encode, specified by Content-Length header
     pub fn new(length: u64) -> Self {
-        Self { received_eof: false, length }
-    }
-
-    /// Returns whether the encoder has finished sending all data.
-    ///
-    /// Returns true if all bytes have been sent and EOF has been received.
-    pub fn is_finish(&self) -> bool {
-        self.length == 0 && self.received_eof
+        Self { received_eof: false, length, written: 0 }
     }
 }
 
@@ -48,31 +44,106 @@ impl<D: Buf> Encoder<PayloadItem<D>> for LengthEncoder {
     /// Encodes a PayloadItem according to the content length.
     ///
     /// # Arguments
-    /// * `item` - The PayloadItem to encode (either Chunk or Eof)
+    /// * `item` - The PayloadItem to encode, trailer fields are ignored
     /// * `dst` - The output buffer to write the encoded data to
     ///
     /// # Returns
     /// * `Ok(())` if encoding succeeds
-    /// * `Err(SendError)` if encoding fails
+    /// * `Err(SendError)` with [`ErrorKind::InvalidData`] if the payload is longer than the content length,
+    ///   or if EOF is received before the whole content length has been sent
     fn encode(&mut self, item: PayloadItem<D>, dst: &mut BytesMut) -> Result<(), Self::Error> {
-        if self.length == 0 && !item.is_eof() {
-            warn!("encode payload_item but no need to encode anymore");
-            return Ok(());
-        }
-
         match item {
-            PayloadItem::Chunk(bytes) => {
-                if !bytes.has_remaining() {
-                    return Ok(());
+            PayloadItem::Chunk(mut bytes) => {
+                let size = bytes.remaining() as u64;
+                if size > self.length {
+                    return Err(io::Error::new(
+                        ErrorKind::InvalidData,
+                        format!(
+                            "payload overflows content length: {} bytes written, {} bytes remaining, got {} bytes",
+                            self.written, self.length, size
+                        ),
+                    )
+                    .into());
+                }
+
+                while bytes.has_remaining() {
+                    let chunk = bytes.chunk();
+                    let len = chunk.len();
+                    dst.extend_from_slice(chunk);
+                    bytes.advance(len);
                 }
-                dst.extend_from_slice(bytes.chunk());
-                self.length -= bytes.remaining() as u64;
+                self.length -= size;
+                self.written += size;
                 Ok(())
             }
+            // a payload with a content length has no trailer section
+            PayloadItem::Trailer(_) => Ok(()),
             PayloadItem::Eof => {
                 self.received_eof = true;
+                if self.length > 0 {
+                    return Err(io::Error::new(
+                        ErrorKind::InvalidData,
+                        format!(
+                            "payload ends before content length: {} bytes written, {} bytes missing",
+                            self.written, self.length
+                        ),
+                    )
+                    .into());
+                }
                 Ok(())
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+
+    fn io_error_kind(error: SendError) -> ErrorKind {
+        match error {
+            SendError::Io { source } => source.kind(),
+            SendError::InvalidBody { .. } => panic!("expect io error"),
+        }
+    }
+
+    #[test]
+    fn test_encode_exact_length() {
+        let mut encoder = LengthEncoder::new(10);
+        let mut buffer = BytesMut::new();
+
+        encoder.encode(PayloadItem::Chunk(Bytes::from_static(b"hello")), &mut buffer).unwrap();
+        assert_eq!(encoder.length, 5);
+
+        encoder.encode(PayloadItem::Chunk(Bytes::from_static(b"world")), &mut buffer).unwrap();
+        assert_eq!(encoder.length, 0);
+        assert!(!encoder.received_eof);
+
+        encoder.encode(PayloadItem::<Bytes>::Eof, &mut buffer).unwrap();
+        assert!(encoder.received_eof);
+        assert_eq!(&buffer[..], b"helloworld");
+    }
+
+    #[test]
+    fn test_encode_overflow() {
+        let mut encoder = LengthEncoder::new(4);
+        let mut buffer = BytesMut::new();
+
+        let error = encoder.encode(PayloadItem::Chunk(Bytes::from_static(b"hello")), &mut buffer).unwrap_err();
+        assert_eq!(io_error_kind(error), ErrorKind::InvalidData);
+        assert!(buffer.is_empty());
+        assert_eq!(encoder.length, 4);
+    }
+
+    #[test]
+    fn test_encode_early_eof() {
+        let mut encoder = LengthEncoder::new(10);
+        let mut buffer = BytesMut::new();
+
+        encoder.encode(PayloadItem::Chunk(Bytes::from_static(b"hello")), &mut buffer).unwrap();
+        let error = encoder.encode(PayloadItem::<Bytes>::Eof, &mut buffer).unwrap_err();
+        assert_eq!(io_error_kind(error), ErrorKind::InvalidData);
+        assert_eq!(encoder.length, 5);
+    }
+}
@@ -4,6 +4,8 @@
 //! - Content-Length based payloads
 //! - Chunked transfer encoding
 //! - Messages with no body
+//! - Payloads delimited by closing the connection, for responses without Content-Length or
+//!   Transfer-Encoding
 //!
 //! The decoder automatically handles the appropriate decoding strategy based on the message headers.
 
@@ -11,6 +13,8 @@ use crate::codec::body::chunked_decoder::ChunkedDecoder;
 use crate::codec::body::length_decoder::LengthDecoder;
 use crate::protocol::{ParseError, PayloadItem};
 use bytes::BytesMut;
+use http::header::{CONTENT_LENGTH, TRANSFER_ENCODING};
+use http::{HeaderMap, HeaderValue};
 use tokio_util::codec::Decoder;
 
 /// A unified decoder for handling HTTP message payloads.
@@ -36,6 +40,9 @@ enum Kind {
 
     /// Handle messages with no body
     NoBody,
+
+    /// Read the payload until the connection is closed
+    UntilClose { received_eof: bool },
 }
 
 impl PayloadDecoder {
@@ -50,6 +57,14 @@ impl PayloadDecoder {
         Self { kind: Kind::Chunked(ChunkedDecoder::new()) }
     }
 
+    /// Creates a PayloadDecoder reading the payload until the connection is closed.
+    ///
+    /// It's how a response with neither Content-Length nor Transfer-Encoding is delimited, see
+    /// RFC 9112 Section 6.3.
+    pub fn until_close() -> Self {
+        Self { kind: Kind::UntilClose { received_eof: false } }
+    }
+
     /// Creates a PayloadDecoder for a fixed-length payload.
     ///
     /// # Arguments
@@ -59,6 +74,41 @@ impl PayloadDecoder {
         Self { kind: Kind::Length(LengthDecoder::new(size)) }
     }
 
+    /// Selects the decoder of a message body from its Content-Length and Transfer-Encoding headers,
+    /// according to RFC 7230 section 3.3.3:
+    /// - Transfer-Encoding whose final encoding is chunked gives a chunked decoder
+    /// - Content-Length gives a fixed-length decoder
+    /// - no header gives a decoder for messages with no body
+    ///
+    /// # Errors
+    /// Returns `ParseError` if:
+    /// - Both Content-Length and Transfer-Encoding headers are present
+    /// - The final transfer encoding is not chunked, the body length can't be determined
+    /// - Content-Length is not a number, or several differing Content-Length values are present
+    pub fn from_headers(headers: &HeaderMap) -> Result<Self, ParseError> {
+        // refer: https://www.rfc-editor.org/rfc/rfc7230#section-3.3
+        let te_header = headers.get_all(TRANSFER_ENCODING).iter().next_back();
+        let has_content_length = headers.contains_key(CONTENT_LENGTH);
+
+        match (te_header, has_content_length) {
+            (None, false) => Ok(Self::empty()),
+
+            (te_value @ Some(_), false) => {
+                if is_chunked(te_value) {
+                    Ok(Self::chunked())
+                } else {
+                    Err(ParseError::invalid_header("transfer_encoding present but the final encoding is not chunked"))
+                }
+            }
+
+            (None, true) => Ok(Self::fix_length(content_length(headers)?)),
+
+            (Some(_), true) => {
+                Err(ParseError::invalid_content_length("transfer_encoding and content_length both present in headers"))
+            }
+        }
+    }
+
     /// Returns whether this decoder handles chunked transfer encoding.
     #[allow(unused)]
     pub fn is_chunked(&self) -> bool {
@@ -66,6 +116,7 @@ impl PayloadDecoder {
             Kind::Length(_) => false,
             Kind::Chunked(_) => true,
             Kind::NoBody => false,
+            Kind::UntilClose { .. } => false,
         }
     }
 
@@ -76,6 +127,7 @@ impl PayloadDecoder {
             Kind::Length(_) => false,
             Kind::Chunked(_) => false,
             Kind::NoBody => true,
+            Kind::UntilClose { .. } => false,
         }
     }
 
@@ -86,8 +138,61 @@ impl PayloadDecoder {
             Kind::Length(_) => true,
             Kind::Chunked(_) => false,
             Kind::NoBody => false,
+            Kind::UntilClose { .. } => false,
+        }
+    }
+
+    /// Returns whether this decoder reads the payload until the connection is closed.
+    #[allow(unused)]
+    pub fn is_until_close(&self) -> bool {
+        matches!(&self.kind, Kind::UntilClose { .. })
+    }
+}
+
+/// Parses the Content-Length headers, which may be repeated or hold a list as long as all the values are equal.
+fn content_length(headers: &HeaderMap) -> Result<u64, ParseError> {
+    let mut length = None;
+    for cl_value in headers.get_all(CONTENT_LENGTH) {
+        let cl_str = cl_value.to_str().map_err(|_| ParseError::invalid_content_length("value can't to_str"))?;
+
+        for value in cl_str.split(',').map(str::trim) {
+            // u64::from_str accepts a leading '+' which is not allowed by the grammar
+            let value = Some(value)
+                .filter(|value| value.bytes().all(|b| b.is_ascii_digit()))
+                .and_then(|value| value.parse::<u64>().ok())
+                .ok_or_else(|| ParseError::invalid_content_length(format!("value {cl_str} is not u64")))?;
+
+            match length {
+                Some(length) if length != value => {
+                    return Err(ParseError::invalid_content_length(format!(
+                        "different content_length values {length} and {value}"
+                    )));
+                }
+                _ => length = Some(value),
+            }
         }
     }
+
+    length.ok_or_else(|| ParseError::invalid_content_length("content_length is empty"))
+}
+
+/// Checks if the Transfer-Encoding header indicates chunked encoding.
+///
+/// According to RFC 7230, chunked must be the last encoding if present.
+///
+/// # Arguments
+///
+/// * `header_value` - Optional reference to the Transfer-Encoding header value
+///
+/// # Returns
+///
+/// Returns true if chunked is the final encoding in the Transfer-Encoding header.
+fn is_chunked(header_value: Option<&HeaderValue>) -> bool {
+    header_value
+        .and_then(|value| value.to_str().ok())
+        .and_then(|encodings| encodings.rsplit(',').next())
+        .map(|last_encoding| last_encoding.trim() == "chunked")
+        .unwrap_or(false)
 }
 
 /// Implementation of the Decoder trait for HTTP payloads.
@@ -110,6 +215,127 @@ impl Decoder for PayloadDecoder {
             Kind::Length(length_decoder) => length_decoder.decode(src),
             Kind::Chunked(chunked_decoder) => chunked_decoder.decode(src),
             Kind::NoBody => Ok(Some(PayloadItem::Eof)),
+            Kind::UntilClose { .. } if src.is_empty() => Ok(None),
+            Kind::UntilClose { .. } => Ok(Some(PayloadItem::Chunk(src.split().freeze()))),
+        }
+    }
+
+    /// Decodes the last bytes once the connection is closed.
+    ///
+    /// The end of the connection is the end of a payload read until close, a payload of any other kind is
+    /// incomplete if it's not finished yet.
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        match self.decode(src)? {
+            Some(item) => Ok(Some(item)),
+            None => match &mut self.kind {
+                Kind::UntilClose { received_eof } if !*received_eof => {
+                    *received_eof = true;
+                    Ok(Some(PayloadItem::Eof))
+                }
+                _ if src.is_empty() => Ok(None),
+                _ => Err(ParseError::invalid_body("bytes remaining on stream")),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_is_chunked() {
+        {
+            let headers = HeaderMap::new();
+            assert!(!is_chunked(headers.get(http::header::TRANSFER_ENCODING)))
+        }
+
+        {
+            let mut headers = HeaderMap::new();
+            headers.insert("Accept", "foo".parse().unwrap());
+            headers.insert("Transfer-Encoding", "gzip, chunked".parse().unwrap());
+            headers.insert("Host", "bar".parse().unwrap());
+            assert!(is_chunked(headers.get(http::header::TRANSFER_ENCODING)));
+        }
+
+        {
+            let mut headers = HeaderMap::new();
+            headers.insert("Accept", "foo".parse().unwrap());
+            headers.insert("Transfer-Encoding", "chunked, gzip".parse().unwrap());
+            headers.insert("Host", "bar".parse().unwrap());
+            assert!(!is_chunked(headers.get(http::header::TRANSFER_ENCODING)));
+        }
+
+        {
+            let mut headers = HeaderMap::new();
+            headers.insert("Accept", "foo".parse().unwrap());
+            headers.insert("Transfer-Encoding", "gzip".parse().unwrap());
+            headers.insert("Host", "bar".parse().unwrap());
+            assert!(!is_chunked(headers.get(http::header::TRANSFER_ENCODING)));
+        }
+    }
+
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(*name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    #[test]
+    fn test_from_headers() {
+        assert!(PayloadDecoder::from_headers(&headers(&[])).unwrap().is_empty());
+        let te = headers(&[("Transfer-Encoding", "gzip, chunked")]);
+        assert!(PayloadDecoder::from_headers(&te).unwrap().is_chunked());
+        assert_eq!(
+            PayloadDecoder::from_headers(&headers(&[("Content-Length", "10")])).unwrap(),
+            PayloadDecoder::fix_length(10)
+        );
+
+        // repeated but equal values are accepted
+        assert_eq!(
+            PayloadDecoder::from_headers(&headers(&[("Content-Length", "10, 10"), ("Content-Length", "10")])).unwrap(),
+            PayloadDecoder::fix_length(10)
+        );
+    }
+
+    #[test]
+    fn test_until_close() {
+        let mut decoder = PayloadDecoder::until_close();
+        let mut src = BytesMut::from(&b"hello"[..]);
+        assert_eq!(decoder.decode(&mut src).unwrap(), Some(PayloadItem::Chunk("hello".into())));
+        assert_eq!(decoder.decode(&mut src).unwrap(), None);
+
+        src.extend_from_slice(b" world");
+        assert_eq!(decoder.decode_eof(&mut src).unwrap(), Some(PayloadItem::Chunk(" world".into())));
+        assert_eq!(decoder.decode_eof(&mut src).unwrap(), Some(PayloadItem::Eof));
+        assert_eq!(decoder.decode_eof(&mut src).unwrap(), None);
+
+        // a fixed-length payload cut by the end of the connection is incomplete
+        let mut decoder = PayloadDecoder::fix_length(10);
+        let mut src = BytesMut::from(&b"hello"[..]);
+        assert!(decoder.decode_eof(&mut src).unwrap().unwrap().is_chunk());
+        assert_eq!(decoder.decode_eof(&mut src).unwrap(), None);
+    }
+
+    #[test]
+    fn test_from_headers_errors() {
+        let invalid = [
+            vec![("Transfer-Encoding", "chunked"), ("Content-Length", "10")],
+            vec![("Transfer-Encoding", "chunked, gzip")],
+            vec![("Content-Length", "10"), ("Content-Length", "11")],
+            vec![("Content-Length", "10, 11")],
+            vec![("Content-Length", "abc")],
+            vec![("Content-Length", "+10")],
+            vec![("Content-Length", "-1")],
+            vec![("Content-Length", "")],
+            vec![("Content-Length", "18446744073709551616")],
+        ];
+
+        for pairs in invalid {
+            assert!(PayloadDecoder::from_headers(&headers(&pairs)).is_err(), "{pairs:?}");
         }
     }
 }
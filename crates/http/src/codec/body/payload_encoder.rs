@@ -4,6 +4,7 @@
 //! - Content-Length based payloads
 //! - Chunked transfer encoding
 //! - Messages with no body
+//! - Payloads delimited by closing the connection, for HTTP/1.0 clients
 //!
 //! The encoder automatically handles the appropriate encoding strategy based on the message headers.
 
@@ -11,6 +12,8 @@ use crate::codec::body::chunked_encoder::ChunkedEncoder;
 use crate::codec::body::length_encoder::LengthEncoder;
 use crate::protocol::{PayloadItem, SendError};
 use bytes::{Buf, BytesMut};
+use std::io;
+use std::io::ErrorKind;
 use tokio_util::codec::Encoder;
 
 /// A unified encoder for handling HTTP message payloads.
@@ -23,6 +26,8 @@ use tokio_util::codec::Encoder;
 pub struct PayloadEncoder {
     /// The specific encoding strategy to use
     kind: Kind,
+    /// The number of payload bytes encoded so far, without the framing of chunks
+    bytes_written: u64,
 }
 
 /// Enum representing different payload encoding strategies.
@@ -36,17 +41,34 @@ enum Kind {
 
     /// Handle messages with no body
     NoBody,
+
+    /// Write the payload as is, its end is signaled by closing the connection
+    CloseDelimited { received_eof: bool },
 }
 
 impl PayloadEncoder {
     /// Creates a PayloadEncoder for messages with no body.
     pub fn empty() -> Self {
-        Self { kind: Kind::NoBody }
+        Self { kind: Kind::NoBody, bytes_written: 0 }
     }
 
     /// Creates a PayloadEncoder for chunked transfer encoding.
     pub fn chunked() -> Self {
-        Self { kind: Kind::Chunked(ChunkedEncoder::new()) }
+        Self { kind: Kind::Chunked(ChunkedEncoder::new()), bytes_written: 0 }
+    }
+
+    /// Creates a PayloadEncoder using chunked transfer encoding that buffers items into chunks of
+    /// `max` bytes, see [`ChunkedEncoder::with_max_chunk_size`].
+    pub fn chunked_with_max(max: usize) -> Self {
+        Self { kind: Kind::Chunked(ChunkedEncoder::with_max_chunk_size(max)), bytes_written: 0 }
+    }
+
+    /// Creates a PayloadEncoder writing the payload without any framing, the connection must be
+    /// closed once it's sent.
+    ///
+    /// HTTP/1.0 clients can't read chunked payloads, so it's used for their responses of unknown length.
+    pub fn close_delimited() -> Self {
+        Self { kind: Kind::CloseDelimited { received_eof: false }, bytes_written: 0 }
     }
 
     /// Creates a PayloadEncoder for a fixed-length payload.
@@ -55,7 +77,7 @@ impl PayloadEncoder {
     /// * `size` - The expected content length in bytes
     #[allow(unused)]
     pub fn fix_length(size: u64) -> Self {
-        Self { kind: Kind::Length(LengthEncoder::new(size)) }
+        Self { kind: Kind::Length(LengthEncoder::new(size)), bytes_written: 0 }
     }
 
     /// Returns whether this encoder handles chunked transfer encoding.
@@ -65,6 +87,7 @@ impl PayloadEncoder {
             Kind::Length(_) => false,
             Kind::Chunked(_) => true,
             Kind::NoBody => false,
+            Kind::CloseDelimited { .. } => false,
         }
     }
 
@@ -75,6 +98,7 @@ impl PayloadEncoder {
             Kind::Length(_) => false,
             Kind::Chunked(_) => false,
             Kind::NoBody => true,
+            Kind::CloseDelimited { .. } => false,
         }
     }
 
@@ -85,16 +109,16 @@ impl PayloadEncoder {
             Kind::Length(_) => true,
             Kind::Chunked(_) => false,
             Kind::NoBody => false,
+            Kind::CloseDelimited { .. } => false,
         }
     }
 
-    /// Returns whether the encoder has finished sending all data.
-    pub fn is_finish(&self) -> bool {
-        match &self.kind {
-            Kind::Length(encoder) => encoder.is_finish(),
-            Kind::Chunked(encoder) => encoder.is_finish(),
-            Kind::NoBody => true,
-        }
+    /// Returns the number of payload bytes encoded so far.
+    ///
+    /// The framing of chunked payloads isn't counted, so once a fixed-length payload is finished it
+    /// equals its declared length.
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
     }
 }
 
@@ -112,12 +136,109 @@ impl<D: Buf> Encoder<PayloadItem<D>> for PayloadEncoder {
     ///
     /// # Returns
     /// * Delegates to the specific encoder implementation, or
-    /// * Returns Ok(()) immediately for no-body messages
+    /// * Returns an error for no-body messages given some data, their empty chunks and `Eof` are ignored
     fn encode(&mut self, item: PayloadItem<D>, dst: &mut BytesMut) -> Result<(), Self::Error> {
-        match &mut self.kind {
+        let len = match &item {
+            PayloadItem::Chunk(bytes) => bytes.remaining() as u64,
+            _ => 0,
+        };
+
+        let result = match &mut self.kind {
             Kind::Length(encoder) => encoder.encode(item, dst),
             Kind::Chunked(encoder) => encoder.encode(item, dst),
-            Kind::NoBody => Ok(()),
+            Kind::NoBody => match item {
+                // the bytes would be dropped silently, or break the framing of the next message
+                PayloadItem::Chunk(bytes) if bytes.has_remaining() => {
+                    Err(io::Error::new(ErrorKind::InvalidInput, "response must have no body").into())
+                }
+                _ => Ok(()),
+            },
+            Kind::CloseDelimited { received_eof } => {
+                match item {
+                    PayloadItem::Chunk(mut bytes) => {
+                        while bytes.has_remaining() {
+                            let chunk = bytes.chunk();
+                            let len = chunk.len();
+                            dst.extend_from_slice(chunk);
+                            bytes.advance(len);
+                        }
+                    }
+                    PayloadItem::Trailer(_) => {}
+                    PayloadItem::Eof => *received_eof = true,
+                }
+                Ok(())
+            }
+        };
+
+        if result.is_ok() {
+            self.bytes_written += len;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+
+    fn encode_all(encoder: &mut PayloadEncoder, chunks: &[&'static str]) -> BytesMut {
+        let mut dst = BytesMut::new();
+        for chunk in chunks {
+            encoder.encode(PayloadItem::Chunk(Bytes::from_static(chunk.as_bytes())), &mut dst).unwrap();
         }
+        encoder.encode(PayloadItem::<Bytes>::Eof, &mut dst).unwrap();
+        dst
+    }
+
+    #[test]
+    fn test_bytes_written() {
+        let mut encoder = PayloadEncoder::chunked();
+        let dst = encode_all(&mut encoder, &["hello", " ", "world"]);
+        assert_eq!(encoder.bytes_written(), 11);
+        assert!(dst.len() > 11);
+
+        let mut encoder = PayloadEncoder::fix_length(11);
+        encode_all(&mut encoder, &["hello ", "world"]);
+        assert_eq!(encoder.bytes_written(), 11);
+
+        let mut encoder = PayloadEncoder::close_delimited();
+        encode_all(&mut encoder, &["hello"]);
+        assert_eq!(encoder.bytes_written(), 5);
+
+        let mut encoder = PayloadEncoder::empty();
+        assert!(encode_all(&mut encoder, &[""]).is_empty());
+        assert_eq!(encoder.bytes_written(), 0);
+    }
+
+    #[test]
+    fn test_chunked_with_max() {
+        let mut encoder = PayloadEncoder::chunked_with_max(8);
+        let dst = encode_all(&mut encoder, &["hello", " ", "world"]);
+        assert_eq!(&dst[..], b"8\r\nhello wo\r\n3\r\nrld\r\n0\r\n\r\n");
+        assert_eq!(encoder.bytes_written(), 11);
+    }
+
+    #[test]
+    fn test_no_body() {
+        let mut encoder = PayloadEncoder::empty();
+        let mut dst = BytesMut::new();
+
+        encoder.encode(PayloadItem::Chunk(Bytes::new()), &mut dst).unwrap();
+        let err = encoder.encode(PayloadItem::Chunk(Bytes::from_static(b"hello")), &mut dst).unwrap_err();
+        assert!(matches!(&err, SendError::Io { source } if source.kind() == ErrorKind::InvalidInput));
+        assert!(err.to_string().contains("response must have no body"), "{err}");
+
+        encoder.encode(PayloadItem::<Bytes>::Eof, &mut dst).unwrap();
+        assert!(dst.is_empty());
+    }
+
+    #[test]
+    fn test_bytes_written_excludes_rejected_data() {
+        let mut encoder = PayloadEncoder::fix_length(5);
+        let mut dst = BytesMut::new();
+        encoder.encode(PayloadItem::Chunk(Bytes::from_static(b"hello")), &mut dst).unwrap();
+        assert!(encoder.encode(PayloadItem::Chunk(Bytes::from_static(b"!")), &mut dst).is_err());
+        assert_eq!(encoder.bytes_written(), 5);
     }
 }
@@ -10,6 +10,7 @@
 //! - Support for HTTP/1.0 and HTTP/1.1
 //! - Memory safety through `MaybeUninit` for header allocation
 //! - Built-in protection against oversized headers
+//! - Header values are checked to be valid UTF-8
 //! - Automatic payload decoder selection based on headers
 //!
 //! # Limits
@@ -17,6 +18,7 @@
 //! - Maximum number of headers: 64 
 //! - Maximum header size: 8KB
 //! - Only supports HTTP/1.0 and HTTP/1.1 (HTTP/2 and HTTP/3 currently not supported)
+//! - Folded header lines (obs-fold) are rejected, as RFC 9112 section 5.2 allows
 //!
 //! # Implementation Details
 //!
@@ -45,10 +47,10 @@ use crate::ensure;
 use crate::protocol::{ParseError, RequestHeader};
 
 /// Maximum number of headers allowed in a request
-const MAX_HEADER_NUM: usize = 64;
+pub(crate) const MAX_HEADER_NUM: usize = 64;
 
 /// Maximum size in bytes allowed for the entire header section
-const MAX_HEADER_BYTES: usize = 8 * 1024;
+pub(crate) const MAX_HEADER_BYTES: usize = 8 * 1024;
 
 /// Decoder for HTTP request headers implementing the [`Decoder`] trait.
 /// 
@@ -79,6 +81,7 @@ impl Decoder for HeaderDecoder {
     /// - The total header size exceeds `MAX_HEADER_BYTES`
     /// - The HTTP version is not supported
     /// - Headers contain invalid characters
+    /// - A header value is not valid UTF-8
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
         // Create an empty HTTP request parser and uninitialized headers array
         let mut req = httparse::Request::new(&mut []);
@@ -112,8 +115,8 @@ impl Decoder for HeaderDecoder {
 
                 // Build request header using parsed method, URI and version
                 let mut header_builder = Request::builder()
-                    .method(req.method.ok_or_else(|| ParseError::InvalidMethod)?)
-                    .uri(req.path.ok_or_else(|| ParseError::InvalidUri)?)
+                    .method(req.method.ok_or(ParseError::InvalidMethod)?)
+                    .uri(req.path.ok_or(ParseError::InvalidUri)?)
                     .version(version);
 
                 // Build headers
@@ -128,9 +131,15 @@ impl Decoder for HeaderDecoder {
                     // Safe to unwrap since httparse verified header name is valid ASCII
                     let name = HeaderName::from_bytes(&header_bytes[index.name.0..index.name.1]).unwrap();
 
+                    // httparse accepts any byte above 0x7f in values, they must form valid UTF-8 here
+                    let value_bytes = &header_bytes[index.value.0..index.value.1];
+                    if !value_bytes.is_ascii() && std::str::from_utf8(value_bytes).is_err() {
+                        return Err(ParseError::invalid_utf8(name));
+                    }
+
                     // inspired by active-web:
-                    // Safe to use from_maybe_shared_unchecked since httparse verified 
-                    // header value contains only visible ASCII chars
+                    // Safe to use from_maybe_shared_unchecked since httparse verified
+                    // header value contains only visible chars
                     let value = unsafe {
                         HeaderValue::from_maybe_shared_unchecked(
                             header_bytes.slice(index.value.0..index.value.1),
@@ -202,119 +211,31 @@ impl HeaderIndex {
 
 /// Determines the appropriate payload decoder based on the request headers.
 ///
-/// This function examines the Content-Length and Transfer-Encoding headers
-/// to select the correct decoder according to RFC 7230 section 3.3.
+/// Requests whose method doesn't expect a body get an empty decoder, others are
+/// delegated to [`PayloadDecoder::from_headers`].
 ///
 /// # Arguments
 ///
 /// * `header` - The parsed request header
 ///
-/// # Returns
-///
-/// Returns a `PayloadDecoder` configured based on the headers:
-/// - Empty decoder if no body is expected
-/// - Chunked decoder if Transfer-Encoding: chunked is present
-/// - Fixed-length decoder if Content-Length is present
-///
 /// # Errors
 ///
-/// Returns `ParseError` if:
-/// - Both Content-Length and Transfer-Encoding headers are present
-/// - Content-Length value is invalid
+/// Returns `ParseError` if the Content-Length and Transfer-Encoding headers
+/// are conflicting or malformed
 fn parse_payload(header: &RequestHeader) -> Result<PayloadDecoder, ParseError> {
     if !header.need_body() {
         return Ok(PayloadDecoder::empty());
     }
 
-    // refer: https://www.rfc-editor.org/rfc/rfc7230#section-3.3
-    let te_header = header.headers().get(http::header::TRANSFER_ENCODING);
-    let cl_header = header.headers().get(http::header::CONTENT_LENGTH);
-
-    match (te_header, cl_header) {
-        (None, None) => Ok(PayloadDecoder::empty()),
-
-        (te_value @ Some(_), None) => {
-            if is_chunked(te_value) {
-                Ok(PayloadDecoder::chunked())
-            } else {
-                Ok(PayloadDecoder::empty())
-            }
-        }
-
-        (None, Some(cl_value)) => {
-            let cl_str = cl_value.to_str().map_err(|_| ParseError::invalid_content_length("value can't to_str"))?;
-
-            let length = cl_str
-                .trim()
-                .parse::<u64>()
-                .map_err(|_| ParseError::invalid_content_length(format!("value {cl_str} is not u64")))?;
-
-            Ok(PayloadDecoder::fix_length(length))
-        }
-
-        (Some(_), Some(_)) => {
-            Err(ParseError::invalid_content_length("transfer_encoding and content_length both present in headers"))
-        }
-    }
-}
-
-/// Checks if the Transfer-Encoding header indicates chunked encoding.
-///
-/// According to RFC 7230, chunked must be the last encoding if present.
-///
-/// # Arguments
-///
-/// * `header_value` - Optional reference to the Transfer-Encoding header value
-///
-/// # Returns
-///
-/// Returns true if chunked is the final encoding in the Transfer-Encoding header.
-fn is_chunked(header_value: Option<&HeaderValue>) -> bool {
-    header_value
-        .and_then(|value| value.to_str().ok())
-        .and_then(|encodings| encodings.rsplit(',').next())
-        .map(|last_encoding| last_encoding.trim() == "chunked")
-        .unwrap_or(false)
+    PayloadDecoder::from_headers(header.headers())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use http::{HeaderMap, Method, Version};
+    use http::{Method, Version};
     use indoc::indoc;
 
-    #[test]
-    fn check_is_chunked() {
-        {
-            let headers = HeaderMap::new();
-            assert!(!is_chunked(headers.get(http::header::TRANSFER_ENCODING)))
-        }
-
-        {
-            let mut headers = HeaderMap::new();
-            headers.insert("Accept", "foo".parse().unwrap());
-            headers.insert("Transfer-Encoding", "gzip, chunked".parse().unwrap());
-            headers.insert("Host", "bar".parse().unwrap());
-            assert!(is_chunked(headers.get(http::header::TRANSFER_ENCODING)));
-        }
-
-        {
-            let mut headers = HeaderMap::new();
-            headers.insert("Accept", "foo".parse().unwrap());
-            headers.insert("Transfer-Encoding", "chunked, gzip".parse().unwrap());
-            headers.insert("Host", "bar".parse().unwrap());
-            assert!(!is_chunked(headers.get(http::header::TRANSFER_ENCODING)));
-        }
-
-        {
-            let mut headers = HeaderMap::new();
-            headers.insert("Accept", "foo".parse().unwrap());
-            headers.insert("Transfer-Encoding", "gzip".parse().unwrap());
-            headers.insert("Host", "bar".parse().unwrap());
-            assert!(!is_chunked(headers.get(http::header::TRANSFER_ENCODING)));
-        }
-    }
-
     #[test]
     fn test_bytes_mut_lens() {
         let str = indoc! {r##"
@@ -456,4 +377,53 @@ mod tests {
             Some(&HeaderValue::from_str("zh-CN,zh;q=0.9,en-US;q=0.8,en;q=0.7").unwrap())
         );
     }
+
+    #[test]
+    fn test_split_reads() {
+        let str = "GET /index.html HTTP/1.1\r\nHost: 127.0.0.1:8080\r\nAccept: */*\r\n\r\n";
+
+        let mut buf = BytesMut::new();
+        for (i, byte) in str.bytes().enumerate() {
+            buf.extend_from_slice(&[byte]);
+            let result = HeaderDecoder.decode(&mut buf).unwrap();
+            if i + 1 < str.len() {
+                assert!(result.is_none(), "decoded before the end of the headers at {i}");
+                continue;
+            }
+
+            let (header, _) = result.unwrap();
+            assert_eq!(header.uri().path(), "/index.html");
+            assert_eq!(header.headers().get(http::header::ACCEPT).unwrap(), "*/*");
+        }
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_obs_fold_rejected() {
+        let mut buf = BytesMut::from("GET / HTTP/1.1\r\nX-Folded: first\r\n second\r\n\r\n");
+        assert!(matches!(HeaderDecoder.decode(&mut buf), Err(ParseError::InvalidHeader { .. })));
+    }
+
+    #[test]
+    fn test_too_large_header() {
+        let value = "a".repeat(MAX_HEADER_BYTES);
+
+        // rejected before the end of the headers is received
+        let mut buf = BytesMut::from(format!("GET / HTTP/1.1\r\nX-Long: {value}").as_str());
+        assert!(matches!(HeaderDecoder.decode(&mut buf), Err(ParseError::TooLargeHeader { .. })));
+
+        let mut buf = BytesMut::from(format!("GET / HTTP/1.1\r\nX-Long: {value}\r\n\r\n").as_str());
+        assert!(matches!(HeaderDecoder.decode(&mut buf), Err(ParseError::TooLargeHeader { .. })));
+    }
+
+    #[test]
+    fn test_utf8_values() {
+        let mut buf = BytesMut::from("GET / HTTP/1.1\r\nX-Name: caf\u{e9}\r\n\r\n");
+        let (header, _) = HeaderDecoder.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(header.headers().get("x-name").unwrap().as_bytes(), "caf\u{e9}".as_bytes());
+
+        let mut buf = BytesMut::from(&b"GET / HTTP/1.1\r\nX-Name: caf\xe9\r\n\r\n"[..]);
+        let result = HeaderDecoder.decode(&mut buf);
+        assert!(matches!(result, Err(ParseError::InvalidUtf8 { name }) if name == "x-name"));
+    }
 }
@@ -8,14 +8,14 @@
 //!
 //! - Efficient header serialization
 //! - Automatic handling of Content-Length and Transfer-Encoding headers
-//! - Support for HTTP/1.1 responses
+//! - Support for HTTP/1.1 responses, and HTTP/1.0 responses without chunked transfer encoding
 //! - Chunked transfer encoding support
 
 use crate::protocol::{PayloadSize, ResponseHead, SendError};
 
 use bytes::{BufMut, BytesMut};
 
-use http::{header, Version};
+use http::{header, StatusCode, Version};
 use std::io;
 use std::io::{ErrorKind, Write};
 use tokio_util::codec::Encoder;
@@ -48,26 +48,27 @@ impl Encoder<(ResponseHead, PayloadSize)> for HeaderEncoder {
     /// # Errors
     ///
     /// Returns error if:
-    /// - HTTP version is not supported (only HTTP/1.1 supported)
+    /// - HTTP version is not supported (only HTTP/1.1 and HTTP/1.0 are supported)
     /// - Writing to buffer fails
     fn encode(&mut self, item: (ResponseHead, PayloadSize), dst: &mut BytesMut) -> Result<(), Self::Error> {
         let (mut header, payload_size) = item;
 
         dst.reserve(INIT_HEADER_SIZE);
-        match header.version() {
-            Version::HTTP_11 => {
-                write!(
-                    FastWrite(dst),
-                    "HTTP/1.1 {} {}\r\n",
-                    header.status().as_str(),
-                    header.status().canonical_reason().unwrap()
-                )?;
-            }
+        let version = match header.version() {
+            Version::HTTP_11 => "HTTP/1.1",
+            Version::HTTP_10 => "HTTP/1.0",
             v => {
                 error!(http_version = ?v, "unsupported http version");
                 return Err(io::Error::from(ErrorKind::Unsupported).into());
             }
-        }
+        };
+        write!(
+            FastWrite(dst),
+            "{} {} {}\r\n",
+            version,
+            header.status().as_str(),
+            header.status().canonical_reason().unwrap()
+        )?;
 
         // Set appropriate content length or transfer encoding header
         match payload_size {
@@ -77,12 +78,20 @@ impl Encoder<(ResponseHead, PayloadSize)> for HeaderEncoder {
                     header.headers_mut().insert(header::CONTENT_LENGTH, n.into());
                 }
             },
+            // HTTP/1.0 has no transfer encoding, the payload is delimited by closing the connection
+            PayloadSize::Chunked if header.version() == Version::HTTP_10 => {
+                header.headers_mut().remove(header::TRANSFER_ENCODING);
+            }
             PayloadSize::Chunked => match header.headers_mut().get_mut(header::TRANSFER_ENCODING) {
                 Some(value) => *value = "chunked".parse().unwrap(),
                 None => {
                     header.headers_mut().insert(header::TRANSFER_ENCODING, "chunked".parse().unwrap());
                 }
             },
+            // a server must not send Content-Length in 1xx and 204 responses, see RFC 9110 Section 8.6
+            PayloadSize::Empty if header.status().is_informational() || header.status() == StatusCode::NO_CONTENT => {
+                header.headers_mut().remove(header::CONTENT_LENGTH);
+            }
             PayloadSize::Empty => match header.headers_mut().get_mut(header::CONTENT_LENGTH) {
                 Some(value) => *value = 0.into(),
                 None => {
@@ -121,3 +130,45 @@ impl<'a> Write for FastWrite<'a> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::Response;
+
+    fn encode(status: StatusCode, payload_size: PayloadSize) -> BytesMut {
+        encode_version(status, Version::HTTP_11, payload_size)
+    }
+
+    fn encode_version(status: StatusCode, version: Version, payload_size: PayloadSize) -> BytesMut {
+        let (parts, _) = Response::builder().status(status).version(version).body(()).unwrap().into_parts();
+        let mut dst = BytesMut::new();
+        HeaderEncoder.encode((ResponseHead::from_parts(parts, ()), payload_size), &mut dst).unwrap();
+        dst
+    }
+
+    #[test]
+    fn test_empty_payload() {
+        assert_eq!(&encode(StatusCode::OK, PayloadSize::Empty)[..], b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n");
+        assert_eq!(&encode(StatusCode::NO_CONTENT, PayloadSize::Empty)[..], b"HTTP/1.1 204 No Content\r\n\r\n");
+        assert_eq!(
+            &encode(StatusCode::SWITCHING_PROTOCOLS, PayloadSize::Empty)[..],
+            b"HTTP/1.1 101 Switching Protocols\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn test_http10() {
+        assert_eq!(
+            &encode_version(StatusCode::OK, Version::HTTP_10, PayloadSize::Length(5))[..],
+            b"HTTP/1.0 200 OK\r\ncontent-length: 5\r\n\r\n"
+        );
+        assert_eq!(
+            &encode_version(StatusCode::OK, Version::HTTP_10, PayloadSize::Chunked)[..],
+            b"HTTP/1.0 200 OK\r\n\r\n"
+        );
+
+        let head = Response::builder().version(Version::HTTP_2).body(()).unwrap();
+        assert!(HeaderEncoder.encode((head, PayloadSize::Empty), &mut BytesMut::new()).is_err());
+    }
+}
@@ -15,6 +15,10 @@
 //!   - Implements standard HTTP/1.1 header formatting
 //!   - Handles header field serialization
 //!   - Manages content-length and transfer-encoding headers
+//!
+//! - [`RequestHeaderEncoder`]: Encodes HTTP request headers to bytes, for clients and proxies
+//!   - Writes the request target in origin, absolute or authority form
+//!   - Ensures the `Host` header is present
 //! 
 //! 
 //! # Features
@@ -27,6 +31,9 @@
 
 mod header_decoder;
 mod header_encoder;
+mod request_encoder;
 
 pub use header_decoder::HeaderDecoder;
+pub(crate) use header_decoder::{MAX_HEADER_BYTES, MAX_HEADER_NUM};
 pub use header_encoder::HeaderEncoder;
+pub use request_encoder::RequestHeaderEncoder;
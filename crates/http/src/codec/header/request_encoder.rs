@@ -0,0 +1,272 @@
+//! HTTP header encoder implementation for serializing HTTP request headers
+//!
+//! This module provides functionality for encoding the head of an outgoing HTTP request into raw
+//! bytes, which is what an HTTP client or a proxy forwarding requests needs. It writes the request
+//! line and the headers, and manages the `Host`, content length and transfer encoding headers
+//! according to HTTP/1.1 specification.
+//!
+//! # Request target
+//!
+//! The request target is written in one of the forms of RFC 9112 Section 3.2:
+//!
+//! - origin form, `/path?query`, for requests sent to an origin server
+//! - absolute form, `http://host/path?query`, for requests sent to a proxy, see
+//!   [`RequestHeaderEncoder::for_proxy`]
+//! - authority form, `host:port`, for `CONNECT` requests
+//! - asterisk form, `*`, for server wide `OPTIONS` requests
+
+use crate::protocol::{PayloadSize, RequestHead, SendError};
+
+use bytes::{BufMut, BytesMut};
+
+use http::{header, HeaderValue, Method, Version};
+use std::io;
+use std::io::ErrorKind;
+use tokio_util::codec::Encoder;
+use tracing::error;
+
+/// Initial buffer size allocated for header serialization
+const INIT_HEADER_SIZE: usize = 4 * 1024;
+
+/// Encoder for HTTP request headers implementing the [`Encoder`] trait.
+///
+/// This encoder serializes a [`RequestHead`] and [`PayloadSize`] into raw bytes. The `Host` header
+/// is taken from the authority of the URI when it's missing, and the Content-Length or
+/// Transfer-Encoding headers are set based on the payload size.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RequestHeaderEncoder {
+    absolute_form: bool,
+}
+
+impl RequestHeaderEncoder {
+    /// Creates an encoder writing the request target in origin form, for requests sent to an origin server.
+    pub fn new() -> Self {
+        Self { absolute_form: false }
+    }
+
+    /// Creates an encoder writing the request target in absolute form, for requests sent to a proxy.
+    ///
+    /// A URI without scheme is still written in origin form.
+    pub fn for_proxy() -> Self {
+        Self { absolute_form: true }
+    }
+}
+
+impl Encoder<(RequestHead, PayloadSize)> for RequestHeaderEncoder {
+    type Error = SendError;
+
+    /// Encodes HTTP request headers into the provided bytes buffer.
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - Tuple of request header and payload size information
+    /// * `dst` - Mutable reference to the destination buffer
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` if encoding succeeds, or `Err(SendError)` if encoding fails
+    ///
+    /// # Errors
+    ///
+    /// Returns error if:
+    /// - HTTP version is not supported (only HTTP/1.1 and HTTP/1.0 are supported)
+    /// - A chunked payload is sent with HTTP/1.0, which has no transfer encoding
+    /// - An HTTP/1.1 request has neither a `Host` header nor a URI authority
+    /// - A `CONNECT` request has no URI authority
+    fn encode(&mut self, item: (RequestHead, PayloadSize), dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let (mut header, payload_size) = item;
+
+        let version = match header.version() {
+            Version::HTTP_11 => "HTTP/1.1",
+            Version::HTTP_10 => "HTTP/1.0",
+            v => {
+                error!(http_version = ?v, "unsupported http version");
+                return Err(io::Error::from(ErrorKind::Unsupported).into());
+            }
+        };
+
+        // the Host header is required by HTTP/1.1, see RFC 9112 Section 3.2
+        if !header.headers().contains_key(header::HOST) {
+            match header.uri().authority().map(|authority| HeaderValue::from_str(authority.as_str())) {
+                Some(Ok(host)) => {
+                    header.headers_mut().insert(header::HOST, host);
+                }
+                _ if header.version() == Version::HTTP_10 => (),
+                _ => return Err(io::Error::new(ErrorKind::InvalidInput, "missing host of the request").into()),
+            }
+        }
+
+        // Set appropriate content length or transfer encoding header
+        match payload_size {
+            PayloadSize::Length(n) => {
+                header.headers_mut().remove(header::TRANSFER_ENCODING);
+                header.headers_mut().insert(header::CONTENT_LENGTH, n.into());
+            }
+            PayloadSize::Chunked if header.version() == Version::HTTP_10 => {
+                return Err(io::Error::new(ErrorKind::InvalidInput, "HTTP/1.0 has no chunked transfer encoding").into());
+            }
+            PayloadSize::Chunked => {
+                header.headers_mut().remove(header::CONTENT_LENGTH);
+                header.headers_mut().insert(header::TRANSFER_ENCODING, HeaderValue::from_static("chunked"));
+            }
+            // only the methods expecting a body tell it's empty, see RFC 9110 Section 8.6
+            PayloadSize::Empty => {
+                header.headers_mut().remove(header::TRANSFER_ENCODING);
+                if expects_body(header.method()) {
+                    header.headers_mut().insert(header::CONTENT_LENGTH, 0.into());
+                } else {
+                    header.headers_mut().remove(header::CONTENT_LENGTH);
+                }
+            }
+        }
+
+        dst.reserve(INIT_HEADER_SIZE);
+        dst.put_slice(header.method().as_str().as_bytes());
+        dst.put_slice(b" ");
+        self.write_request_target(&header, dst)?;
+        dst.put_slice(b" ");
+        dst.put_slice(version.as_bytes());
+        dst.put_slice(b"\r\n");
+
+        // Host goes first, as recommended by RFC 9110 Section 7.2
+        if let Some(host) = header.headers().get(header::HOST) {
+            dst.put_slice(b"host: ");
+            dst.put_slice(host.as_bytes());
+            dst.put_slice(b"\r\n");
+        }
+
+        // Write all headers
+        for (header_name, header_value) in header.headers().iter().filter(|(name, _)| *name != header::HOST) {
+            dst.put_slice(header_name.as_ref());
+            dst.put_slice(b": ");
+            dst.put_slice(header_value.as_ref());
+            dst.put_slice(b"\r\n");
+        }
+        dst.put_slice(b"\r\n");
+        Ok(())
+    }
+}
+
+impl RequestHeaderEncoder {
+    /// Writes the request target of `header` in the form matching its method and URI.
+    fn write_request_target(&self, header: &RequestHead, dst: &mut BytesMut) -> Result<(), SendError> {
+        let uri = header.uri();
+
+        if header.method() == Method::CONNECT {
+            let authority = uri
+                .authority()
+                .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "missing authority of the CONNECT request"))?;
+            dst.put_slice(authority.as_str().as_bytes());
+            return Ok(());
+        }
+
+        if self.absolute_form && uri.scheme().is_some() && uri.authority().is_some() {
+            dst.put_slice(uri.to_string().as_bytes());
+            return Ok(());
+        }
+
+        match uri.path_and_query() {
+            // `OPTIONS *` is parsed as a `*` path
+            Some(path_and_query) if !path_and_query.as_str().is_empty() => {
+                dst.put_slice(path_and_query.as_str().as_bytes())
+            }
+            _ => dst.put_slice(b"/"),
+        }
+        Ok(())
+    }
+}
+
+/// Returns true if requests with `method` are expected to carry a body.
+fn expects_body(method: &Method) -> bool {
+    !matches!(*method, Method::GET | Method::HEAD | Method::DELETE | Method::OPTIONS | Method::CONNECT | Method::TRACE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::Request;
+
+    fn encode(encoder: &mut RequestHeaderEncoder, head: RequestHead, payload_size: PayloadSize) -> String {
+        let mut dst = BytesMut::new();
+        encoder.encode((head, payload_size), &mut dst).unwrap();
+        String::from_utf8(dst.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn test_origin_form() {
+        let head = Request::get("http://example.com:8080/search?q=rust").header("accept", "*/*").body(()).unwrap();
+        assert_eq!(
+            encode(&mut RequestHeaderEncoder::new(), head, PayloadSize::Empty),
+            "GET /search?q=rust HTTP/1.1\r\nhost: example.com:8080\r\naccept: */*\r\n\r\n"
+        );
+
+        // the Host header is kept as is, and written first
+        let head = Request::get("/").header("accept", "*/*").header("host", "example.com").body(()).unwrap();
+        assert_eq!(
+            encode(&mut RequestHeaderEncoder::new(), head, PayloadSize::Empty),
+            "GET / HTTP/1.1\r\nhost: example.com\r\naccept: */*\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn test_absolute_form() {
+        let head = Request::get("http://example.com/index.html").body(()).unwrap();
+        assert_eq!(
+            encode(&mut RequestHeaderEncoder::for_proxy(), head, PayloadSize::Empty),
+            "GET http://example.com/index.html HTTP/1.1\r\nhost: example.com\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn test_authority_and_asterisk_form() {
+        let head = Request::connect("example.com:443").body(()).unwrap();
+        assert_eq!(
+            encode(&mut RequestHeaderEncoder::for_proxy(), head, PayloadSize::Empty),
+            "CONNECT example.com:443 HTTP/1.1\r\nhost: example.com:443\r\n\r\n"
+        );
+
+        let head = Request::options("*").header("host", "example.com").body(()).unwrap();
+        assert_eq!(
+            encode(&mut RequestHeaderEncoder::new(), head, PayloadSize::Empty),
+            "OPTIONS * HTTP/1.1\r\nhost: example.com\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn test_payload_size() {
+        let head = || Request::post("http://example.com/upload").body(()).unwrap();
+        let mut encoder = RequestHeaderEncoder::new();
+        assert_eq!(
+            encode(&mut encoder, head(), PayloadSize::Length(5)),
+            "POST /upload HTTP/1.1\r\nhost: example.com\r\ncontent-length: 5\r\n\r\n"
+        );
+        assert_eq!(
+            encode(&mut encoder, head(), PayloadSize::Chunked),
+            "POST /upload HTTP/1.1\r\nhost: example.com\r\ntransfer-encoding: chunked\r\n\r\n"
+        );
+        assert_eq!(
+            encode(&mut encoder, head(), PayloadSize::Empty),
+            "POST /upload HTTP/1.1\r\nhost: example.com\r\ncontent-length: 0\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn test_invalid_requests() {
+        let mut encoder = RequestHeaderEncoder::new();
+        let mut dst = BytesMut::new();
+
+        let missing_host = Request::get("/").body(()).unwrap();
+        assert!(encoder.encode((missing_host, PayloadSize::Empty), &mut dst).is_err());
+
+        let http10_chunked = Request::post("http://example.com/").version(Version::HTTP_10).body(()).unwrap();
+        assert!(encoder.encode((http10_chunked, PayloadSize::Chunked), &mut dst).is_err());
+
+        let http2 = Request::get("http://example.com/").version(Version::HTTP_2).body(()).unwrap();
+        assert!(encoder.encode((http2, PayloadSize::Empty), &mut dst).is_err());
+        assert!(dst.is_empty());
+
+        // HTTP/1.0 has no Host requirement
+        let http10 = Request::get("/").version(Version::HTTP_10).body(()).unwrap();
+        assert_eq!(encode(&mut encoder, http10, PayloadSize::Empty), "GET / HTTP/1.0\r\n\r\n");
+    }
+}
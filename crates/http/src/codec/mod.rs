@@ -17,6 +17,10 @@
 //!   - [`ResponseEncoder`]: Encodes outgoing HTTP responses
 //!   - Header encoding via [`header`] module
 //!   - Payload encoding via [`body`] module
+//!
+//! - Client side:
+//!   - [`RequestEncoder`]: Encodes outgoing HTTP requests, their head with [`RequestHeaderEncoder`]
+//!   - [`ResponseDecoder`]: Decodes incoming HTTP responses
 //! 
 //! # Example
 //! 
@@ -47,7 +51,12 @@
 mod body;
 mod header;
 mod request_decoder;
+mod request_encoder;
+mod response_decoder;
 mod response_encoder;
 
+pub use header::RequestHeaderEncoder;
 pub use request_decoder::RequestDecoder;
+pub use request_encoder::RequestEncoder;
+pub use response_decoder::ResponseDecoder;
 pub use response_encoder::ResponseEncoder;
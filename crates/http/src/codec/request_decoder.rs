@@ -73,7 +73,7 @@ impl Decoder for RequestDecoder {
         // parse payload if have payload_decoder
         if let Some(payload_decoder) = &mut self.payload_decoder {
             let message = match payload_decoder.decode(src)? {
-                Some(item @ PayloadItem::Chunk(_)) => Some(Message::Payload(item)),
+                Some(item @ (PayloadItem::Chunk(_) | PayloadItem::Trailer(_))) => Some(Message::Payload(item)),
                 Some(item @ PayloadItem::Eof) => {
                     // no need payload decoder in this request now
                     self.payload_decoder.take();
@@ -0,0 +1,147 @@
+//! HTTP request encoder module
+//!
+//! This module provides functionality for encoding the HTTP requests sent by a client, using the
+//! same streaming approach as the [`ResponseEncoder`](super::ResponseEncoder).
+//!
+//! # Components
+//!
+//! - [`RequestEncoder`]: Main encoder that coordinates header and payload encoding
+//! - Header encoding: Uses [`RequestHeaderEncoder`] for encoding request headers
+//! - Payload handling: Uses [`PayloadEncoder`] for encoding request bodies
+//!
+//! # Example
+//!
+//! ```no_run
+//! use micro_http::codec::RequestEncoder;
+//! use tokio_util::codec::Encoder;
+//! use bytes::BytesMut;
+//!
+//! let mut encoder = RequestEncoder::new();
+//! let mut buffer = BytesMut::new();
+//! // ... encode request data to buffer ...
+//! ```
+
+use crate::codec::body::PayloadEncoder;
+use crate::codec::header::RequestHeaderEncoder;
+use crate::protocol::{Message, PayloadItem, PayloadSize, RequestHead, SendError};
+use bytes::{Buf, BytesMut};
+use std::io;
+use std::io::ErrorKind;
+use tokio_util::codec::Encoder;
+use tracing::error;
+
+/// A encoder for HTTP requests that handles both headers and payload
+///
+/// The encoder operates in two phases:
+/// 1. Header encoding: Encodes the request headers using [`RequestHeaderEncoder`]
+/// 2. Payload encoding: If present, encodes the request body using [`PayloadEncoder`]
+pub struct RequestEncoder {
+    /// Encoder for HTTP request headers
+    header_encoder: RequestHeaderEncoder,
+    /// Encoder for HTTP request payload (body)
+    payload_encoder: Option<PayloadEncoder>,
+}
+
+impl RequestEncoder {
+    /// Creates a new `RequestEncoder` instance, for requests sent to an origin server
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Creates a `RequestEncoder` for requests sent to a proxy, see [`RequestHeaderEncoder::for_proxy`]
+    pub fn for_proxy() -> Self {
+        Self { header_encoder: RequestHeaderEncoder::for_proxy(), payload_encoder: None }
+    }
+
+    /// Resets the encoder to its initial state, ready to encode the next request head
+    ///
+    /// It's called automatically once the payload's [`PayloadItem::Eof`] is encoded.
+    pub fn reset(&mut self) {
+        self.payload_encoder = None;
+    }
+}
+
+impl Default for RequestEncoder {
+    fn default() -> Self {
+        Self { header_encoder: RequestHeaderEncoder::new(), payload_encoder: None }
+    }
+}
+
+impl<D: Buf> Encoder<Message<(RequestHead, PayloadSize), D>> for RequestEncoder {
+    type Error = SendError;
+
+    /// Attempts to encode an HTTP request to the provided buffer
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - The message to encode, either headers or payload
+    /// * `dst` - The buffer to write the encoded data to
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(())`: Successfully encoded the message
+    /// - `Err(_)`: Encountered an encoding error
+    fn encode(&mut self, item: Message<(RequestHead, PayloadSize), D>, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        match item {
+            Message::Header((head, payload_size)) => {
+                if self.payload_encoder.is_some() {
+                    error!("expect payload item but receive request head");
+                    return Err(io::Error::from(ErrorKind::InvalidInput).into());
+                }
+
+                self.header_encoder.encode((head, payload_size), dst)?;
+                self.payload_encoder = Some(match payload_size {
+                    PayloadSize::Length(size) => PayloadEncoder::fix_length(size),
+                    PayloadSize::Chunked => PayloadEncoder::chunked(),
+                    PayloadSize::Empty => PayloadEncoder::empty(),
+                });
+                Ok(())
+            }
+
+            Message::Payload(payload_item) => {
+                let Some(payload_encoder) = &mut self.payload_encoder else {
+                    error!("expect request header but receive payload item");
+                    return Err(io::Error::from(ErrorKind::InvalidInput).into());
+                };
+
+                let is_eof = matches!(payload_item, PayloadItem::Eof);
+                let result = payload_encoder.encode(payload_item, dst);
+
+                // The request is complete, get ready for the next one
+                if is_eof {
+                    self.reset();
+                }
+
+                result
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use http::Request;
+
+    #[test]
+    fn test_sequential_requests() {
+        let mut encoder = RequestEncoder::new();
+        let mut dst = BytesMut::new();
+
+        let head = Request::post("http://example.com/upload").body(()).unwrap();
+        encoder.encode(Message::<_, Bytes>::Header((head, PayloadSize::Chunked)), &mut dst).unwrap();
+        encoder.encode(Message::Payload(PayloadItem::Chunk(Bytes::from_static(b"hello"))), &mut dst).unwrap();
+        encoder.encode(Message::Payload(PayloadItem::<Bytes>::Eof), &mut dst).unwrap();
+
+        let head = Request::get("http://example.com/").body(()).unwrap();
+        encoder.encode(Message::<_, Bytes>::Header((head, PayloadSize::Empty)), &mut dst).unwrap();
+        encoder.encode(Message::Payload(PayloadItem::<Bytes>::Eof), &mut dst).unwrap();
+
+        assert_eq!(
+            &dst[..],
+            b"POST /upload HTTP/1.1\r\nhost: example.com\r\ntransfer-encoding: chunked\r\n\r\n5\r\nhello\r\n0\r\n\r\n\
+              GET / HTTP/1.1\r\nhost: example.com\r\n\r\n"
+        );
+    }
+}
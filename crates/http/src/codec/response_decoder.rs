@@ -0,0 +1,268 @@
+//! HTTP response decoder module
+//!
+//! This module provides functionality for decoding the HTTP responses received by a client,
+//! using the same streaming approach as the [`RequestDecoder`](super::RequestDecoder).
+//!
+//! # Components
+//!
+//! - [`ResponseDecoder`]: Main decoder that coordinates header and payload parsing
+//! - Payload handling: Uses [`PayloadDecoder`] for handling response bodies if any
+//!
+//! The length of a response body also depends on the request it answers, a response to a `HEAD`
+//! request has no body whatever its headers say. See [`ResponseDecoder::set_head_request`].
+//!
+//! # Example
+//!
+//! ```no_run
+//! use micro_http::codec::ResponseDecoder;
+//! use tokio_util::codec::Decoder;
+//! use bytes::BytesMut;
+//!
+//! let mut decoder = ResponseDecoder::new();
+//! let mut buffer = BytesMut::new();
+//! // ... add response data to buffer ...
+//! let result = decoder.decode(&mut buffer);
+//! ```
+
+use crate::codec::body::PayloadDecoder;
+use crate::codec::header::{MAX_HEADER_BYTES, MAX_HEADER_NUM};
+use crate::ensure;
+use crate::protocol::{Message, ParseError, PayloadItem, ResponseHead};
+use bytes::{Buf, BytesMut};
+use http::header::{CONTENT_LENGTH, TRANSFER_ENCODING};
+use http::{HeaderName, HeaderValue, Response, StatusCode, Version};
+use httparse::{Error, Status};
+use tokio_util::codec::Decoder;
+use tracing::trace;
+
+/// A decoder for HTTP responses that handles both headers and payload
+///
+/// The decoder operates in two phases:
+/// 1. Header parsing: Decodes the status line and the headers of the response
+/// 2. Payload parsing: If present, decodes the response body using [`PayloadDecoder`]
+///
+/// A response with neither Content-Length nor Transfer-Encoding is read until the connection is
+/// closed, its [`PayloadItem::Eof`] is only decoded at the end of the stream.
+#[derive(Debug, Default)]
+pub struct ResponseDecoder {
+    /// Whether the next response answers a `HEAD` request
+    head_request: bool,
+    payload_decoder: Option<PayloadDecoder>,
+}
+
+impl ResponseDecoder {
+    /// Creates a new `ResponseDecoder` instance
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Sets whether the next response answers a `HEAD` request, in which case it has no body.
+    pub fn set_head_request(&mut self, head_request: bool) {
+        self.head_request = head_request;
+    }
+
+    /// Parses the head of a response, returns `None` if more data is needed.
+    fn decode_head(&mut self, src: &mut BytesMut) -> Result<Option<ResponseHead>, ParseError> {
+        let mut headers = [httparse::EMPTY_HEADER; MAX_HEADER_NUM];
+        let mut resp = httparse::Response::new(&mut headers);
+
+        let parsed_result = resp.parse(src).map_err(|e| match e {
+            Error::TooManyHeaders => ParseError::too_many_headers(MAX_HEADER_NUM),
+            e => ParseError::invalid_header(e.to_string()),
+        });
+
+        let body_offset = match parsed_result? {
+            Status::Complete(body_offset) => body_offset,
+            Status::Partial => {
+                ensure!(src.len() <= MAX_HEADER_BYTES, ParseError::too_large_header(src.len(), MAX_HEADER_BYTES));
+                return Ok(None);
+            }
+        };
+        ensure!(body_offset <= MAX_HEADER_BYTES, ParseError::too_large_header(body_offset, MAX_HEADER_BYTES));
+
+        let version = match resp.version {
+            Some(0) => Version::HTTP_10,
+            Some(1) => Version::HTTP_11,
+            _ => return Err(ParseError::InvalidVersion(resp.version)),
+        };
+        let status = resp
+            .code
+            .and_then(|code| StatusCode::from_u16(code).ok())
+            .ok_or_else(|| ParseError::invalid_header("invalid status code"))?;
+
+        let mut head = Response::new(());
+        *head.version_mut() = version;
+        *head.status_mut() = status;
+        head.headers_mut().reserve(resp.headers.len());
+        for header in resp.headers.iter() {
+            let name = HeaderName::from_bytes(header.name.as_bytes()).map_err(ParseError::invalid_header)?;
+            let value = HeaderValue::from_bytes(header.value).map_err(ParseError::invalid_header)?;
+            head.headers_mut().append(name, value);
+        }
+
+        src.advance(body_offset);
+        Ok(Some(head))
+    }
+
+    /// Selects the decoder of the body of `head`, according to RFC 9112 Section 6.3.
+    fn payload_decoder(&self, head: &ResponseHead) -> Result<PayloadDecoder, ParseError> {
+        let status = head.status();
+        if self.head_request
+            || status.is_informational()
+            || status == StatusCode::NO_CONTENT
+            || status == StatusCode::NOT_MODIFIED
+        {
+            return Ok(PayloadDecoder::empty());
+        }
+
+        let headers = head.headers();
+        if headers.contains_key(TRANSFER_ENCODING) || headers.contains_key(CONTENT_LENGTH) {
+            PayloadDecoder::from_headers(headers)
+        } else {
+            Ok(PayloadDecoder::until_close())
+        }
+    }
+}
+
+impl Decoder for ResponseDecoder {
+    type Item = Message<ResponseHead>;
+    type Error = ParseError;
+
+    /// Attempts to decode an HTTP response from the provided buffer
+    ///
+    /// # Returns
+    ///
+    /// - `Ok(Some(Message::Header(_)))`: Successfully decoded response headers
+    /// - `Ok(Some(Message::Payload(_)))`: Successfully decoded a payload chunk
+    /// - `Ok(None)`: Need more data to proceed
+    /// - `Err(_)`: Encountered a parsing error
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        if let Some(payload_decoder) = &mut self.payload_decoder {
+            let message = match payload_decoder.decode(src)? {
+                Some(PayloadItem::Eof) => {
+                    self.payload_decoder.take();
+                    Some(Message::Payload(PayloadItem::Eof))
+                }
+                Some(item) => Some(Message::Payload(item)),
+                None => None,
+            };
+            return Ok(message);
+        }
+
+        let Some(head) = self.decode_head(src)? else {
+            return Ok(None);
+        };
+        trace!(status = %head.status(), "parsed response head");
+
+        self.payload_decoder = Some(self.payload_decoder(&head)?);
+        Ok(Some(Message::Header(head)))
+    }
+
+    /// Decodes the last bytes once the connection is closed, which ends a body read until close.
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let Some(payload_decoder) = &mut self.payload_decoder else {
+            return match self.decode(src)? {
+                Some(message) => Ok(Some(message)),
+                None if src.is_empty() => Ok(None),
+                None => Err(ParseError::invalid_header("incomplete response head")),
+            };
+        };
+
+        let message = match payload_decoder.decode_eof(src)? {
+            Some(PayloadItem::Eof) => {
+                self.payload_decoder.take();
+                Some(Message::Payload(PayloadItem::Eof))
+            }
+            Some(item) => Some(Message::Payload(item)),
+            None => None,
+        };
+        Ok(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+
+    fn decode_all(decoder: &mut ResponseDecoder, src: &mut BytesMut) -> (ResponseHead, Bytes) {
+        let head = match decoder.decode(src).unwrap() {
+            Some(Message::Header(head)) => head,
+            _ => panic!("expect a response head"),
+        };
+
+        let mut body = BytesMut::new();
+        loop {
+            let message = match decoder.decode(src).unwrap() {
+                Some(message) => message,
+                None => decoder.decode_eof(src).unwrap().expect("expect the end of the body"),
+            };
+            match message.into_payload_item() {
+                Some(PayloadItem::Chunk(bytes)) => body.extend_from_slice(&bytes),
+                Some(PayloadItem::Trailer(_)) => (),
+                Some(PayloadItem::Eof) => return (head, body.freeze()),
+                None => panic!("expect a payload item"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode_responses() {
+        let mut decoder = ResponseDecoder::new();
+        let mut src = BytesMut::from(
+            &b"HTTP/1.1 200 OK\r\ncontent-length: 5\r\nx-id: 1\r\n\r\nhello\
+               HTTP/1.1 201 Created\r\ntransfer-encoding: chunked\r\n\r\n5\r\nworld\r\n0\r\n\r\n\
+               HTTP/1.0 404 Not Found\r\n\r\nnot found"[..],
+        );
+
+        let (head, body) = decode_all(&mut decoder, &mut src);
+        assert_eq!(head.status(), StatusCode::OK);
+        assert_eq!(head.headers().get("x-id").unwrap(), "1");
+        assert_eq!(body, "hello");
+
+        let (head, body) = decode_all(&mut decoder, &mut src);
+        assert_eq!(head.status(), StatusCode::CREATED);
+        assert_eq!(body, "world");
+
+        // read until the end of the connection
+        let (head, body) = decode_all(&mut decoder, &mut src);
+        assert_eq!(head.version(), Version::HTTP_10);
+        assert_eq!(head.status(), StatusCode::NOT_FOUND);
+        assert_eq!(body, "not found");
+    }
+
+    #[test]
+    fn test_responses_without_body() {
+        let mut decoder = ResponseDecoder::new();
+        let mut src = BytesMut::from(
+            &b"HTTP/1.1 100 Continue\r\n\r\n\
+               HTTP/1.1 204 No Content\r\n\r\n\
+               HTTP/1.1 200 OK\r\ncontent-length: 5\r\n\r\n"[..],
+        );
+
+        assert_eq!(decode_all(&mut decoder, &mut src).0.status(), StatusCode::CONTINUE);
+        assert_eq!(decode_all(&mut decoder, &mut src).0.status(), StatusCode::NO_CONTENT);
+
+        decoder.set_head_request(true);
+        let (head, body) = decode_all(&mut decoder, &mut src);
+        assert_eq!(head.headers().get(CONTENT_LENGTH).unwrap(), "5");
+        assert!(body.is_empty());
+        assert!(src.is_empty());
+    }
+
+    #[test]
+    fn test_invalid_responses() {
+        let mut src = BytesMut::from(&b"HTTP/2 200 OK\r\n\r\n"[..]);
+        assert!(ResponseDecoder::new().decode(&mut src).is_err());
+
+        let mut src = BytesMut::from(&b"HTTP/1.1 200 OK\r\ncontent-length: 1\r\ncontent-length: 2\r\n\r\n"[..]);
+        assert!(ResponseDecoder::new().decode(&mut src).is_err());
+
+        // the connection is closed before the end of the body
+        let mut decoder = ResponseDecoder::new();
+        let mut src = BytesMut::from(&b"HTTP/1.1 200 OK\r\ncontent-length: 10\r\n\r\nhello"[..]);
+        assert!(decoder.decode(&mut src).unwrap().unwrap().is_header());
+        assert!(decoder.decode_eof(&mut src).unwrap().unwrap().is_payload());
+        assert!(decoder.decode_eof(&mut src).unwrap().is_none());
+    }
+}
@@ -23,13 +23,25 @@
 
 use crate::codec::body::PayloadEncoder;
 use crate::codec::header::HeaderEncoder;
-use crate::protocol::{Message, PayloadSize, ResponseHead, SendError};
+use crate::protocol::{Message, PayloadItem, PayloadSize, ResponseHead, SendError};
 use bytes::{Buf, BytesMut};
+use http::Version;
 use std::io;
 use std::io::ErrorKind;
 use tokio_util::codec::Encoder;
 use tracing::error;
 
+/// The state of a [`ResponseEncoder`].
+#[derive(Debug)]
+enum State {
+    /// Waiting for the head of the first response
+    Head,
+    /// The head is written, encoding its payload
+    Payload(PayloadEncoder),
+    /// The payload's [`PayloadItem::Eof`] is written, waiting for the head of the next response
+    Complete,
+}
+
 /// A encoder for HTTP responses that handles both headers and payload
 ///
 /// The encoder operates in two phases:
@@ -38,8 +50,12 @@ use tracing::error;
 pub struct ResponseEncoder {
     /// Encoder for HTTP response headers
     header_encoder: HeaderEncoder,
-    /// Encoder for HTTP response payload (body)
-    payload_encoder: Option<PayloadEncoder>,
+    /// Where the encoder is in the current response
+    state: State,
+    /// The number of payload bytes written for the current, or the last, response
+    bytes_written: u64,
+    /// The size chunked payloads are buffered to, `None` writes one chunk per payload item
+    max_chunk_size: Option<usize>,
 }
 
 impl ResponseEncoder {
@@ -47,11 +63,34 @@ impl ResponseEncoder {
     pub fn new() -> Self {
         Default::default()
     }
+
+    /// Creates a `ResponseEncoder` buffering the items of chunked payloads into chunks of `max` bytes,
+    /// a `max` of 0 buffers the whole payload until its end.
+    ///
+    /// Bodies yielding many small items are sent with less framing overhead. The payloads of HTTP/1.0
+    /// responses and the ones of known length aren't chunked, they're written as is.
+    pub fn with_max_chunk_size(max: usize) -> Self {
+        Self { max_chunk_size: Some(max), ..Self::new() }
+    }
+
+    /// Resets the encoder to its initial state, ready to encode the next response head
+    ///
+    /// There's no need to call it once the payload's [`PayloadItem::Eof`] is encoded, the responses
+    /// of pipelined requests can be encoded one after another. It discards a response left incomplete.
+    pub fn reset(&mut self) {
+        self.state = State::Head;
+    }
+
+    /// Returns the number of payload bytes written for the response being encoded, or for the last
+    /// one once it's complete, see [`PayloadEncoder::bytes_written`].
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
 }
 
 impl Default for ResponseEncoder {
     fn default() -> Self {
-        Self { header_encoder: HeaderEncoder, payload_encoder: None }
+        Self { header_encoder: HeaderEncoder, state: State::Head, bytes_written: 0, max_chunk_size: None }
     }
 }
 
@@ -72,36 +111,42 @@ impl<D: Buf> Encoder<Message<(ResponseHead, PayloadSize), D>> for ResponseEncode
     fn encode(&mut self, item: Message<(ResponseHead, PayloadSize), D>, dst: &mut BytesMut) -> Result<(), Self::Error> {
         match item {
             Message::Header((head, payload_size)) => {
-                // If a payload encoder already exists, it's an error
-                if self.payload_encoder.is_some() {
-                    error!("expect payload item but receive response head");
-                    return Err(io::Error::from(ErrorKind::InvalidInput).into());
+                // the previous response must be complete before the next one starts
+                if let State::Payload(_) = self.state {
+                    error!("unexpected new response header while previous response not complete");
+                    return Err(unexpected("unexpected new response header while previous response not complete"));
                 }
 
-                // Create a payload encoder based on the payload size
-                let payload_encoder = parse_payload_encoder(payload_size);
-                self.payload_encoder = Some(payload_encoder);
+                // Create a payload encoder based on the payload size and the version of the response
+                let payload_encoder = parse_payload_encoder(payload_size, head.version(), self.max_chunk_size);
+                self.state = State::Payload(payload_encoder);
+                self.bytes_written = 0;
                 // Encode the response headers
                 self.header_encoder.encode((head, payload_size), dst)
             }
 
             Message::Payload(payload_item) => {
-                // Get the payload encoder, return error if it doesn't exist
-                let payload_encoder = if let Some(encoder) = &mut self.payload_encoder {
-                    encoder
-                } else {
-                    error!("expect response header but receive payload item");
-                    return Err(io::Error::from(ErrorKind::InvalidInput).into());
+                // Get the payload encoder, return error if there is no response being encoded
+                let payload_encoder = match &mut self.state {
+                    State::Payload(encoder) => encoder,
+                    State::Head => {
+                        error!("expect response header but receive payload item");
+                        return Err(unexpected("expect response header but receive payload item"));
+                    }
+                    State::Complete => {
+                        error!("unexpected payload item after the end of the response");
+                        return Err(unexpected("unexpected payload item after the end of the response"));
+                    }
                 };
 
                 // Encode the payload
+                let is_eof = matches!(payload_item, PayloadItem::Eof);
                 let result = payload_encoder.encode(payload_item, dst);
+                self.bytes_written = payload_encoder.bytes_written();
 
-                // Check if the payload encoder is finished
-                let is_eof = payload_encoder.is_finish();
-                // If finished, remove the payload encoder
+                // The response is complete, get ready for the next one
                 if is_eof {
-                    self.payload_encoder.take();
+                    self.state = State::Complete;
                 }
 
                 result
@@ -110,19 +155,150 @@ impl<D: Buf> Encoder<Message<(ResponseHead, PayloadSize), D>> for ResponseEncode
     }
 }
 
+/// Creates the error of an item that isn't expected in the current state of the encoder.
+fn unexpected(reason: &'static str) -> SendError {
+    io::Error::new(ErrorKind::InvalidInput, reason).into()
+}
+
 /// Creates a payload encoder based on the payload size
 ///
 /// # Arguments
 ///
 /// * `payload_size` - The size specification for the payload
+/// * `version` - The version of the response, HTTP/1.0 has no chunked transfer encoding
+/// * `max_chunk_size` - The size chunked payloads are buffered to, see [`ResponseEncoder::with_max_chunk_size`]
 ///
 /// # Returns
 ///
-/// Returns a [`PayloadEncoder`] configured according to the payload size
-fn parse_payload_encoder(payload_size: PayloadSize) -> PayloadEncoder {
+/// Returns a [`PayloadEncoder`] configured according to the payload size. A payload of unknown length
+/// sent in an HTTP/1.0 response is delimited by closing the connection.
+fn parse_payload_encoder(payload_size: PayloadSize, version: Version, max_chunk_size: Option<usize>) -> PayloadEncoder {
     match payload_size {
         PayloadSize::Length(size) => PayloadEncoder::fix_length(size),
-        PayloadSize::Chunked => PayloadEncoder::chunked(),
+        PayloadSize::Chunked if version == Version::HTTP_10 => PayloadEncoder::close_delimited(),
+        PayloadSize::Chunked => match max_chunk_size {
+            Some(max) => PayloadEncoder::chunked_with_max(max),
+            None => PayloadEncoder::chunked(),
+        },
         PayloadSize::Empty => PayloadEncoder::empty(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use http::Response;
+
+    fn encode(encoder: &mut ResponseEncoder, item: Message<(ResponseHead, PayloadSize)>, dst: &mut BytesMut) {
+        encoder.encode(item, dst).unwrap();
+    }
+
+    #[test]
+    fn test_pipelined_responses() {
+        let mut encoder = ResponseEncoder::new();
+        let mut dst = BytesMut::new();
+
+        encode(&mut encoder, Message::Header((Response::new(()), PayloadSize::Length(5))), &mut dst);
+        encode(&mut encoder, Message::Payload(PayloadItem::Chunk(Bytes::from_static(b"first"))), &mut dst);
+        encode(&mut encoder, Message::Payload(PayloadItem::Eof), &mut dst);
+        assert_eq!(encoder.bytes_written(), 5);
+
+        encode(&mut encoder, Message::Header((Response::new(()), PayloadSize::Chunked)), &mut dst);
+        assert_eq!(encoder.bytes_written(), 0);
+        encode(&mut encoder, Message::Payload(PayloadItem::Chunk(Bytes::from_static(b"second"))), &mut dst);
+        encode(&mut encoder, Message::Payload(PayloadItem::Eof), &mut dst);
+        assert_eq!(encoder.bytes_written(), 6);
+
+        assert_eq!(
+            &dst[..],
+            b"HTTP/1.1 200 OK\r\ncontent-length: 5\r\n\r\nfirst\
+              HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n6\r\nsecond\r\n0\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn test_http10_unknown_length() {
+        let mut encoder = ResponseEncoder::new();
+        let mut dst = BytesMut::new();
+
+        let head = Response::builder().version(Version::HTTP_10).body(()).unwrap();
+        encode(&mut encoder, Message::Header((head, PayloadSize::Chunked)), &mut dst);
+        encode(&mut encoder, Message::Payload(PayloadItem::Chunk(Bytes::from_static(b"hello "))), &mut dst);
+        encode(&mut encoder, Message::Payload(PayloadItem::Chunk(Bytes::from_static(b"world"))), &mut dst);
+        encode(&mut encoder, Message::Payload(PayloadItem::Eof), &mut dst);
+
+        assert_eq!(&dst[..], b"HTTP/1.0 200 OK\r\n\r\nhello world");
+    }
+
+    #[test]
+    fn test_max_chunk_size() {
+        let mut encoder = ResponseEncoder::with_max_chunk_size(8);
+        let mut dst = BytesMut::new();
+
+        encode(&mut encoder, Message::Header((Response::new(()), PayloadSize::Chunked)), &mut dst);
+        for item in ["hello", " ", "world"] {
+            encode(&mut encoder, Message::Payload(PayloadItem::Chunk(Bytes::from_static(item.as_bytes()))), &mut dst);
+        }
+        encode(&mut encoder, Message::Payload(PayloadItem::Eof), &mut dst);
+
+        assert_eq!(
+            &dst[..],
+            b"HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n8\r\nhello wo\r\n3\r\nrld\r\n0\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn test_header_before_eof() {
+        let mut encoder = ResponseEncoder::new();
+        let mut dst = BytesMut::new();
+
+        encode(&mut encoder, Message::Header((Response::new(()), PayloadSize::Chunked)), &mut dst);
+        let head: Message<(ResponseHead, PayloadSize)> = Message::Header((Response::new(()), PayloadSize::Chunked));
+        match encoder.encode(head, &mut dst) {
+            Err(SendError::Io { source }) => {
+                assert_eq!(source.kind(), ErrorKind::InvalidInput);
+                assert_eq!(source.to_string(), "unexpected new response header while previous response not complete");
+            }
+            result => panic!("unexpected result: {result:?}"),
+        }
+
+        encoder.reset();
+        encode(&mut encoder, Message::Header((Response::new(()), PayloadSize::Empty)), &mut dst);
+    }
+
+    #[test]
+    fn test_body_of_empty_response() {
+        let mut encoder = ResponseEncoder::new();
+        let mut dst = BytesMut::new();
+
+        let head = Response::builder().status(http::StatusCode::NO_CONTENT).body(()).unwrap();
+        encode(&mut encoder, Message::Header((head, PayloadSize::Empty)), &mut dst);
+        let chunk: Message<(ResponseHead, PayloadSize)> = Message::Payload(PayloadItem::Chunk(Bytes::from("oops")));
+        let err = encoder.encode(chunk, &mut dst).unwrap_err();
+        assert!(matches!(&err, SendError::Io { source } if source.kind() == ErrorKind::InvalidInput));
+        assert_eq!(&dst[..], b"HTTP/1.1 204 No Content\r\n\r\n");
+    }
+
+    #[test]
+    fn test_payload_out_of_response() {
+        let mut encoder = ResponseEncoder::new();
+        let mut dst = BytesMut::new();
+
+        let chunk = Bytes::from_static(b"a");
+        let chunk: Message<(ResponseHead, PayloadSize)> = Message::Payload(PayloadItem::Chunk(chunk));
+        let err = encoder.encode(chunk, &mut dst).unwrap_err();
+        assert!(err.to_string().contains("expect response header but receive payload item"), "{err}");
+
+        encode(&mut encoder, Message::Header((Response::new(()), PayloadSize::Length(1))), &mut dst);
+        encode(&mut encoder, Message::Payload(PayloadItem::Chunk(Bytes::from_static(b"a"))), &mut dst);
+        encode(&mut encoder, Message::Payload(PayloadItem::Eof), &mut dst);
+
+        // a late item of the previous response isn't mistaken for a missing head
+        let eof: Message<(ResponseHead, PayloadSize)> = Message::Payload(PayloadItem::Eof);
+        let err = encoder.encode(eof, &mut dst).unwrap_err();
+        assert!(matches!(&err, SendError::Io { source } if source.kind() == ErrorKind::InvalidInput));
+        assert!(err.to_string().contains("after the end of the response"), "{err}");
+        assert_eq!(&dst[..], b"HTTP/1.1 200 OK\r\ncontent-length: 1\r\n\r\na");
+    }
+}
@@ -0,0 +1,113 @@
+//! Configuration of [`HttpConnection`](super::HttpConnection).
+//!
+//! [`ServerConfig`] holds the timeouts protecting the server from slow or idle clients:
+//! - `read_header_timeout`: waiting for the first request head. On a kept-alive connection it starts with
+//!   the first byte of the next request, the idle time before it is bounded by the keep-alive timeout.
+//!   The connection is closed without a response when it elapses
+//! - `read_body_timeout`: waiting for each read of the request body. The client gets `408 Request Timeout`
+//!   and the connection is closed when it elapses
+//! - `write_response_timeout`: waiting for each write of the response. The connection is closed when it
+//!   elapses
+//!
+//! `None` disables a timeout.
+//!
+//! [`KeepAliveConfig`] limits how long and for how many requests a connection is kept open, see `keep_alive`.
+//!
+//! It also decides whether requests with `Expect: 100-continue` get a `100 Continue` response once the
+//! handler reads their body, see `send_100_continue`, and which `Server` header the responses get, see
+//! `server_header`.
+
+use std::time::Duration;
+
+/// Timeouts and options of an HTTP connection.
+///
+/// # Example
+/// ```
+/// use micro_http::connection::ServerConfig;
+/// use std::time::Duration;
+///
+/// let config = ServerConfig { read_header_timeout: Some(Duration::from_secs(5)), ..ServerConfig::default() };
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerConfig {
+    /// Maximum time to receive a request head, 30 seconds by default. Between keep-alive requests it
+    /// starts with the first byte of the next request
+    pub read_header_timeout: Option<Duration>,
+    /// Maximum time to receive each part of a request body, 60 seconds by default
+    pub read_body_timeout: Option<Duration>,
+    /// Maximum time to write each part of a response, 60 seconds by default
+    pub write_response_timeout: Option<Duration>,
+    /// Sends `100 Continue` to requests with `Expect: 100-continue` when the handler starts reading their
+    /// body, `true` by default.
+    ///
+    /// A handler answering without reading the body rejects it: the client never sends it, and the
+    /// connection is closed after the response.
+    pub send_100_continue: bool,
+    /// Limits of persistent connections, 75 seconds and 1000 requests by default.
+    ///
+    /// Responses advertise the limits with a `Keep-Alive: timeout=<n>, max=<m>` header, `None` keeps
+    /// connections open as long as the other timeouts allow.
+    pub keep_alive: Option<KeepAliveConfig>,
+    /// The `Server` header of the responses that don't set one, `micro-http/0.1` by default.
+    ///
+    /// `None` sends no `Server` header, so the software isn't disclosed. A value that isn't a valid header
+    /// value is ignored.
+    pub server_header: Option<String>,
+}
+
+/// The default of [`ServerConfig::server_header`].
+const DEFAULT_SERVER_HEADER: &str = "micro-http/0.1";
+
+impl ServerConfig {
+    /// Disables all the timeouts, including the limits of persistent connections.
+    pub fn no_timeouts() -> Self {
+        Self {
+            read_header_timeout: None,
+            read_body_timeout: None,
+            write_response_timeout: None,
+            keep_alive: None,
+            ..Self::default()
+        }
+    }
+
+    /// Sets the `Server` header of the responses that don't set one.
+    pub fn with_server_header(mut self, server_header: impl Into<String>) -> Self {
+        self.server_header = Some(server_header.into());
+        self
+    }
+
+    /// Sends no `Server` header, unless the handler sets one.
+    pub fn suppress_server_header(mut self) -> Self {
+        self.server_header = None;
+        self
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            read_header_timeout: Some(Duration::from_secs(30)),
+            read_body_timeout: Some(Duration::from_secs(60)),
+            write_response_timeout: Some(Duration::from_secs(60)),
+            send_100_continue: true,
+            keep_alive: Some(KeepAliveConfig::default()),
+            server_header: Some(DEFAULT_SERVER_HEADER.to_string()),
+        }
+    }
+}
+
+/// Limits of a persistent connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeepAliveConfig {
+    /// Maximum idle time between two requests, until the first byte of the next one, the connection is
+    /// closed without a response when it elapses
+    pub timeout: Duration,
+    /// Maximum number of requests served on a connection, the last response has `Connection: close`
+    pub max_requests: usize,
+}
+
+impl Default for KeepAliveConfig {
+    fn default() -> Self {
+        Self { timeout: Duration::from_secs(75), max_requests: 1000 }
+    }
+}
@@ -1,26 +1,37 @@
 use std::error::Error;
 use std::fmt::Display;
+use std::future::{poll_fn, Future};
+use std::io;
+use std::task::Poll;
+use std::time::Duration;
 
 use bytes::Bytes;
 use std::sync::Arc;
 
 use futures::{SinkExt, StreamExt};
-use http::header::EXPECT;
-use http::{Response, StatusCode};
+use http::header::{CONNECTION, EXPECT, HeaderName, SERVER, TE};
+use http::{HeaderMap, HeaderValue, Method, Response, StatusCode, Version};
 use http_body::Body;
 use http_body_util::{BodyExt, Empty};
 use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
 use tokio::select;
+use tokio::sync::watch;
 
 use crate::codec::{RequestDecoder, ResponseEncoder};
+use crate::connection::upgrade::{self, UpgradeSender, Upgraded};
+use crate::connection::ServerConfig;
 use crate::handler::Handler;
 use crate::protocol::body::ReqBody;
 use crate::protocol::{
     HttpError, Message, ParseError, PayloadItem, PayloadSize, RequestHeader, ResponseHead, SendError,
 };
+use crate::utils::has_connection_option;
 
 use tokio_util::codec::{FramedRead, FramedWrite};
-use tracing::{error, info};
+use tracing::{error, info, trace, warn};
+
+/// A message written by the [`ResponseEncoder`].
+type ResponseMessage = Message<(ResponseHead, PayloadSize), Bytes>;
 
 /// An HTTP connection that manages request processing and response streaming
 /// 
@@ -29,6 +40,16 @@ use tracing::{error, info};
 /// - Processing request headers and bodies
 /// - Handling expect-continue mechanism
 /// - Streaming responses back to clients
+/// - Handing the IO over to the handler after a protocol upgrade, see [`OnUpgrade`](super::OnUpgrade)
+/// - Closing the connection between requests once a shutdown is signaled, see [`with_shutdown`](Self::with_shutdown)
+/// - Timing out slow reads and writes, see [`with_config`](Self::with_config)
+/// - Answering HTTP/1.0 clients without chunked transfer encoding, closing the connection after the
+///   response unless they asked for `Connection: Keep-Alive`
+/// - Answering `HEAD` requests with the headers of the response only, see RFC 9110 Section 9.3.2
+/// - Closing idle connections and connections that served too many requests, see
+///   [`KeepAliveConfig`](super::KeepAliveConfig)
+/// - Sending the trailers of chunked responses to clients that sent `TE: trailers`, they are dropped
+///   otherwise
 /// 
 /// # Type Parameters
 /// 
@@ -38,6 +59,37 @@ use tracing::{error, info};
 pub struct HttpConnection<R, W> {
     framed_read: FramedRead<R, RequestDecoder>,
     framed_write: FramedWrite<W, ResponseEncoder>,
+    shutdown: Option<watch::Receiver<bool>>,
+    config: ServerConfig,
+    /// The `Server` header of the config, checked once
+    server_header: Option<HeaderValue>,
+    http10: Option<Http10Compat>,
+    head: bool,
+    trailers: bool,
+    close: bool,
+    requests: usize,
+}
+
+/// The `Keep-Alive` response header, see RFC 2068 Section 19.7.1.1.
+const KEEP_ALIVE: HeaderName = HeaderName::from_static("keep-alive");
+
+/// How the response to an HTTP/1.0 request is sent.
+///
+/// HTTP/1.0 has no chunked transfer encoding, and its connections are closed after each response unless
+/// the client sent `Connection: Keep-Alive`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Http10Compat {
+    keep_alive: bool,
+}
+
+impl Http10Compat {
+    /// Returns the mode of `header`, `None` if it's not an HTTP/1.0 request.
+    fn from_request(header: &RequestHeader) -> Option<Self> {
+        if header.version() != Version::HTTP_10 {
+            return None;
+        }
+        Some(Self { keep_alive: has_connection_option(header.headers(), "keep-alive") })
+    }
 }
 
 impl<R, W> HttpConnection<R, W>
@@ -46,22 +98,84 @@ where
     W: AsyncWrite + Unpin,
 {
     pub fn new(reader: R, writer: W) -> Self {
+        let config = ServerConfig::default();
         Self {
             framed_read: FramedRead::with_capacity(reader, RequestDecoder::new(), 8 * 1024),
             framed_write: FramedWrite::new(writer, ResponseEncoder::new()),
+            shutdown: None,
+            server_header: server_header(&config),
+            config,
+            http10: None,
+            head: false,
+            trailers: false,
+            close: false,
+            requests: 0,
         }
     }
 
+    /// Sets the timeouts and options of the connection, [`ServerConfig::default`] is used otherwise.
+    pub fn with_config(mut self, config: ServerConfig) -> Self {
+        self.server_header = server_header(&config);
+        self.config = config;
+        self
+    }
+
+    /// Closes the connection once `shutdown` turns `true`.
+    ///
+    /// The request being processed is completed first, only a connection waiting for its next
+    /// request is closed right away.
+    pub fn with_shutdown(mut self, shutdown: watch::Receiver<bool>) -> Self {
+        self.shutdown = Some(shutdown);
+        self
+    }
+
     pub async fn process<H>(mut self, mut handler: Arc<H>) -> Result<(), HttpError>
     where
+        R: Send + 'static,
+        W: Send + 'static,
         H: Handler,
         H::RespBody: Body<Data = Bytes> + Unpin,
         <H::RespBody as Body>::Error: Display,
     {
         loop {
-            match self.framed_read.next().await {
+            // the end of the last response, with its trailers, is fed without being flushed
+            let flushed = SinkExt::<ResponseMessage>::flush(&mut self.framed_write);
+            with_write_timeout(self.config.write_response_timeout, flushed).await?;
+
+            // the error responses below don't answer the previous request, they must not be sent like it
+            self.http10 = None;
+            self.head = false;
+            self.trailers = false;
+
+            let idle_timeout = self.idle_timeout();
+            let read_header_timeout = self.config.read_header_timeout;
+            let message = select! {
+                biased;
+                _ = shutdown_signaled(&mut self.shutdown) => {
+                    info!("server is shutting down, close the connection");
+                    return Ok(());
+                }
+                message = read_header(&mut self.framed_read, idle_timeout, read_header_timeout) => match message {
+                    Ok(message) => message,
+                    Err(timeout) => {
+                        info!("no request received within {:?}, close the connection", timeout);
+                        return Ok(());
+                    }
+                },
+            };
+
+            match message {
                 Some(Ok(Message::Header(header))) => {
-                    self.do_process(header, &mut handler).await?;
+                    if let Some(upgrade_sender) = self.do_process(header, &mut handler).await? {
+                        info!("switched protocols, hand the connection over to the handler");
+                        upgrade_sender.send(self.into_upgraded());
+                        return Ok(());
+                    }
+
+                    if self.close {
+                        info!("connection can't be reused, close it");
+                        return self.close().await;
+                    }
                 }
 
                 Some(Ok(Message::Payload(_))) => {
@@ -86,29 +200,40 @@ where
         }
     }
 
-    async fn do_process<H>(&mut self, header: RequestHeader, handler: &mut Arc<H>) -> Result<(), HttpError>
+    /// Processes a single request.
+    ///
+    /// Returns the sender of the request's [`OnUpgrade`](super::OnUpgrade) if the connection switched protocols.
+    async fn do_process<H>(
+        &mut self,
+        header: RequestHeader,
+        handler: &mut Arc<H>,
+    ) -> Result<Option<UpgradeSender>, HttpError>
     where
         H: Handler,
         H::RespBody: Body<Data = Bytes> + Unpin,
         <H::RespBody as Body>::Error: Display,
     {
-        // Check if the request header contains the "Expect: 100-continue" field.
-        if let Some(value) = header.headers().get(EXPECT) {
-            let slice = value.as_bytes();
-            // Verify if the value of the "Expect" field is "100-continue".
-            if slice.len() >= 4 && &slice[0..4] == b"100-" {
-                let writer = self.framed_write.get_mut();
-                // Send a "100 Continue" response to the client.
-                let _ = writer.write(b"HTTP/1.1 100 Continue\r\n\r\n").await.map_err(SendError::io)?;
-                writer.flush().await.map_err(SendError::io)?;
-                // Log the event of sending a "100 Continue" response.
-                info!("receive expect request header, sent continue response");
-            }
-        }
+        self.requests += 1;
+        self.http10 = Http10Compat::from_request(&header);
+        self.head = header.method() == Method::HEAD;
+        self.trailers = accepts_trailers(header.headers());
+
+        // HTTP/1.0 clients don't understand 1xx responses, so they never get one, see RFC 9110 Section 15.2
+        let expect_continue =
+            self.config.send_100_continue && self.http10.is_none() && expects_continue(header.headers());
+
+        let (req_body, body_sender) = ReqBody::body_channel(&mut self.framed_read);
+        let mut body_sender = body_sender.with_read_timeout(self.config.read_body_timeout);
 
-        let (req_body, mut body_sender) = ReqBody::body_channel(&mut self.framed_read);
+        let mut request = header.body(req_body);
 
-        let request = header.body(req_body);
+        let upgrade_sender = if upgrade::is_upgrade_request(request.headers()) {
+            let (upgrade_sender, on_upgrade) = upgrade::pending();
+            request.extensions_mut().insert(on_upgrade);
+            Some(upgrade_sender)
+        } else {
+            None
+        };
 
         // This block handles concurrent processing of the request handler and request body streaming.
         // We need this concurrent processing because:
@@ -116,18 +241,37 @@ where
         //    from the underlying TCP stream to maintain protocol correctness
         // 2. The request handler and body streaming need to happen simultaneously to avoid deadlocks,
         //    since the handler may be waiting for body data while the body sender is waiting to send
-        let response_result = {
+        let mut continue_sent = false;
+        let (response_result, body_result) = {
             // Pin both futures to the stack since they are used in select! macro
             // The futures are lazy and won't start executing until polled
+            let framed_write = &mut self.framed_write;
+            let continue_sent = &mut continue_sent;
             tokio::pin! {
                 let request_handle_future = handler.call(request);
-                let body_sender_future = body_sender.send_body();
+                let body_sender_future = async {
+                    // the client waits for "100 Continue" before sending the body, which is only sent once
+                    // the handler reads the body, so it can answer without receiving it
+                    if expect_continue && body_sender.wait_for_read().await {
+                        // the end of the previous response may still be buffered
+                        let buffered = framed_write.write_buffer_mut().split();
+                        let writer = framed_write.get_mut();
+                        writer.write_all(&buffered).await.map_err(ParseError::io)?;
+                        writer.write_all(b"HTTP/1.1 100 Continue\r\n\r\n").await.map_err(ParseError::io)?;
+                        writer.flush().await.map_err(ParseError::io)?;
+                        *continue_sent = true;
+                        info!("receive expect request header, sent continue response");
+                    }
+                    body_sender.send_body().await
+                };
             }
 
             // Store the handler result to return after body is fully processed
             #[allow(unused_assignments)]
             let mut result = Option::<Result<_, _>>::None;
-            
+            // The body streaming result, the future must not be polled once it completed
+            let mut body_result = None;
+
             // Keep processing until handler completes
             loop {
                 select! {
@@ -139,23 +283,79 @@ where
                         break;
                     }
                     // Keep processing body chunks in background
-                    _ = &mut body_sender_future => {
-                        // No action needed - just keep streaming body
+                    sent = &mut body_sender_future, if body_result.is_none() => {
+                        body_result = Some(sent);
                     }
                 }
             }
             // Safe: result is Some if handler completed
-            result.unwrap()
+            (result.unwrap(), body_result)
         };
 
+        // the body was rejected without being sent, the client may still send it later, so the connection
+        // can't be reused
+        let rejected = expect_continue && !continue_sent;
+
         // skip body if request handler don't read body
-        body_sender.skip_body().await;
+        let body_result = match body_result {
+            Some(Err(e)) => Err(e),
+            _ if rejected => Ok(()),
+            _ => body_sender.skip_body().await,
+        };
+        if let Err(e @ ParseError::ReadTimeout { .. }) = body_result {
+            warn!("request body not received in time, close the connection");
+            let mut timeout_response = build_error_response(StatusCode::REQUEST_TIMEOUT);
+            timeout_response.headers_mut().insert(CONNECTION, HeaderValue::from_static("close"));
+            self.do_send_response(timeout_response).await?;
+            return Err(e.into());
+        }
+
+        let switching_protocols =
+            matches!(&response_result, Ok(response) if response.status() == StatusCode::SWITCHING_PROTOCOLS);
+
+        let response_result = response_result.map(|mut response| {
+            if rejected {
+                info!("expected body not read, close the connection after the response");
+                response.headers_mut().insert(CONNECTION, HeaderValue::from_static("close"));
+                self.close = true;
+            }
+            response
+        });
 
         self.send_response(response_result).await?;
 
+        Ok(upgrade_sender.filter(|_| switching_protocols))
+    }
+
+    /// Returns how long to wait for the first byte of the next request, the keep-alive timeout advertised
+    /// to the client.
+    ///
+    /// `None` before the first request, the read header timeout then covers the whole wait.
+    fn idle_timeout(&self) -> Option<Duration> {
+        self.config.keep_alive.filter(|_| self.requests > 0).map(|keep_alive| keep_alive.timeout)
+    }
+
+    /// Flushes the response and shuts the writer down.
+    async fn close(&mut self) -> Result<(), HttpError> {
+        let closed = SinkExt::<ResponseMessage>::close(&mut self.framed_write);
+        with_write_timeout(self.config.write_response_timeout, closed).await?;
         Ok(())
     }
 
+    /// Turns the connection into the IO of an upgraded connection.
+    ///
+    /// The response head has been flushed, so the write buffer is empty, while the read buffer may
+    /// already hold bytes of the new protocol.
+    fn into_upgraded(self) -> Upgraded
+    where
+        R: Send + 'static,
+        W: Send + 'static,
+    {
+        let read_parts = self.framed_read.into_parts();
+        let writer = self.framed_write.into_inner();
+        Upgraded::new(Box::new(read_parts.io), Box::new(writer), read_parts.read_buf.freeze())
+    }
+
     async fn send_response<T, E>(&mut self, response_result: Result<Response<T>, E>) -> Result<(), HttpError>
     where
         T: Body + Unpin,
@@ -177,7 +377,7 @@ where
         T: Body + Unpin,
         T::Error: Display,
     {
-        let (header_parts, mut body) = response.into_parts();
+        let (mut header_parts, mut body) = response.into_parts();
 
         let payload_size = {
             let size_hint = body.size_hint();
@@ -188,34 +388,77 @@ where
             }
         };
 
+        if let Some(http10) = self.http10 {
+            // a payload of unknown length can only be delimited by closing the connection
+            let keep_alive = http10.keep_alive && (self.head || !payload_size.is_chunked());
+            let connection = if keep_alive { "keep-alive" } else { "close" };
+            header_parts.version = Version::HTTP_10;
+            header_parts.headers.insert(CONNECTION, HeaderValue::from_static(connection));
+            self.close |= !keep_alive;
+        }
+
+        if let Some(keep_alive) = self.config.keep_alive {
+            if self.requests >= keep_alive.max_requests {
+                info!("served {} requests, close the connection after the response", self.requests);
+                header_parts.headers.insert(CONNECTION, HeaderValue::from_static("close"));
+                self.close = true;
+            } else if !self.close && !has_connection_option(&header_parts.headers, "close") {
+                let remaining = keep_alive.max_requests - self.requests;
+                let value = format!("timeout={}, max={}", keep_alive.timeout.as_secs(), remaining);
+                // the value is made of ascii digits and letters only
+                header_parts.headers.insert(KEEP_ALIVE, HeaderValue::try_from(value).unwrap());
+            }
+        }
+
+        if let Some(server_header) = &self.server_header {
+            header_parts.headers.entry(SERVER).or_insert_with(|| server_header.clone());
+        }
+
         let header = Message::<_, T::Data>::Header((ResponseHead::from_parts(header_parts, ()), payload_size));
+        let write_timeout = self.config.write_response_timeout;
+        if self.head {
+            // the headers describe the body a GET would get, including its Content-Length, but the body
+            // is dropped without being sent
+            with_write_timeout(write_timeout, self.framed_write.send(header)).await?;
+            self.framed_write.encoder_mut().reset();
+            return Ok(());
+        }
+
         if !payload_size.is_empty() {
-            self.framed_write.feed(header).await?;
+            with_write_timeout(write_timeout, self.framed_write.feed(header)).await?;
         } else {
             // using send instead of feed, because we want to flush the underlying IO
             // when response only has header, we need to send header,
             // otherwise, we just feed header to the buffer
-            self.framed_write.send(header).await?;
+            with_write_timeout(write_timeout, self.framed_write.send(header)).await?;
         }
 
         loop {
             match body.frame().await {
                 Some(Ok(frame)) => {
-                    let payload_item = frame
-                        .into_data()
-                        .map(PayloadItem::Chunk)
-                        .map_err(|_e| SendError::invalid_body("resolve body response error"))?;
+                    let payload_item = match frame.into_data() {
+                        Ok(data) => PayloadItem::Chunk(data),
+                        Err(frame) => match frame.into_trailers() {
+                            Ok(trailers) if self.trailers => PayloadItem::Trailer(trailers),
+                            Ok(_) => {
+                                trace!("the client doesn't accept trailers, drop them");
+                                continue;
+                            }
+                            Err(_) => return Err(SendError::invalid_body("resolve body response error").into()),
+                        },
+                    };
 
-                    self.framed_write
-                        .send(Message::Payload(payload_item))
-                        .await
-                        .map_err(|_e| SendError::invalid_body("can't send response"))?;
+                    let sent = self.framed_write.send(Message::Payload(payload_item));
+                    with_write_timeout(write_timeout, sent).await.map_err(|e| match e {
+                        e @ SendError::Io { .. } => e,
+                        _ => SendError::invalid_body("can't send response"),
+                    })?;
                 }
                 Some(Err(e)) => return Err(SendError::invalid_body(format!("resolve response body error: {e}")).into()),
                 None => {
-                    self.framed_write
-                        // using feed instead of send, because we don't want to flush the underlying IO
-                        .feed(Message::Payload(PayloadItem::<T::Data>::Eof))
+                    // using feed instead of send, because we don't want to flush the underlying IO
+                    let eof = self.framed_write.feed(Message::Payload(PayloadItem::<T::Data>::Eof));
+                    with_write_timeout(write_timeout, eof)
                         .await
                         .map_err(|e| SendError::invalid_body(format!("can't send eof response: {}", e)))?;
                     return Ok(());
@@ -225,6 +468,485 @@ where
     }
 }
 
+/// Reads the next message, returns the timeout as an error if it elapses first.
+///
+/// Waiting for the first byte of the message is bounded by `idle_timeout`, the rest of the head must then
+/// arrive within `timeout`. Without `idle_timeout`, `timeout` covers the whole wait.
+async fn read_header<R>(
+    framed_read: &mut FramedRead<R, RequestDecoder>,
+    idle_timeout: Option<Duration>,
+    timeout: Option<Duration>,
+) -> Result<Option<Result<Message<RequestHeader>, ParseError>>, Duration>
+where
+    R: AsyncRead + Unpin,
+{
+    // the next request may already be buffered
+    if let Some(idle_timeout) = idle_timeout.filter(|_| framed_read.read_buffer().is_empty()) {
+        let first_byte = poll_fn(|cx| match framed_read.poll_next_unpin(cx) {
+            Poll::Ready(message) => Poll::Ready(Some(message)),
+            // a part of the head arrived, it's kept in the read buffer
+            Poll::Pending if !framed_read.read_buffer().is_empty() => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        });
+        match tokio::time::timeout(idle_timeout, first_byte).await {
+            Ok(Some(message)) => return Ok(message),
+            Ok(None) => {}
+            Err(_) => return Err(idle_timeout),
+        }
+    }
+
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, framed_read.next()).await.map_err(|_| timeout),
+        None => Ok(framed_read.next().await),
+    }
+}
+
+/// Awaits a write of the response, failing with a `TimedOut` error if it takes longer than `timeout`.
+async fn with_write_timeout<F>(timeout: Option<Duration>, write: F) -> Result<(), SendError>
+where
+    F: Future<Output = Result<(), SendError>>,
+{
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return write.await,
+    };
+
+    match tokio::time::timeout(timeout, write).await {
+        Ok(result) => result,
+        Err(_) => {
+            warn!("response not written within {:?}, close the connection", timeout);
+            Err(SendError::io(io::Error::new(io::ErrorKind::TimedOut, "write response timeout")))
+        }
+    }
+}
+
+/// Resolves once the shutdown is signaled, never if there is no shutdown receiver or its sender is dropped.
+async fn shutdown_signaled(shutdown: &mut Option<watch::Receiver<bool>>) {
+    if let Some(shutdown) = shutdown {
+        if shutdown.wait_for(|signaled| *signaled).await.is_ok() {
+            return;
+        }
+    }
+    std::future::pending().await
+}
+
+/// Returns true if the `Expect` header is `100-continue`, the only expectation defined by RFC 9110.
+fn expects_continue(headers: &HeaderMap) -> bool {
+    headers.get(EXPECT).is_some_and(|value| value.as_bytes().eq_ignore_ascii_case(b"100-continue"))
+}
+
+/// Returns true if the `TE` header has `trailers`, the client is willing to accept trailer fields.
+fn accepts_trailers(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(TE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|value| value.trim().eq_ignore_ascii_case("trailers"))
+}
+
+/// Returns the `Server` header of `config`, `None` if it's disabled or invalid.
+fn server_header(config: &ServerConfig) -> Option<HeaderValue> {
+    let server_header = config.server_header.as_deref()?;
+    match HeaderValue::from_str(server_header) {
+        Ok(value) => Some(value),
+        Err(_) => {
+            warn!("invalid Server header {:?}, it is not sent", server_header);
+            None
+        }
+    }
+}
+
 fn build_error_response(status_code: StatusCode) -> Response<Empty<Bytes>> {
     Response::builder().status(status_code).body(Empty::<Bytes>::new()).unwrap()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::KeepAliveConfig;
+    use crate::handler::make_handler;
+    use http::Request;
+    use http_body::Frame;
+    use http_body_util::StreamBody;
+    use tokio::io::AsyncReadExt;
+
+    async fn read_body(req: Request<ReqBody>) -> Result<Response<String>, Box<dyn Error + Send + Sync>> {
+        let body = req.into_body().collect().await?.to_bytes();
+        Ok(Response::new(format!("received {} bytes", body.len())))
+    }
+
+    fn config(timeout: Duration) -> ServerConfig {
+        ServerConfig { read_header_timeout: Some(timeout), read_body_timeout: Some(timeout), ..ServerConfig::default() }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_read_header_timeout() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let (reader, writer) = tokio::io::split(server);
+        let connection = HttpConnection::new(reader, writer).with_config(config(Duration::from_secs(5)));
+
+        let processed = connection.process(Arc::new(make_handler(read_body))).await;
+        assert!(processed.is_ok());
+
+        let mut response = vec![];
+        client.read_to_end(&mut response).await.unwrap();
+        assert!(response.is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_read_body_timeout() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let (reader, writer) = tokio::io::split(server);
+        let connection = HttpConnection::new(reader, writer).with_config(config(Duration::from_secs(5)));
+
+        client.write_all(b"POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nabc").await.unwrap();
+        let processed = connection.process(Arc::new(make_handler(read_body))).await;
+        assert!(matches!(processed, Err(HttpError::RequestError { source: ParseError::ReadTimeout { .. } })));
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 408 Request Timeout\r\n"), "{response}");
+        assert!(response.contains("connection: close\r\n"), "{response}");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_keep_alive_within_timeouts() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let (reader, writer) = tokio::io::split(server);
+        let connection = HttpConnection::new(reader, writer).with_config(config(Duration::from_secs(5)));
+
+        client.write_all(b"POST / HTTP/1.1\r\nContent-Length: 3\r\n\r\nabc").await.unwrap();
+        let processed = connection.process(Arc::new(make_handler(read_body))).await;
+        assert!(processed.is_ok());
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{response}");
+        assert!(response.ends_with("received 3 bytes"), "{response}");
+    }
+
+    async fn stream_body(_req: Request<ReqBody>) -> Result<Response<StreamBody<Chunks>>, Box<dyn Error + Send + Sync>> {
+        let chunks = ["hello ", "world"].map(|chunk| Ok(Frame::data(Bytes::from_static(chunk.as_bytes()))));
+        Ok(Response::new(StreamBody::new(futures::stream::iter(chunks))))
+    }
+
+    type Chunks = futures::stream::Iter<std::array::IntoIter<Result<Frame<Bytes>, io::Error>, 2>>;
+
+    #[tokio::test]
+    async fn test_http10_unknown_length() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let (reader, writer) = tokio::io::split(server);
+        let connection = HttpConnection::new(reader, writer);
+
+        // the connection is closed even though the client asked to keep it alive
+        client.write_all(b"GET / HTTP/1.0\r\nConnection: Keep-Alive\r\n\r\n").await.unwrap();
+        let processed = tokio::spawn(connection.process(Arc::new(make_handler(stream_body))));
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert!(processed.await.unwrap().is_ok());
+        assert_eq!(response, "HTTP/1.0 200 OK\r\nconnection: close\r\nserver: micro-http/0.1\r\n\r\nhello world");
+    }
+
+    #[tokio::test]
+    async fn test_http10_keep_alive() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let (reader, writer) = tokio::io::split(server);
+        let connection = HttpConnection::new(reader, writer);
+
+        client
+            .write_all(
+                b"POST / HTTP/1.0\r\nConnection: keep-alive\r\nContent-Length: 3\r\n\r\nabc\
+                  POST / HTTP/1.0\r\nContent-Length: 2\r\n\r\nab",
+            )
+            .await
+            .unwrap();
+        let processed = connection.process(Arc::new(make_handler(read_body))).await;
+        assert!(processed.is_ok());
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert_eq!(
+            response,
+            "HTTP/1.0 200 OK\r\nconnection: keep-alive\r\nkeep-alive: timeout=75, max=999\r\nserver: micro-http/0.1\r\n\
+             content-length: 16\r\n\r\n\
+             received 3 bytes\
+             HTTP/1.0 200 OK\r\nconnection: close\r\nserver: micro-http/0.1\r\n\
+             content-length: 16\r\n\r\nreceived 2 bytes"
+        );
+        assert!(!response.contains("chunked"));
+    }
+
+    async fn hello(_req: Request<ReqBody>) -> Result<Response<String>, Box<dyn Error + Send + Sync>> {
+        Ok(Response::new("hello".to_string()))
+    }
+
+    #[tokio::test]
+    async fn test_head_request() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let (reader, writer) = tokio::io::split(server);
+        let connection = HttpConnection::new(reader, writer);
+
+        client.write_all(b"HEAD / HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\n\r\n").await.unwrap();
+        client.shutdown().await.unwrap();
+        let processed = connection.process(Arc::new(make_handler(hello))).await;
+        assert!(processed.is_ok());
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        let head = |max| {
+            format!(
+                "HTTP/1.1 200 OK\r\nkeep-alive: timeout=75, max={max}\r\nserver: micro-http/0.1\r\n\
+                 content-length: 5\r\n\r\n"
+            )
+        };
+        assert_eq!(response, format!("{}{}hello", head(999), head(998)));
+    }
+
+    #[tokio::test]
+    async fn test_bad_request_after_head_and_http10() {
+        for first in ["HEAD / HTTP/1.1\r\n\r\n", "GET / HTTP/1.0\r\nConnection: keep-alive\r\n\r\n"] {
+            let (mut client, server) = tokio::io::duplex(1024);
+            let (reader, writer) = tokio::io::split(server);
+            let connection = HttpConnection::new(reader, writer);
+
+            client.write_all(format!("{first}GARBAGE\r\n\r\n").as_bytes()).await.unwrap();
+            let processed = connection.process(Arc::new(make_handler(hello))).await;
+            assert!(processed.is_err());
+
+            let mut response = String::new();
+            client.read_to_string(&mut response).await.unwrap();
+            let (_, error_response) = response.split_once("\r\n\r\n").unwrap();
+            let error_response = error_response.trim_start_matches("hello");
+            assert!(error_response.starts_with("HTTP/1.1 400 Bad Request\r\n"), "{response}");
+            assert!(!error_response.contains("connection: keep-alive"), "{response}");
+            assert!(error_response.ends_with("content-length: 0\r\n\r\n"), "{response}");
+        }
+    }
+
+    fn keep_alive_config(timeout: Duration, max_requests: usize) -> ServerConfig {
+        ServerConfig { keep_alive: Some(KeepAliveConfig { timeout, max_requests }), ..ServerConfig::default() }
+    }
+
+    #[tokio::test]
+    async fn test_keep_alive_max_requests() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let (reader, writer) = tokio::io::split(server);
+        let connection =
+            HttpConnection::new(reader, writer).with_config(keep_alive_config(Duration::from_secs(5), 2));
+
+        // the third request is never read
+        client.write_all(b"GET / HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\n\r\n").await.unwrap();
+        let processed = connection.process(Arc::new(make_handler(hello))).await;
+        assert!(processed.is_ok());
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert_eq!(
+            response,
+            "HTTP/1.1 200 OK\r\nkeep-alive: timeout=5, max=1\r\nserver: micro-http/0.1\r\n\
+             content-length: 5\r\n\r\nhello\
+             HTTP/1.1 200 OK\r\nconnection: close\r\nserver: micro-http/0.1\r\ncontent-length: 5\r\n\r\nhello"
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_keep_alive_timeout() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let (reader, writer) = tokio::io::split(server);
+        let connection =
+            HttpConnection::new(reader, writer).with_config(keep_alive_config(Duration::from_secs(5), 100));
+        let processed = tokio::spawn(connection.process(Arc::new(make_handler(hello))));
+
+        client.write_all(b"GET / HTTP/1.1\r\n\r\n").await.unwrap();
+        read_until(&mut client, "hello").await;
+
+        // closed after the keep-alive timeout, well before the 30 seconds of the read header timeout
+        tokio::time::sleep(Duration::from_secs(6)).await;
+        assert!(processed.is_finished());
+        let mut response = vec![];
+        client.read_to_end(&mut response).await.unwrap();
+        assert!(response.is_empty());
+        assert!(processed.await.unwrap().is_ok());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_idle_connection_kept_for_advertised_timeout() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let (reader, writer) = tokio::io::split(server);
+        let connection = HttpConnection::new(reader, writer);
+        let processed = tokio::spawn(connection.process(Arc::new(make_handler(hello))));
+
+        client.write_all(b"GET / HTTP/1.1\r\n\r\n").await.unwrap();
+        assert!(read_until(&mut client, "hello").await.contains("keep-alive: timeout=75, max=999\r\n"));
+
+        // idle for longer than the 30 seconds of the read header timeout, but within the advertised 75
+        tokio::time::sleep(Duration::from_secs(70)).await;
+        assert!(!processed.is_finished());
+        client.write_all(b"GET / HTTP/1.1\r\n\r\n").await.unwrap();
+        read_until(&mut client, "hello").await;
+
+        // once the next request started, its head must arrive within the read header timeout
+        tokio::time::sleep(Duration::from_secs(70)).await;
+        client.write_all(b"GET / HTTP/1.1\r\n").await.unwrap();
+        tokio::time::sleep(Duration::from_secs(31)).await;
+        assert!(processed.is_finished());
+        assert!(processed.await.unwrap().is_ok());
+    }
+
+    /// Answers with the `x-checksum` trailer of the request as a trailer of the response.
+    async fn echo_trailers(
+        req: Request<ReqBody>,
+    ) -> Result<Response<StreamBody<Chunks>>, Box<dyn Error + Send + Sync>> {
+        let collected = req.into_body().collect().await?;
+        let mut trailers = HeaderMap::new();
+        if let Some(checksum) = collected.trailers().and_then(|trailers| trailers.get("x-checksum")) {
+            trailers.insert("x-checksum", checksum.clone());
+        }
+        let frames = [Ok(Frame::data(collected.to_bytes())), Ok(Frame::trailers(trailers))];
+        Ok(Response::new(StreamBody::new(futures::stream::iter(frames))))
+    }
+
+    #[tokio::test]
+    async fn test_trailers() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let (reader, writer) = tokio::io::split(server);
+        let connection = HttpConnection::new(reader, writer);
+
+        client
+            .write_all(
+                b"POST / HTTP/1.1\r\nTE: trailers\r\nTransfer-Encoding: chunked\r\n\r\n\
+                  5\r\nhello\r\n0\r\nx-checksum: 42\r\n\r\n",
+            )
+            .await
+            .unwrap();
+        client.shutdown().await.unwrap();
+        let processed = connection.process(Arc::new(make_handler(echo_trailers))).await;
+        assert!(processed.is_ok());
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert!(response.ends_with("\r\n\r\n5\r\nhello\r\n0\r\nx-checksum: 42\r\n\r\n"), "{response}");
+    }
+
+    #[tokio::test]
+    async fn test_trailers_not_accepted() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let (reader, writer) = tokio::io::split(server);
+        let connection = HttpConnection::new(reader, writer);
+
+        client
+            .write_all(
+                b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n\
+                  5\r\nhello\r\n0\r\nx-checksum: 42\r\n\r\n",
+            )
+            .await
+            .unwrap();
+        client.shutdown().await.unwrap();
+        let processed = connection.process(Arc::new(make_handler(echo_trailers))).await;
+        assert!(processed.is_ok());
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert!(response.ends_with("\r\n\r\n5\r\nhello\r\n0\r\n\r\n"), "{response}");
+    }
+
+    async fn reject(_req: Request<ReqBody>) -> Result<Response<String>, Box<dyn Error + Send + Sync>> {
+        let mut response = Response::new("too large".to_string());
+        *response.status_mut() = StatusCode::PAYLOAD_TOO_LARGE;
+        Ok(response)
+    }
+
+    /// Reads from `client` until `expected` is received.
+    async fn read_until(client: &mut tokio::io::DuplexStream, expected: &str) -> String {
+        let mut received = vec![];
+        let mut buf = [0; 256];
+        while !String::from_utf8_lossy(&received).contains(expected) {
+            let n = client.read(&mut buf).await.unwrap();
+            assert!(n > 0, "connection closed before {expected:?}, received {:?}", String::from_utf8_lossy(&received));
+            received.extend_from_slice(&buf[..n]);
+        }
+        String::from_utf8(received).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_expect_continue() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let (reader, writer) = tokio::io::split(server);
+        let connection = HttpConnection::new(reader, writer);
+        let processed = tokio::spawn(connection.process(Arc::new(make_handler(read_body))));
+
+        client.write_all(b"POST / HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 5\r\n\r\n").await.unwrap();
+        assert_eq!(read_until(&mut client, "\r\n\r\n").await, "HTTP/1.1 100 Continue\r\n\r\n");
+
+        client.write_all(b"hello").await.unwrap();
+        client.shutdown().await.unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{response}");
+        assert!(response.ends_with("received 5 bytes"), "{response}");
+        assert!(processed.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_expect_continue_rejected() {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let (reader, writer) = tokio::io::split(server);
+        let connection = HttpConnection::new(reader, writer);
+        let processed = tokio::spawn(connection.process(Arc::new(make_handler(reject))));
+
+        // the body is never sent
+        client.write_all(b"POST / HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 5\r\n\r\n").await.unwrap();
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert_eq!(
+            response,
+            "HTTP/1.1 413 Payload Too Large\r\nconnection: close\r\nserver: micro-http/0.1\r\n\
+             content-length: 9\r\n\r\ntoo large"
+        );
+        assert!(processed.await.unwrap().is_ok());
+    }
+
+    /// Answers like `hello`, with its own `Server` header for the `/custom` path.
+    async fn custom_server(req: Request<ReqBody>) -> Result<Response<String>, Box<dyn Error + Send + Sync>> {
+        let mut response = Response::new("hello".to_string());
+        if req.uri().path() == "/custom" {
+            response.headers_mut().insert(SERVER, HeaderValue::from_static("custom/1.0"));
+        }
+        Ok(response)
+    }
+
+    async fn server_headers(config: ServerConfig, path: &str) -> Vec<String> {
+        let (mut client, server) = tokio::io::duplex(1024);
+        let (reader, writer) = tokio::io::split(server);
+        let connection = HttpConnection::new(reader, writer).with_config(config);
+
+        client.write_all(format!("GET {path} HTTP/1.1\r\n\r\n").as_bytes()).await.unwrap();
+        client.shutdown().await.unwrap();
+        assert!(connection.process(Arc::new(make_handler(custom_server))).await.is_ok());
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        response.lines().filter_map(|line| line.strip_prefix("server: ")).map(str::to_string).collect()
+    }
+
+    #[tokio::test]
+    async fn test_server_header() {
+        assert_eq!(server_headers(ServerConfig::default(), "/").await, ["micro-http/0.1"]);
+
+        let config = ServerConfig::default().with_server_header("edge");
+        assert_eq!(server_headers(config, "/").await, ["edge"]);
+
+        let config = ServerConfig::default().suppress_server_header();
+        assert!(server_headers(config, "/").await.is_empty());
+
+        // an invalid value is ignored
+        let config = ServerConfig::default().with_server_header("bad\nvalue");
+        assert!(server_headers(config, "/").await.is_empty());
+
+        // the one of the handler is kept
+        assert_eq!(server_headers(ServerConfig::default(), "/custom").await, ["custom/1.0"]);
+    }
+}
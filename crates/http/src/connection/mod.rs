@@ -17,11 +17,17 @@
 //! 
 //! - Asynchronous I/O handling
 //! - Streaming request and response processing
-//! - Keep-alive connection support
+//! - Keep-alive connection support, with idle timeout and request limits, see [`KeepAliveConfig`]
 //! - Error handling and recovery
 //! - Expect-continue mechanism
+//! - Protocol upgrades, see [`OnUpgrade`]
+//! - Read and write timeouts, see [`ServerConfig`]
 //! - Efficient memory usage through buffering
 
+mod config;
 mod http_connection;
+mod upgrade;
 
+pub use config::{KeepAliveConfig, ServerConfig};
 pub use http_connection::HttpConnection;
+pub use upgrade::{OnUpgrade, UpgradeError, Upgraded};
@@ -0,0 +1,173 @@
+//! HTTP/1.1 protocol upgrades, such as WebSocket.
+//!
+//! When a request asks for an upgrade with `Connection: upgrade` and an `Upgrade` header,
+//! [`HttpConnection`](super::HttpConnection) stores an [`OnUpgrade`] in the request extensions.
+//! If the handler answers with `101 Switching Protocols`, the connection stops processing HTTP
+//! after sending the response head and hands the underlying IO over as an [`Upgraded`], which
+//! `OnUpgrade` resolves to.
+
+use bytes::{Buf, Bytes};
+use futures::channel::oneshot;
+use http::header::{CONNECTION, UPGRADE};
+use http::HeaderMap;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// The IO of an upgraded connection.
+///
+/// Bytes the client sent after the upgrade request, which were already read by the HTTP decoder,
+/// are returned first.
+pub struct Upgraded {
+    reader: Box<dyn AsyncRead + Send + Unpin>,
+    writer: Box<dyn AsyncWrite + Send + Unpin>,
+    read_buf: Bytes,
+}
+
+impl Upgraded {
+    pub(crate) fn new(
+        reader: Box<dyn AsyncRead + Send + Unpin>,
+        writer: Box<dyn AsyncWrite + Send + Unpin>,
+        read_buf: Bytes,
+    ) -> Self {
+        Self { reader, writer, read_buf }
+    }
+}
+
+impl AsyncRead for Upgraded {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        if self.read_buf.is_empty() {
+            return Pin::new(&mut self.reader).poll_read(cx, buf);
+        }
+
+        let amt = self.read_buf.len().min(buf.remaining());
+        buf.put_slice(&self.read_buf[..amt]);
+        self.read_buf.advance(amt);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for Upgraded {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.writer).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.writer).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.writer).poll_shutdown(cx)
+    }
+}
+
+/// Errors returned by [`OnUpgrade::upgraded`].
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum UpgradeError {
+    /// The connection was closed without switching protocols, e.g. because the response wasn't
+    /// `101 Switching Protocols`
+    #[error("connection was not upgraded")]
+    NotUpgraded,
+
+    /// Another clone of the `OnUpgrade` already took the upgraded connection
+    #[error("upgraded connection has already been taken")]
+    AlreadyTaken,
+}
+
+/// Resolves to the [`Upgraded`] connection once the `101 Switching Protocols` response is sent.
+///
+/// It's stored in the request extensions, which require `Clone`, but only one clone can take the
+/// connection.
+#[derive(Clone)]
+pub struct OnUpgrade {
+    receiver: Arc<Mutex<Option<oneshot::Receiver<Upgraded>>>>,
+}
+
+impl OnUpgrade {
+    /// Waits for the connection to be upgraded.
+    ///
+    /// Must be awaited after the response is returned to the connection, usually in a spawned task.
+    pub async fn upgraded(self) -> Result<Upgraded, UpgradeError> {
+        let receiver = self.receiver.lock().unwrap().take().ok_or(UpgradeError::AlreadyTaken)?;
+        receiver.await.map_err(|_| UpgradeError::NotUpgraded)
+    }
+}
+
+/// The connection side of an [`OnUpgrade`].
+pub(crate) struct UpgradeSender {
+    sender: oneshot::Sender<Upgraded>,
+}
+
+impl UpgradeSender {
+    pub(crate) fn send(self, upgraded: Upgraded) {
+        // the handler may have dropped the `OnUpgrade`, then nobody wants the connection
+        let _ = self.sender.send(upgraded);
+    }
+}
+
+pub(crate) fn pending() -> (UpgradeSender, OnUpgrade) {
+    let (sender, receiver) = oneshot::channel();
+    (UpgradeSender { sender }, OnUpgrade { receiver: Arc::new(Mutex::new(Some(receiver))) })
+}
+
+/// Returns true if the request asks for a protocol upgrade.
+pub(crate) fn is_upgrade_request(headers: &HeaderMap) -> bool {
+    let connection_upgrade = headers
+        .get_all(CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|token| token.trim().eq_ignore_ascii_case("upgrade"));
+    connection_upgrade && headers.contains_key(UPGRADE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[test]
+    fn test_is_upgrade_request() {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONNECTION, "keep-alive, Upgrade".parse().unwrap());
+        assert!(!is_upgrade_request(&headers));
+
+        headers.insert(UPGRADE, "websocket".parse().unwrap());
+        assert!(is_upgrade_request(&headers));
+
+        headers.insert(CONNECTION, "keep-alive".parse().unwrap());
+        assert!(!is_upgrade_request(&headers));
+    }
+
+    #[tokio::test]
+    async fn test_upgraded_reads_buffered_bytes_first() {
+        let (sender, on_upgrade) = pending();
+        let (client, server) = tokio::io::duplex(64);
+        let (reader, writer) = tokio::io::split(server);
+        sender.send(Upgraded::new(Box::new(reader), Box::new(writer), Bytes::from_static(b"early ")));
+
+        let mut upgraded = on_upgrade.clone().upgraded().await.unwrap();
+        assert_eq!(on_upgrade.upgraded().await.err(), Some(UpgradeError::AlreadyTaken));
+
+        let (mut client_reader, mut client_writer) = tokio::io::split(client);
+        client_writer.write_all(b"late").await.unwrap();
+        let mut buf = [0u8; 10];
+        upgraded.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"early late");
+
+        upgraded.write_all(b"pong").await.unwrap();
+        let mut buf = [0u8; 4];
+        client_reader.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"pong");
+    }
+
+    #[tokio::test]
+    async fn test_not_upgraded() {
+        let (sender, on_upgrade) = pending();
+        drop(sender);
+        assert_eq!(on_upgrade.upgraded().await.err(), Some(UpgradeError::NotUpgraded));
+    }
+}
@@ -0,0 +1,736 @@
+//! HTTP/2 frames, see [RFC 9113 Section 6](https://www.rfc-editor.org/rfc/rfc9113#section-6)
+//!
+//! Every frame starts with a 9 bytes header holding the length of its payload, its type, its flags and
+//! the stream it belongs to. [`FrameDecoder`] reads whole frames from a byte stream and checks the
+//! constraints a frame must meet on its own, such as the stream a `SETTINGS` frame is sent on or the
+//! length of a `PING`. The rules spanning several frames, such as a `HEADERS` frame being followed by
+//! its `CONTINUATION` frames, are left to the connection.
+//!
+//! A frame is written with [`Frame::encode`].
+//!
+//! # Example
+//!
+//! ```
+//! use bytes::BytesMut;
+//! use micro_http::h2::frame::{Frame, FrameDecoder, PingFrame};
+//! use tokio_util::codec::Decoder;
+//!
+//! let mut buf = BytesMut::new();
+//! Frame::Ping(PingFrame { ack: false, data: *b"12345678" }).encode(&mut buf);
+//!
+//! let frame = FrameDecoder::new().decode(&mut buf).unwrap();
+//! assert_eq!(frame, Some(Frame::Ping(PingFrame { ack: false, data: *b"12345678" })));
+//! ```
+
+use crate::ensure;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use std::fmt;
+use std::io;
+use thiserror::Error;
+use tokio_util::codec::Decoder;
+use tracing::trace;
+
+/// The length of the header of every frame
+pub const FRAME_HEADER_LEN: usize = 9;
+
+/// The initial value of `SETTINGS_MAX_FRAME_SIZE`, the largest payload a peer must accept
+pub const DEFAULT_MAX_FRAME_SIZE: u32 = 16_384;
+
+/// The largest value `SETTINGS_MAX_FRAME_SIZE` can be set to
+pub const MAX_MAX_FRAME_SIZE: u32 = (1 << 24) - 1;
+
+const DATA: u8 = 0x0;
+const HEADERS: u8 = 0x1;
+const PRIORITY: u8 = 0x2;
+const RST_STREAM: u8 = 0x3;
+const SETTINGS: u8 = 0x4;
+const PUSH_PROMISE: u8 = 0x5;
+const PING: u8 = 0x6;
+const GOAWAY: u8 = 0x7;
+const WINDOW_UPDATE: u8 = 0x8;
+const CONTINUATION: u8 = 0x9;
+
+const FLAG_END_STREAM: u8 = 0x1;
+const FLAG_ACK: u8 = 0x1;
+const FLAG_END_HEADERS: u8 = 0x4;
+const FLAG_PADDED: u8 = 0x8;
+const FLAG_PRIORITY: u8 = 0x20;
+
+/// A 31 bits stream identifier, the reserved high bit is always cleared
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct StreamId(u32);
+
+impl StreamId {
+    /// The stream of the frames applying to the whole connection
+    pub const ZERO: StreamId = StreamId(0);
+
+    /// The largest stream identifier
+    pub const MAX: StreamId = StreamId(u32::MAX >> 1);
+
+    /// Creates a stream identifier from the low 31 bits of `id`
+    pub const fn new(id: u32) -> Self {
+        StreamId(id & Self::MAX.0)
+    }
+
+    /// Returns the identifier as a number
+    pub const fn value(self) -> u32 {
+        self.0
+    }
+
+    /// Returns true for the stream of the frames applying to the whole connection
+    pub const fn is_zero(self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl From<u32> for StreamId {
+    fn from(id: u32) -> Self {
+        StreamId::new(id)
+    }
+}
+
+impl fmt::Display for StreamId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// The reason of a `RST_STREAM` or `GOAWAY` frame, see RFC 9113 Section 7
+///
+/// An unknown code is kept as is, it must not trigger any special behavior.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ErrorCode(pub u32);
+
+impl ErrorCode {
+    pub const NO_ERROR: ErrorCode = ErrorCode(0x0);
+    pub const PROTOCOL_ERROR: ErrorCode = ErrorCode(0x1);
+    pub const INTERNAL_ERROR: ErrorCode = ErrorCode(0x2);
+    pub const FLOW_CONTROL_ERROR: ErrorCode = ErrorCode(0x3);
+    pub const SETTINGS_TIMEOUT: ErrorCode = ErrorCode(0x4);
+    pub const STREAM_CLOSED: ErrorCode = ErrorCode(0x5);
+    pub const FRAME_SIZE_ERROR: ErrorCode = ErrorCode(0x6);
+    pub const REFUSED_STREAM: ErrorCode = ErrorCode(0x7);
+    pub const CANCEL: ErrorCode = ErrorCode(0x8);
+    pub const COMPRESSION_ERROR: ErrorCode = ErrorCode(0x9);
+    pub const CONNECT_ERROR: ErrorCode = ErrorCode(0xa);
+    pub const ENHANCE_YOUR_CALM: ErrorCode = ErrorCode(0xb);
+    pub const INADEQUATE_SECURITY: ErrorCode = ErrorCode(0xc);
+    pub const HTTP_1_1_REQUIRED: ErrorCode = ErrorCode(0xd);
+}
+
+/// A parameter of a `SETTINGS` frame
+///
+/// An unknown parameter is kept as is, it must be ignored by the receiver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Setting {
+    pub id: u16,
+    pub value: u32,
+}
+
+impl Setting {
+    pub const HEADER_TABLE_SIZE: u16 = 0x1;
+    pub const ENABLE_PUSH: u16 = 0x2;
+    pub const MAX_CONCURRENT_STREAMS: u16 = 0x3;
+    pub const INITIAL_WINDOW_SIZE: u16 = 0x4;
+    pub const MAX_FRAME_SIZE: u16 = 0x5;
+    pub const MAX_HEADER_LIST_SIZE: u16 = 0x6;
+}
+
+/// The priority of a stream carried by `HEADERS` and `PRIORITY` frames
+///
+/// The priority scheme is deprecated by RFC 9113, but the fields must still be parsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrioritySpec {
+    pub exclusive: bool,
+    pub dependency: StreamId,
+    /// The weight minus one, as sent on the wire
+    pub weight: u8,
+}
+
+/// A `DATA` frame, carrying a part of the body of a stream
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataFrame {
+    pub stream_id: StreamId,
+    pub end_stream: bool,
+    /// The number of padding bytes, `None` if the frame is not padded
+    pub padding: Option<u8>,
+    pub data: Bytes,
+}
+
+/// A `HEADERS` frame, opening a stream with the start of its header block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeadersFrame {
+    pub stream_id: StreamId,
+    pub end_stream: bool,
+    /// Whether the header block ends with this frame, or continues in `CONTINUATION` frames
+    pub end_headers: bool,
+    pub padding: Option<u8>,
+    pub priority: Option<PrioritySpec>,
+    /// The HPACK encoded header block fragment
+    pub header_block: Bytes,
+}
+
+/// A `PRIORITY` frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PriorityFrame {
+    pub stream_id: StreamId,
+    pub priority: PrioritySpec,
+}
+
+/// A `RST_STREAM` frame, terminating a stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RstStreamFrame {
+    pub stream_id: StreamId,
+    pub error_code: ErrorCode,
+}
+
+/// A `SETTINGS` frame, or its acknowledgment which has no settings
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SettingsFrame {
+    pub ack: bool,
+    pub settings: Vec<Setting>,
+}
+
+/// A `PUSH_PROMISE` frame, announcing a stream the server is about to open
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PushPromiseFrame {
+    pub stream_id: StreamId,
+    pub end_headers: bool,
+    pub padding: Option<u8>,
+    pub promised_stream_id: StreamId,
+    pub header_block: Bytes,
+}
+
+/// A `PING` frame, or its acknowledgment carrying the same data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PingFrame {
+    pub ack: bool,
+    pub data: [u8; 8],
+}
+
+/// A `GOAWAY` frame, shutting the connection down
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GoAwayFrame {
+    /// The last stream the sender may have processed
+    pub last_stream_id: StreamId,
+    pub error_code: ErrorCode,
+    pub debug_data: Bytes,
+}
+
+/// A `WINDOW_UPDATE` frame, of a stream or of the whole connection on [`StreamId::ZERO`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WindowUpdateFrame {
+    pub stream_id: StreamId,
+    /// The number of bytes added to the flow control window, in `1..=2^31-1`
+    pub increment: u32,
+}
+
+/// A `CONTINUATION` frame, carrying the rest of a header block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContinuationFrame {
+    pub stream_id: StreamId,
+    pub end_headers: bool,
+    pub header_block: Bytes,
+}
+
+/// An HTTP/2 frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Frame {
+    Data(DataFrame),
+    Headers(HeadersFrame),
+    Priority(PriorityFrame),
+    RstStream(RstStreamFrame),
+    Settings(SettingsFrame),
+    PushPromise(PushPromiseFrame),
+    Ping(PingFrame),
+    GoAway(GoAwayFrame),
+    WindowUpdate(WindowUpdateFrame),
+    Continuation(ContinuationFrame),
+}
+
+/// Errors of frame decoding
+///
+/// They are connection errors, except where noted. See [`error_code`](Self::error_code) for the code
+/// the connection is closed with.
+#[derive(Error, Debug)]
+pub enum FrameError {
+    /// The frame is larger than the maximum frame size, or has a wrong length for its type
+    #[error("invalid size {length} of frame type {frame_type:#x}")]
+    FrameSize { frame_type: u8, length: usize },
+
+    /// The frame breaks a rule of RFC 9113
+    #[error("protocol error: {reason}")]
+    Protocol { reason: &'static str },
+
+    /// The underlying stream failed
+    #[error("io error: {source}")]
+    Io {
+        #[from]
+        source: io::Error,
+    },
+}
+
+impl FrameError {
+    /// Returns the code to send in the `GOAWAY` frame
+    pub fn error_code(&self) -> ErrorCode {
+        match self {
+            FrameError::FrameSize { .. } => ErrorCode::FRAME_SIZE_ERROR,
+            FrameError::Protocol { .. } => ErrorCode::PROTOCOL_ERROR,
+            FrameError::Io { .. } => ErrorCode::INTERNAL_ERROR,
+        }
+    }
+
+    fn protocol(reason: &'static str) -> Self {
+        FrameError::Protocol { reason }
+    }
+}
+
+impl Frame {
+    /// Returns the stream the frame belongs to, [`StreamId::ZERO`] for the connection frames
+    pub fn stream_id(&self) -> StreamId {
+        match self {
+            Frame::Data(frame) => frame.stream_id,
+            Frame::Headers(frame) => frame.stream_id,
+            Frame::Priority(frame) => frame.stream_id,
+            Frame::RstStream(frame) => frame.stream_id,
+            Frame::PushPromise(frame) => frame.stream_id,
+            Frame::WindowUpdate(frame) => frame.stream_id,
+            Frame::Continuation(frame) => frame.stream_id,
+            Frame::Settings(_) | Frame::Ping(_) | Frame::GoAway(_) => StreamId::ZERO,
+        }
+    }
+
+    /// Writes the frame, its header included, to `dst`.
+    ///
+    /// The payload is not checked against the maximum frame size of the peer, a larger payload must be
+    /// split by the caller.
+    pub fn encode(&self, dst: &mut BytesMut) {
+        match self {
+            Frame::Data(frame) => {
+                let flags = flag(frame.end_stream, FLAG_END_STREAM) | flag(frame.padding.is_some(), FLAG_PADDED);
+                encode_padded(dst, DATA, flags, frame.stream_id, frame.padding, &[], &frame.data);
+            }
+            Frame::Headers(frame) => {
+                let flags = flag(frame.end_stream, FLAG_END_STREAM)
+                    | flag(frame.end_headers, FLAG_END_HEADERS)
+                    | flag(frame.padding.is_some(), FLAG_PADDED)
+                    | flag(frame.priority.is_some(), FLAG_PRIORITY);
+                let mut priority = [0; 5];
+                let prefix = match &frame.priority {
+                    Some(spec) => {
+                        encode_priority(spec, &mut priority);
+                        &priority[..]
+                    }
+                    None => &[],
+                };
+                encode_padded(dst, HEADERS, flags, frame.stream_id, frame.padding, prefix, &frame.header_block);
+            }
+            Frame::Priority(frame) => {
+                let mut priority = [0; 5];
+                encode_priority(&frame.priority, &mut priority);
+                encode_header(dst, priority.len(), PRIORITY, 0, frame.stream_id);
+                dst.put_slice(&priority);
+            }
+            Frame::RstStream(frame) => {
+                encode_header(dst, 4, RST_STREAM, 0, frame.stream_id);
+                dst.put_u32(frame.error_code.0);
+            }
+            Frame::Settings(frame) => {
+                encode_header(dst, frame.settings.len() * 6, SETTINGS, flag(frame.ack, FLAG_ACK), StreamId::ZERO);
+                for setting in &frame.settings {
+                    dst.put_u16(setting.id);
+                    dst.put_u32(setting.value);
+                }
+            }
+            Frame::PushPromise(frame) => {
+                let flags = flag(frame.end_headers, FLAG_END_HEADERS) | flag(frame.padding.is_some(), FLAG_PADDED);
+                let promised = frame.promised_stream_id.value().to_be_bytes();
+                encode_padded(dst, PUSH_PROMISE, flags, frame.stream_id, frame.padding, &promised, &frame.header_block);
+            }
+            Frame::Ping(frame) => {
+                encode_header(dst, frame.data.len(), PING, flag(frame.ack, FLAG_ACK), StreamId::ZERO);
+                dst.put_slice(&frame.data);
+            }
+            Frame::GoAway(frame) => {
+                encode_header(dst, 8 + frame.debug_data.len(), GOAWAY, 0, StreamId::ZERO);
+                dst.put_u32(frame.last_stream_id.value());
+                dst.put_u32(frame.error_code.0);
+                dst.put_slice(&frame.debug_data);
+            }
+            Frame::WindowUpdate(frame) => {
+                encode_header(dst, 4, WINDOW_UPDATE, 0, frame.stream_id);
+                dst.put_u32(frame.increment & StreamId::MAX.value());
+            }
+            Frame::Continuation(frame) => {
+                let flags = flag(frame.end_headers, FLAG_END_HEADERS);
+                encode_header(dst, frame.header_block.len(), CONTINUATION, flags, frame.stream_id);
+                dst.put_slice(&frame.header_block);
+            }
+        }
+    }
+}
+
+fn flag(set: bool, flag: u8) -> u8 {
+    if set {
+        flag
+    } else {
+        0
+    }
+}
+
+fn encode_header(dst: &mut BytesMut, length: usize, frame_type: u8, flags: u8, stream_id: StreamId) {
+    dst.reserve(FRAME_HEADER_LEN + length);
+    dst.put_uint(length as u64, 3);
+    dst.put_u8(frame_type);
+    dst.put_u8(flags);
+    dst.put_u32(stream_id.value());
+}
+
+/// Writes a frame whose payload is `prefix` then `data`, surrounded by the padding if any.
+fn encode_padded(
+    dst: &mut BytesMut,
+    frame_type: u8,
+    flags: u8,
+    stream_id: StreamId,
+    padding: Option<u8>,
+    prefix: &[u8],
+    data: &[u8],
+) {
+    let padding_len = padding.map_or(0, |padding| 1 + padding as usize);
+    encode_header(dst, padding_len + prefix.len() + data.len(), frame_type, flags, stream_id);
+    if let Some(padding) = padding {
+        dst.put_u8(padding);
+    }
+    dst.put_slice(prefix);
+    dst.put_slice(data);
+    // padding bytes must be zero
+    dst.put_bytes(0, padding.unwrap_or(0) as usize);
+}
+
+fn encode_priority(spec: &PrioritySpec, dst: &mut [u8; 5]) {
+    let dependency = spec.dependency.value() | if spec.exclusive { 1 << 31 } else { 0 };
+    dst[..4].copy_from_slice(&dependency.to_be_bytes());
+    dst[4] = spec.weight;
+}
+
+/// A decoder of HTTP/2 frames
+///
+/// Frames of an unknown type are skipped, as RFC 9113 requires. The connection preface is not
+/// handled, it must be read before the first frame.
+#[derive(Debug)]
+pub struct FrameDecoder {
+    max_frame_size: u32,
+}
+
+impl Default for FrameDecoder {
+    fn default() -> Self {
+        Self { max_frame_size: DEFAULT_MAX_FRAME_SIZE }
+    }
+}
+
+impl FrameDecoder {
+    /// Creates a decoder accepting payloads up to [`DEFAULT_MAX_FRAME_SIZE`]
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Sets the largest payload accepted, once the `SETTINGS_MAX_FRAME_SIZE` sent to the peer is
+    /// acknowledged. It's clamped to `DEFAULT_MAX_FRAME_SIZE..=MAX_MAX_FRAME_SIZE`.
+    pub fn set_max_frame_size(&mut self, max_frame_size: u32) {
+        self.max_frame_size = max_frame_size.clamp(DEFAULT_MAX_FRAME_SIZE, MAX_MAX_FRAME_SIZE);
+    }
+
+    /// Returns the largest payload accepted
+    pub fn max_frame_size(&self) -> u32 {
+        self.max_frame_size
+    }
+}
+
+impl Decoder for FrameDecoder {
+    type Item = Frame;
+    type Error = FrameError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        loop {
+            if src.len() < FRAME_HEADER_LEN {
+                return Ok(None);
+            }
+
+            let length = (src[0] as usize) << 16 | (src[1] as usize) << 8 | src[2] as usize;
+            let frame_type = src[3];
+            ensure!(length <= self.max_frame_size as usize, FrameError::FrameSize { frame_type, length });
+            if src.len() < FRAME_HEADER_LEN + length {
+                src.reserve(FRAME_HEADER_LEN + length - src.len());
+                return Ok(None);
+            }
+
+            let flags = src[4];
+            let stream_id = StreamId::new(u32::from_be_bytes([src[5], src[6], src[7], src[8]]));
+            src.advance(FRAME_HEADER_LEN);
+            let payload = src.split_to(length).freeze();
+
+            match decode_payload(frame_type, flags, stream_id, payload)? {
+                Some(frame) => return Ok(Some(frame)),
+                None => trace!(frame_type, length, "skip frame of unknown type"),
+            }
+        }
+    }
+}
+
+/// Parses the payload of a frame, returns `None` for an unknown frame type.
+fn decode_payload(
+    frame_type: u8,
+    flags: u8,
+    stream_id: StreamId,
+    mut payload: Bytes,
+) -> Result<Option<Frame>, FrameError> {
+    let length = payload.len();
+    let frame_size_error = FrameError::FrameSize { frame_type, length };
+
+    let frame = match frame_type {
+        DATA => {
+            ensure!(!stream_id.is_zero(), FrameError::protocol("DATA frame on stream 0"));
+            let padding = strip_padding(flags, &mut payload)?;
+            Frame::Data(DataFrame { stream_id, end_stream: flags & FLAG_END_STREAM != 0, padding, data: payload })
+        }
+        HEADERS => {
+            ensure!(!stream_id.is_zero(), FrameError::protocol("HEADERS frame on stream 0"));
+            let padding = strip_padding(flags, &mut payload)?;
+            let priority = if flags & FLAG_PRIORITY != 0 {
+                ensure!(payload.len() >= 5, frame_size_error);
+                Some(decode_priority(&mut payload))
+            } else {
+                None
+            };
+            Frame::Headers(HeadersFrame {
+                stream_id,
+                end_stream: flags & FLAG_END_STREAM != 0,
+                end_headers: flags & FLAG_END_HEADERS != 0,
+                padding,
+                priority,
+                header_block: payload,
+            })
+        }
+        PRIORITY => {
+            ensure!(!stream_id.is_zero(), FrameError::protocol("PRIORITY frame on stream 0"));
+            ensure!(length == 5, frame_size_error);
+            Frame::Priority(PriorityFrame { stream_id, priority: decode_priority(&mut payload) })
+        }
+        RST_STREAM => {
+            ensure!(!stream_id.is_zero(), FrameError::protocol("RST_STREAM frame on stream 0"));
+            ensure!(length == 4, frame_size_error);
+            Frame::RstStream(RstStreamFrame { stream_id, error_code: ErrorCode(payload.get_u32()) })
+        }
+        SETTINGS => {
+            ensure!(stream_id.is_zero(), FrameError::protocol("SETTINGS frame on a stream"));
+            let ack = flags & FLAG_ACK != 0;
+            // each setting takes 6 bytes
+            let (count, rest) = (length / 6, length % 6);
+            ensure!(rest == 0 && !(ack && count > 0), frame_size_error);
+            let mut settings = Vec::with_capacity(count);
+            while payload.has_remaining() {
+                settings.push(Setting { id: payload.get_u16(), value: payload.get_u32() });
+            }
+            Frame::Settings(SettingsFrame { ack, settings })
+        }
+        PUSH_PROMISE => {
+            ensure!(!stream_id.is_zero(), FrameError::protocol("PUSH_PROMISE frame on stream 0"));
+            let padding = strip_padding(flags, &mut payload)?;
+            ensure!(payload.len() >= 4, frame_size_error);
+            let promised_stream_id = StreamId::new(payload.get_u32());
+            Frame::PushPromise(PushPromiseFrame {
+                stream_id,
+                end_headers: flags & FLAG_END_HEADERS != 0,
+                padding,
+                promised_stream_id,
+                header_block: payload,
+            })
+        }
+        PING => {
+            ensure!(stream_id.is_zero(), FrameError::protocol("PING frame on a stream"));
+            ensure!(length == 8, frame_size_error);
+            let mut data = [0; 8];
+            payload.copy_to_slice(&mut data);
+            Frame::Ping(PingFrame { ack: flags & FLAG_ACK != 0, data })
+        }
+        GOAWAY => {
+            ensure!(stream_id.is_zero(), FrameError::protocol("GOAWAY frame on a stream"));
+            ensure!(length >= 8, frame_size_error);
+            let last_stream_id = StreamId::new(payload.get_u32());
+            let error_code = ErrorCode(payload.get_u32());
+            Frame::GoAway(GoAwayFrame { last_stream_id, error_code, debug_data: payload })
+        }
+        WINDOW_UPDATE => {
+            ensure!(length == 4, frame_size_error);
+            let increment = payload.get_u32() & StreamId::MAX.value();
+            ensure!(increment > 0, FrameError::protocol("WINDOW_UPDATE frame with a zero increment"));
+            Frame::WindowUpdate(WindowUpdateFrame { stream_id, increment })
+        }
+        CONTINUATION => {
+            ensure!(!stream_id.is_zero(), FrameError::protocol("CONTINUATION frame on stream 0"));
+            let end_headers = flags & FLAG_END_HEADERS != 0;
+            Frame::Continuation(ContinuationFrame { stream_id, end_headers, header_block: payload })
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(frame))
+}
+
+/// Removes the padding of a frame with the `PADDED` flag, returns its length.
+fn strip_padding(flags: u8, payload: &mut Bytes) -> Result<Option<u8>, FrameError> {
+    if flags & FLAG_PADDED == 0 {
+        return Ok(None);
+    }
+
+    ensure!(!payload.is_empty(), FrameError::protocol("padded frame without a pad length"));
+    let padding = payload.get_u8();
+    ensure!((padding as usize) <= payload.len(), FrameError::protocol("padding exceeds the frame payload"));
+    payload.truncate(payload.len() - padding as usize);
+    Ok(Some(padding))
+}
+
+fn decode_priority(payload: &mut Bytes) -> PrioritySpec {
+    let dependency = payload.get_u32();
+    PrioritySpec { exclusive: dependency >> 31 == 1, dependency: StreamId::new(dependency), weight: payload.get_u8() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(frame: Frame) {
+        let mut buf = BytesMut::new();
+        frame.encode(&mut buf);
+        assert_eq!(FrameDecoder::new().decode(&mut buf).unwrap(), Some(frame));
+        assert!(buf.is_empty());
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Option<Frame>, FrameError> {
+        FrameDecoder::new().decode(&mut BytesMut::from(bytes))
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let stream_id = StreamId::new(1);
+        let header_block = Bytes::from_static(b"\x82\x86\x84");
+        let priority = PrioritySpec { exclusive: true, dependency: StreamId::new(3), weight: 15 };
+
+        round_trip(Frame::Data(DataFrame { stream_id, end_stream: true, padding: None, data: Bytes::from("hello") }));
+        round_trip(Frame::Data(DataFrame { stream_id, end_stream: false, padding: Some(4), data: Bytes::from("hi") }));
+        round_trip(Frame::Headers(HeadersFrame {
+            stream_id,
+            end_stream: false,
+            end_headers: true,
+            padding: Some(2),
+            priority: Some(priority),
+            header_block: header_block.clone(),
+        }));
+        round_trip(Frame::Headers(HeadersFrame {
+            stream_id,
+            end_stream: true,
+            end_headers: false,
+            padding: None,
+            priority: None,
+            header_block: header_block.clone(),
+        }));
+        round_trip(Frame::Priority(PriorityFrame { stream_id, priority }));
+        round_trip(Frame::RstStream(RstStreamFrame { stream_id, error_code: ErrorCode::CANCEL }));
+        round_trip(Frame::Settings(SettingsFrame {
+            ack: false,
+            settings: vec![
+                Setting { id: Setting::MAX_CONCURRENT_STREAMS, value: 100 },
+                Setting { id: Setting::INITIAL_WINDOW_SIZE, value: 65_535 },
+            ],
+        }));
+        round_trip(Frame::Settings(SettingsFrame { ack: true, settings: vec![] }));
+        round_trip(Frame::PushPromise(PushPromiseFrame {
+            stream_id,
+            end_headers: true,
+            padding: Some(0),
+            promised_stream_id: StreamId::new(2),
+            header_block: header_block.clone(),
+        }));
+        round_trip(Frame::Ping(PingFrame { ack: true, data: *b"pingpong" }));
+        round_trip(Frame::GoAway(GoAwayFrame {
+            last_stream_id: StreamId::new(7),
+            error_code: ErrorCode::ENHANCE_YOUR_CALM,
+            debug_data: Bytes::from("slow down"),
+        }));
+        round_trip(Frame::WindowUpdate(WindowUpdateFrame { stream_id: StreamId::ZERO, increment: 1 << 20 }));
+        round_trip(Frame::Continuation(ContinuationFrame { stream_id, end_headers: true, header_block }));
+    }
+
+    #[test]
+    fn test_wire_format() {
+        let mut buf = BytesMut::new();
+        let data =
+            DataFrame { stream_id: StreamId::new(3), end_stream: true, padding: Some(2), data: Bytes::from("ab") };
+        Frame::Data(data).encode(&mut buf);
+        assert_eq!(&buf[..], b"\x00\x00\x05\x00\x09\x00\x00\x00\x03\x02ab\x00\x00");
+
+        // the reserved bit of the stream identifier is ignored
+        let frame = decode(b"\x00\x00\x00\x09\x04\x80\x00\x00\x05").unwrap().unwrap();
+        assert_eq!(frame.stream_id(), StreamId::new(5));
+    }
+
+    #[test]
+    fn test_partial_frame() {
+        let mut buf = BytesMut::new();
+        Frame::Ping(PingFrame { ack: false, data: [1; 8] }).encode(&mut buf);
+        let mut partial = BytesMut::from(&buf[..12]);
+        let mut decoder = FrameDecoder::new();
+        assert!(decoder.decode(&mut partial).unwrap().is_none());
+        partial.extend_from_slice(&buf[12..]);
+        assert_eq!(decoder.decode(&mut partial).unwrap(), Some(Frame::Ping(PingFrame { ack: false, data: [1; 8] })));
+    }
+
+    #[test]
+    fn test_skip_unknown_frame() {
+        let mut buf = BytesMut::from(&b"\x00\x00\x03\xfa\x00\x00\x00\x00\x01abc"[..]);
+        Frame::Ping(PingFrame { ack: true, data: [0; 8] }).encode(&mut buf);
+        let frame = FrameDecoder::new().decode(&mut buf).unwrap();
+        assert_eq!(frame, Some(Frame::Ping(PingFrame { ack: true, data: [0; 8] })));
+    }
+
+    #[test]
+    fn test_frame_size_errors() {
+        let mut decoder = FrameDecoder::new();
+        let mut oversized = BytesMut::from(&b"\x00\x40\x01\x00\x00\x00\x00\x00\x01"[..]);
+        let error = decoder.decode(&mut oversized).unwrap_err();
+        assert!(matches!(error, FrameError::FrameSize { frame_type: DATA, length: 16_385 }), "{error}");
+
+        // accepted once the maximum frame size is raised
+        decoder.set_max_frame_size(1 << 20);
+        oversized.extend_from_slice(&[0; 16_385]);
+        assert!(matches!(decoder.decode(&mut oversized).unwrap(), Some(Frame::Data(_))));
+
+        for frame in [
+            &b"\x00\x00\x04\x06\x00\x00\x00\x00\x00pong"[..],
+            b"\x00\x00\x05\x04\x00\x00\x00\x00\x00\x00\x01\x00\x00\x00",
+            b"\x00\x00\x06\x04\x01\x00\x00\x00\x00\x00\x01\x00\x00\x00\x01",
+            b"\x00\x00\x03\x08\x00\x00\x00\x00\x00\x00\x00\x01",
+        ] {
+            let error = decode(frame).unwrap_err();
+            assert_eq!(error.error_code(), ErrorCode::FRAME_SIZE_ERROR, "{error}");
+        }
+    }
+
+    #[test]
+    fn test_protocol_errors() {
+        for frame in [
+            // DATA on stream 0
+            &b"\x00\x00\x01\x00\x00\x00\x00\x00\x00a"[..],
+            // padding longer than the payload
+            b"\x00\x00\x02\x00\x08\x00\x00\x00\x01\x02a",
+            // SETTINGS on a stream
+            b"\x00\x00\x00\x04\x00\x00\x00\x00\x01",
+            // PING on a stream
+            b"\x00\x00\x08\x06\x00\x00\x00\x00\x01pingpong",
+            // zero window increment
+            b"\x00\x00\x04\x08\x00\x00\x00\x00\x01\x00\x00\x00\x00",
+        ] {
+            let error = decode(frame).unwrap_err();
+            assert_eq!(error.error_code(), ErrorCode::PROTOCOL_ERROR, "{error}");
+        }
+    }
+}
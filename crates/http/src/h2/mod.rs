@@ -0,0 +1,9 @@
+//! HTTP/2 building blocks
+//!
+//! This module holds the parts of HTTP/2 that can be used on their own, before the connection logic
+//! is built on top of them:
+//!
+//! - [`frame`]: The frame types of RFC 9113 and the [`FrameDecoder`](frame::FrameDecoder) reading them
+//!   from a byte stream
+
+pub mod frame;
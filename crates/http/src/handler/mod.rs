@@ -90,7 +90,7 @@ where
 /// # Arguments
 /// 
 /// * `f` - An async function that takes a [`Request`] and returns a [`Future`]
-///         resolving to a [`Response`]
+///   resolving to a [`Response`]
 /// 
 /// # Examples
 /// 
@@ -102,8 +102,10 @@
 //! The crate is organized into several key modules:
 //! 
 //! - [`connection`]: Core connection handling and lifecycle management
+//! - [`client`]: An HTTP/1.1 client built on the same codec
 //! - [`protocol`]: Protocol types and abstractions
 //! - [`codec`]: Protocol encoding/decoding implementation
+//! - [`h2`]: HTTP/2 frames, the foundation of a future HTTP/2 support
 //! - [`handler`]: Request handler traits and utilities
 //! 
 //! 
@@ -164,8 +166,10 @@
 //! reviewed and tested.
 
 
+pub mod client;
 pub mod codec;
 pub mod connection;
+pub mod h2;
 pub mod handler;
 pub mod protocol;
 
@@ -1,5 +1,6 @@
 use std::pin::Pin;
 use std::task::{ready, Context, Poll};
+use std::time::Duration;
 
 use bytes::Bytes;
 
@@ -54,7 +55,7 @@ impl ReqBody {
     /// 
     /// The returned ReqBody implements http_body::Body and can be passed to request handlers,
     /// while ReqBodySender handles reading from the underlying stream.
-    pub fn body_channel<S>(payload_stream: &mut S) -> (ReqBody, ReqBodySender<S>)
+    pub fn body_channel<S>(payload_stream: &mut S) -> (ReqBody, ReqBodySender<'_, S>)
     where
         S: Stream + Unpin,
     {
@@ -62,7 +63,7 @@ impl ReqBody {
 
         let req_body = ReqBody::new(tx);
 
-        let body_sender = ReqBodySender { payload_stream, receiver, eof: false };
+        let body_sender = ReqBodySender { payload_stream, receiver, pending: None, eof: false, read_timeout: None };
 
         (req_body, body_sender)
     }
@@ -83,13 +84,45 @@ where
 {
     payload_stream: &'conn mut S,
     receiver: mpsc::Receiver<oneshot::Sender<PayloadItem>>,
+    /// A chunk request received by [`wait_for_read`](Self::wait_for_read), not answered yet
+    pending: Option<oneshot::Sender<PayloadItem>>,
     eof: bool,
+    read_timeout: Option<Duration>,
 }
 
 impl<'conn, S> ReqBodySender<'conn, S>
 where
     S: Stream<Item = Result<Message<RequestHeader>, ParseError>> + Unpin,
 {
+    /// Sets the maximum time to wait for each read of the body, reads fail with
+    /// [`ParseError::ReadTimeout`] once it elapses.
+    pub fn with_read_timeout(mut self, read_timeout: Option<Duration>) -> Self {
+        self.read_timeout = read_timeout;
+        self
+    }
+
+    /// Reads the next item of the payload stream, within the read timeout.
+    async fn next_payload(&mut self) -> Option<Result<Message<RequestHeader>, ParseError>> {
+        match self.read_timeout {
+            Some(read_timeout) => match tokio::time::timeout(read_timeout, self.payload_stream.next()).await {
+                Ok(next) => next,
+                Err(_) => Some(Err(ParseError::read_timeout(read_timeout))),
+            },
+            None => self.payload_stream.next().await,
+        }
+    }
+
+    /// Waits until the [`ReqBody`] requests its first chunk, without reading the payload stream.
+    ///
+    /// Returns false if the body was dropped without being read. It's used to send `100 Continue`
+    /// only once the handler reads the body.
+    pub async fn wait_for_read(&mut self) -> bool {
+        if self.pending.is_none() {
+            self.pending = self.receiver.next().await;
+        }
+        self.pending.is_some()
+    }
+
     /// Streams body chunks from payload stream to ReqBody consumer.
     /// 
     /// This method runs in a loop, responding to chunk requests from the ReqBody
@@ -102,28 +135,36 @@ where
                 return Ok(());
             }
 
-            if let Some(sender) = self.receiver.next().await {
-                match self.payload_stream.next().await {
-                    Some(Ok(Message::Payload(payload_item))) => {
-                        if payload_item.is_eof() {
-                            self.eof = true;
-                        }
-                        sender.send(payload_item).unwrap();
-                    }
+            let sender = match self.pending.take() {
+                Some(sender) => Some(sender),
+                None => self.receiver.next().await,
+            };
 
-                    Some(Ok(Message::Header(_header))) => {
-                        error!("received header from receive body phase");
-                        return Err(ParseError::invalid_body("received header from receive body phase"));
-                    }
+            // the body was dropped, what's left of it is skipped once the handler returns
+            let Some(sender) = sender else {
+                return Ok(());
+            };
 
-                    Some(Err(e)) => {
-                        return Err(e);
+            match self.next_payload().await {
+                Some(Ok(Message::Payload(payload_item))) => {
+                    if payload_item.is_eof() {
+                        self.eof = true;
                     }
+                    sender.send(payload_item).unwrap();
+                }
 
-                    None => {
-                        error!("cant read body");
-                        return Err(ParseError::invalid_body("cant read body"));
-                    }
+                Some(Ok(Message::Header(_header))) => {
+                    error!("received header from receive body phase");
+                    return Err(ParseError::invalid_body("received header from receive body phase"));
+                }
+
+                Some(Err(e)) => {
+                    return Err(e);
+                }
+
+                None => {
+                    error!("cant read body");
+                    return Err(ParseError::invalid_body("cant read body"));
                 }
             }
         }
@@ -136,10 +177,19 @@ where
     /// - The connection will be reused for future requests
     /// 
     /// It ensures the connection is in a clean state for the next request.
-    pub async fn skip_body(&mut self) {
+    ///
+    /// Returns an error if the body couldn't be read within the read timeout.
+    pub async fn skip_body(&mut self) -> Result<(), ParseError> {
         if !self.eof {
             let mut size: usize = 0;
-            while let Some(Ok(Message::Payload(payload_item))) = self.payload_stream.next().await {
+            loop {
+                let payload_item = match self.next_payload().await {
+                    Some(Ok(Message::Payload(payload_item))) => payload_item,
+                    Some(Err(e @ ParseError::ReadTimeout { .. })) => return Err(e),
+                    // the next read of the connection reports the error
+                    _ => break,
+                };
+
                 if payload_item.is_eof() {
                     self.eof = true;
                     if size > 0 {
@@ -153,6 +203,7 @@ where
                 }
             }
         }
+        Ok(())
     }
 }
 
@@ -176,6 +227,10 @@ impl Body for ReqBody {
                         self.receiving.take();
                         Poll::Ready(Some(Ok(Frame::data(bytes))))
                     }
+                    Ok(PayloadItem::Trailer(trailers)) => {
+                        self.receiving.take();
+                        Poll::Ready(Some(Ok(Frame::trailers(trailers))))
+                    }
                     Ok(PayloadItem::Eof) => {
                         self.receiving.take();
                         Poll::Ready(None)
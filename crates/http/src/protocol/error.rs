@@ -12,7 +12,11 @@
 //! The error types form a hierarchy where `HttpError` is the top-level error that can
 //! contain either a `ParseError` or `SendError`. This allows for granular error handling
 //! while still providing a unified error type at the API boundary.
+//!
+//! Each level keeps the error it wraps as its [`source`](std::error::Error::source), so the chain
+//! leads down to the underlying [`io::Error`] when there is one.
 use std::io;
+use std::time::Duration;
 use thiserror::Error;
 
 /// The top-level error type for HTTP operations
@@ -58,6 +62,10 @@ pub enum ParseError {
     #[error("invalid http version: {0:?}")]
     InvalidVersion(Option<u8>),
 
+    /// A header value isn't valid UTF-8
+    #[error("header {name} value is not valid utf-8")]
+    InvalidUtf8 { name: String },
+
     /// Invalid or unsupported HTTP method
     #[error("invalid http method")]
     InvalidMethod,
@@ -74,6 +82,14 @@ pub enum ParseError {
     #[error("invalid body: {reason}")]
     InvalidBody { reason: String },
 
+    /// Body size exceeds the maximum allowed size
+    #[error("body size too large, current: {current_size} exceed the limit {max_size}")]
+    TooLargeBody { current_size: u64, max_size: u64 },
+
+    /// The client didn't send the expected data in time
+    #[error("read timeout after {timeout:?}")]
+    ReadTimeout { timeout: Duration },
+
     /// I/O error during parsing
     #[error("io error: {source}")]
     Io {
@@ -103,22 +119,54 @@ impl ParseError {
         Self::InvalidBody { reason: str.to_string() }
     }
 
+    /// Creates a new InvalidUtf8 error
+    pub fn invalid_utf8<S: ToString>(name: S) -> Self {
+        Self::InvalidUtf8 { name: name.to_string() }
+    }
+
     /// Creates a new InvalidContentLength error
     pub fn invalid_content_length<S: ToString>(str: S) -> Self {
         Self::InvalidContentLength { reason: str.to_string() }
     }
 
+    /// Creates a new TooLargeBody error
+    pub fn too_large_body(current_size: u64, max_size: u64) -> Self {
+        Self::TooLargeBody { current_size, max_size }
+    }
+
+    /// Creates a new ReadTimeout error
+    pub fn read_timeout(timeout: Duration) -> Self {
+        Self::ReadTimeout { timeout }
+    }
+
     /// Creates a new I/O error
     pub fn io<E: Into<io::Error>>(e: E) -> Self {
         Self::Io { source: e.into() }
     }
 }
 
+/// Converts to an I/O error, for APIs such as `AsyncRead` that can only return one.
+///
+/// I/O errors are unwrapped, a read timeout becomes [`TimedOut`](io::ErrorKind::TimedOut) and the
+/// other errors [`InvalidData`](io::ErrorKind::InvalidData), with the parse error as their source.
+impl From<ParseError> for io::Error {
+    fn from(e: ParseError) -> Self {
+        match e {
+            ParseError::Io { source } => source,
+            e @ ParseError::ReadTimeout { .. } => io::Error::new(io::ErrorKind::TimedOut, e),
+            e => io::Error::new(io::ErrorKind::InvalidData, e),
+        }
+    }
+}
+
 /// Errors that occur during HTTP response generation and sending
 /// 
 /// This enum represents error conditions that can occur while generating
 /// and sending HTTP responses.
+///
+/// It means the response is broken, so it's marked `#[must_use]` to never be dropped silently.
 #[derive(Error, Debug)]
+#[must_use]
 pub enum SendError {
     /// Invalid response body
     #[error("invalid body: {reason}")]
@@ -142,4 +190,67 @@ impl SendError {
     pub fn io<E: Into<io::Error>>(e: E) -> Self {
         Self::Io { source: e.into() }
     }
+
+    /// Returns the underlying I/O error, if the response failed because of one
+    pub fn into_io_error(self) -> Option<io::Error> {
+        match self {
+            Self::Io { source } => Some(source),
+            Self::InvalidBody { .. } => None,
+        }
+    }
+}
+
+/// Converts the error that happened while reading the data to send, such as a forwarded request
+/// body, wrapping the same I/O error as the `io::Error` conversion.
+impl From<ParseError> for SendError {
+    fn from(e: ParseError) -> Self {
+        Self::Io { source: e.into() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error as _;
+
+    #[test]
+    fn test_send_error_source() {
+        let error = SendError::io(io::Error::new(io::ErrorKind::BrokenPipe, "peer gone"));
+        let source = error.source().and_then(|e| e.downcast_ref::<io::Error>()).unwrap();
+        assert_eq!(source.kind(), io::ErrorKind::BrokenPipe);
+        assert_eq!(error.into_io_error().unwrap().kind(), io::ErrorKind::BrokenPipe);
+
+        let error = SendError::invalid_body("broken");
+        assert!(error.source().is_none());
+        assert!(error.into_io_error().is_none());
+    }
+
+    #[test]
+    fn test_http_error_source_chain() {
+        let error = HttpError::from(SendError::io(io::Error::new(io::ErrorKind::BrokenPipe, "peer gone")));
+        let send_error = error.source().and_then(|e| e.downcast_ref::<SendError>()).unwrap();
+        assert!(matches!(send_error, SendError::Io { .. }));
+
+        let io_error = send_error.source().and_then(|e| e.downcast_ref::<io::Error>()).unwrap();
+        assert_eq!(io_error.kind(), io::ErrorKind::BrokenPipe);
+        assert_eq!(io_error.to_string(), "peer gone");
+    }
+
+    #[test]
+    fn test_parse_error_into_io_error() {
+        let error = io::Error::from(ParseError::io(io::Error::new(io::ErrorKind::ConnectionReset, "reset")));
+        assert_eq!(error.kind(), io::ErrorKind::ConnectionReset);
+        assert_eq!(error.to_string(), "reset");
+
+        let error = io::Error::from(ParseError::read_timeout(Duration::from_secs(1)));
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+
+        let error = io::Error::from(ParseError::too_many_headers(64));
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        let source = error.get_ref().and_then(|e| e.downcast_ref::<ParseError>()).unwrap();
+        assert!(matches!(source, ParseError::TooManyHeaders { max_num: 64 }));
+
+        let error = SendError::from(ParseError::invalid_body("broken"));
+        assert_eq!(error.into_io_error().unwrap().kind(), io::ErrorKind::InvalidData);
+    }
 }
@@ -0,0 +1,124 @@
+//! Formatting of header maps for logs.
+//!
+//! [`HeadersDisplay`] writes one `name: value` line per header value, with the values of the headers
+//! carrying credentials replaced by `<redacted>`:
+//!
+//! ```
+//! use http::HeaderMap;
+//! use micro_http::protocol::HeadersDisplay;
+//!
+//! let mut headers = HeaderMap::new();
+//! headers.insert("host", "example.com".parse().unwrap());
+//! headers.insert("authorization", "Bearer secret".parse().unwrap());
+//!
+//! let display = HeadersDisplay::new(&headers).to_string();
+//! assert_eq!(display, "host: example.com\nauthorization: <redacted>\n");
+//! ```
+//!
+//! With the `serde` feature, it also serializes into a map from each header name to its values.
+
+use http::header::{AUTHORIZATION, COOKIE, PROXY_AUTHORIZATION, SET_COOKIE};
+use http::{HeaderMap, HeaderName, HeaderValue};
+use std::borrow::Cow;
+use std::fmt;
+
+/// The headers whose values are redacted by default
+const SENSITIVE_HEADERS: [HeaderName; 4] = [AUTHORIZATION, PROXY_AUTHORIZATION, COOKIE, SET_COOKIE];
+
+const REDACTED: &str = "<redacted>";
+
+/// Displays the headers of a [`HeaderMap`], see the [module documentation](self).
+#[derive(Debug, Clone, Copy)]
+pub struct HeadersDisplay<'a> {
+    headers: &'a HeaderMap,
+    redact: bool,
+}
+
+impl<'a> HeadersDisplay<'a> {
+    /// Displays `headers`, redacting `Authorization`, `Proxy-Authorization`, `Cookie` and `Set-Cookie`.
+    pub fn new(headers: &'a HeaderMap) -> Self {
+        Self { headers, redact: true }
+    }
+
+    /// Sets whether the values of the sensitive headers are redacted, to debug them.
+    pub fn with_sensitive_redaction(mut self, redact: bool) -> Self {
+        self.redact = redact;
+        self
+    }
+
+    /// Returns the value to display, non UTF-8 bytes are replaced.
+    fn value(&self, name: &HeaderName, value: &'a HeaderValue) -> Cow<'a, str> {
+        if self.redact && SENSITIVE_HEADERS.contains(name) {
+            Cow::Borrowed(REDACTED)
+        } else {
+            String::from_utf8_lossy(value.as_bytes())
+        }
+    }
+}
+
+impl fmt::Display for HeadersDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, value) in self.headers {
+            writeln!(f, "{}: {}", name, self.value(name, value))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for HeadersDisplay<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+
+        let mut map = serializer.serialize_map(Some(self.headers.keys_len()))?;
+        for name in self.headers.keys() {
+            let values = self.headers.get_all(name).iter().map(|value| self.value(name, value)).collect::<Vec<_>>();
+            map.serialize_entry(name.as_str(), &values)?;
+        }
+        map.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("host", HeaderValue::from_static("example.com"));
+        headers.append("accept", HeaderValue::from_static("text/html"));
+        headers.append("accept", HeaderValue::from_static("application/json"));
+        headers.insert("cookie", HeaderValue::from_static("session=secret"));
+        headers.insert("x-binary", HeaderValue::from_bytes(b"caf\xe9").unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_display() {
+        let headers = headers();
+        assert_eq!(
+            HeadersDisplay::new(&headers).to_string(),
+            "host: example.com\naccept: text/html\naccept: application/json\ncookie: <redacted>\nx-binary: caf\u{FFFD}\n"
+        );
+
+        let display = HeadersDisplay::new(&headers).with_sensitive_redaction(false).to_string();
+        assert!(display.contains("cookie: session=secret\n"), "{display}");
+        assert_eq!(HeadersDisplay::new(&HeaderMap::new()).to_string(), "");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serialize() {
+        let headers = headers();
+        let value = serde_json::to_value(HeadersDisplay::new(&headers)).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "host": ["example.com"],
+                "accept": ["text/html", "application/json"],
+                "cookie": ["<redacted>"],
+                "x-binary": ["caf\u{FFFD}"],
+            })
+        );
+    }
+}
@@ -1,4 +1,5 @@
 use bytes::{Buf, Bytes};
+use http::HeaderMap;
 
 /// Represents a HTTP message that can either be a header or payload.
 /// 
@@ -16,10 +17,15 @@ pub enum Message<T, Data: Buf = Bytes> {
 /// 
 /// This enum is used by the payload decoder to produce either data chunks
 /// or signal the end of the payload stream (EOF).
+///
+/// A chunked payload may end with trailer fields, they are sent as a [`Trailer`](PayloadItem::Trailer)
+/// item right before the EOF. The other payloads have no trailer section, so they ignore them.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum PayloadItem<Data: Buf = Bytes> {
     /// A chunk of payload data
     Chunk(Data),
+    /// The trailer fields sent after the last chunk
+    Trailer(HeaderMap),
     /// Marks the end of the payload stream
     Eof,
 }
@@ -100,36 +106,50 @@ impl<D: Buf> PayloadItem<D> {
     pub fn is_chunk(&self) -> bool {
         matches!(self, PayloadItem::Chunk(_))
     }
+
+    /// Returns true if this item contains trailer fields
+    #[inline]
+    pub fn is_trailer(&self) -> bool {
+        matches!(self, PayloadItem::Trailer(_))
+    }
 }
 
 impl PayloadItem {
     /// Returns a reference to the contained bytes if this is a Chunk
     /// 
-    /// Returns None if this is an EOF marker
+    /// Returns None if this is an EOF marker or trailer fields
     pub fn as_bytes(&self) -> Option<&Bytes> {
         match self {
             PayloadItem::Chunk(bytes) => Some(bytes),
-            PayloadItem::Eof => None,
+            PayloadItem::Trailer(_) | PayloadItem::Eof => None,
         }
     }
 
     /// Returns a mutable reference to the contained bytes if this is a Chunk
     /// 
-    /// Returns None if this is an EOF marker
+    /// Returns None if this is an EOF marker or trailer fields
     pub fn as_mut_bytes(&mut self) -> Option<&mut Bytes> {
         match self {
             PayloadItem::Chunk(bytes) => Some(bytes),
-            PayloadItem::Eof => None,
+            PayloadItem::Trailer(_) | PayloadItem::Eof => None,
         }
     }
 
     /// Consumes the PayloadItem and returns the contained bytes if this is a Chunk
     /// 
-    /// Returns None if this is an EOF marker
+    /// Returns None if this is an EOF marker or trailer fields
     pub fn into_bytes(self) -> Option<Bytes> {
         match self {
             PayloadItem::Chunk(bytes) => Some(bytes),
-            PayloadItem::Eof => None,
+            PayloadItem::Trailer(_) | PayloadItem::Eof => None,
+        }
+    }
+
+    /// Consumes the PayloadItem and returns the contained fields if this is a Trailer
+    pub fn into_trailers(self) -> Option<HeaderMap> {
+        match self {
+            PayloadItem::Trailer(trailers) => Some(trailers),
+            PayloadItem::Chunk(_) | PayloadItem::Eof => None,
         }
     }
 }
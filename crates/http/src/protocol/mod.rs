@@ -16,6 +16,7 @@
 //!
 //! - **Request Processing** ([`request`]): Request header handling
 //!   - [`RequestHeader`]: Wraps HTTP request headers with additional functionality
+//!   - [`RequestHead`]: Type alias for outgoing request headers before body attachment
 //!
 //! - **Response Processing** ([`response`]): Response header handling
 //!   - [`ResponseHead`]: Type alias for response headers before body attachment
@@ -61,6 +62,7 @@ pub use message::PayloadItem;
 pub use message::PayloadSize;
 
 mod request;
+pub use request::RequestHead;
 pub use request::RequestHeader;
 
 mod response;
@@ -71,4 +73,7 @@ pub use error::HttpError;
 pub use error::ParseError;
 pub use error::SendError;
 
+mod headers_display;
+pub use headers_display::HeadersDisplay;
+
 pub mod body;
@@ -79,6 +79,12 @@ impl RequestHeader {
     }
 }
 
+/// Type alias for the head of an outgoing HTTP request.
+///
+/// Unlike [`RequestHeader`], which wraps the head of a received request, this is the
+/// `http::Request<()>` built by a client, before its body is attached.
+pub type RequestHead = Request<()>;
+
 /// Converts request parts into a RequestHeader.
 impl From<Parts> for RequestHeader {
     #[inline]
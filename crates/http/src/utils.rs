@@ -3,6 +3,9 @@
 //! This module provides helper macros and functions that are used internally
 //! by the HTTP crate implementation.
 
+use http::header::CONNECTION;
+use http::HeaderMap;
+
 /// A macro for early returns with an error if a condition is not met.
 /// 
 /// This is similar to the `assert!` macro, but returns an error instead of panicking.
@@ -24,3 +27,13 @@ macro_rules! ensure {
 }
 
 pub(crate) use ensure;
+
+/// Returns true if the `Connection` header has `option`, such as `keep-alive` or `close`.
+pub(crate) fn has_connection_option(headers: &HeaderMap, option: &str) -> bool {
+    headers
+        .get_all(CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|value| value.trim().eq_ignore_ascii_case(option))
+}
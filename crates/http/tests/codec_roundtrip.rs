@@ -0,0 +1,197 @@
+//! Round trips of random responses through the `ResponseEncoder` and the `ResponseDecoder`.
+//!
+//! Each case encodes a few pipelined responses into one buffer, then decodes them back from reads
+//! of random sizes. The responses have fixed-length, chunked or empty bodies, made of random chunks,
+//! and some of them are larger than 1 MB. The cases are generated from fixed seeds, so a failure
+//! is reproduced by running the test again, its seed is in the panic message.
+
+use bytes::{Bytes, BytesMut};
+use http::header::{CONTENT_LENGTH, TRANSFER_ENCODING};
+use http::{HeaderMap, HeaderName, HeaderValue, Response, StatusCode, Version};
+use micro_http::codec::{ResponseDecoder, ResponseEncoder};
+use micro_http::protocol::{Message, PayloadItem, PayloadSize, ResponseHead};
+use tokio_util::codec::{Decoder, Encoder};
+
+const CASES: u64 = 256;
+
+const STATUSES: &[u16] = &[200, 201, 206, 301, 400, 404, 500, 503];
+
+/// A small splitmix64 generator, the cases only need to be spread and reproducible.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a number in `0..n`.
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn bytes(&mut self, len: usize) -> Bytes {
+        let mut bytes = Vec::with_capacity(len + 8);
+        while bytes.len() < len {
+            bytes.extend_from_slice(&self.next().to_le_bytes());
+        }
+        bytes.truncate(len);
+        bytes.into()
+    }
+}
+
+struct Case {
+    head: ResponseHead,
+    payload_size: PayloadSize,
+    chunks: Vec<Bytes>,
+    trailers: Option<HeaderMap>,
+}
+
+impl Case {
+    fn generate(rng: &mut Rng) -> Self {
+        let mut head = Response::new(());
+        *head.status_mut() = StatusCode::from_u16(STATUSES[rng.below(STATUSES.len())]).unwrap();
+        for i in 0..rng.below(8) {
+            let len = rng.below(32);
+            let value = (0..len).map(|_| (b'a' + rng.below(26) as u8) as char).collect::<String>();
+            head.headers_mut()
+                .append(format!("x-header-{}", i % 3).parse::<HeaderName>().unwrap(), value.parse().unwrap());
+        }
+
+        let mut chunks = vec![];
+        for _ in 0..rng.below(8) {
+            let len = rng.below(2049);
+            chunks.push(rng.bytes(len));
+        }
+        // one body in 32 is larger than 1 MB
+        if rng.below(32) == 0 {
+            let index = rng.below(chunks.len() + 1);
+            chunks.insert(index, rng.bytes(1024 * 1024 + 1));
+        }
+
+        let mut trailers = None;
+        let payload_size = match rng.below(3) {
+            0 => {
+                chunks.clear();
+                PayloadSize::Empty
+            }
+            1 => {
+                // HTTP/1.0 has no chunked encoding, only the fixed-length bodies are sent with it
+                if rng.below(4) == 0 {
+                    *head.version_mut() = Version::HTTP_10;
+                }
+                PayloadSize::Length(chunks.iter().map(|chunk| chunk.len() as u64).sum())
+            }
+            _ => {
+                if rng.below(4) == 0 {
+                    let mut map = HeaderMap::new();
+                    map.insert("x-checksum", HeaderValue::from(rng.next()));
+                    trailers = Some(map);
+                }
+                PayloadSize::Chunked
+            }
+        };
+
+        Self { head, payload_size, chunks, trailers }
+    }
+
+    fn encode(&self, encoder: &mut ResponseEncoder, dst: &mut BytesMut) {
+        encoder.encode(Message::<_, Bytes>::Header((self.head.clone(), self.payload_size)), dst).unwrap();
+        for chunk in &self.chunks {
+            encoder
+                .encode(Message::<(ResponseHead, PayloadSize), _>::Payload(PayloadItem::Chunk(chunk.clone())), dst)
+                .unwrap();
+        }
+        if let Some(trailers) = &self.trailers {
+            let trailer = PayloadItem::<Bytes>::Trailer(trailers.clone());
+            encoder.encode(Message::<(ResponseHead, PayloadSize), _>::Payload(trailer), dst).unwrap();
+        }
+        encoder.encode(Message::<(ResponseHead, PayloadSize), _>::Payload(PayloadItem::<Bytes>::Eof), dst).unwrap();
+    }
+
+    fn check(&self, decoded: &Decoded, seed: u64) {
+        assert_eq!(decoded.head.status(), self.head.status(), "seed {seed}");
+        assert_eq!(decoded.head.version(), self.head.version(), "seed {seed}");
+
+        let mut headers = decoded.head.headers().clone();
+        let framing = match self.payload_size {
+            PayloadSize::Length(length) => headers.remove(CONTENT_LENGTH) == Some(length.into()),
+            PayloadSize::Chunked => headers.remove(TRANSFER_ENCODING) == Some(HeaderValue::from_static("chunked")),
+            PayloadSize::Empty => headers.remove(CONTENT_LENGTH) == Some(0.into()),
+        };
+        assert!(framing, "seed {seed}: wrong framing header for {:?}", self.payload_size);
+        assert_eq!(&headers, self.head.headers(), "seed {seed}");
+
+        // not compared with assert_eq, the bodies can be too large to print
+        let body = self.chunks.concat();
+        assert!(decoded.body == body, "seed {seed}: decoded {} bytes instead of {}", decoded.body.len(), body.len());
+        assert_eq!(decoded.trailers, self.trailers, "seed {seed}");
+    }
+}
+
+struct Decoded {
+    head: ResponseHead,
+    body: Vec<u8>,
+    trailers: Option<HeaderMap>,
+}
+
+/// Decodes the responses of `encoded`, the bytes are moved into the decoder's buffer in pieces of
+/// random sizes, as they would come from the network.
+fn decode_all(encoded: Bytes, rng: &mut Rng, seed: u64) -> Vec<Decoded> {
+    let mut decoder = ResponseDecoder::new();
+    let mut buf = BytesMut::new();
+    let mut offset = 0;
+    let mut responses: Vec<Decoded> = vec![];
+    let mut complete = true;
+
+    loop {
+        match decoder.decode(&mut buf).unwrap_or_else(|e| panic!("seed {seed}: {e}")) {
+            Some(Message::Header(head)) => {
+                assert!(complete, "seed {seed}: a response head is decoded before the end of the previous body");
+                complete = false;
+                responses.push(Decoded { head, body: vec![], trailers: None });
+            }
+            Some(Message::Payload(item)) => {
+                let current = responses.last_mut().expect("a payload is decoded before the head");
+                match item {
+                    PayloadItem::Chunk(bytes) => current.body.extend_from_slice(&bytes),
+                    PayloadItem::Trailer(trailers) => current.trailers = Some(trailers),
+                    PayloadItem::Eof => complete = true,
+                }
+            }
+            None if offset == encoded.len() => {
+                assert!(complete, "seed {seed}: the last response is incomplete");
+                assert!(buf.is_empty(), "seed {seed}: {} bytes left after the last response", buf.len());
+                return responses;
+            }
+            None => {
+                let read = (1 + rng.below(64 * 1024)).min(encoded.len() - offset);
+                buf.extend_from_slice(&encoded[offset..offset + read]);
+                offset += read;
+            }
+        }
+    }
+}
+
+#[test]
+fn test_response_round_trip() {
+    for seed in 0..CASES {
+        let mut rng = Rng(seed);
+        let cases = (0..1 + rng.below(4)).map(|_| Case::generate(&mut rng)).collect::<Vec<_>>();
+
+        let mut encoder = ResponseEncoder::new();
+        let mut encoded = BytesMut::new();
+        for case in &cases {
+            case.encode(&mut encoder, &mut encoded);
+        }
+
+        let decoded = decode_all(encoded.freeze(), &mut rng, seed);
+        assert_eq!(decoded.len(), cases.len(), "seed {seed}");
+        for (case, decoded) in cases.iter().zip(&decoded) {
+            case.check(decoded, seed);
+        }
+    }
+}
@@ -0,0 +1,94 @@
+//! Hooks to prepare accepted connections before HTTP is served on them.
+//!
+//! An [`Acceptor`] receives every TCP connection accepted by the [`Server`](crate::Server) and returns
+//! the stream requests are read from, which is how TLS is plugged in. The handshake runs in the task of
+//! the connection, so a slow client doesn't hold up the accept loop.
+//!
+//! TLS itself isn't built in: an acceptor doing the handshake, e.g. with `tokio-rustls`, returns the
+//! decrypted stream and marks the connection [`Secure`]. Values attached to the [`Accepted`]
+//! connection, such as the certificate of the peer, are added to the extensions of every request it
+//! carries:
+//!
+//! ```
+//! use async_trait::async_trait;
+//! use micro_web::acceptor::{Accepted, Acceptor, Secure};
+//! use std::io;
+//! use std::net::SocketAddr;
+//! use tokio::net::TcpStream;
+//!
+//! #[derive(Clone)]
+//! struct PeerAddr(SocketAddr);
+//!
+//! /// Trusts the TLS terminating proxy running on the same host.
+//! struct LoopbackAcceptor;
+//!
+//! #[async_trait]
+//! impl Acceptor for LoopbackAcceptor {
+//!     async fn accept(&self, stream: TcpStream, remote_addr: SocketAddr) -> io::Result<Accepted> {
+//!         let accepted = Accepted::new(stream).with_extension(PeerAddr(remote_addr));
+//!         Ok(match remote_addr.ip().is_loopback() {
+//!             true => accepted.with_extension(Secure),
+//!             false => accepted,
+//!         })
+//!     }
+//! }
+//!
+//! let builder = micro_web::Server::builder().bind("127.0.0.1:0").acceptor(LoopbackAcceptor);
+//! ```
+
+use async_trait::async_trait;
+use http::Extensions;
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+
+/// A bidirectional stream HTTP can be served on.
+pub trait IoStream: AsyncRead + AsyncWrite + Send + Unpin + 'static {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin + 'static> IoStream for T {}
+
+/// A connection prepared by an [`Acceptor`].
+pub struct Accepted {
+    stream: Box<dyn IoStream>,
+    extensions: Extensions,
+}
+
+impl Accepted {
+    /// Serves HTTP on `stream`.
+    pub fn new(stream: impl IoStream) -> Self {
+        Self { stream: Box::new(stream), extensions: Extensions::new() }
+    }
+
+    /// Adds `value` to the extensions of every request of the connection.
+    pub fn with_extension<T: Clone + Send + Sync + 'static>(mut self, value: T) -> Self {
+        self.extensions.insert(value);
+        self
+    }
+
+    pub(crate) fn into_parts(self) -> (Box<dyn IoStream>, Extensions) {
+        (self.stream, self.extensions)
+    }
+}
+
+impl fmt::Debug for Accepted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Accepted").field("extensions", &self.extensions).finish_non_exhaustive()
+    }
+}
+
+/// Marks a connection as secured by the [`Acceptor`], e.g. with TLS.
+///
+/// An acceptor adds it with [`Accepted::with_extension`], the requests of the connections with it are
+/// [secure](crate::RequestContext::is_secure). The others are redirected to HTTPS by the
+/// [`HttpsRedirectWrapper`](crate::wrapper::HttpsRedirectWrapper).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Secure;
+
+/// Prepares the accepted TCP connections of a server, see the [module documentation](self).
+#[async_trait]
+pub trait Acceptor: Send + Sync {
+    /// Prepares the connection from `remote_addr`, an error closes it.
+    async fn accept(&self, stream: TcpStream, remote_addr: SocketAddr) -> io::Result<Accepted>;
+}
@@ -1,22 +1,36 @@
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
 use http_body::Body as HttpBody;
 use http_body::{Frame, SizeHint};
-use http_body_util::combinators::UnsyncBoxBody;
+use http_body_util::combinators::{BoxBody, UnsyncBoxBody};
+use http_body_util::{BodyExt, Empty};
 use micro_http::protocol::body::ReqBody;
-use micro_http::protocol::{HttpError, ParseError};
+use futures::Stream;
+use micro_http::protocol::{HttpError, ParseError, SendError};
+use pin_project_lite::pin_project;
 use std::future::Future;
+use std::io;
+use std::path::Path;
 use std::pin::Pin;
 use std::sync::Arc;
-use std::task::{Context, Poll};
+use std::task::{ready, Context, Poll};
+use thiserror::Error;
+use tokio::fs::File;
 use tokio::sync::Mutex;
+use tokio_util::io::ReaderStream;
 
 #[derive(Clone)]
 pub struct OptionReqBody {
-    inner: Arc<Mutex<Option<ReqBody>>>,
+    inner: Arc<Mutex<Option<RequestBody>>>,
 }
 
 impl From<ReqBody> for OptionReqBody {
     fn from(body: ReqBody) -> Self {
+        RequestBody::from(body).into()
+    }
+}
+
+impl From<RequestBody> for OptionReqBody {
+    fn from(body: RequestBody) -> Self {
         OptionReqBody { inner: Arc::new(Mutex::new(Some(body))) }
     }
 }
@@ -29,7 +43,7 @@ impl OptionReqBody {
 
     pub async fn apply<T, F, Fut>(&self, f: F) -> Fut::Output
     where
-        F: FnOnce(ReqBody) -> Fut,
+        F: FnOnce(RequestBody) -> Fut,
         Fut: Future<Output = Result<T, ParseError>>,
     {
         let mut guard = self.inner.lock().await;
@@ -41,10 +55,106 @@ impl OptionReqBody {
 
         f(req_body).await
     }
+
+    /// Takes the body out without waiting, returns `None` if it has been consumed or is being used.
+    pub(crate) fn try_take(&self) -> Option<RequestBody> {
+        self.inner.try_lock().ok().and_then(|mut guard| guard.take())
+    }
+
+    /// Replaces the body with the result of `f`, e.g. to wrap it in a decoder.
+    ///
+    /// Returns an error if the body has already been consumed.
+    pub async fn map<F>(&self, f: F) -> Result<(), ParseError>
+    where
+        F: FnOnce(RequestBody) -> RequestBody,
+    {
+        let mut guard = self.inner.lock().await;
+        match (*guard).take() {
+            Some(req_body) => {
+                *guard = Some(f(req_body));
+                Ok(())
+            }
+            None => Err(ParseError::invalid_body("body has been consumed")),
+        }
+    }
+}
+
+/// The request body handed to handlers and extractors.
+///
+/// It is either the raw [`ReqBody`] read from the connection, or a boxed body that
+/// transforms it, such as a decompressed stream.
+pub struct RequestBody {
+    inner: RequestBodyKind,
+}
+
+enum RequestBodyKind {
+    Raw(ReqBody),
+    Boxed(UnsyncBoxBody<Bytes, ParseError>),
+}
+
+impl RequestBody {
+    pub fn empty() -> Self {
+        Self::boxed(Empty::new().map_err(|never| match never {}))
+    }
+
+    /// Wraps any body yielding `Bytes`, such as a body built by a wrapper around the body of the request.
+    pub fn boxed<B>(body: B) -> Self
+    where
+        B: HttpBody<Data = Bytes, Error = ParseError> + Send + 'static,
+    {
+        Self { inner: RequestBodyKind::Boxed(UnsyncBoxBody::new(body)) }
+    }
+}
+
+impl From<ReqBody> for RequestBody {
+    fn from(body: ReqBody) -> Self {
+        Self { inner: RequestBodyKind::Raw(body) }
+    }
+}
+
+impl HttpBody for RequestBody {
+    type Data = Bytes;
+    type Error = ParseError;
+
+    fn poll_frame(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        match &mut self.get_mut().inner {
+            RequestBodyKind::Raw(req_body) => Pin::new(req_body).poll_frame(cx),
+            RequestBodyKind::Boxed(box_body) => Pin::new(box_body).poll_frame(cx),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match &self.inner {
+            RequestBodyKind::Raw(req_body) => req_body.is_end_stream(),
+            RequestBodyKind::Boxed(box_body) => box_body.is_end_stream(),
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match &self.inner {
+            RequestBodyKind::Raw(req_body) => req_body.size_hint(),
+            RequestBodyKind::Boxed(box_body) => box_body.size_hint(),
+        }
+    }
+}
+
+/// Errors of [`ResponseBody::collect_limited`].
+#[derive(Error, Debug)]
+pub enum CollectError {
+    /// The body is larger than the limit
+    #[error("body exceeds the limit of {limit} bytes")]
+    TooLarge { limit: usize },
+
+    /// The body could not be read
+    #[error("failed to read the body: {0}")]
+    Io(#[from] io::Error),
 }
 
 pub struct ResponseBody {
     inner: Kind,
+    /// Whether the body was left empty by [`take`](Self::take), waiting for [`replace`](Self::replace)
+    #[cfg(debug_assertions)]
+    taken: bool,
 }
 
 enum Kind {
@@ -53,41 +163,169 @@ enum Kind {
 }
 
 impl ResponseBody {
+    fn new(inner: Kind) -> Self {
+        Self {
+            inner,
+            #[cfg(debug_assertions)]
+            taken: false,
+        }
+    }
+
     pub fn empty() -> Self {
-        Self { inner: Kind::Once(None) }
+        Self::new(Kind::Once(None))
     }
 
     pub fn once(bytes: Bytes) -> Self {
-        Self { inner: Kind::Once(Some(bytes)) }
+        Self::new(Kind::Once(Some(bytes)))
     }
 
     pub fn stream<B>(body: B) -> Self
     where
         B: HttpBody<Data = Bytes, Error = HttpError> + Send + 'static,
     {
-        Self { inner: Kind::Stream(UnsyncBoxBody::new(body)) }
+        Self::new(Kind::Stream(UnsyncBoxBody::new(body)))
     }
 
+    /// Creates a streaming body of `size` bytes, so the response is sent with a `Content-Length` instead
+    /// of chunked.
+    ///
+    /// `body` must yield exactly `size` bytes, sending the response fails otherwise.
+    pub fn stream_with_size<B>(body: B, size: u64) -> Self
+    where
+        B: HttpBody<Data = Bytes, Error = HttpError> + Send + 'static,
+    {
+        Self::stream(SizedBody { body: UnsyncBoxBody::new(body), remaining: size })
+    }
+
+    /// Creates a body streaming `file`, sent with a `Content-Length` of `size`.
+    ///
+    /// Reading the body fails if the file turns out to be shorter or longer than `size`, e.g. when it's
+    /// changed while being sent. The file is closed when the body is dropped, read to the end or not.
+    pub fn from_file(file: File, size: u64) -> Self {
+        Self::stream(FileBody { reader: ReaderStream::new(file), remaining: size })
+    }
+
+    /// Opens the file at `path` and creates a body streaming it, see [`from_file`](Self::from_file).
+    pub async fn from_path(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = File::open(path).await?;
+        let size = file.metadata().await?.len();
+        Ok(Self::from_file(file, size))
+    }
+
+    /// Passes each chunk of data through `f`, e.g. to inject a nonce into an HTML page.
+    ///
+    /// `f` may change the length of the data, so the size of a streaming body becomes unknown, see
+    /// [`map_data_same_size`](Self::map_data_same_size) to keep it.
+    pub fn map_data<F>(self, f: F) -> Self
+    where
+        F: Fn(Bytes) -> Bytes + Send + 'static,
+    {
+        self.map_data_inner(f, false)
+    }
+
+    /// Like [`map_data`](Self::map_data), for an `f` that never changes the length of the data, so the
+    /// size of the body is kept.
+    pub fn map_data_same_size<F>(self, f: F) -> Self
+    where
+        F: Fn(Bytes) -> Bytes + Send + 'static,
+    {
+        self.map_data_inner(f, true)
+    }
+
+    fn map_data_inner<F>(self, f: F, same_size: bool) -> Self
+    where
+        F: Fn(Bytes) -> Bytes + Send + 'static,
+    {
+        match self.inner {
+            // a single chunk is mapped right away, its size is exact either way
+            Kind::Once(Some(bytes)) => Self::once(f(bytes)),
+            Kind::Once(None) => self,
+            Kind::Stream(body) => Self::stream(MappedBody { body, f, same_size }),
+        }
+    }
+
+    /// Returns true if the body is known to have no data, without polling it.
+    ///
+    /// The check is conservative: a body that may still yield data, such as a stream that hasn't been
+    /// polled yet, is not empty even if it turns out to end without data. A false positive would drop the
+    /// data of the body where it's skipped as empty, e.g. when choosing to encode it.
     pub fn is_empty(&self) -> bool {
         match &self.inner {
-            Kind::Once(None) => false,
+            Kind::Once(None) => true,
             Kind::Once(Some(bytes)) => bytes.is_empty(),
             Kind::Stream(body) => body.is_end_stream(),
         }
     }
 
+    /// Reads the whole body into a single buffer, failing with [`CollectError::TooLarge`] as soon as it
+    /// would exceed `max_bytes`, trailers are discarded.
+    ///
+    /// The buffer is allocated up front from the lower bound of the size hint.
+    pub async fn collect_limited(self, max_bytes: usize) -> Result<Bytes, CollectError> {
+        let mut body = match self.inner {
+            Kind::Once(None) => return Ok(Bytes::new()),
+            Kind::Once(Some(bytes)) if bytes.len() <= max_bytes => return Ok(bytes),
+            Kind::Once(Some(_)) => return Err(CollectError::TooLarge { limit: max_bytes }),
+            Kind::Stream(body) => body,
+        };
+
+        let lower = body.size_hint().lower();
+        if lower > max_bytes as u64 {
+            return Err(CollectError::TooLarge { limit: max_bytes });
+        }
+
+        let mut buf = BytesMut::with_capacity(lower as usize);
+        while let Some(frame) = body.frame().await {
+            let frame = frame.map_err(io::Error::other)?;
+            if let Ok(data) = frame.into_data() {
+                if buf.len() + data.len() > max_bytes {
+                    return Err(CollectError::TooLarge { limit: max_bytes });
+                }
+                buf.extend_from_slice(&data);
+            }
+        }
+        Ok(buf.freeze())
+    }
+
+    /// Takes the body out, leaving an empty body to be [`replace`](Self::replace)d.
+    ///
+    /// # Panics
+    /// In debug builds, if the body was already taken and not replaced since.
     pub fn take(&mut self) -> Self {
-        self.replace(ResponseBody::empty())
+        #[cfg(debug_assertions)]
+        assert!(!self.taken, "response body taken twice without being replaced in between");
+
+        let body = self.swap(ResponseBody::empty());
+        #[cfg(debug_assertions)]
+        {
+            self.taken = true;
+        }
+        body
     }
 
+    /// Puts `body` in place of the body left by [`take`](Self::take), returns the empty body left.
+    ///
+    /// # Panics
+    /// In debug builds, if the body wasn't taken before, see [`swap`](Self::swap) to replace any body.
     pub fn replace(&mut self, body: Self) -> Self {
-        std::mem::replace(self, body)
+        #[cfg(debug_assertions)]
+        assert!(self.taken, "response body replaced without being taken first");
+
+        self.swap(body)
+    }
+
+    /// Puts `body` in place of this body, and returns this body.
+    pub fn swap(&mut self, body: Self) -> Self {
+        let old = std::mem::replace(self, body);
+        #[cfg(debug_assertions)]
+        let old = Self { taken: false, ..old };
+        old
     }
 }
 
 impl From<String> for ResponseBody {
     fn from(value: String) -> Self {
-        ResponseBody { inner: Kind::Once(Some(Bytes::from(value))) }
+        ResponseBody::once(Bytes::from(value))
     }
 }
 
@@ -97,6 +335,12 @@ impl From<()> for ResponseBody {
     }
 }
 
+impl From<Bytes> for ResponseBody {
+    fn from(bytes: Bytes) -> Self {
+        Self::once(bytes)
+    }
+}
+
 impl From<Option<Bytes>> for ResponseBody {
     fn from(option: Option<Bytes>) -> Self {
         match option {
@@ -150,15 +394,162 @@ impl HttpBody for ResponseBody {
     }
 }
 
+/// A response body that is `Send` and `Sync`, unlike [`ResponseBody`].
+///
+/// [`ResponseBody`] accepts any `Send` stream, so it can't be `Sync`. This body can be kept where
+/// `Sync` is needed, such as in a value shared behind an `Arc`, and turned into a [`ResponseBody`]
+/// to be sent.
+pub struct SyncResponseBody {
+    inner: BoxBody<Bytes, HttpError>,
+}
+
+impl SyncResponseBody {
+    pub fn new<B>(body: B) -> Self
+    where
+        B: HttpBody<Data = Bytes, Error = HttpError> + Send + Sync + 'static,
+    {
+        Self { inner: BoxBody::new(body) }
+    }
+}
+
+impl From<SyncResponseBody> for ResponseBody {
+    fn from(body: SyncResponseBody) -> Self {
+        ResponseBody::stream(body)
+    }
+}
+
+impl HttpBody for SyncResponseBody {
+    type Data = Bytes;
+    type Error = HttpError;
+
+    fn poll_frame(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        Pin::new(&mut self.get_mut().inner).poll_frame(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// A body of known size, counting down the bytes left to read.
+struct SizedBody {
+    body: UnsyncBoxBody<Bytes, HttpError>,
+    remaining: u64,
+}
+
+impl HttpBody for SizedBody {
+    type Data = Bytes;
+    type Error = HttpError;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let frame = ready!(Pin::new(&mut self.body).poll_frame(cx));
+        if let Some(data) = frame.as_ref().and_then(|frame| frame.as_ref().ok()).and_then(Frame::data_ref) {
+            self.remaining = self.remaining.saturating_sub(data.len() as u64);
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        SizeHint::with_exact(self.remaining)
+    }
+}
+
+pin_project! {
+    /// A body streaming a file of a known size, see [`ResponseBody::from_file`].
+    struct FileBody {
+        #[pin]
+        reader: ReaderStream<File>,
+        remaining: u64,
+    }
+}
+
+impl HttpBody for FileBody {
+    type Data = Bytes;
+    type Error = HttpError;
+
+    fn poll_frame(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        match ready!(this.reader.poll_next(cx)) {
+            Some(Ok(data)) if data.len() as u64 > *this.remaining => {
+                let e = io::Error::new(io::ErrorKind::InvalidData, "file grew while being sent");
+                Poll::Ready(Some(Err(SendError::io(e).into())))
+            }
+            Some(Ok(data)) => {
+                *this.remaining -= data.len() as u64;
+                Poll::Ready(Some(Ok(Frame::data(data))))
+            }
+            Some(Err(e)) => Poll::Ready(Some(Err(SendError::io(e).into()))),
+            None if *this.remaining > 0 => {
+                let e = io::Error::new(io::ErrorKind::UnexpectedEof, "file was truncated while being sent");
+                Poll::Ready(Some(Err(SendError::io(e).into())))
+            }
+            None => Poll::Ready(None),
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        SizeHint::with_exact(self.remaining)
+    }
+}
+
+pin_project! {
+    /// A body passing each chunk of data through `f`, see [`ResponseBody::map_data`].
+    struct MappedBody<F> {
+        #[pin]
+        body: UnsyncBoxBody<Bytes, HttpError>,
+        f: F,
+        same_size: bool,
+    }
+}
+
+impl<F> HttpBody for MappedBody<F>
+where
+    F: Fn(Bytes) -> Bytes,
+{
+    type Data = Bytes;
+    type Error = HttpError;
+
+    fn poll_frame(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        let frame = ready!(this.body.poll_frame(cx));
+        Poll::Ready(frame.map(|frame| frame.map(|frame| frame.map_data(this.f))))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        if self.same_size {
+            self.body.size_hint()
+        } else {
+            SizeHint::default()
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::body::ResponseBody;
+    use crate::body::{CollectError, ResponseBody, SyncResponseBody};
     use bytes::Bytes;
     use futures::TryStreamExt;
     use http_body::{Body as HttpBody, Frame};
-    use http_body_util::{BodyExt, StreamBody};
-    use micro_http::protocol::ParseError;
+    use http_body_util::{BodyExt, Full, StreamBody};
+    use micro_http::protocol::{HttpError, ParseError};
     use std::io;
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
 
     fn check_send<T: Send>() {}
 
@@ -194,6 +585,56 @@ mod tests {
         assert!(body.frame().await.is_none());
     }
 
+    #[test]
+    fn test_take_replace_swap() {
+        let mut body = ResponseBody::from("hello");
+        let taken = body.take();
+        assert!(body.is_empty());
+        let mut empty = body.replace(taken);
+        assert!(empty.is_empty());
+        assert_eq!(body.size_hint().exact(), Some(5));
+
+        // the bodies given back can be taken again
+        let mut old = body.swap(ResponseBody::from("hello world"));
+        assert_eq!(old.size_hint().exact(), Some(5));
+        assert_eq!(body.size_hint().exact(), Some(11));
+        let _ = old.take();
+        let _ = empty.take();
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "taken twice")]
+    fn test_take_twice() {
+        let mut body = ResponseBody::from("hello");
+        let _ = body.take();
+        let _ = body.take();
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "replaced without being taken")]
+    fn test_replace_without_take() {
+        let mut body = ResponseBody::from("hello");
+        let _ = body.replace(ResponseBody::empty());
+    }
+
+    #[test]
+    fn test_is_empty() {
+        assert!(ResponseBody::empty().is_empty());
+        assert!(ResponseBody::from("").is_empty());
+        assert!(!ResponseBody::from("hello").is_empty());
+
+        // a stream of unknown size may yield data once polled, even if it ends without any
+        let chunks: Vec<Result<Frame<Bytes>, HttpError>> = vec![];
+        let body = ResponseBody::stream(StreamBody::new(futures::stream::iter(chunks)));
+        assert_eq!(body.size_hint().exact(), None);
+        assert!(!body.is_empty());
+
+        let body = ResponseBody::stream(Full::new(Bytes::new()).map_err(|never| match never {}));
+        assert!(body.is_empty());
+    }
+
     #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
     async fn test_stream_body() {
         let chunks: Vec<Result<_, io::Error>> = vec![
@@ -218,4 +659,109 @@ mod tests {
 
         assert!(!body.is_end_stream());
     }
+
+    #[tokio::test]
+    async fn test_map_data() {
+        let body = ResponseBody::from("<script nonce=NONCE>");
+        let mut body = body.map_data(|data| Bytes::from(String::from_utf8_lossy(&data).replace("NONCE", "abc123")));
+        assert_eq!(body.size_hint().exact(), Some(21));
+        assert_eq!(body.frame().await.unwrap().unwrap().into_data().unwrap(), "<script nonce=abc123>");
+
+        let chunks: Vec<Result<_, io::Error>> =
+            vec![Ok(Frame::data(Bytes::from_static(b"hello "))), Ok(Frame::data(Bytes::from_static(b"world")))];
+        let stream = futures::stream::iter(chunks).map_err(|err| ParseError::io(err).into());
+        let body = ResponseBody::stream_with_size(StreamBody::new(stream), 11);
+        let body = body.map_data(|data| data.to_ascii_uppercase().into());
+        assert_eq!(body.size_hint().exact(), None);
+        assert_eq!(body.collect().await.unwrap().to_bytes(), "HELLO WORLD");
+    }
+
+    #[tokio::test]
+    async fn test_map_data_same_size() {
+        let chunks: Vec<Result<_, io::Error>> =
+            vec![Ok(Frame::data(Bytes::from_static(b"hello "))), Ok(Frame::data(Bytes::from_static(b"world")))];
+        let stream = futures::stream::iter(chunks).map_err(|err| ParseError::io(err).into());
+        let body = ResponseBody::stream_with_size(StreamBody::new(stream), 11);
+        let mut body = body.map_data_same_size(|data| data.to_ascii_uppercase().into());
+
+        assert_eq!(body.size_hint().exact(), Some(11));
+        assert_eq!(body.frame().await.unwrap().unwrap().into_data().unwrap(), "HELLO ");
+        assert_eq!(body.size_hint().exact(), Some(5));
+        assert!(!body.is_end_stream());
+        assert_eq!(body.frame().await.unwrap().unwrap().into_data().unwrap(), "WORLD");
+        assert!(body.frame().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_stream_with_size() {
+        let chunks: Vec<Result<_, io::Error>> =
+            vec![Ok(Frame::data(Bytes::from_static(b"hello "))), Ok(Frame::data(Bytes::from_static(b"world")))];
+        let stream = futures::stream::iter(chunks).map_err(|err| ParseError::io(err).into());
+
+        let mut body = ResponseBody::stream_with_size(StreamBody::new(stream), 11);
+
+        assert_eq!(body.size_hint().exact(), Some(11));
+        assert_eq!(body.frame().await.unwrap().unwrap().into_data().unwrap(), "hello ");
+        assert_eq!(body.size_hint().exact(), Some(5));
+        assert_eq!(body.frame().await.unwrap().unwrap().into_data().unwrap(), "world");
+        assert_eq!(body.size_hint().exact(), Some(0));
+        assert!(body.frame().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_sync_response_body() {
+        let body = SyncResponseBody::new(Full::new(Bytes::from_static(b"hello")).map_err(|never| match never {}));
+        let shared = Arc::new(Mutex::new(Some(body)));
+
+        let body = tokio::spawn(async move { shared.lock().await.take().unwrap() }).await.unwrap();
+        let body = ResponseBody::from(body);
+        assert_eq!(body.size_hint().exact(), Some(5));
+        assert_eq!(body.collect().await.unwrap().to_bytes(), "hello");
+    }
+
+    #[tokio::test]
+    async fn test_collect_limited() {
+        let collected = ResponseBody::from("hello").collect_limited(5).await.unwrap();
+        assert_eq!(collected, Bytes::from_static(b"hello"));
+        assert!(ResponseBody::empty().collect_limited(0).await.unwrap().is_empty());
+        let result = ResponseBody::from("hello").collect_limited(4).await;
+        assert!(matches!(result, Err(CollectError::TooLarge { limit: 4 })));
+
+        let stream = || {
+            let chunks = vec![Ok(Frame::data(Bytes::from("hello "))), Ok(Frame::data(Bytes::from("world")))];
+            ResponseBody::stream(StreamBody::new(futures::stream::iter(chunks)))
+        };
+        let collected = stream().collect_limited(11).await.unwrap();
+        assert_eq!(collected, Bytes::from_static(b"hello world"));
+        let result = stream().collect_limited(10).await;
+        assert!(matches!(result, Err(CollectError::TooLarge { limit: 10 })));
+
+        // rejected from the size hint, before reading the body
+        let body = ResponseBody::stream_with_size(stream(), 11);
+        let result = body.collect_limited(10).await;
+        assert!(matches!(result, Err(CollectError::TooLarge { limit: 10 })));
+
+        let chunks = vec![Ok(Frame::data(Bytes::from("hello"))), Err(ParseError::invalid_body("broken").into())];
+        let body = ResponseBody::stream(StreamBody::new(futures::stream::iter(chunks)));
+        assert!(matches!(body.collect_limited(1024).await, Err(CollectError::Io(_))));
+    }
+
+    #[tokio::test]
+    async fn test_from_file() {
+        let path = std::env::temp_dir().join(format!("micro_web_body_{}", std::process::id()));
+        std::fs::write(&path, "hello world").unwrap();
+
+        let body = ResponseBody::from_path(&path).await.unwrap();
+        assert_eq!(body.size_hint().exact(), Some(11));
+        assert_eq!(body.collect().await.unwrap().to_bytes(), "hello world");
+
+        // the file changed after its size was read
+        let file = tokio::fs::File::open(&path).await.unwrap();
+        assert!(ResponseBody::from_file(file, 12).collect().await.is_err());
+        let file = tokio::fs::File::open(&path).await.unwrap();
+        assert!(ResponseBody::from_file(file, 10).collect().await.is_err());
+
+        assert!(ResponseBody::from_path(path.with_extension("missing")).await.is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
 }
@@ -0,0 +1,403 @@
+//! Cookie parsing for incoming requests.
+//!
+//! This module parses the `Cookie` request headers as described in
+//! [RFC 6265 Section 5.4](https://tools.ietf.org/html/rfc6265#section-5.4):
+//! - A request may carry several `Cookie` headers
+//! - Each header holds `name=value` pairs separated by semicolons
+//! - A value may be wrapped in double quotes, which are not part of the value
+//!
+//! Malformed pairs and values that are not valid UTF-8 are skipped instead of failing the request.
+//!
+//! [`SignedCookieJar`] signs the cookies it sets with HMAC-SHA256, so the ones sent back can be trusted
+//! without a server-side session store.
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use http::header::SET_COOKIE;
+use http::{HeaderMap, HeaderValue};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
+use sha2::Sha256;
+use std::borrow::Cow;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+use tracing::trace;
+
+/// A single cookie sent by the client, borrowed from the request headers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cookie<'req> {
+    name: &'req str,
+    value: &'req str,
+}
+
+impl<'req> Cookie<'req> {
+    /// Returns the name of the cookie
+    pub fn name(&self) -> &'req str {
+        self.name
+    }
+
+    /// Returns the raw value of the cookie, without surrounding double quotes
+    pub fn value(&self) -> &'req str {
+        self.value
+    }
+
+    /// Returns the percent-decoded value of the cookie
+    ///
+    /// Invalid UTF-8 sequences produced by decoding are replaced with `U+FFFD`.
+    pub fn value_decoded(&self) -> Cow<'req, str> {
+        percent_decode_str(self.value).decode_utf8_lossy()
+    }
+}
+
+/// All cookies sent with a request, in the order they appear.
+///
+/// # Example
+/// ```
+/// use micro_web::CookieJar;
+/// use http::HeaderMap;
+///
+/// let mut headers = HeaderMap::new();
+/// headers.insert(http::header::COOKIE, "id=42; name=hello%20world".parse().unwrap());
+///
+/// let jar = CookieJar::from_headers(&headers);
+/// assert_eq!(jar.get("id").unwrap().value(), "42");
+/// assert_eq!(jar.get("name").unwrap().value_decoded(), "hello world");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CookieJar<'req> {
+    cookies: Vec<Cookie<'req>>,
+}
+
+impl<'req> CookieJar<'req> {
+    /// Parses all `Cookie` headers in `headers`
+    pub fn from_headers(headers: &'req HeaderMap) -> Self {
+        let mut cookies = Vec::new();
+        for header_value in headers.get_all(http::header::COOKIE) {
+            for pair in header_value.as_bytes().split(|b| *b == b';') {
+                let pair = match std::str::from_utf8(pair) {
+                    Ok(pair) => pair,
+                    Err(e) => {
+                        trace!("skip cookie which is not valid utf8: {}", e);
+                        continue;
+                    }
+                };
+
+                if let Some(cookie) = parse_cookie(pair) {
+                    cookies.push(cookie);
+                }
+            }
+        }
+
+        Self { cookies }
+    }
+
+    /// Gets the first cookie with the given name
+    /// Returns None if the cookie doesn't exist
+    pub fn get(&self, name: &str) -> Option<Cookie<'req>> {
+        self.cookies.iter().find(|cookie| cookie.name == name).copied()
+    }
+
+    /// Iterates over all cookies
+    pub fn iter(&self) -> impl Iterator<Item = Cookie<'req>> + '_ {
+        self.cookies.iter().copied()
+    }
+
+    /// Returns the number of cookies
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.cookies.len()
+    }
+
+    /// Returns true if there are no cookies
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.cookies.is_empty()
+    }
+}
+
+/// The characters percent-encoded in the values of signed cookies: the ones that are not cookie-octets
+/// of RFC 6265 Section 4.1.1, and `%` itself.
+const COOKIE_VALUE: &AsciiSet = &CONTROLS.add(b' ').add(b'"').add(b',').add(b';').add(b'\\').add(b'%');
+
+/// Errors of [`SignedCookieJar::get`].
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureError {
+    /// The value has no signature, it wasn't set by a [`SignedCookieJar`]
+    #[error("cookie is not signed")]
+    Missing,
+    /// The signature doesn't match the value, or was made with another key
+    #[error("cookie signature mismatch")]
+    Tampered,
+    /// The signature is valid, but older than the max age of the jar
+    #[error("cookie has expired")]
+    Expired,
+}
+
+/// The cookies of a request signed with HMAC-SHA256, and the signed cookies to set in the response.
+///
+/// A signed value is sent as `value.signature`, the signature being the base64url encoded HMAC of the
+/// name and value of the cookie. With a [`max_age`](Self::max_age), the time the cookie was set is
+/// signed too, as `value.timestamp.signature`, and older cookies are rejected as expired. The values
+/// are percent-encoded as needed, so any string can be set.
+///
+/// # Example
+/// ```
+/// use http::HeaderMap;
+/// use micro_web::{SignatureError, SignedCookieJar};
+///
+/// let key = [7u8; 32];
+/// let mut jar = SignedCookieJar::new(&key);
+/// jar.set("user", "alice");
+/// let mut response_headers = HeaderMap::new();
+/// jar.write_headers(&mut response_headers);
+///
+/// // the browser sends the cookie back
+/// let set_cookie = response_headers[http::header::SET_COOKIE].to_str().unwrap();
+/// let cookie = set_cookie.split(';').next().unwrap();
+/// let mut request_headers = HeaderMap::new();
+/// request_headers.insert(http::header::COOKIE, cookie.parse().unwrap());
+///
+/// let jar = SignedCookieJar::from_headers(&key, &request_headers);
+/// assert_eq!(jar.get("user"), Some(Ok("alice".into())));
+/// assert_eq!(jar.get("other"), None);
+/// ```
+#[derive(Clone)]
+pub struct SignedCookieJar<'req> {
+    jar: CookieJar<'req>,
+    mac: Hmac<Sha256>,
+    max_age: Option<Duration>,
+    /// The `Set-Cookie` values of the cookies set
+    set_cookies: Vec<HeaderValue>,
+}
+
+impl SignedCookieJar<'static> {
+    /// Creates an empty jar signing with `key`, to set cookies.
+    pub fn new(key: &[u8; 32]) -> Self {
+        SignedCookieJar::from_jar(key, CookieJar::default())
+    }
+}
+
+impl<'req> SignedCookieJar<'req> {
+    /// Creates a jar signing with `key`, holding the cookies of the `Cookie` headers in `headers`.
+    pub fn from_headers(key: &[u8; 32], headers: &'req HeaderMap) -> Self {
+        Self::from_jar(key, CookieJar::from_headers(headers))
+    }
+
+    /// Creates a jar signing with `key`, holding the cookies of `jar`.
+    pub fn from_jar(key: &[u8; 32], jar: CookieJar<'req>) -> Self {
+        // hmac accepts keys of any length
+        Self { jar, mac: Hmac::new_from_slice(key).unwrap(), max_age: None, set_cookies: vec![] }
+    }
+
+    /// Sets how long the cookies are valid, they are sent with a `Max-Age` and rejected once expired.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Gets the value of the first cookie named `name`, once its signature is verified.
+    ///
+    /// Returns `None` if there is no such cookie.
+    pub fn get(&self, name: &str) -> Option<Result<Cow<'req, str>, SignatureError>> {
+        self.get_at(name, SystemTime::now())
+    }
+
+    fn get_at(&self, name: &str, now: SystemTime) -> Option<Result<Cow<'req, str>, SignatureError>> {
+        let cookie = self.jar.get(name)?;
+        Some(self.verify(name, cookie.value(), now).map(|value| percent_decode_str(value).decode_utf8_lossy()))
+    }
+
+    /// Returns the value of `signed`, without its timestamp and signature.
+    fn verify<'a>(&self, name: &str, signed: &'a str, now: SystemTime) -> Result<&'a str, SignatureError> {
+        let (payload, signature) = signed.rsplit_once('.').ok_or(SignatureError::Missing)?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).map_err(|_| SignatureError::Tampered)?;
+
+        let mut mac = self.mac.clone();
+        mac.update(name.as_bytes());
+        mac.update(b"=");
+        mac.update(payload.as_bytes());
+        // compares in constant time
+        mac.verify_slice(&signature).map_err(|_| SignatureError::Tampered)?;
+
+        let Some(max_age) = self.max_age else {
+            return Ok(payload);
+        };
+        // a signed payload without a valid timestamp was signed by a jar without max age
+        let (value, timestamp) = payload.rsplit_once('.').ok_or(SignatureError::Expired)?;
+        let set_at = timestamp.parse::<u64>().map_err(|_| SignatureError::Expired)?;
+        let now = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        if now.saturating_sub(set_at) > max_age.as_secs() {
+            return Err(SignatureError::Expired);
+        }
+        Ok(value)
+    }
+
+    /// Sets the cookie `name` to `value` signed, with the `Path=/`, `HttpOnly` and `SameSite=Lax` attributes.
+    ///
+    /// The cookies are added to a response by [`write_headers`](Self::write_headers).
+    ///
+    /// # Panics
+    /// If `name` has characters that are not allowed in a header value.
+    pub fn set(&mut self, name: &str, value: &str) {
+        self.set_at(name, value, SystemTime::now())
+    }
+
+    fn set_at(&mut self, name: &str, value: &str, now: SystemTime) {
+        let mut payload = utf8_percent_encode(value, COOKIE_VALUE).to_string();
+        if self.max_age.is_some() {
+            let timestamp = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+            payload = format!("{payload}.{timestamp}");
+        }
+
+        let mut mac = self.mac.clone();
+        mac.update(name.as_bytes());
+        mac.update(b"=");
+        mac.update(payload.as_bytes());
+        let signature = URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes());
+
+        let mut set_cookie = format!("{name}={payload}.{signature}; Path=/; HttpOnly; SameSite=Lax");
+        if let Some(max_age) = self.max_age {
+            set_cookie.push_str(&format!("; Max-Age={}", max_age.as_secs()));
+        }
+        self.set_cookies.push(HeaderValue::try_from(set_cookie).expect("invalid cookie name"));
+    }
+
+    /// Appends a `Set-Cookie` header to `headers` for each cookie set.
+    pub fn write_headers(&self, headers: &mut HeaderMap) {
+        for set_cookie in &self.set_cookies {
+            headers.append(SET_COOKIE, set_cookie.clone());
+        }
+    }
+}
+
+/// Parses a single `name=value` pair, returns `None` if the pair is malformed.
+fn parse_cookie(pair: &str) -> Option<Cookie<'_>> {
+    let (name, value) = pair.split_once('=')?;
+    let name = name.trim();
+    if name.is_empty() {
+        trace!("skip cookie without name: {}", pair);
+        return None;
+    }
+
+    let value = value.trim();
+    let value = match value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+        Some(unquoted) => unquoted,
+        None => value,
+    };
+
+    Some(Cookie { name, value })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::HeaderValue;
+
+    #[test]
+    fn test_multiple_headers() {
+        let mut headers = HeaderMap::new();
+        headers.append(http::header::COOKIE, HeaderValue::from_static("a=1; b=2"));
+        headers.append(http::header::COOKIE, HeaderValue::from_static("c=\"3\""));
+
+        let jar = CookieJar::from_headers(&headers);
+        assert_eq!(jar.len(), 3);
+        assert_eq!(jar.get("b").unwrap().value(), "2");
+        assert_eq!(jar.get("c").unwrap().value(), "3");
+        assert!(jar.get("d").is_none());
+
+        let names: Vec<_> = jar.iter().map(|cookie| cookie.name()).collect();
+        assert_eq!(names, vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_skip_malformed() {
+        let mut headers = HeaderMap::new();
+        headers.append(http::header::COOKIE, HeaderValue::from_bytes(b"a=\xff\xfe; b=2;; novalue; =3").unwrap());
+
+        let jar = CookieJar::from_headers(&headers);
+        assert_eq!(jar.len(), 1);
+        assert_eq!(jar.get("b").unwrap().value(), "2");
+    }
+
+    #[test]
+    fn test_value_decoded() {
+        let mut headers = HeaderMap::new();
+        headers.append(http::header::COOKIE, HeaderValue::from_static("name=J%C3%BCrgen%3B"));
+
+        let jar = CookieJar::from_headers(&headers);
+        let cookie = jar.get("name").unwrap();
+        assert_eq!(cookie.value(), "J%C3%BCrgen%3B");
+        assert_eq!(cookie.value_decoded(), "Jürgen;");
+    }
+
+    /// Returns the `Cookie` request header sending back the cookies set by `jar`.
+    fn send_back(jar: &SignedCookieJar) -> HeaderMap {
+        let mut response_headers = HeaderMap::new();
+        jar.write_headers(&mut response_headers);
+        let cookies: Vec<_> = response_headers
+            .get_all(SET_COOKIE)
+            .iter()
+            .map(|set_cookie| set_cookie.to_str().unwrap().split(';').next().unwrap().to_string())
+            .collect();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(http::header::COOKIE, cookies.join("; ").parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_signed_cookies() {
+        let key = [1u8; 32];
+        let mut jar = SignedCookieJar::new(&key);
+        jar.set("user", "alice");
+        jar.set("note", "a; b.c \"d\" 100%");
+        let headers = send_back(&jar);
+
+        let jar = SignedCookieJar::from_headers(&key, &headers);
+        assert_eq!(jar.get("user"), Some(Ok("alice".into())));
+        assert_eq!(jar.get("note"), Some(Ok("a; b.c \"d\" 100%".into())));
+        assert_eq!(jar.get("missing"), None);
+
+        // another key
+        let jar = SignedCookieJar::from_headers(&[2u8; 32], &headers);
+        assert_eq!(jar.get("user"), Some(Err(SignatureError::Tampered)));
+    }
+
+    #[test]
+    fn test_tampered_signed_cookie() {
+        let key = [1u8; 32];
+        let mut jar = SignedCookieJar::new(&key);
+        jar.set("user", "alice");
+        let cookie = send_back(&jar)[http::header::COOKIE].to_str().unwrap().to_string();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(http::header::COOKIE, cookie.replace("alice", "admin").parse().unwrap());
+        let jar = SignedCookieJar::from_headers(&key, &headers);
+        assert_eq!(jar.get("user"), Some(Err(SignatureError::Tampered)));
+
+        // the signature of a cookie doesn't match another name
+        headers.insert(http::header::COOKIE, cookie.replace("user=", "role=").parse().unwrap());
+        let jar = SignedCookieJar::from_headers(&key, &headers);
+        assert_eq!(jar.get("role"), Some(Err(SignatureError::Tampered)));
+
+        headers.insert(http::header::COOKIE, HeaderValue::from_static("user=alice"));
+        let jar = SignedCookieJar::from_headers(&key, &headers);
+        assert_eq!(jar.get("user"), Some(Err(SignatureError::Missing)));
+    }
+
+    #[test]
+    fn test_expired_signed_cookie() {
+        let key = [1u8; 32];
+        let set_at = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let mut jar = SignedCookieJar::new(&key).max_age(Duration::from_secs(60));
+        jar.set_at("session", "s1", set_at);
+        let mut response_headers = HeaderMap::new();
+        jar.write_headers(&mut response_headers);
+        assert!(response_headers[SET_COOKIE].to_str().unwrap().ends_with("; Max-Age=60"));
+
+        let headers = send_back(&jar);
+        let jar = SignedCookieJar::from_headers(&key, &headers).max_age(Duration::from_secs(60));
+        assert_eq!(jar.get_at("session", set_at + Duration::from_secs(60)), Some(Ok("s1".into())));
+        assert_eq!(jar.get_at("session", set_at + Duration::from_secs(61)), Some(Err(SignatureError::Expired)));
+    }
+}
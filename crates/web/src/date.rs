@@ -1,65 +1,57 @@
 //! HTTP date header value management service.
-//! 
-//! This module provides a service for efficiently managing and updating HTTP date header values
-//! in a concurrent environment. It updates the date string periodically to avoid repeated
-//! date string formatting operations in high-concurrency scenarios.
+//!
+//! This module provides a service for efficiently producing HTTP date header values in a concurrent
+//! environment. The formatted date is cached and only formatted again once the second changes, to avoid
+//! repeated date string formatting operations in high-concurrency scenarios.
 
 use arc_swap::ArcSwap;
+use http::HeaderValue;
 use httpdate::fmt_http_date;
 use std::sync::Arc;
-use std::time::{Duration, SystemTime};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-/// A service that maintains and periodically updates the current HTTP date string.
+/// A service that maintains the current HTTP date header value.
 ///
-/// This service runs a background task that updates the date string every 700ms,
-/// providing an efficient way to access formatted HTTP date strings without
-/// formatting them on every request.
-pub struct DateService {
-    current: Arc<ArcSwap<(SystemTime, String)>>,
-    handle: tokio::task::JoinHandle<()>,
+/// The value is formatted lazily, on the first request of each second, so no background task is needed.
+pub(crate) struct DateService {
+    clock: fn() -> SystemTime,
+    current: ArcSwap<(u64, HeaderValue)>,
 }
 
 impl DateService {
-    /// Creates a new `DateService` instance.
-    ///
-    /// This method initializes the service with the current system time and starts
-    /// a background task that updates the date string every 700ms.
-    ///
-    /// # Returns
-    /// Returns a new `DateService` instance with the background update task running.
+    /// Creates a `DateService` reading the system clock.
     pub(crate) fn new() -> Self {
-        let system_time = SystemTime::now();
-        let http_date = fmt_http_date(system_time);
+        Self::with_clock(SystemTime::now)
+    }
 
-        let current = Arc::new(ArcSwap::new(Arc::new((system_time, http_date))));
-        let current_arc = Arc::clone(&current);
+    /// Creates a `DateService` reading the time from `clock`.
+    pub(crate) fn with_clock(clock: fn() -> SystemTime) -> Self {
+        let now = clock();
+        let current = ArcSwap::from_pointee((unix_secs(now), format(now)));
+        DateService { clock, current }
+    }
 
-        let handle = tokio::spawn(async move {
-            loop {
-                tokio::time::sleep(Duration::from_millis(700)).await;
-                let system_time = SystemTime::now();
-                let http_date = fmt_http_date(system_time);
-                current_arc.store(Arc::new((system_time, http_date)));
-            }
-        });
+    /// Returns the current HTTP date, formatting it only if the second changed since the last call.
+    pub(crate) fn http_date(&self) -> HeaderValue {
+        let now = (self.clock)();
+        let secs = unix_secs(now);
 
-        DateService { current, handle }
-    }
+        let current = self.current.load();
+        if current.0 == secs {
+            return current.1.clone();
+        }
 
-    /// Provides access to the current HTTP date string through a callback function.
-    ///
-    /// This method allows safe access to the current date string without exposing
-    /// the internal synchronization mechanisms.
-    pub(crate) fn with_http_date<F>(&self, mut f: F) where F: FnMut(&str) {
-        let date = &self.current.load().1;
-        f(date)
+        let date = format(now);
+        self.current.store(Arc::new((secs, date.clone())));
+        date
     }
 }
 
-/// Implements the `Drop` trait to ensure the background task is properly cleaned up
-/// when the `DateService` is dropped.
-impl Drop for DateService {
-    fn drop(&mut self) {
-        self.handle.abort();
-    }
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_secs()).unwrap_or_default()
+}
+
+fn format(time: SystemTime) -> HeaderValue {
+    // an http date only contains ascii letters, digits, spaces, commas and colons
+    HeaderValue::try_from(fmt_http_date(time)).unwrap()
 }
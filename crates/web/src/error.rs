@@ -0,0 +1,264 @@
+//! Error responses following [RFC 9457](https://www.rfc-editor.org/rfc/rfc9457) (Problem Details for HTTP APIs).
+//!
+//! [`ProblemDetails`] is a [`Responder`] answering with its members serialized as JSON and
+//! `Content-Type: application/problem+json`. Errors implementing [`std::error::Error`] and
+//! [`HttpStatusCode`] convert into it, so handlers returning `Result<_, ProblemDetails>` can use `?`
+//! on their own errors. The message of the error becomes the `detail` member, except for server errors
+//! (`5xx`): their message may reveal internals, such as a failed database query, so it's only logged.
+//!
+//! ```
+//! use http::StatusCode;
+//! use micro_web::{HttpStatusCode, ProblemDetails};
+//!
+//! #[derive(Debug, thiserror::Error)]
+//! enum AppError {
+//!     #[error("user {0} not found")]
+//!     UserNotFound(u64),
+//! }
+//!
+//! impl HttpStatusCode for AppError {
+//!     fn status_code(&self) -> StatusCode {
+//!         match self {
+//!             AppError::UserNotFound(_) => StatusCode::NOT_FOUND,
+//!         }
+//!     }
+//! }
+//!
+//! fn find_user(id: u64) -> Result<String, AppError> {
+//!     Err(AppError::UserNotFound(id))
+//! }
+//!
+//! async fn get_user() -> Result<String, ProblemDetails> {
+//!     let user = find_user(42)?;
+//!     Ok(user)
+//! }
+//! ```
+
+use crate::responder::Responder;
+use crate::{RequestContext, ResponseBody};
+use bytes::Bytes;
+use http::header::CONTENT_TYPE;
+use http::{HeaderValue, Response, StatusCode};
+use serde::Serialize;
+use std::collections::HashMap;
+use tracing::error;
+
+/// The media type of problem details serialized as JSON.
+const APPLICATION_PROBLEM_JSON: &str = "application/problem+json";
+
+/// The names of the standard members, which extension members can't overwrite.
+const STANDARD_MEMBERS: [&str; 5] = ["type", "title", "status", "detail", "instance"];
+
+/// Errors that are answered with a specific status code.
+pub trait HttpStatusCode {
+    /// The status code of the response answering this error.
+    fn status_code(&self) -> StatusCode;
+}
+
+/// A problem details object, see [RFC 9457 section 3](https://www.rfc-editor.org/rfc/rfc9457#section-3).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProblemDetails {
+    /// A URI identifying the problem type, `about:blank` when absent
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub type_uri: Option<String>,
+    /// A short summary of the problem type
+    pub title: String,
+    /// The status code of the response
+    pub status: u16,
+    /// An explanation specific to this occurrence of the problem
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    /// A URI identifying this occurrence of the problem
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
+    /// Extension members, serialized next to the standard members
+    #[serde(flatten)]
+    pub extensions: HashMap<String, serde_json::Value>,
+}
+
+impl ProblemDetails {
+    /// Creates problem details for `status`, titled with its canonical reason.
+    pub fn new(status: StatusCode) -> Self {
+        Self {
+            type_uri: None,
+            title: status.canonical_reason().unwrap_or_default().to_string(),
+            status: status.as_u16(),
+            detail: None,
+            instance: None,
+            extensions: HashMap::new(),
+        }
+    }
+
+    /// Sets the problem type URI.
+    pub fn with_type(mut self, type_uri: impl Into<String>) -> Self {
+        self.type_uri = Some(type_uri.into());
+        self
+    }
+
+    /// Sets the title.
+    pub fn with_title(mut self, title: impl Into<String>) -> Self {
+        self.title = title.into();
+        self
+    }
+
+    /// Sets the detail.
+    pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    /// Sets the instance URI.
+    pub fn with_instance(mut self, instance: impl Into<String>) -> Self {
+        self.instance = Some(instance.into());
+        self
+    }
+
+    /// Adds the extension member `name`.
+    ///
+    /// Names of standard members, such as `title` or `status`, are ignored.
+    pub fn with_extension(mut self, name: impl Into<String>, value: impl Into<serde_json::Value>) -> Self {
+        let name = name.into();
+        if !STANDARD_MEMBERS.contains(&name.as_str()) {
+            self.extensions.insert(name, value.into());
+        }
+        self
+    }
+
+    /// The status code of the response, `500 Internal Server Error` if `status` is not a valid one.
+    pub fn status_code(&self) -> StatusCode {
+        StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+}
+
+impl<E: std::error::Error + HttpStatusCode> From<E> for ProblemDetails {
+    fn from(e: E) -> Self {
+        let status = e.status_code();
+        if status.is_server_error() {
+            error!("server error: {}", e);
+            return ProblemDetails::new(status);
+        }
+        ProblemDetails::new(status).with_detail(e.to_string())
+    }
+}
+
+impl From<ProblemDetails> for Response<ResponseBody> {
+    fn from(mut problem: ProblemDetails) -> Self {
+        let status = problem.status_code();
+        // the extensions may have been inserted without `with_extension`
+        problem.extensions.retain(|name, _| !STANDARD_MEMBERS.contains(&name.as_str()));
+        let body = match serde_json::to_vec(&problem) {
+            Ok(json) => json,
+            Err(e) => {
+                error!("failed to serialize the problem details as json: {}", e);
+                let mut resp = Response::new(ResponseBody::empty());
+                *resp.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+                return resp;
+            }
+        };
+
+        let mut resp = Response::new(ResponseBody::from(Bytes::from(body)));
+        *resp.status_mut() = status;
+        resp.headers_mut().insert(CONTENT_TYPE, HeaderValue::from_static(APPLICATION_PROBLEM_JSON));
+        resp
+    }
+}
+
+impl Responder for ProblemDetails {
+    fn response_to(self, _req: &RequestContext) -> Response<ResponseBody> {
+        self.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handler::RequestHandler;
+    use crate::{handler_fn, OptionReqBody, PathParams, RequestBody};
+    use http_body_util::BodyExt;
+    use micro_http::protocol::RequestHeader;
+    use serde_json::json;
+
+    #[derive(Debug, thiserror::Error)]
+    #[error("user {0} not found")]
+    struct UserNotFound(u64);
+
+    impl HttpStatusCode for UserNotFound {
+        fn status_code(&self) -> StatusCode {
+            StatusCode::NOT_FOUND
+        }
+    }
+
+    async fn json_of(resp: Response<ResponseBody>) -> serde_json::Value {
+        serde_json::from_slice(&resp.into_body().collect().await.unwrap().to_bytes()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_problem_response() {
+        let problem = ProblemDetails::new(StatusCode::FORBIDDEN)
+            .with_type("https://example.com/probs/out-of-credit")
+            .with_title("You do not have enough credit.")
+            .with_detail("Your current balance is 30, but that costs 50.")
+            .with_instance("/account/12345/msgs/abc")
+            .with_extension("balance", 30);
+
+        let resp: Response<ResponseBody> = problem.into();
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        assert_eq!(resp.headers().get(CONTENT_TYPE).unwrap(), "application/problem+json");
+        assert_eq!(
+            json_of(resp).await,
+            json!({
+                "type": "https://example.com/probs/out-of-credit",
+                "title": "You do not have enough credit.",
+                "status": 403,
+                "detail": "Your current balance is 30, but that costs 50.",
+                "instance": "/account/12345/msgs/abc",
+                "balance": 30,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_handler_error() {
+        async fn get_user() -> Result<&'static str, ProblemDetails> {
+            Err(UserNotFound(42))?
+        }
+
+        let header: RequestHeader = http::Request::builder().body(()).unwrap().into();
+        let mut req = RequestContext::new(&header, PathParams::empty());
+        let resp = handler_fn(get_user).invoke(&mut req, OptionReqBody::from(RequestBody::empty())).await;
+
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        assert_eq!(resp.headers().get(CONTENT_TYPE).unwrap(), "application/problem+json");
+        assert_eq!(json_of(resp).await, json!({"title": "Not Found", "status": 404, "detail": "user 42 not found"}));
+    }
+
+    #[tokio::test]
+    async fn test_server_error_has_no_detail() {
+        #[derive(Debug, thiserror::Error)]
+        #[error("connection to db.internal:5432 refused")]
+        struct DatabaseError;
+
+        impl HttpStatusCode for DatabaseError {
+            fn status_code(&self) -> StatusCode {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+        }
+
+        let resp: Response<ResponseBody> = ProblemDetails::from(DatabaseError).into();
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(json_of(resp).await, json!({"title": "Service Unavailable", "status": 503}));
+    }
+
+    #[tokio::test]
+    async fn test_extensions_dont_overwrite_members() {
+        let mut problem = ProblemDetails::new(StatusCode::BAD_REQUEST)
+            .with_extension("status", 200)
+            .with_extension("title", "OK")
+            .with_extension("field", "name");
+        assert_eq!(problem.extensions.len(), 1);
+
+        problem.extensions.insert("detail".to_string(), json!("overwritten"));
+        let resp: Response<ResponseBody> = problem.into();
+        assert_eq!(json_of(resp).await, json!({"title": "Bad Request", "status": 400, "field": "name"}));
+    }
+}
@@ -125,6 +125,7 @@ impl Responder for ParseError {
             }
             ParseError::TooManyHeaders { .. } => (StatusCode::BAD_REQUEST, "too many headers").response_to(req),
             ParseError::InvalidHeader { .. } => (StatusCode::BAD_REQUEST, "invalid header").response_to(req),
+            ParseError::InvalidUtf8 { .. } => (StatusCode::BAD_REQUEST, "invalid header").response_to(req),
             ParseError::InvalidVersion(_) => (StatusCode::BAD_REQUEST, "invalid version").response_to(req),
             ParseError::InvalidMethod => (StatusCode::BAD_REQUEST, "invalid method").response_to(req),
             ParseError::InvalidUri => (StatusCode::BAD_REQUEST, "invalid uri").response_to(req),
@@ -132,6 +133,8 @@ impl Responder for ParseError {
                 (StatusCode::BAD_REQUEST, "invalid content length").response_to(req)
             }
             ParseError::InvalidBody { .. } => (StatusCode::BAD_REQUEST, "invalid body").response_to(req),
+            ParseError::TooLargeBody { .. } => (StatusCode::PAYLOAD_TOO_LARGE, "payload too large").response_to(req),
+            ParseError::ReadTimeout { .. } => (StatusCode::REQUEST_TIMEOUT, "request timeout").response_to(req),
             ParseError::Io { .. } => (StatusCode::BAD_REQUEST, "connection error").response_to(req),
         }
     }
@@ -0,0 +1,226 @@
+//! `application/x-www-form-urlencoded` body parsing.
+//!
+//! [`FormData`] keeps the decoded fields of a form in order, without deserializing them into a type as
+//! [`Form`](crate::extract::Form) does. The body is read up to a size limit before being parsed:
+//!
+//! ```no_run
+//! use micro_web::form::FormError;
+//! use micro_web::{RequestBody, RequestContext};
+//!
+//! async fn login(req: &RequestContext<'_, '_>, body: RequestBody) -> Result<String, FormError> {
+//!     let form = req.form_data(body, 16 * 1024).await?;
+//!     Ok(format!("hello {}", form.get("user").unwrap_or("anonymous")))
+//! }
+//! ```
+
+use crate::responder::Responder;
+use crate::{RequestBody, RequestContext, ResponseBody};
+use bytes::BytesMut;
+use http::header::CONTENT_TYPE;
+use http::{HeaderMap, Response, StatusCode};
+use http_body::Body;
+use http_body_util::BodyExt;
+use micro_http::protocol::ParseError;
+use thiserror::Error;
+use tracing::trace;
+
+/// Errors of form parsing.
+#[derive(Error, Debug)]
+pub enum FormError {
+    /// The request `Content-Type` isn't `application/x-www-form-urlencoded`
+    #[error("content type is not application/x-www-form-urlencoded")]
+    UnsupportedContentType,
+
+    /// The body is larger than the limit
+    #[error("form body exceeds the limit of {limit} bytes")]
+    TooLarge { limit: usize },
+
+    /// The body, or one of its percent-decoded fields, is not valid UTF-8
+    #[error("form body is not valid utf-8")]
+    InvalidUtf8,
+
+    /// The body could not be read
+    #[error("failed to read the body: {0}")]
+    Body(#[from] ParseError),
+}
+
+impl Responder for FormError {
+    fn response_to(self, req: &RequestContext) -> Response<ResponseBody> {
+        trace!("reject form body: {}", self);
+        match self {
+            FormError::UnsupportedContentType => {
+                (StatusCode::UNSUPPORTED_MEDIA_TYPE, "content type is not application/x-www-form-urlencoded")
+                    .response_to(req)
+            }
+            FormError::TooLarge { .. } => (StatusCode::PAYLOAD_TOO_LARGE, "payload too large").response_to(req),
+            FormError::InvalidUtf8 => (StatusCode::BAD_REQUEST, "form body is not valid utf-8").response_to(req),
+            FormError::Body(e) => e.response_to(req),
+        }
+    }
+}
+
+/// The decoded fields of an `application/x-www-form-urlencoded` body.
+///
+/// A key may appear more than once, in which case all of its values are kept in order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FormData {
+    pairs: Vec<(String, String)>,
+}
+
+impl FormData {
+    /// Reads `body` up to `max_bytes` and parses it.
+    pub async fn from_body(mut body: RequestBody, max_bytes: usize) -> Result<FormData, FormError> {
+        if body.size_hint().lower() > max_bytes as u64 {
+            return Err(FormError::TooLarge { limit: max_bytes });
+        }
+
+        let mut buf = BytesMut::new();
+        while let Some(frame) = body.frame().await {
+            if let Ok(data) = frame?.into_data() {
+                if buf.len() + data.len() > max_bytes {
+                    return Err(FormError::TooLarge { limit: max_bytes });
+                }
+                buf.extend_from_slice(&data);
+            }
+        }
+
+        Self::parse(&buf)
+    }
+
+    /// Parses an `application/x-www-form-urlencoded` string.
+    pub fn parse(input: &[u8]) -> Result<FormData, FormError> {
+        let input = std::str::from_utf8(input).map_err(|_| FormError::InvalidUtf8)?;
+        // invalid percent-encoded sequences are decoded as U+FFFD by `form_urlencoded`
+        let has_replacement = input.contains(char::REPLACEMENT_CHARACTER);
+
+        let mut pairs = vec![];
+        for (key, value) in form_urlencoded::parse(input.as_bytes()) {
+            if !has_replacement
+                && (key.contains(char::REPLACEMENT_CHARACTER) || value.contains(char::REPLACEMENT_CHARACTER))
+            {
+                return Err(FormError::InvalidUtf8);
+            }
+            pairs.push((key.into_owned(), value.into_owned()));
+        }
+        Ok(Self { pairs })
+    }
+
+    /// Returns true if there are no fields
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    /// Returns the number of fields, counting repeated keys
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    /// Gets the first value of a field by its name
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.pairs.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+    }
+
+    /// Gets all values of a field by its name, in the order they appear
+    pub fn get_all<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.pairs.iter().filter(move |(k, _)| k == key).map(|(_, v)| v.as_str())
+    }
+
+    /// Iterates over the field names in the order they appear, a repeated name is returned each time
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.pairs.iter().map(|(k, _)| k.as_str())
+    }
+
+    /// Iterates over the field values in the order they appear
+    pub fn values(&self) -> impl Iterator<Item = &str> {
+        self.pairs.iter().map(|(_, v)| v.as_str())
+    }
+
+    /// Iterates over all fields as `(key, value)` pairs, in the order they appear
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.pairs.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+}
+
+/// Checks the `Content-Type` of `headers` is `application/x-www-form-urlencoded`, parameters are ignored.
+pub(crate) fn check_content_type(headers: &HeaderMap) -> Result<(), FormError> {
+    let mime: mime::Mime = headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+        .ok_or(FormError::UnsupportedContentType)?;
+
+    if mime.type_() == mime::APPLICATION && mime.subtype() == mime::WWW_FORM_URLENCODED {
+        Ok(())
+    } else {
+        Err(FormError::UnsupportedContentType)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PathParams;
+    use bytes::Bytes;
+    use http_body_util::{Full, StreamBody};
+    use http_body::Frame;
+    use micro_http::protocol::RequestHeader;
+
+    fn body_of(chunks: Vec<&'static str>) -> RequestBody {
+        let frames = chunks.into_iter().map(|chunk| Ok::<_, ParseError>(Frame::data(Bytes::from(chunk))));
+        RequestBody::boxed(StreamBody::new(futures::stream::iter(frames)))
+    }
+
+    #[tokio::test]
+    async fn test_from_body() {
+        let form = FormData::from_body(body_of(vec!["name=alice&tag=a", "&tag=b+c&empty=&city=S%C3%A3o"]), 1024)
+            .await
+            .unwrap();
+
+        assert_eq!(form.len(), 5);
+        assert_eq!(form.get("name"), Some("alice"));
+        assert_eq!(form.get_all("tag").collect::<Vec<_>>(), vec!["a", "b c"]);
+        assert_eq!(form.get("empty"), Some(""));
+        assert_eq!(form.get("city"), Some("São"));
+        assert_eq!(form.get("missing"), None);
+        assert_eq!(form.keys().collect::<Vec<_>>(), vec!["name", "tag", "tag", "empty", "city"]);
+        assert_eq!(form.values().collect::<Vec<_>>(), vec!["alice", "a", "b c", "", "São"]);
+    }
+
+    #[tokio::test]
+    async fn test_too_large() {
+        let result = FormData::from_body(body_of(vec!["name=alice", "&city=paris"]), 16).await;
+        assert!(matches!(result, Err(FormError::TooLarge { limit: 16 })));
+
+        // rejected from the size hint, before reading the body
+        let body = RequestBody::boxed(Full::new(Bytes::from("name=alice&city=paris")).map_err(|never| match never {}));
+        let result = FormData::from_body(body, 16).await;
+        assert!(matches!(result, Err(FormError::TooLarge { limit: 16 })));
+    }
+
+    #[test]
+    fn test_invalid_utf8() {
+        assert!(matches!(FormData::parse(b"name=\xff"), Err(FormError::InvalidUtf8)));
+        assert!(matches!(FormData::parse(b"name=%FF"), Err(FormError::InvalidUtf8)));
+        assert_eq!(FormData::parse("name=\u{FFFD}%FF".as_bytes()).unwrap().get("name"), Some("\u{FFFD}\u{FFFD}"));
+    }
+
+    #[tokio::test]
+    async fn test_form_data_content_type() {
+        let header: RequestHeader = http::Request::builder()
+            .header(CONTENT_TYPE, "application/x-www-form-urlencoded; charset=utf-8")
+            .body(())
+            .unwrap()
+            .into();
+        let req = RequestContext::new(&header, PathParams::empty());
+        let form = req.form_data(body_of(vec!["a=1"]), 1024).await.unwrap();
+        assert_eq!(form.get("a"), Some("1"));
+
+        let header: RequestHeader =
+            http::Request::builder().header(CONTENT_TYPE, "application/json").body(()).unwrap().into();
+        let req = RequestContext::new(&header, PathParams::empty());
+        let result = req.form_data(body_of(vec!["a=1"]), 1024).await;
+        assert!(matches!(result, Err(FormError::UnsupportedContentType)));
+    }
+}
@@ -0,0 +1,159 @@
+//! `application/json` body parsing.
+//!
+//! [`RequestContext::json`] checks the request `Content-Type`, reads the body up to
+//! [`DEFAULT_JSON_LIMIT`] and deserializes it, without going through the
+//! [`Json`](crate::extract::Json) extractor:
+//!
+//! ```no_run
+//! use micro_web::json::JsonBodyError;
+//! use micro_web::{RequestBody, RequestContext};
+//! use serde::Deserialize;
+//!
+//! #[derive(Deserialize)]
+//! struct User {
+//!     name: String,
+//! }
+//!
+//! async fn create_user(req: &RequestContext<'_, '_>, body: RequestBody) -> Result<String, JsonBodyError> {
+//!     let user: User = req.json(body).await?;
+//!     Ok(format!("hello {}", user.name))
+//! }
+//! ```
+
+use crate::responder::Responder;
+use crate::{RequestBody, RequestContext, ResponseBody};
+use bytes::BytesMut;
+use http::header::CONTENT_TYPE;
+use http::{HeaderMap, Response, StatusCode};
+use http_body::Body;
+use http_body_util::BodyExt;
+use micro_http::protocol::ParseError;
+use serde::de::DeserializeOwned;
+use thiserror::Error;
+use tracing::trace;
+
+/// The largest body read by [`RequestContext::json`], 4 MiB.
+pub const DEFAULT_JSON_LIMIT: usize = 4 * 1024 * 1024;
+
+/// Errors of JSON body parsing.
+#[derive(Error, Debug)]
+pub enum JsonBodyError {
+    /// The request `Content-Type` isn't `application/json`
+    #[error("content type is not application/json")]
+    UnsupportedContentType,
+
+    /// The body is larger than the limit
+    #[error("json body exceeds the limit of {limit} bytes")]
+    TooLarge { limit: usize },
+
+    /// The body could not be read
+    #[error("failed to read the body: {0}")]
+    Body(#[from] ParseError),
+
+    /// The body is not valid JSON, or doesn't match the target type
+    #[error("failed to deserialize the json body: {0}")]
+    Deserialize(#[from] serde_json::Error),
+}
+
+impl Responder for JsonBodyError {
+    fn response_to(self, req: &RequestContext) -> Response<ResponseBody> {
+        trace!("reject json body: {}", self);
+        match self {
+            JsonBodyError::UnsupportedContentType => {
+                (StatusCode::UNSUPPORTED_MEDIA_TYPE, "content type is not application/json").response_to(req)
+            }
+            JsonBodyError::TooLarge { .. } => (StatusCode::PAYLOAD_TOO_LARGE, "payload too large").response_to(req),
+            JsonBodyError::Body(e) => e.response_to(req),
+            JsonBodyError::Deserialize(e) => (StatusCode::BAD_REQUEST, e.to_string()).response_to(req),
+        }
+    }
+}
+
+/// Reads `body` up to `max_bytes` and deserializes it.
+pub(crate) async fn from_body<T: DeserializeOwned>(
+    mut body: RequestBody,
+    max_bytes: usize,
+) -> Result<T, JsonBodyError> {
+    if body.size_hint().lower() > max_bytes as u64 {
+        return Err(JsonBodyError::TooLarge { limit: max_bytes });
+    }
+
+    let mut buf = BytesMut::new();
+    while let Some(frame) = body.frame().await {
+        if let Ok(data) = frame?.into_data() {
+            if buf.len() + data.len() > max_bytes {
+                return Err(JsonBodyError::TooLarge { limit: max_bytes });
+            }
+            buf.extend_from_slice(&data);
+        }
+    }
+
+    Ok(serde_json::from_slice(&buf)?)
+}
+
+/// Checks the `Content-Type` of `headers` is `application/json` or a `+json` type, such as
+/// `application/problem+json`, parameters are ignored.
+pub(crate) fn check_content_type(headers: &HeaderMap) -> Result<(), JsonBodyError> {
+    let mime: mime::Mime = headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+        .ok_or(JsonBodyError::UnsupportedContentType)?;
+
+    if mime.type_() == mime::APPLICATION && (mime.subtype() == mime::JSON || mime.suffix() == Some(mime::JSON)) {
+        Ok(())
+    } else {
+        Err(JsonBodyError::UnsupportedContentType)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PathParams;
+    use bytes::Bytes;
+    use http_body::Frame;
+    use http_body_util::StreamBody;
+    use micro_http::protocol::RequestHeader;
+    use serde::Deserialize;
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct User {
+        name: String,
+    }
+
+    fn body_of(chunks: Vec<&'static str>) -> RequestBody {
+        let frames = chunks.into_iter().map(|chunk| Ok::<_, ParseError>(Frame::data(Bytes::from(chunk))));
+        RequestBody::boxed(StreamBody::new(futures::stream::iter(frames)))
+    }
+
+    fn header_of(content_type: &str) -> RequestHeader {
+        http::Request::builder().header(CONTENT_TYPE, content_type).body(()).unwrap().into()
+    }
+
+    #[tokio::test]
+    async fn test_json() {
+        for content_type in ["application/json", "application/json; charset=utf-8", "application/merge-patch+json"] {
+            let header = header_of(content_type);
+            let req = RequestContext::new(&header, PathParams::empty());
+            let user: User = req.json(body_of(vec!["{\"name\":", "\"alice\"}"])).await.unwrap();
+            assert_eq!(user, User { name: "alice".to_string() });
+        }
+    }
+
+    #[tokio::test]
+    async fn test_json_errors() {
+        let header = header_of("text/plain");
+        let req = RequestContext::new(&header, PathParams::empty());
+        let result = req.json::<User>(body_of(vec!["{\"name\":\"alice\"}"])).await;
+        assert!(matches!(result, Err(JsonBodyError::UnsupportedContentType)));
+
+        let header = header_of("application/json");
+        let req = RequestContext::new(&header, PathParams::empty());
+        let result = req.json::<User>(body_of(vec!["{\"name\":1}"])).await;
+        assert!(matches!(result, Err(JsonBodyError::Deserialize(_))));
+
+        let result = from_body::<User>(body_of(vec!["{\"name\":", "\"alice\"}"]), 10).await;
+        assert!(matches!(result, Err(JsonBodyError::TooLarge { limit: 10 })));
+    }
+}
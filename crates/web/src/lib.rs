@@ -99,25 +99,77 @@
 
 // Internal modules
 mod body;
+mod cookie;
+mod error;
 mod fn_trait;
 mod handler;
 mod request;
 mod responder;
+mod response;
 mod server;
+mod sse;
+mod static_files;
+mod stats;
 mod date;
 
 // Public modules
+pub mod acceptor;
 pub mod extract;
 pub mod filter;
+pub mod form;
+pub mod json;
+pub mod multipart;
+pub mod range;
 pub mod wrapper;
 pub mod router;
+pub mod testing;
+pub mod websocket;
 
 // Public re-exports
+pub use body::CollectError;
 pub use body::OptionReqBody;
+pub use body::RequestBody;
 pub use body::ResponseBody;
+pub use body::SyncResponseBody;
+pub use cookie::Cookie;
+pub use cookie::CookieJar;
+pub use cookie::SignatureError;
+pub use cookie::SignedCookieJar;
+pub use error::HttpStatusCode;
+pub use error::ProblemDetails;
 pub use fn_trait::FnTrait;
 pub use handler::handler_fn;
 pub use handler::FnHandler;
+pub use request::OwnedPathParams;
 pub use request::PathParams;
+pub use request::QueryParams;
 pub use request::RequestContext;
+pub use response::ResponseBuilder;
 pub use server::Server;
+pub use server::ShutdownHandle;
+pub use stats::ConnectionStats;
+pub use micro_http::connection::KeepAliveConfig;
+pub use micro_http::connection::ServerConfig;
+pub use sse::SseBody;
+pub use sse::SseEvent;
+pub use static_files::StaticFileHandler;
+
+// Compile-time checks of the thread safety of the public types, so a change can't break it silently.
+// `ResponseBody` and the request bodies hold any `Send` stream, so they are `Send` but not `Sync`.
+const _: () = {
+    const fn assert_send<T: Send>() {}
+    const fn assert_send_sync<T: Send + Sync>() {}
+
+    assert_send::<ResponseBody>();
+    assert_send::<RequestBody>();
+    assert_send::<OptionReqBody>();
+    assert_send_sync::<SyncResponseBody>();
+    assert_send_sync::<RequestContext<'static, 'static>>();
+    assert_send_sync::<PathParams<'static, 'static>>();
+    assert_send_sync::<OwnedPathParams>();
+    assert_send_sync::<QueryParams>();
+    assert_send_sync::<CookieJar>();
+    assert_send_sync::<SignedCookieJar>();
+    assert_send_sync::<router::Router>();
+    assert_send_sync::<Server>();
+};
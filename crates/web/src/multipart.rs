@@ -0,0 +1,601 @@
+//! Streaming `multipart/form-data` parser, see [RFC 7578](https://www.rfc-editor.org/rfc/rfc7578).
+//!
+//! [`MultipartReader`] is a stream of [`MultipartPart`]s read incrementally from the request body, the
+//! data of each part is itself a stream, so large uploads are never buffered in memory:
+//!
+//! ```no_run
+//! use futures::StreamExt;
+//! use micro_web::multipart::{MultipartError, MultipartReader};
+//!
+//! async fn upload(mut multipart: MultipartReader) -> Result<String, MultipartError> {
+//!     let mut received = vec![];
+//!     while let Some(part) = multipart.next().await {
+//!         let mut part = part?;
+//!         let name = part.name().unwrap_or_default().to_string();
+//!
+//!         let mut size = 0;
+//!         let mut data = part.data();
+//!         while let Some(chunk) = data.next().await {
+//!             size += chunk?.len();
+//!         }
+//!         received.push(format!("{name}: {size} bytes"));
+//!     }
+//!     Ok(received.join("\n"))
+//! }
+//! ```
+//!
+//! Reading the next part skips the unread data of the previous one. The size of each part is limited by
+//! [`MultipartReader::max_part_size`].
+
+use crate::extract::FromRequest;
+use crate::responder::Responder;
+use crate::{OptionReqBody, RequestBody, RequestContext, ResponseBody};
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use futures::Stream;
+use http::header::{CONTENT_DISPOSITION, CONTENT_TYPE};
+use http::{HeaderMap, HeaderName, HeaderValue, Response, StatusCode};
+use http_body::Body;
+use micro_http::protocol::ParseError;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{ready, Context, Poll};
+use thiserror::Error;
+use tracing::trace;
+
+/// The default of [`MultipartReader::max_part_size`], 10 MiB.
+const DEFAULT_MAX_PART_SIZE: u64 = 10 * 1024 * 1024;
+
+/// The maximum size of the headers of a part.
+const MAX_HEADERS_SIZE: usize = 8 * 1024;
+
+/// Errors of multipart parsing.
+#[derive(Error, Debug)]
+pub enum MultipartError {
+    /// The request `Content-Type` isn't `multipart/form-data`
+    #[error("content type is not multipart/form-data")]
+    NotMultipart,
+
+    /// The `boundary` parameter is missing or invalid
+    #[error("missing or invalid multipart boundary")]
+    InvalidBoundary,
+
+    /// The headers of a part are invalid or too large
+    #[error("invalid part headers")]
+    InvalidHeaders,
+
+    /// A part exceeds [`MultipartReader::max_part_size`]
+    #[error("part exceeds the limit of {limit} bytes")]
+    PartTooLarge { limit: u64 },
+
+    /// A boundary isn't followed by a line break or the closing `--`
+    #[error("malformed multipart body")]
+    Malformed,
+
+    /// The body ended before the closing boundary
+    #[error("multipart body ended before the closing boundary")]
+    Incomplete,
+
+    /// Reading the request body failed
+    #[error("failed to read the body: {0}")]
+    Body(#[from] ParseError),
+}
+
+impl Responder for MultipartError {
+    fn response_to(self, req: &RequestContext) -> Response<ResponseBody> {
+        trace!("reject multipart body: {}", self);
+        match self {
+            MultipartError::NotMultipart => {
+                (StatusCode::UNSUPPORTED_MEDIA_TYPE, "content type is not multipart/form-data").response_to(req)
+            }
+            MultipartError::PartTooLarge { .. } => {
+                (StatusCode::PAYLOAD_TOO_LARGE, "payload too large").response_to(req)
+            }
+            MultipartError::Body(e) => e.response_to(req),
+            _ => (StatusCode::BAD_REQUEST, "invalid multipart body").response_to(req),
+        }
+    }
+}
+
+/// A stream of the parts of a `multipart/form-data` body.
+pub struct MultipartReader {
+    state: Arc<Mutex<State>>,
+}
+
+impl MultipartReader {
+    /// Creates a reader of `body`, whose parts are delimited by `boundary`.
+    pub fn new(body: RequestBody, boundary: &str) -> Self {
+        let mut delimiter = BytesMut::with_capacity(boundary.len() + 4);
+        delimiter.extend_from_slice(b"\r\n--");
+        delimiter.extend_from_slice(boundary.as_bytes());
+
+        let state = State {
+            body,
+            body_eof: false,
+            buf: BytesMut::new(),
+            delimiter: delimiter.freeze(),
+            phase: Phase::Preamble,
+            max_part_size: DEFAULT_MAX_PART_SIZE,
+            part_index: 0,
+            part_size: 0,
+        };
+        Self { state: Arc::new(Mutex::new(state)) }
+    }
+
+    /// Creates a reader of `body`, taking the boundary from the `Content-Type` of `headers`.
+    pub fn from_headers(headers: &HeaderMap, body: RequestBody) -> Result<Self, MultipartError> {
+        Ok(Self::new(body, &boundary(headers)?))
+    }
+
+    /// Sets the maximum size of the data of each part, 10 MiB by default.
+    pub fn max_part_size(self, max_part_size: u64) -> Self {
+        self.state.lock().unwrap().max_part_size = max_part_size;
+        self
+    }
+}
+
+impl Stream for MultipartReader {
+    type Item = Result<MultipartPart, MultipartError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut state = self.state.lock().unwrap();
+        let result = ready!(state.poll_next_part(cx));
+        Poll::Ready(result.map(|headers| {
+            headers.map(|headers| MultipartPart::new(headers, state.part_index, Arc::clone(&self.state)))
+        }))
+    }
+}
+
+#[async_trait]
+impl FromRequest for MultipartReader {
+    type Output<'r> = MultipartReader;
+    type Error = MultipartError;
+
+    async fn from_request<'r>(req: &'r RequestContext, body: OptionReqBody) -> Result<Self::Output<'r>, Self::Error> {
+        // checks the content type before consuming the body
+        let boundary = boundary(req.headers())?;
+        let body = body.apply(|body| async { Ok(body) }).await?;
+        Ok(MultipartReader::new(body, &boundary))
+    }
+}
+
+/// A part of a multipart body.
+pub struct MultipartPart {
+    headers: HeaderMap,
+    name: Option<String>,
+    filename: Option<String>,
+    index: usize,
+    state: Arc<Mutex<State>>,
+}
+
+impl MultipartPart {
+    fn new(headers: HeaderMap, index: usize, state: Arc<Mutex<State>>) -> Self {
+        let disposition = headers.get(CONTENT_DISPOSITION).and_then(|value| value.to_str().ok()).unwrap_or_default();
+        let name = disposition_param(disposition, "name");
+        let filename = disposition_param(disposition, "filename");
+        Self { headers, name, filename, index, state }
+    }
+
+    /// The headers of the part.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// The form field name, from the `Content-Disposition` header.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// The name of the uploaded file, from the `Content-Disposition` header.
+    pub fn filename(&self) -> Option<&str> {
+        self.filename.as_deref()
+    }
+
+    /// The `Content-Type` of the part.
+    pub fn content_type(&self) -> Option<&str> {
+        self.headers.get(CONTENT_TYPE).and_then(|value| value.to_str().ok())
+    }
+
+    /// The data of the part, as it's received.
+    ///
+    /// The stream ends once the next part is read from the [`MultipartReader`].
+    pub fn data(&mut self) -> impl Stream<Item = Result<Bytes, MultipartError>> + '_ {
+        futures::stream::poll_fn(move |cx| self.poll_data(cx))
+    }
+
+    /// Reads the whole data of the part.
+    pub async fn bytes(mut self) -> Result<Bytes, MultipartError> {
+        let mut bytes = BytesMut::new();
+        while let Some(chunk) = futures::future::poll_fn(|cx| self.poll_data(cx)).await {
+            bytes.extend_from_slice(&chunk?);
+        }
+        Ok(bytes.freeze())
+    }
+
+    fn poll_data(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<Bytes, MultipartError>>> {
+        let mut state = self.state.lock().unwrap();
+        if state.part_index != self.index {
+            return Poll::Ready(None);
+        }
+        state.poll_part_data(cx)
+    }
+}
+
+/// What the parser expects next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    /// The text before the first boundary
+    Preamble,
+    /// The headers of the next part
+    Headers,
+    /// The data of the current part
+    Data,
+    /// The closing boundary has been read
+    End,
+    /// An error has been returned
+    Failed,
+}
+
+/// The parser shared by the reader and its parts.
+struct State {
+    body: RequestBody,
+    body_eof: bool,
+    buf: BytesMut,
+    /// `\r\n--boundary`
+    delimiter: Bytes,
+    phase: Phase,
+    max_part_size: u64,
+    /// The index of the current part, starting from 1
+    part_index: usize,
+    part_size: u64,
+}
+
+impl State {
+    /// Reads the next frame of the body into the buffer.
+    ///
+    /// Returns [`MultipartError::Incomplete`] if the body has ended.
+    fn poll_fill(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), MultipartError>> {
+        loop {
+            if self.body_eof {
+                return Poll::Ready(Err(MultipartError::Incomplete));
+            }
+
+            match ready!(Pin::new(&mut self.body).poll_frame(cx)) {
+                Some(Ok(frame)) => {
+                    // trailers are ignored
+                    if let Ok(data) = frame.into_data() {
+                        self.buf.extend_from_slice(&data);
+                        return Poll::Ready(Ok(()));
+                    }
+                }
+                Some(Err(e)) => return Poll::Ready(Err(e.into())),
+                None => self.body_eof = true,
+            }
+        }
+    }
+
+    fn poll_next_part(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<HeaderMap, MultipartError>>> {
+        let result = ready!(self.poll_next_part_inner(cx));
+        if let Some(Err(_)) = result {
+            self.phase = Phase::Failed;
+        }
+        Poll::Ready(result)
+    }
+
+    fn poll_next_part_inner(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<HeaderMap, MultipartError>>> {
+        loop {
+            match self.phase {
+                Phase::Preamble => {
+                    // the first boundary may not be preceded by a line break
+                    let dash_boundary = self.delimiter.slice(2..);
+                    match find(&self.buf, &dash_boundary) {
+                        Some(index) if self.buf.len() >= index + dash_boundary.len() + 2 => {
+                            let _ = self.buf.split_to(index + dash_boundary.len());
+                            if let Err(e) = self.after_boundary() {
+                                return Poll::Ready(Some(Err(e)));
+                            }
+                        }
+                        Some(_) => {
+                            if let Err(e) = ready!(self.poll_fill(cx)) {
+                                return Poll::Ready(Some(Err(e)));
+                            }
+                        }
+                        None => {
+                            // keeps the bytes that may start a boundary
+                            let keep = (dash_boundary.len() - 1).min(self.buf.len());
+                            let _ = self.buf.split_to(self.buf.len() - keep);
+                            if let Err(e) = ready!(self.poll_fill(cx)) {
+                                return Poll::Ready(Some(Err(e)));
+                            }
+                        }
+                    }
+                }
+
+                // skips the unread data of the current part
+                Phase::Data => match ready!(self.poll_part_data(cx)) {
+                    Some(Ok(_)) => {}
+                    Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                    None => {}
+                },
+
+                Phase::Headers => {
+                    if self.buf.starts_with(b"\r\n") {
+                        // a part without headers
+                        let _ = self.buf.split_to(2);
+                        return Poll::Ready(Some(Ok(self.start_part(HeaderMap::new()))));
+                    }
+
+                    match find(&self.buf, b"\r\n\r\n") {
+                        Some(index) => {
+                            let block = self.buf.split_to(index + 4);
+                            return match parse_headers(&block[..index]) {
+                                Ok(headers) => Poll::Ready(Some(Ok(self.start_part(headers)))),
+                                Err(e) => Poll::Ready(Some(Err(e))),
+                            };
+                        }
+                        None if self.buf.len() > MAX_HEADERS_SIZE => {
+                            return Poll::Ready(Some(Err(MultipartError::InvalidHeaders)));
+                        }
+                        None => {
+                            if let Err(e) = ready!(self.poll_fill(cx)) {
+                                return Poll::Ready(Some(Err(e)));
+                            }
+                        }
+                    }
+                }
+
+                Phase::End | Phase::Failed => return Poll::Ready(None),
+            }
+        }
+    }
+
+    fn start_part(&mut self, headers: HeaderMap) -> HeaderMap {
+        self.phase = Phase::Data;
+        self.part_index += 1;
+        self.part_size = 0;
+        headers
+    }
+
+    /// Reads the line break or the `--` following a boundary, which must be in the buffer.
+    fn after_boundary(&mut self) -> Result<(), MultipartError> {
+        let suffix = self.buf.split_to(2);
+        match &suffix[..] {
+            b"\r\n" => {
+                self.phase = Phase::Headers;
+                Ok(())
+            }
+            b"--" => {
+                // the epilogue is ignored
+                self.phase = Phase::End;
+                self.buf.clear();
+                Ok(())
+            }
+            _ => Err(MultipartError::Malformed),
+        }
+    }
+
+    fn poll_part_data(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<Bytes, MultipartError>>> {
+        let result = ready!(self.poll_part_data_inner(cx));
+        if let Some(Err(_)) = result {
+            self.phase = Phase::Failed;
+        }
+        Poll::Ready(result)
+    }
+
+    fn poll_part_data_inner(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<Bytes, MultipartError>>> {
+        loop {
+            if self.phase != Phase::Data {
+                return Poll::Ready(None);
+            }
+
+            let data_len = match find(&self.buf, &self.delimiter) {
+                Some(0) if self.buf.len() >= self.delimiter.len() + 2 => {
+                    let _ = self.buf.split_to(self.delimiter.len());
+                    return match self.after_boundary() {
+                        Ok(()) => Poll::Ready(None),
+                        Err(e) => Poll::Ready(Some(Err(e))),
+                    };
+                }
+                Some(0) => 0,
+                Some(index) => index,
+                // the end of the buffer may be the start of a delimiter split over two frames
+                None => self.buf.len().saturating_sub(self.delimiter.len() - 1),
+            };
+
+            if data_len == 0 {
+                if let Err(e) = ready!(self.poll_fill(cx)) {
+                    return Poll::Ready(Some(Err(e)));
+                }
+                continue;
+            }
+
+            self.part_size += data_len as u64;
+            if self.part_size > self.max_part_size {
+                return Poll::Ready(Some(Err(MultipartError::PartTooLarge { limit: self.max_part_size })));
+            }
+            return Poll::Ready(Some(Ok(self.buf.split_to(data_len).freeze())));
+        }
+    }
+}
+
+/// Returns the boundary of a `multipart/form-data` content type.
+fn boundary(headers: &HeaderMap) -> Result<String, MultipartError> {
+    let mime: mime::Mime = headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+        .ok_or(MultipartError::NotMultipart)?;
+    if mime.type_() != mime::MULTIPART || mime.subtype() != mime::FORM_DATA {
+        return Err(MultipartError::NotMultipart);
+    }
+
+    match mime.get_param(mime::BOUNDARY) {
+        Some(boundary) if (1..=70).contains(&boundary.as_str().len()) => Ok(boundary.to_string()),
+        _ => Err(MultipartError::InvalidBoundary),
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+fn parse_headers(block: &[u8]) -> Result<HeaderMap, MultipartError> {
+    let mut headers = HeaderMap::new();
+    for line in block.split(|b| *b == b'\n') {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let colon = line.iter().position(|b| *b == b':').ok_or(MultipartError::InvalidHeaders)?;
+        let name = HeaderName::from_bytes(&line[..colon]).map_err(|_| MultipartError::InvalidHeaders)?;
+        let value = std::str::from_utf8(&line[colon + 1..]).map_err(|_| MultipartError::InvalidHeaders)?;
+        let value = HeaderValue::from_str(value.trim()).map_err(|_| MultipartError::InvalidHeaders)?;
+        headers.append(name, value);
+    }
+    Ok(headers)
+}
+
+/// Returns the parameter `key` of a `Content-Disposition` value, unquoting it.
+fn disposition_param(disposition: &str, key: &str) -> Option<String> {
+    let mut rest = disposition.split_once(';')?.1;
+    loop {
+        let (name, after) = rest.split_once('=')?;
+        let after = after.trim_start();
+
+        let (value, next) = match after.strip_prefix('"') {
+            Some(quoted) => {
+                let mut value = String::new();
+                let mut chars = quoted.char_indices();
+                let end = loop {
+                    match chars.next()? {
+                        (_, '\\') => value.push(chars.next()?.1),
+                        (index, '"') => break index + 1,
+                        (_, c) => value.push(c),
+                    }
+                };
+                (value, &quoted[end..])
+            }
+            None => {
+                let end = after.find(';').unwrap_or(after.len());
+                (after[..end].trim_end().to_string(), &after[end..])
+            }
+        };
+
+        if name.trim().eq_ignore_ascii_case(key) {
+            return Some(value);
+        }
+        rest = next.split_once(';')?.1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use http_body::Frame;
+    use http_body_util::StreamBody;
+
+    const BODY: &str = "preamble\r\n\
+        --XyZ\r\n\
+        Content-Disposition: form-data; name=\"title\"\r\n\
+        \r\n\
+        hello\r\n\
+        --XyZ\r\n\
+        Content-Disposition: form-data; name=\"file\"; filename=\"a \\\"b\\\".txt\"\r\n\
+        Content-Type: text/plain\r\n\
+        \r\n\
+        line 1\r\nline 2 --XyZ\r\n\
+        --XyZ--\r\n\
+        epilogue";
+
+    fn body(chunks: Vec<Bytes>) -> RequestBody {
+        let frames = chunks.into_iter().map(|chunk| Ok::<_, ParseError>(Frame::data(chunk)));
+        RequestBody::boxed(StreamBody::new(futures::stream::iter(frames)))
+    }
+
+    async fn read_all(
+        mut reader: MultipartReader,
+    ) -> Result<Vec<(Option<String>, Option<String>, Bytes)>, MultipartError> {
+        let mut parts = vec![];
+        while let Some(part) = reader.next().await {
+            let part = part?;
+            let (name, filename) = (part.name().map(String::from), part.filename().map(String::from));
+            parts.push((name, filename, part.bytes().await?));
+        }
+        Ok(parts)
+    }
+
+    #[tokio::test]
+    async fn test_read_parts() {
+        let expected = vec![
+            (Some("title".to_string()), None, Bytes::from("hello")),
+            (Some("file".to_string()), Some("a \"b\".txt".to_string()), Bytes::from("line 1\r\nline 2 --XyZ")),
+        ];
+
+        // every split of the body, including the splits within a boundary
+        for split in 0..=BODY.len() {
+            let chunks = vec![Bytes::from(&BODY[..split]), Bytes::from(&BODY[split..])];
+            let parts = read_all(MultipartReader::new(body(chunks), "XyZ")).await.unwrap();
+            assert_eq!(parts, expected, "split at {split}");
+        }
+
+        let one_byte_chunks = BODY.bytes().map(|b| Bytes::from(vec![b])).collect();
+        assert_eq!(read_all(MultipartReader::new(body(one_byte_chunks), "XyZ")).await.unwrap(), expected);
+    }
+
+    #[tokio::test]
+    async fn test_part_headers() {
+        let mut reader = MultipartReader::new(body(vec![Bytes::from(BODY)]), "XyZ");
+        reader.next().await.unwrap().unwrap();
+
+        let part = reader.next().await.unwrap().unwrap();
+        assert_eq!(part.content_type(), Some("text/plain"));
+        assert_eq!(part.headers().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_skip_unread_part() {
+        let mut reader = MultipartReader::new(body(vec![Bytes::from(BODY)]), "XyZ");
+        let mut first = reader.next().await.unwrap().unwrap();
+        let second = reader.next().await.unwrap().unwrap();
+
+        assert_eq!(second.name(), Some("file"));
+        // the data of a previous part is no longer available
+        assert!(first.data().next().await.is_none());
+        assert!(reader.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_errors() {
+        let reader = MultipartReader::new(body(vec![Bytes::from(BODY)]), "XyZ").max_part_size(10);
+        assert!(matches!(read_all(reader).await, Err(MultipartError::PartTooLarge { limit: 10 })));
+
+        let truncated = Bytes::from(&BODY[..BODY.len() - 20]);
+        let reader = MultipartReader::new(body(vec![truncated]), "XyZ");
+        assert!(matches!(read_all(reader).await, Err(MultipartError::Incomplete)));
+
+        let malformed = Bytes::from("--XyZ\r\n\r\ndata\r\n--XyZ!!\r\n");
+        let reader = MultipartReader::new(body(vec![malformed]), "XyZ");
+        assert!(matches!(read_all(reader).await, Err(MultipartError::Malformed)));
+    }
+
+    #[test]
+    fn test_boundary() {
+        let mut headers = HeaderMap::new();
+        assert!(matches!(boundary(&headers), Err(MultipartError::NotMultipart)));
+
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("multipart/form-data; boundary=\"a b\""));
+        assert_eq!(boundary(&headers).unwrap(), "a b");
+
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("multipart/form-data"));
+        assert!(matches!(boundary(&headers), Err(MultipartError::InvalidBoundary)));
+
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        assert!(matches!(boundary(&headers), Err(MultipartError::NotMultipart)));
+    }
+
+    #[test]
+    fn test_disposition_param() {
+        let disposition = "form-data; name=\"field; 1\"; filename=plain.txt";
+        assert_eq!(disposition_param(disposition, "name").unwrap(), "field; 1");
+        assert_eq!(disposition_param(disposition, "filename").unwrap(), "plain.txt");
+        assert_eq!(disposition_param(disposition, "other"), None);
+    }
+}
@@ -1,27 +1,118 @@
 //! Request handling module that provides access to HTTP request information and path parameters.
 //! 
 //! This module contains the core types for working with HTTP requests in the web framework:
-//! - `RequestContext`: Provides access to request headers and path parameters
+//! - `RequestContext`: Provides access to request headers, path parameters and extensions
 //! - `PathParams`: Handles URL path parameters extracted from request paths
+//! - `OwnedPathParams`: An owned copy of `PathParams`, for tasks outliving the request
+//! - `QueryParams`: Handles query string parameters parsed from the request URI
 
-use http::{HeaderMap, Method, Uri, Version};
+use crate::acceptor::Secure;
+use crate::form::{self, FormData, FormError};
+use crate::json::{self, JsonBodyError, DEFAULT_JSON_LIMIT};
+use crate::multipart::{MultipartError, MultipartReader};
+use crate::{CookieJar, OptionReqBody, RequestBody, SignedCookieJar};
+use http::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use http::{Extensions, HeaderMap, Method, Uri, Version};
 use matchit::Params;
 use micro_http::protocol::RequestHeader;
+use percent_encoding::percent_decode_str;
+use serde::de::DeserializeOwned;
+use std::borrow::Cow;
+use std::net::{IpAddr, SocketAddr};
+#[cfg(feature = "unix")]
+use std::os::unix::net::SocketAddr as UnixSocketAddr;
+use std::str::FromStr;
+use std::sync::{Mutex, PoisonError};
+use tracing::warn;
 
 /// Represents the context of an HTTP request, providing access to both the request headers
 /// and any path parameters extracted from the URL.
 ///
+/// It also carries [`Extensions`], which wrappers can use to pass data such as the
+/// authenticated user to the handlers they wrap.
+///
 /// The lifetime parameters ensure that the request context does not outlive the server
 /// or the request data it references.
 pub struct RequestContext<'server: 'req, 'req> {
     request_header: &'req RequestHeader,
     path_params: PathParams<'server, 'req>,
+    extensions: Extensions,
+    remote_addr: Option<SocketAddr>,
+    #[cfg(feature = "unix")]
+    remote_addr_unix: Option<UnixSocketAddr>,
+    trust_proxy: bool,
+    /// The body of the request, taken from it by [`body_mut`](Self::body_mut)
+    req_body: Option<OptionReqBody>,
+    /// Only accessed with `get_mut`, the mutex makes the context `Sync` while `RequestBody` isn't
+    body: Option<Mutex<RequestBody>>,
 }
 
 impl<'server, 'req> RequestContext<'server, 'req> {
     /// Creates a new RequestContext with the given request header and path parameters
     pub fn new(request_header: &'req RequestHeader, path_params: PathParams<'server, 'req>) -> Self {
-        Self { request_header, path_params }
+        Self {
+            request_header,
+            path_params,
+            extensions: Extensions::new(),
+            remote_addr: None,
+            #[cfg(feature = "unix")]
+            remote_addr_unix: None,
+            trust_proxy: false,
+            req_body: None,
+            body: None,
+        }
+    }
+
+    /// Sets the address of the peer the request was received from
+    pub fn with_remote_addr(mut self, remote_addr: Option<SocketAddr>) -> Self {
+        self.remote_addr = remote_addr;
+        self
+    }
+
+    /// Sets the address of the peer of a Unix domain socket the request was received from
+    #[cfg(feature = "unix")]
+    pub fn with_remote_addr_unix(mut self, remote_addr_unix: Option<UnixSocketAddr>) -> Self {
+        self.remote_addr_unix = remote_addr_unix;
+        self
+    }
+
+    /// Sets whether [`client_ip`](Self::client_ip) trusts the `X-Forwarded-For` and `X-Real-IP` headers
+    pub fn with_trust_proxy(mut self, trust_proxy: bool) -> Self {
+        self.trust_proxy = trust_proxy;
+        self
+    }
+
+    /// Sets the body of the request, read by [`body_mut`](Self::body_mut)
+    pub fn with_body(mut self, req_body: OptionReqBody) -> Self {
+        self.req_body = Some(req_body);
+        self
+    }
+
+    /// Replaces the extensions of this context, e.g. to pre-populate them in tests
+    pub fn with_extensions(mut self, extensions: Extensions) -> Self {
+        self.extensions = extensions;
+        self
+    }
+
+    /// Creates a context for another request header, keeping everything else of this context
+    ///
+    /// The extensions are moved into the new context, wrappers should hand them back once the
+    /// inner handler returns.
+    pub(crate) fn with_request_header<'a>(&mut self, request_header: &'a RequestHeader) -> RequestContext<'server, 'a>
+    where
+        'req: 'a,
+    {
+        RequestContext {
+            request_header,
+            path_params: self.path_params.clone(),
+            extensions: std::mem::take(&mut self.extensions),
+            remote_addr: self.remote_addr,
+            #[cfg(feature = "unix")]
+            remote_addr_unix: self.remote_addr_unix.clone(),
+            trust_proxy: self.trust_proxy,
+            req_body: self.req_body.clone(),
+            body: self.body.take(),
+        }
     }
 
     /// Returns a reference to the underlying RequestHeader
@@ -50,9 +141,272 @@ impl<'server, 'req> RequestContext<'server, 'req> {
     }
 
     /// Returns a reference to the path parameters extracted from the request URL
-    pub fn path_params(&self) -> &PathParams {
+    pub fn path_params(&self) -> &PathParams<'server, 'req> {
         &self.path_params
     }
+
+    /// Returns an owned copy of the path parameters, which can be moved into a spawned task
+    ///
+    /// The rest of the context borrows the [`RequestHeader`], it can only outlive the request by cloning
+    /// the parts it needs, such as the [`uri`](Self::uri) or the [`headers`](Self::headers).
+    pub fn to_owned_params(&self) -> OwnedPathParams {
+        self.path_params.clone().into_owned()
+    }
+
+    /// Returns the address of the peer the request was received from
+    ///
+    /// This is the address of the proxy if the server runs behind one, see [`client_ip`](Self::client_ip).
+    /// It's `None` for requests received on a Unix domain socket, see [`remote_addr_unix`](Self::remote_addr_unix).
+    pub fn remote_addr(&self) -> Option<SocketAddr> {
+        self.remote_addr
+    }
+
+    /// Returns the address of the peer of the Unix domain socket the request was received from, `None` for
+    /// requests received over TCP
+    ///
+    /// Clients rarely bind their socket to a path, so the address is usually unnamed.
+    #[cfg(feature = "unix")]
+    pub fn remote_addr_unix(&self) -> Option<&UnixSocketAddr> {
+        self.remote_addr_unix.as_ref()
+    }
+
+    /// Returns the IP address of the client
+    ///
+    /// When proxy headers are trusted, the first address in `X-Forwarded-For`, or else the address
+    /// in `X-Real-IP`, is returned. Otherwise, or if neither header holds a valid address, the IP of
+    /// [`remote_addr`](Self::remote_addr) is returned.
+    pub fn client_ip(&self) -> Option<IpAddr> {
+        if self.trust_proxy {
+            let forwarded_ip = self
+                .headers()
+                .get("x-forwarded-for")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.split(',').next())
+                .and_then(|ip| ip.trim().parse().ok());
+
+            let real_ip = || {
+                self.headers()
+                    .get("x-real-ip")
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|ip| ip.trim().parse().ok())
+            };
+
+            if let Some(ip) = forwarded_ip.or_else(real_ip) {
+                return Some(ip);
+            }
+        }
+
+        self.remote_addr.map(|addr| addr.ip())
+    }
+
+    /// Returns true if the request was received over HTTPS
+    ///
+    /// That's the case if the [`Acceptor`](crate::acceptor::Acceptor) marked the connection as [`Secure`].
+    /// When proxy headers are trusted, the first value of `X-Forwarded-Proto` decides instead, if present.
+    /// The scheme of the request target isn't used, clients choose it freely.
+    pub fn is_secure(&self) -> bool {
+        if self.trust_proxy {
+            let forwarded_proto = self
+                .headers()
+                .get("x-forwarded-proto")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.split(',').next());
+            if let Some(proto) = forwarded_proto {
+                return proto.trim().eq_ignore_ascii_case("https");
+            }
+        }
+
+        self.extensions().get::<Secure>().is_some()
+    }
+
+    /// Returns the body of the request, so handlers can read it without the [`OptionReqBody`] parameter.
+    ///
+    /// The body is taken out of the [`OptionReqBody`] on the first call, the extractors find it consumed
+    /// afterwards. It's empty if it was already consumed, or if the context has no body. The next calls
+    /// return the same body and log a warning, since it may have been partially read.
+    pub fn body_mut(&mut self) -> &mut RequestBody {
+        if self.body.is_some() {
+            warn!(uri = %self.uri(), "the request body is accessed again, it may have been partially read");
+        }
+
+        let req_body = &self.req_body;
+        let body = self.body.get_or_insert_with(|| {
+            Mutex::new(req_body.as_ref().and_then(OptionReqBody::try_take).unwrap_or_else(RequestBody::empty))
+        });
+        body.get_mut().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Returns a reference to the extensions attached to this request
+    pub fn extensions(&self) -> &Extensions {
+        &self.extensions
+    }
+
+    /// Returns a mutable reference to the extensions attached to this request
+    pub fn extensions_mut(&mut self) -> &mut Extensions {
+        &mut self.extensions
+    }
+
+    /// Parses the query string of the request URL into [`QueryParams`]
+    ///
+    /// The query string is parsed on every call, so keep the result if it is needed more than once.
+    pub fn query_params(&self) -> QueryParams<'_> {
+        QueryParams::parse(self.uri().query().unwrap_or_default())
+    }
+
+    /// Parses the `Cookie` headers of the request into a [`CookieJar`]
+    pub fn cookies(&self) -> CookieJar<'_> {
+        CookieJar::from_headers(self.headers())
+    }
+
+    /// Returns the cookies of this request signed with `key`, see [`SignedCookieJar`]
+    pub fn signed_cookies(&self, key: &[u8; 32]) -> SignedCookieJar<'_> {
+        SignedCookieJar::from_headers(key, self.headers())
+    }
+
+    /// Selects the media type of the response from `offered`, ordered by the server's preference,
+    /// according to the `Accept` header
+    ///
+    /// Each offered type gets the quality value of the most specific media range matching it, a
+    /// `type/subtype` range before `type/*` and `*/*`, see RFC 9110 Section 12.5.1. A higher quality
+    /// value wins, the order of `offered` only breaks ties, and `q=0` types are never selected.
+    /// Without an `Accept` header, any type is acceptable, so the first one is returned.
+    pub fn negotiate_content_type<'a>(&self, offered: &[&'a str]) -> Option<&'a str> {
+        if !self.headers().contains_key(http::header::ACCEPT) {
+            return offered.first().copied();
+        }
+        let ranges = self.headers().get_all(http::header::ACCEPT).iter().filter_map(|value| value.to_str().ok());
+        let ranges = ranges.flat_map(|value| value.split(',')).filter_map(MediaRange::parse).collect::<Vec<_>>();
+
+        offered
+            .iter()
+            .enumerate()
+            .filter_map(|(index, offered)| {
+                let (type_, subtype) = offered.split(';').next().unwrap_or_default().trim().split_once('/')?;
+                let quality = ranges
+                    .iter()
+                    .filter_map(|range| Some((range.specificity(type_, subtype)?, range.quality)))
+                    .max_by_key(|(specificity, _)| *specificity)
+                    .map(|(_, quality)| quality)?;
+                (quality > 0).then_some((quality, index, *offered))
+            })
+            // prefer higher quality first, then the lower index in `offered`
+            .max_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)))
+            .map(|(_, _, offered)| offered)
+    }
+
+    /// Returns true if the client accepts `application/json` responses, see
+    /// [`negotiate_content_type`](Self::negotiate_content_type)
+    pub fn accepts_json(&self) -> bool {
+        self.negotiate_content_type(&["application/json"]).is_some()
+    }
+
+    /// Returns true if the client accepts `text/html` responses, see
+    /// [`negotiate_content_type`](Self::negotiate_content_type)
+    pub fn accepts_html(&self) -> bool {
+        self.negotiate_content_type(&["text/html"]).is_some()
+    }
+
+    /// Returns the request `Content-Type`, or `None` if it's missing or isn't valid UTF-8
+    pub fn content_type(&self) -> Option<&str> {
+        self.headers().get(CONTENT_TYPE).and_then(|value| value.to_str().ok())
+    }
+
+    /// Returns the request `Content-Length`, or `None` if it's missing or invalid
+    pub fn content_length(&self) -> Option<u64> {
+        let value = self.headers().get(CONTENT_LENGTH)?.to_str().ok()?;
+        value.trim().parse().ok()
+    }
+
+    /// Returns true if the request `Content-Type` is `application/json` or an `application/*+json` type,
+    /// as required by [`json`](Self::json)
+    pub fn is_json(&self) -> bool {
+        json::check_content_type(self.headers()).is_ok()
+    }
+
+    /// Returns true if the request `Content-Type` is `application/x-www-form-urlencoded`, as required by
+    /// [`form_data`](Self::form_data)
+    pub fn is_form(&self) -> bool {
+        form::check_content_type(self.headers()).is_ok()
+    }
+
+    /// Returns true if the request `Content-Type` is `multipart/form-data`, the boundary is only checked
+    /// by [`multipart`](Self::multipart)
+    pub fn is_multipart(&self) -> bool {
+        self.content_type()
+            .and_then(|value| value.parse::<mime::Mime>().ok())
+            .is_some_and(|mime| mime.type_() == mime::MULTIPART && mime.subtype() == mime::FORM_DATA)
+    }
+
+    /// Reads `body` as `multipart/form-data`, see [`MultipartReader`].
+    ///
+    /// Returns an error if the request `Content-Type` isn't `multipart/form-data` with a boundary.
+    pub fn multipart(&self, body: RequestBody) -> Result<MultipartReader, MultipartError> {
+        MultipartReader::from_headers(self.headers(), body)
+    }
+
+    /// Reads `body` up to `max_bytes` as `application/x-www-form-urlencoded`, see [`FormData`].
+    ///
+    /// Returns an error without reading the body if the request `Content-Type` isn't
+    /// `application/x-www-form-urlencoded`.
+    pub async fn form_data(&self, body: RequestBody, max_bytes: usize) -> Result<FormData, FormError> {
+        form::check_content_type(self.headers())?;
+        FormData::from_body(body, max_bytes).await
+    }
+
+    /// Reads `body` up to [`DEFAULT_JSON_LIMIT`] and deserializes it from JSON, see
+    /// [`json`](crate::json).
+    ///
+    /// Returns an error without reading the body if the request `Content-Type` isn't `application/json`.
+    pub async fn json<T: DeserializeOwned>(&self, body: RequestBody) -> Result<T, JsonBodyError> {
+        json::check_content_type(self.headers())?;
+        json::from_body(body, DEFAULT_JSON_LIMIT).await
+    }
+}
+
+/// A media range of the `Accept` header, such as `text/*;q=0.5`.
+struct MediaRange<'a> {
+    type_: &'a str,
+    subtype: &'a str,
+    /// The quality value in thousandths
+    quality: u16,
+}
+
+impl<'a> MediaRange<'a> {
+    /// Parses a media range, returns `None` if it's malformed.
+    fn parse(value: &'a str) -> Option<Self> {
+        let mut parts = value.split(';');
+        let (type_, subtype) = parts.next()?.trim().split_once('/')?;
+        let (type_, subtype) = (type_.trim(), subtype.trim());
+        if type_.is_empty() || subtype.is_empty() || (type_ == "*" && subtype != "*") {
+            return None;
+        }
+
+        let mut quality = 1000;
+        for param in parts {
+            if let Some((key, value)) = param.split_once('=') {
+                if key.trim().eq_ignore_ascii_case("q") {
+                    let value = value.trim().parse::<f32>().ok().filter(|value| (0.0..=1.0).contains(value))?;
+                    quality = (value * 1000.0).round() as u16;
+                }
+            }
+        }
+        Some(Self { type_, subtype, quality })
+    }
+
+    /// Returns how specifically this range matches `type_/subtype`, higher is more specific, or `None`
+    /// if it doesn't match.
+    fn specificity(&self, type_: &str, subtype: &str) -> Option<u8> {
+        match (self.type_, self.subtype) {
+            ("*", "*") => Some(0),
+            (range_type, "*") if range_type.eq_ignore_ascii_case(type_) => Some(1),
+            (range_type, range_subtype)
+                if range_type.eq_ignore_ascii_case(type_) && range_subtype.eq_ignore_ascii_case(subtype) =>
+            {
+                Some(2)
+            }
+            _ => None,
+        }
+    }
 }
 
 /// Represents path parameters extracted from the URL path of an HTTP request.
@@ -116,6 +470,91 @@ impl<'server, 'req> PathParams<'server, 'req> {
             PathParamsKind::None => None,
         }
     }
+
+    /// Gets the percent-decoded value of a path parameter by its name
+    ///
+    /// The value is borrowed if it has no percent-encoded bytes. Returns None if the parameter doesn't
+    /// exist, or if it's not valid UTF-8 once decoded.
+    pub fn get_decoded(&self, key: impl AsRef<str>) -> Option<Cow<'req, str>> {
+        let key = key.as_ref();
+        match percent_decode_str(self.get(key)?).decode_utf8() {
+            Ok(value) => Some(value),
+            Err(e) => {
+                warn!(key, "path parameter is not valid utf-8 once decoded: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Gets the percent-decoded value of a path parameter by its name, invalid UTF-8 sequences are
+    /// replaced with `U+FFFD`
+    ///
+    /// The value is borrowed if it has no percent-encoded bytes. Returns None if the parameter doesn't exist.
+    pub fn get_decoded_lossy(&self, key: impl AsRef<str>) -> Option<Cow<'req, str>> {
+        self.get(key).map(|value| percent_decode_str(value).decode_utf8_lossy())
+    }
+
+    /// Gets the value of a path parameter by its name and parses it into `T`
+    /// Returns None if the parameter doesn't exist
+    #[inline]
+    pub fn get_typed<T: FromStr>(&self, key: impl AsRef<str>) -> Option<Result<T, T::Err>> {
+        self.get(key).map(str::parse)
+    }
+
+    /// Iterates over all path parameters as `(name, value)` pairs, in the order they appear in the route
+    pub fn iter(&self) -> impl Iterator<Item = (&'server str, &'req str)> + '_ {
+        let params = match &self.kind {
+            PathParamsKind::Params(params) => Some(params.iter()),
+            PathParamsKind::None => None,
+        };
+        params.into_iter().flatten()
+    }
+
+    /// Copies the parameters into [`OwnedPathParams`], which don't borrow the router or the request
+    pub fn into_owned(self) -> OwnedPathParams {
+        OwnedPathParams { params: self.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect() }
+    }
+}
+
+/// Path parameters owning their names and values, see [`PathParams::into_owned`].
+///
+/// Unlike [`PathParams`], they can be moved into `'static` tasks spawned by a handler.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OwnedPathParams {
+    params: Vec<(String, String)>,
+}
+
+impl OwnedPathParams {
+    /// Returns true if there are no path parameters
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.params.is_empty()
+    }
+
+    /// Returns the number of path parameters
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.params.len()
+    }
+
+    /// Gets the value of a path parameter by its name
+    /// Returns None if the parameter doesn't exist
+    pub fn get(&self, key: impl AsRef<str>) -> Option<&str> {
+        let key = key.as_ref();
+        self.params.iter().find(|(name, _)| name == key).map(|(_, value)| value.as_str())
+    }
+
+    /// Gets the value of a path parameter by its name and parses it into `T`
+    /// Returns None if the parameter doesn't exist
+    #[inline]
+    pub fn get_typed<T: FromStr>(&self, key: impl AsRef<str>) -> Option<Result<T, T::Err>> {
+        self.get(key).map(str::parse)
+    }
+
+    /// Iterates over all path parameters as `(name, value)` pairs, in the order they appear in the route
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> + '_ {
+        self.params.iter().map(|(key, value)| (key.as_str(), value.as_str()))
+    }
 }
 
 // Implementation of From trait to convert from Params to PathParams
@@ -124,3 +563,333 @@ impl<'server, 'req> From<Params<'server, 'req>> for PathParams<'server, 'req> {
         PathParams::new(params)
     }
 }
+
+/// Represents query parameters parsed from the query string of an HTTP request.
+///
+/// Both keys and values are percent-decoded, and `+` is decoded as a space.
+/// A key may appear more than once, in which case all of its values are kept in order.
+#[derive(Debug, Clone, Default)]
+pub struct QueryParams<'req> {
+    pairs: Vec<(Cow<'req, str>, Cow<'req, str>)>,
+}
+
+impl<'req> QueryParams<'req> {
+    /// Parses the given query string, without the leading `?`
+    pub fn parse(query: &'req str) -> Self {
+        Self { pairs: form_urlencoded::parse(query.as_bytes()).collect() }
+    }
+
+    /// Returns true if there are no query parameters
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    /// Returns the number of query parameters, counting repeated keys
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    /// Gets the first value of a query parameter by its name
+    /// Returns None if the parameter doesn't exist
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.pairs.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_ref())
+    }
+
+    /// Gets all values of a query parameter by its name, in the order they appear
+    pub fn get_all<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.pairs.iter().filter(move |(k, _)| k == key).map(|(_, v)| v.as_ref())
+    }
+
+    /// Gets the first value of a query parameter and parses it into `T`
+    /// Returns None if the parameter doesn't exist
+    pub fn get_typed<T: FromStr>(&self, key: &str) -> Option<Result<T, T::Err>> {
+        self.get(key).map(str::parse)
+    }
+
+    /// Iterates over all query parameters as `(key, value)` pairs, in the order they appear
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.pairs.iter().map(|(k, v)| (k.as_ref(), v.as_ref()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_params() {
+        let params = QueryParams::parse("a=1&b=hello%20world&a=3&c");
+
+        assert_eq!(params.len(), 4);
+        assert_eq!(params.get("a"), Some("1"));
+        assert_eq!(params.get_all("a").collect::<Vec<_>>(), vec!["1", "3"]);
+        assert_eq!(params.get("b"), Some("hello world"));
+        assert_eq!(params.get("c"), Some(""));
+        assert_eq!(params.get("d"), None);
+    }
+
+    #[test]
+    fn test_query_params_decode_keys() {
+        let params = QueryParams::parse("first%20name=J%C3%BCrgen&last+name=a+b");
+
+        let pairs: Vec<_> = params.iter().collect();
+        assert_eq!(pairs, vec![("first name", "Jürgen"), ("last name", "a b")]);
+    }
+
+    #[test]
+    fn test_query_params_typed() {
+        let params = QueryParams::parse("page=2&size=abc");
+
+        assert_eq!(params.get_typed::<u32>("page"), Some(Ok(2)));
+        assert!(params.get_typed::<u32>("size").unwrap().is_err());
+        assert!(params.get_typed::<u32>("missing").is_none());
+    }
+
+    #[test]
+    fn test_empty_query() {
+        let header: RequestHeader = http::Request::builder().uri("/index").body(()).unwrap().into();
+        let req = RequestContext::new(&header, PathParams::empty());
+        assert!(req.query_params().is_empty());
+
+        let header: RequestHeader = http::Request::builder().uri("/index?").body(()).unwrap().into();
+        let req = RequestContext::new(&header, PathParams::empty());
+        assert!(req.query_params().is_empty());
+
+        let header: RequestHeader = http::Request::builder().uri("/index?id=7").body(()).unwrap().into();
+        let req = RequestContext::new(&header, PathParams::empty());
+        assert_eq!(req.query_params().get("id"), Some("7"));
+    }
+
+    fn accept(value: &str) -> RequestHeader {
+        http::Request::builder().header(http::header::ACCEPT, value).body(()).unwrap().into()
+    }
+
+    #[test]
+    fn test_negotiate_content_type() {
+        let offered = ["application/json", "application/msgpack", "text/html"];
+
+        let header = accept("*/*");
+        let req = RequestContext::new(&header, PathParams::empty());
+        assert_eq!(req.negotiate_content_type(&offered), Some("application/json"));
+        assert!(req.accepts_json() && req.accepts_html());
+
+        let header = accept("application/json;q=0.9, text/html");
+        let req = RequestContext::new(&header, PathParams::empty());
+        assert_eq!(req.negotiate_content_type(&offered), Some("text/html"));
+        assert_eq!(req.negotiate_content_type(&offered[..2]), Some("application/json"));
+        assert!(req.accepts_json() && req.accepts_html());
+
+        let header = accept("text/html, application/xhtml+xml");
+        let req = RequestContext::new(&header, PathParams::empty());
+        assert_eq!(req.negotiate_content_type(&offered), Some("text/html"));
+        assert_eq!(req.negotiate_content_type(&offered[..2]), None);
+        assert!(!req.accepts_json() && req.accepts_html());
+
+        // the most specific range decides, even with a lower quality value
+        let header = accept("application/*;q=0.8, application/msgpack;q=0.1, */*;q=0.5");
+        let req = RequestContext::new(&header, PathParams::empty());
+        assert_eq!(req.negotiate_content_type(&["application/msgpack", "text/html"]), Some("text/html"));
+        assert_eq!(req.negotiate_content_type(&["application/msgpack", "application/json"]), Some("application/json"));
+
+        let header = accept("application/json;q=0, */*");
+        let req = RequestContext::new(&header, PathParams::empty());
+        assert!(!req.accepts_json());
+        assert_eq!(req.negotiate_content_type(&offered), Some("application/msgpack"));
+
+        let header = http::Request::builder().body(()).unwrap().into();
+        let req = RequestContext::new(&header, PathParams::empty());
+        assert_eq!(req.negotiate_content_type(&offered), Some("application/json"));
+        assert_eq!(req.negotiate_content_type(&[]), None);
+    }
+
+    #[test]
+    fn test_content_type() {
+        let header: RequestHeader = http::Request::builder()
+            .header(CONTENT_TYPE, "Application/JSON; charset=utf-8")
+            .header(CONTENT_LENGTH, "42")
+            .body(())
+            .unwrap()
+            .into();
+        let req = RequestContext::new(&header, PathParams::empty());
+        assert_eq!(req.content_type(), Some("Application/JSON; charset=utf-8"));
+        assert_eq!(req.content_length(), Some(42));
+        assert!(req.is_json() && !req.is_form() && !req.is_multipart());
+
+        let header: RequestHeader = http::Request::builder()
+            .header(CONTENT_TYPE, "multipart/form-data; boundary=x")
+            .header(CONTENT_LENGTH, "-1")
+            .body(())
+            .unwrap()
+            .into();
+        let req = RequestContext::new(&header, PathParams::empty());
+        assert_eq!(req.content_length(), None);
+        assert!(req.is_multipart() && !req.is_json());
+
+        let header: RequestHeader = http::Request::builder()
+            .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(())
+            .unwrap()
+            .into();
+        let req = RequestContext::new(&header, PathParams::empty());
+        assert!(req.is_form() && !req.is_multipart());
+
+        let header: RequestHeader = http::Request::builder()
+            .header(CONTENT_TYPE, http::HeaderValue::from_bytes(b"text/\xff").unwrap())
+            .body(())
+            .unwrap()
+            .into();
+        let req = RequestContext::new(&header, PathParams::empty());
+        assert_eq!(req.content_type(), None);
+        assert_eq!(req.content_length(), None);
+        assert!(!req.is_json() && !req.is_form() && !req.is_multipart());
+    }
+
+    #[test]
+    fn test_path_params() {
+        let mut router = matchit::Router::new();
+        router.insert("/users/{id}/posts/{slug}", ()).unwrap();
+        let matched = router.at("/users/42/posts/hello").unwrap();
+        let params = PathParams::from(matched.params);
+
+        assert_eq!(params.len(), 2);
+        assert_eq!(params.get_typed::<u64>("id"), Some(Ok(42)));
+        assert!(params.get_typed::<u64>("slug").unwrap().is_err());
+        assert!(params.get_typed::<u64>("missing").is_none());
+        assert_eq!(params.iter().collect::<Vec<_>>(), vec![("id", "42"), ("slug", "hello")]);
+
+        let params = PathParams::empty();
+        assert!(params.is_empty());
+        assert_eq!(params.iter().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_owned_path_params() {
+        let mut router = matchit::Router::new();
+        router.insert("/users/{id}/posts/{slug}", ()).unwrap();
+        let matched = router.at("/users/42/posts/hello").unwrap();
+        let header: RequestHeader = http::Request::builder().uri("/users/42/posts/hello").body(()).unwrap().into();
+        let req = RequestContext::new(&header, matched.params.into());
+
+        let params = req.to_owned_params();
+        assert_eq!(params, req.path_params().clone().into_owned());
+        let params = tokio::spawn(async move { params }).await.unwrap();
+        assert_eq!(params.len(), 2);
+        assert_eq!(params.get("slug"), Some("hello"));
+        assert_eq!(params.get_typed::<u64>("id"), Some(Ok(42)));
+        assert!(params.get_typed::<u64>("slug").unwrap().is_err());
+        assert!(params.get("missing").is_none());
+        assert_eq!(params.iter().collect::<Vec<_>>(), vec![("id", "42"), ("slug", "hello")]);
+
+        assert!(PathParams::empty().into_owned().is_empty());
+    }
+
+    #[test]
+    fn test_path_params_decoded() {
+        let mut router = matchit::Router::new();
+        router.insert("/files/{name}", ()).unwrap();
+
+        let matched = router.at("/files/hello%20w%C3%B6rld+1").unwrap();
+        let params = PathParams::from(matched.params);
+        assert_eq!(params.get("name"), Some("hello%20w%C3%B6rld+1"));
+        assert_eq!(params.get_decoded("name"), Some(Cow::Owned("hello wörld+1".to_string())));
+        assert_eq!(params.get_decoded_lossy("name").as_deref(), Some("hello wörld+1"));
+        assert!(params.get_decoded("missing").is_none());
+
+        let matched = router.at("/files/plain").unwrap();
+        let params = PathParams::from(matched.params);
+        assert!(matches!(params.get_decoded("name"), Some(Cow::Borrowed("plain"))));
+        assert!(matches!(params.get_decoded_lossy("name"), Some(Cow::Borrowed("plain"))));
+
+        let matched = router.at("/files/a%FFb").unwrap();
+        let params = PathParams::from(matched.params);
+        assert!(params.get_decoded("name").is_none());
+        assert_eq!(params.get_decoded_lossy("name").as_deref(), Some("a\u{FFFD}b"));
+    }
+
+    #[test]
+    fn test_extensions() {
+        #[derive(Debug, Clone, PartialEq)]
+        struct AuthUser {
+            id: u64,
+        }
+
+        let header: RequestHeader = http::Request::builder().uri("/index").body(()).unwrap().into();
+        let mut req = RequestContext::new(&header, PathParams::empty());
+        assert!(req.extensions().get::<AuthUser>().is_none());
+
+        req.extensions_mut().insert(AuthUser { id: 42 });
+        assert_eq!(req.extensions().get::<AuthUser>(), Some(&AuthUser { id: 42 }));
+
+        let mut extensions = Extensions::new();
+        extensions.insert(AuthUser { id: 7 });
+        let req = RequestContext::new(&header, PathParams::empty()).with_extensions(extensions);
+        assert_eq!(req.extensions().get::<AuthUser>(), Some(&AuthUser { id: 7 }));
+    }
+
+    #[test]
+    fn test_client_ip() {
+        let header: RequestHeader = http::Request::builder()
+            .header("x-forwarded-for", "203.0.113.7, 10.0.0.2")
+            .header("x-real-ip", "198.51.100.1")
+            .body(())
+            .unwrap()
+            .into();
+        let remote_addr: SocketAddr = "10.0.0.1:50000".parse().unwrap();
+
+        let req = RequestContext::new(&header, PathParams::empty()).with_remote_addr(Some(remote_addr));
+        assert_eq!(req.remote_addr(), Some(remote_addr));
+        assert_eq!(req.client_ip(), Some(remote_addr.ip()));
+
+        let req = req.with_trust_proxy(true);
+        assert_eq!(req.client_ip(), Some("203.0.113.7".parse().unwrap()));
+
+        let header: RequestHeader =
+            http::Request::builder().header("x-forwarded-for", "garbage").header("x-real-ip", "198.51.100.1").body(()).unwrap().into();
+        let req = RequestContext::new(&header, PathParams::empty()).with_trust_proxy(true);
+        assert_eq!(req.client_ip(), Some("198.51.100.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_is_secure() {
+        let header: RequestHeader = http::Request::builder().uri("https://example.com/").body(()).unwrap().into();
+        let mut req = RequestContext::new(&header, PathParams::empty());
+        assert!(!req.is_secure());
+        req.extensions_mut().insert(Secure);
+        assert!(req.is_secure());
+
+        let header: RequestHeader =
+            http::Request::builder().header("x-forwarded-proto", "https, http").body(()).unwrap().into();
+        let req = RequestContext::new(&header, PathParams::empty());
+        assert!(!req.is_secure());
+        let req = req.with_trust_proxy(true);
+        assert!(req.is_secure());
+
+        // a trusted proxy received the request over plain HTTP
+        let header: RequestHeader =
+            http::Request::builder().header("x-forwarded-proto", "http").body(()).unwrap().into();
+        let mut req = RequestContext::new(&header, PathParams::empty()).with_trust_proxy(true);
+        req.extensions_mut().insert(Secure);
+        assert!(!req.is_secure());
+    }
+
+    #[tokio::test]
+    async fn test_body_mut() {
+        use http_body_util::{BodyExt, Full};
+
+        let header: RequestHeader = http::Request::builder().uri("/upload").body(()).unwrap().into();
+        let body = RequestBody::boxed(Full::new("hello".into()).map_err(|never| match never {}));
+        let req_body = OptionReqBody::from(body);
+        let mut req = RequestContext::new(&header, PathParams::empty()).with_body(req_body.clone());
+
+        let frame = req.body_mut().frame().await.unwrap().unwrap();
+        assert_eq!(frame.into_data().unwrap(), "hello");
+        // the same body, read to the end
+        assert!(req.body_mut().frame().await.is_none());
+        assert!(!req_body.can_consume().await);
+
+        let mut req = RequestContext::new(&header, PathParams::empty());
+        assert!(req.body_mut().frame().await.is_none());
+    }
+}
@@ -0,0 +1,242 @@
+//! Builder for common HTTP responses.
+//!
+//! [`ResponseBuilder`] provides constructors for the responses handlers return most often, so they
+//! don't have to assemble them with `http::Response::builder()`:
+//!
+//! ```
+//! use micro_web::ResponseBuilder;
+//! use serde::Serialize;
+//!
+//! #[derive(Serialize)]
+//! struct User {
+//!     id: u64,
+//! }
+//!
+//! async fn create_user() -> ResponseBuilder {
+//!     ResponseBuilder::json(&User { id: 42 }).status(http::StatusCode::CREATED)
+//! }
+//!
+//! async fn delete_user() -> ResponseBuilder {
+//!     ResponseBuilder::no_content()
+//! }
+//! ```
+//!
+//! A `ResponseBuilder` is a [`Responder`], so handlers can return it directly, or turn it into a
+//! response with [`ResponseBuilder::body`] or [`ResponseBuilder::build`].
+
+use crate::responder::Responder;
+use crate::sse::{SseBody, SseEvent};
+use crate::{RequestContext, ResponseBody};
+use bytes::Bytes;
+use futures::Stream;
+use http::header::{CACHE_CONTROL, CONNECTION, CONTENT_TYPE, LOCATION};
+use http::{HeaderMap, HeaderName, HeaderValue, Response, StatusCode};
+use mime::Mime;
+use serde::Serialize;
+use tracing::error;
+
+/// A builder of `Response<ResponseBody>`.
+pub struct ResponseBuilder {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: ResponseBody,
+}
+
+impl ResponseBuilder {
+    /// Creates a builder with the given status and an empty body.
+    pub fn new(status: StatusCode) -> Self {
+        Self { status, headers: HeaderMap::new(), body: ResponseBody::empty() }
+    }
+
+    /// `200 OK`
+    pub fn ok() -> Self {
+        Self::new(StatusCode::OK)
+    }
+
+    /// `201 Created` with the `Location` of the new resource.
+    ///
+    /// # Panics
+    /// Panics if `location` contains characters that are not allowed in a header value.
+    pub fn created(location: &str) -> Self {
+        Self::new(StatusCode::CREATED).header(LOCATION, HeaderValue::from_str(location).unwrap())
+    }
+
+    /// `204 No Content`
+    pub fn no_content() -> Self {
+        Self::new(StatusCode::NO_CONTENT)
+    }
+
+    /// A redirect to `location`, `status` should be one of the `3xx` status codes.
+    ///
+    /// # Panics
+    /// Panics if `location` contains characters that are not allowed in a header value.
+    pub fn redirect(status: StatusCode, location: &str) -> Self {
+        Self::new(status).header(LOCATION, HeaderValue::from_str(location).unwrap())
+    }
+
+    /// `400 Bad Request` with the given body.
+    pub fn bad_request(body: impl Into<ResponseBody>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST).with_body(body)
+    }
+
+    /// `404 Not Found`
+    pub fn not_found() -> Self {
+        Self::new(StatusCode::NOT_FOUND)
+    }
+
+    /// `500 Internal Server Error`
+    pub fn internal_error() -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+
+    /// `200 OK` with `value` serialized as JSON.
+    ///
+    /// If serialization fails, the error is logged and a `500 Internal Server Error` is built instead.
+    pub fn json<T: Serialize + ?Sized>(value: &T) -> Self {
+        match serde_json::to_vec(value) {
+            Ok(json) => Self::ok().content_type(mime::APPLICATION_JSON).with_body(Bytes::from(json)),
+            Err(e) => {
+                error!("failed to serialize the response body as json: {}", e);
+                Self::internal_error()
+            }
+        }
+    }
+
+    /// `200 OK` sending the events of `stream` as server-sent events, see [`SseBody`].
+    pub fn sse<S>(stream: S) -> Self
+    where
+        S: Stream<Item = SseEvent> + Send + 'static,
+    {
+        Self::sse_body(SseBody::new(stream))
+    }
+
+    /// `200 OK` with the given server-sent events body, to customize its heartbeat interval.
+    ///
+    /// The response is sent with `Cache-Control: no-cache, no-transform`, so
+    /// [`EncodeWrapper`](struct@crate::wrapper::EncodeWrapper) doesn't compress it: an encoder holds back its output
+    /// until enough data is buffered, which would delay the events.
+    pub fn sse_body<S>(body: SseBody<S>) -> Self
+    where
+        S: Stream<Item = SseEvent> + Send + 'static,
+    {
+        Self::ok()
+            .content_type(mime::TEXT_EVENT_STREAM)
+            .header(CACHE_CONTROL, HeaderValue::from_static("no-cache, no-transform"))
+            .header(CONNECTION, HeaderValue::from_static("keep-alive"))
+            .with_body(ResponseBody::stream(body))
+    }
+
+    /// Sets the status.
+    pub fn status(mut self, status: StatusCode) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// Sets the header `name`, replacing any previous value.
+    pub fn header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.insert(name, value);
+        self
+    }
+
+    /// Sets the `Content-Type` header.
+    pub fn content_type(self, mime: Mime) -> Self {
+        // a parsed mime is always a valid header value
+        self.header(CONTENT_TYPE, HeaderValue::from_str(mime.as_ref()).unwrap())
+    }
+
+    /// Builds the response with the given body.
+    pub fn body(self, body: impl Into<ResponseBody>) -> Response<ResponseBody> {
+        self.with_body(body).build()
+    }
+
+    /// Builds the response with the body set by the constructor, or an empty one.
+    pub fn build(self) -> Response<ResponseBody> {
+        let mut resp = Response::new(self.body);
+        *resp.status_mut() = self.status;
+        *resp.headers_mut() = self.headers;
+        resp
+    }
+
+    fn with_body(mut self, body: impl Into<ResponseBody>) -> Self {
+        self.body = body.into();
+        self
+    }
+}
+
+impl From<ResponseBuilder> for Response<ResponseBody> {
+    fn from(builder: ResponseBuilder) -> Self {
+        builder.build()
+    }
+}
+
+impl Responder for ResponseBuilder {
+    fn response_to(self, _req: &RequestContext) -> Response<ResponseBody> {
+        self.build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::BodyExt;
+    use serde::Serialize;
+
+    async fn body_of(resp: Response<ResponseBody>) -> Bytes {
+        resp.into_body().collect().await.unwrap().to_bytes()
+    }
+
+    #[tokio::test]
+    async fn test_constructors() {
+        let resp = ResponseBuilder::created("/users/42").build();
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        assert_eq!(resp.headers().get(LOCATION).unwrap(), "/users/42");
+
+        let resp = ResponseBuilder::redirect(StatusCode::SEE_OTHER, "/login").build();
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        assert_eq!(resp.headers().get(LOCATION).unwrap(), "/login");
+
+        let resp = ResponseBuilder::bad_request("missing name").build();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(body_of(resp).await, "missing name");
+
+        assert_eq!(ResponseBuilder::no_content().build().status(), StatusCode::NO_CONTENT);
+        assert_eq!(ResponseBuilder::not_found().build().status(), StatusCode::NOT_FOUND);
+        assert_eq!(ResponseBuilder::internal_error().build().status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[tokio::test]
+    async fn test_setters() {
+        let resp = ResponseBuilder::ok()
+            .content_type(mime::TEXT_HTML_UTF_8)
+            .header(http::header::CACHE_CONTROL, HeaderValue::from_static("no-cache"))
+            .body("<p>hello</p>");
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers().get(CONTENT_TYPE).unwrap(), "text/html; charset=utf-8");
+        assert_eq!(resp.headers().get(http::header::CACHE_CONTROL).unwrap(), "no-cache");
+        assert_eq!(body_of(resp).await, "<p>hello</p>");
+    }
+
+    #[tokio::test]
+    async fn test_json() {
+        #[derive(Serialize)]
+        struct User {
+            id: u64,
+            name: &'static str,
+        }
+
+        let resp = ResponseBuilder::json(&User { id: 42, name: "alice" }).build();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers().get(CONTENT_TYPE).unwrap(), "application/json");
+        assert_eq!(body_of(resp).await, r#"{"id":42,"name":"alice"}"#);
+    }
+
+    #[tokio::test]
+    async fn test_sse() {
+        let resp = ResponseBuilder::sse(futures::stream::iter(vec![SseEvent::new("hello")])).build();
+        assert_eq!(resp.headers().get(CONTENT_TYPE).unwrap(), "text/event-stream");
+        assert_eq!(resp.headers().get(CACHE_CONTROL).unwrap(), "no-cache, no-transform");
+        assert_eq!(resp.headers().get(CONNECTION).unwrap(), "keep-alive");
+        assert_eq!(body_of(resp).await, "data: hello\n\n");
+    }
+}
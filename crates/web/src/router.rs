@@ -18,10 +18,17 @@
 //! ```
 
 use crate::filter::{AllFilter, Filter};
-use crate::handler::RequestHandler;
-use crate::{filter, PathParams};
+use crate::handler::{handler_fn, RequestHandler};
+use crate::{filter, PathParams, RequestContext};
 
+use std::any::type_name;
+use std::cmp::Reverse;
 use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use http::{HeaderValue, Method, StatusCode};
+use thiserror::Error;
 
 use crate::wrapper::{IdentityWrapper, IdentityWrappers, Wrapper, Wrappers};
 use tracing::error;
@@ -30,8 +37,47 @@ type RouterFilter = dyn Filter + Send + Sync + 'static;
 type InnerRouter<T> = matchit::Router<T>;
 
 /// Main router structure that handles HTTP request routing
+///
+/// Its [`Display`](fmt::Display) implementation prints a table of the registered routes, see [`Router::routes`].
 pub struct Router {
-    inner_router: InnerRouter<Vec<RouterItem>>,
+    inner_router: InnerRouter<Route>,
+    routes: Vec<RouteInfo>,
+    not_found: Option<Box<dyn RequestHandler>>,
+    method_not_allowed: Box<dyn RequestHandler>,
+}
+
+/// The items registered for a route
+struct Route {
+    route: MatchedRoute,
+    items: Vec<RouterItem>,
+    /// The methods of the items, `HEAD` included when there is a `GET` item
+    methods: Vec<Method>,
+}
+
+/// A route registered in a [`Router`], see [`Router::routes`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouteInfo {
+    /// The method of the route, a `GET` route also answers `HEAD` requests
+    pub method: Method,
+    /// The path pattern, such as `/users/{id}`
+    pub pattern: String,
+    /// The type name of the handler, before it was wrapped
+    pub handler: &'static str,
+    /// The type names of the wrappers of the route, in the order they run
+    pub wrappers: Vec<&'static str>,
+}
+
+/// The route template a request matched, such as `/users/{id}`.
+///
+/// The server adds it to the [request extensions](crate::RequestContext::extensions) of matched requests.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatchedRoute(Arc<str>);
+
+impl MatchedRoute {
+    /// Returns the route template.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
 }
 
 /// A router item containing a filter and handler
@@ -40,8 +86,55 @@ pub struct RouterItem {
     handler: Box<dyn RequestHandler>,
 }
 
+/// The route item selected for a request, see [`RouteResult::find`]
+#[derive(Clone, Copy)]
+pub struct RouteMatch<'router> {
+    route: &'router MatchedRoute,
+    item: &'router RouterItem,
+}
+
+impl<'router> RouteMatch<'router> {
+    /// Gets the matched route template
+    pub fn route(&self) -> &'router MatchedRoute {
+        self.route
+    }
+
+    /// Gets the request handler of the selected item
+    pub fn handler(&self) -> &'router dyn RequestHandler {
+        self.item.handler()
+    }
+}
+
+/// Why no route item was selected for a request, see [`RouteResult::find`]
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum RoutingError {
+    /// No route matches the path, or the filters of its items rejected the request
+    #[error("no route found")]
+    NotFound,
+
+    /// A route matches the path, but none of its items is registered for the method
+    #[error("method not allowed, allowed methods: {allowed_methods:?}")]
+    MethodNotAllowed { allowed_methods: Vec<Method> },
+}
+
+impl RoutingError {
+    /// Returns the `Allow` header listing the allowed methods of a [`MethodNotAllowed`](Self::MethodNotAllowed)
+    /// error
+    pub fn allow(&self) -> Option<HeaderValue> {
+        match self {
+            RoutingError::NotFound => None,
+            RoutingError::MethodNotAllowed { allowed_methods } => {
+                let allow = allowed_methods.iter().map(Method::as_str).collect::<Vec<_>>().join(", ");
+                // methods are tokens, always valid header values
+                Some(HeaderValue::from_str(&allow).unwrap())
+            }
+        }
+    }
+}
+
 /// Result of matching a route, containing matched items and path parameters
 pub struct RouteResult<'router, 'req> {
+    route: Option<&'router Route>,
     router_item: &'router [RouterItem],
     params: PathParams<'router, 'req>,
 }
@@ -61,13 +154,54 @@ impl Router {
     pub fn at<'router, 'req>(&'router self, path: &'req str) -> RouteResult<'router, 'req> {
         self.inner_router
             .at(path)
-            .map(|matched| RouteResult { 
-                router_item: matched.value.as_slice(), 
-                params: matched.params.into() 
+            .map(|matched| RouteResult {
+                route: Some(matched.value),
+                router_item: matched.value.items.as_slice(),
+                params: matched.params.into()
             })
             .map_err(|e| error!("match {} error: {}", path, e))
             .unwrap_or(RouteResult::empty())
     }
+
+    /// Returns the registered routes, sorted by pattern, the routes of a pattern in the order they were added
+    pub fn routes(&self) -> impl Iterator<Item = &RouteInfo> {
+        self.routes.iter()
+    }
+
+    /// Returns the handler of the requests whose path matches no route, see
+    /// [`RouterBuilder::not_found_handler`]
+    pub fn not_found_handler(&self) -> Option<&dyn RequestHandler> {
+        self.not_found.as_deref()
+    }
+
+    /// Returns the handler of the requests whose path matches a route but whose method doesn't, see
+    /// [`RouterBuilder::method_not_allowed_handler`]
+    pub fn method_not_allowed_handler(&self) -> &dyn RequestHandler {
+        self.method_not_allowed.as_ref()
+    }
+}
+
+impl fmt::Display for Router {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let method_width = self.routes.iter().map(|route| route.method.as_str().len()).chain([6]).max().unwrap();
+        let pattern_width = self.routes.iter().map(|route| route.pattern.len()).chain([7]).max().unwrap();
+
+        write!(f, "{:method_width$}  {:pattern_width$}  HANDLER", "METHOD", "PATTERN")?;
+        for route in &self.routes {
+            let (method, pattern) = (route.method.as_str(), route.pattern.as_str());
+            write!(f, "\n{method:method_width$}  {pattern:pattern_width$}  {}", route.handler)?;
+            if !route.wrappers.is_empty() {
+                write!(f, " [{}]", route.wrappers.join(", "))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Router {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Router").field("routes", &self.routes).finish_non_exhaustive()
+    }
 }
 
 impl RouterItem {
@@ -84,7 +218,30 @@ impl RouterItem {
 
 impl<'router, 'req> RouteResult<'router, 'req> {
     fn empty() -> Self {
-        Self { router_item: &[], params: PathParams::empty() }
+        Self { route: None, router_item: &[], params: PathParams::empty() }
+    }
+
+    /// Gets the matched route template, `None` if no route matched
+    pub fn route(&self) -> Option<&'router MatchedRoute> {
+        self.route.map(|route| &route.route)
+    }
+
+    /// Returns true if a route matched and one of its items is registered for `method`
+    pub fn allows(&self, method: &Method) -> bool {
+        self.route.is_some_and(|route| route.methods.contains(method))
+    }
+
+    /// Selects the first item of the matched route whose filter matches `req`
+    ///
+    /// Returns [`RoutingError::MethodNotAllowed`] if no item is registered for the method of the request,
+    /// and [`RoutingError::NotFound`] if no route matched or the other filters rejected the request.
+    pub fn find(&self, req: &RequestContext) -> Result<RouteMatch<'router>, RoutingError> {
+        let route = self.route.ok_or(RoutingError::NotFound)?;
+        match route.items.iter().find(|item| item.filter().matches(req)) {
+            Some(item) => Ok(RouteMatch { route: &route.route, item }),
+            None if route.methods.contains(req.method()) => Err(RoutingError::NotFound),
+            None => Err(RoutingError::MethodNotAllowed { allowed_methods: route.methods.clone() }),
+        }
     }
 
     /// Returns true if no routes were matched
@@ -104,6 +261,23 @@ impl<'router, 'req> RouteResult<'router, 'req> {
     }
 }
 
+/// Errors of [`RouterBuilder::try_build`].
+#[derive(Error, Debug)]
+pub enum RouterBuildError {
+    /// The routes name the parameter at the same position differently, e.g. `/users/{id}` and
+    /// `/users/{name}/posts`, so the name a handler gets would depend on the route that matched
+    #[error("routes name the same path parameter differently: {}", routes.join(", "))]
+    ConflictingParams { routes: Vec<String> },
+
+    /// The route is invalid, or conflicts with another one
+    #[error("invalid route {route}: {source}")]
+    InvalidRoute {
+        route: String,
+        #[source]
+        source: matchit::InsertError,
+    },
+}
+
 /// Builder for constructing a router with routes and wrappers
 pub struct RouterBuilder<HeadW, TailW>
 where
@@ -113,11 +287,20 @@ where
 {
     data: HashMap<String, Vec<RouterItemBuilder>>,
     wrappers: Wrappers<HeadW, TailW, Box<dyn RequestHandler>>,
+    wrapper_names: Vec<&'static str>,
+    not_found: Option<Box<dyn RequestHandler>>,
+    method_not_allowed: Option<Box<dyn RequestHandler>>,
 }
 
 impl RouterBuilder<IdentityWrapper, IdentityWrapper> {
     fn new() -> Self {
-        Self { data: HashMap::new(), wrappers: IdentityWrappers::default() }
+        Self {
+            data: HashMap::new(),
+            wrappers: IdentityWrappers::default(),
+            wrapper_names: vec![],
+            not_found: None,
+            method_not_allowed: None,
+        }
     }
 }
 impl<HeadW, TailW> RouterBuilder<HeadW, TailW>
@@ -132,14 +315,32 @@ where
     /// * `route` - The path pattern to match
     /// * `item_builder` - The router item builder containing filters and handler
     pub fn route(mut self, route: impl Into<String>, item_builder: RouterItemBuilder) -> Self {
-        let vec = self.data.entry(route.into()).or_insert_with(Vec::new);
+        let vec = self.data.entry(route.into()).or_default();
         vec.push(item_builder);
         self
     }
 
+    /// Adds the routes of `group`, under its prefix and wrapped by its wrappers
+    ///
+    /// The wrappers of the router run before the wrappers of the group, which are ordered by their
+    /// [`priority`](Wrapper::priority).
+    pub fn group(mut self, group: RouteGroup) -> Self {
+        let mut wrappers = group.wrappers;
+        // the first wrapper is the innermost, sorting is stable so equal priorities keep the order they were added
+        wrappers.sort_by_key(|wrapper| Reverse(wrapper.priority));
+        for (route, mut item_builder) in group.routes {
+            item_builder.handler =
+                wrappers.iter().fold(item_builder.handler, |handler, wrapper| (wrapper.wrap)(handler));
+            item_builder.wrappers.extend(wrappers.iter().rev().map(|wrapper| wrapper.name));
+            self = self.route(route, item_builder);
+        }
+        self
+    }
+
     /// Adds a wrapper to the router builder
     ///
-    /// Wrappers can modify or enhance the behavior of handlers
+    /// Wrappers can modify or enhance the behavior of handlers, the last added wrapper runs first. Unlike
+    /// the wrappers of a [`RouteGroup`], they are not reordered by their [`priority`](Wrapper::priority).
     pub fn wrap<NewW>(
         self,
         handler_wrapper: NewW,
@@ -148,56 +349,195 @@ where
         NewW: Wrapper<TailW::Out>,
         NewW::Out: RequestHandler,
     {
-        RouterBuilder { data: self.data, wrappers: self.wrappers.and_then(handler_wrapper) }
+        let mut wrapper_names = self.wrapper_names;
+        wrapper_names.push(type_name::<NewW>());
+        RouterBuilder {
+            data: self.data,
+            wrappers: self.wrappers.and_then(handler_wrapper),
+            wrapper_names,
+            not_found: self.not_found,
+            method_not_allowed: self.method_not_allowed,
+        }
+    }
+
+    /// Sets the handler of the requests whose path matches no route, such as a custom 404 page
+    ///
+    /// Like the routes, it's wrapped by the wrappers of the router. Without it, the requests are answered by
+    /// the default handler of the [`Server`](crate::Server), which isn't wrapped.
+    pub fn not_found_handler(mut self, handler: impl RequestHandler + 'static) -> Self {
+        self.not_found = Some(Box::new(handler));
+        self
+    }
+
+    /// Sets the handler of the requests whose path matches a route but whose method doesn't
+    ///
+    /// Like the routes, it's wrapped by the wrappers of the router. The server adds an `Allow` header listing
+    /// the methods of the route to its response, unless the handler set one, and the [`RoutingError`] to the
+    /// [request extensions](RequestContext::extensions). Defaults to a `405 Method Not Allowed` response.
+    pub fn method_not_allowed_handler(mut self, handler: impl RequestHandler + 'static) -> Self {
+        self.method_not_allowed = Some(Box::new(handler));
+        self
     }
 
     /// Builds the router from the accumulated routes and wrappers
+    ///
+    /// # Panics
+    /// If the routes are invalid, see [`try_build`](Self::try_build).
     pub fn build(self) -> Router {
+        self.try_build().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Builds the router from the accumulated routes and wrappers, or returns why the routes are invalid
+    pub fn try_build(self) -> Result<Router, RouterBuildError> {
+        check_param_names(self.data.keys().map(String::as_str))?;
+
         let mut inner_router = InnerRouter::new();
+        let mut routes = vec![];
 
         for (path, items) in self.data.into_iter() {
+            let mut methods = vec![];
+            for method in items.iter().flat_map(|item| match item.method {
+                Method::GET => vec![Method::GET, Method::HEAD],
+                ref method => vec![method.clone()],
+            }) {
+                if !methods.contains(&method) {
+                    methods.push(method);
+                }
+            }
+
             let router_items = items
                 .into_iter()
-                .map(|item_builder| item_builder.build())
+                .map(|item_builder| {
+                    // the last added wrapper of the router runs first, then the wrappers of the group
+                    let wrappers = self.wrapper_names.iter().rev().chain(&item_builder.wrappers).copied().collect();
+                    let handler = item_builder.handler_name;
+                    let method = item_builder.method.clone();
+                    routes.push(RouteInfo { method, pattern: path.clone(), handler, wrappers });
+                    item_builder.build()
+                })
                 .map(|item| {
                     let handler = self.wrappers.wrap(item.handler);
                     RouterItem { handler: Box::new(handler), ..item }
                 })
                 .collect::<Vec<_>>();
 
-            inner_router.insert(path, router_items).unwrap();
+            let route = MatchedRoute(Arc::from(path.as_str()));
+            inner_router
+                .insert(path.clone(), Route { route, items: router_items, methods })
+                .map_err(|source| RouterBuildError::InvalidRoute { route: path, source })?;
         }
 
-        Router { inner_router }
+        // sorting is stable, so the routes of a pattern stay in the order they were added
+        routes.sort_by(|a, b| a.pattern.cmp(&b.pattern));
+
+        let not_found =
+            self.not_found.map(|handler| Box::new(self.wrappers.wrap(handler)) as Box<dyn RequestHandler>);
+        let method_not_allowed =
+            self.method_not_allowed.unwrap_or_else(|| Box::new(handler_fn(method_not_allowed_handler)));
+        let method_not_allowed = Box::new(self.wrappers.wrap(method_not_allowed));
+        Ok(Router { inner_router, routes, not_found, method_not_allowed })
+    }
+}
+
+async fn method_not_allowed_handler() -> (StatusCode, &'static str) {
+    (StatusCode::METHOD_NOT_ALLOWED, "405 Method Not Allowed")
+}
+
+/// Checks the routes give the same name to the parameters at the same position.
+///
+/// The position of a parameter is the route before it, with the names of the previous parameters removed.
+fn check_param_names<'a>(routes: impl Iterator<Item = &'a str>) -> Result<(), RouterBuildError> {
+    let mut routes = routes.collect::<Vec<_>>();
+    routes.sort_unstable();
+
+    // the name of the parameter and the routes naming it, by position
+    let mut positions: HashMap<String, Vec<(&str, &str)>> = HashMap::new();
+    for &route in &routes {
+        let mut position = String::new();
+        let mut rest = route;
+        while let Some(start) = rest.find(['{', '}']) {
+            let (text, tail) = rest.split_at(start);
+            position.push_str(text);
+            // `{{` and `}}` are escaped braces
+            if tail.starts_with("{{") || tail.starts_with("}}") {
+                position.push_str(&tail[..2]);
+                rest = &tail[2..];
+                continue;
+            }
+            let Some(end) = tail.find('}') else {
+                // invalid, left to the router to report
+                break;
+            };
+
+            let name = &tail[1..end];
+            let marker = if name.starts_with('*') { "{*}" } else { "{}" };
+            positions.entry(format!("{position}{marker}")).or_default().push((name, route));
+            position.push_str(marker);
+            rest = &tail[end + 1..];
+        }
+    }
+
+    let mut conflicts = positions
+        .into_values()
+        .filter(|names| names.iter().any(|(name, _)| *name != names[0].0))
+        .map(|names| {
+            let mut routes = names.into_iter().map(|(_, route)| route.to_string()).collect::<Vec<_>>();
+            routes.dedup();
+            routes
+        })
+        .collect::<Vec<_>>();
+    conflicts.sort_unstable();
+
+    match conflicts.into_iter().next() {
+        Some(routes) => Err(RouterBuildError::ConflictingParams { routes }),
+        None => Ok(()),
     }
 }
 
 macro_rules! method_router_filter {
-    ($method:ident, $method_name:ident) => {
+    ($method:ident, $method_name:ident, $method_const:ident) => {
         pub fn $method<H: RequestHandler + 'static>(handler: H) -> RouterItemBuilder {
             let mut filters = filter::all_filter();
             filters.and(filter::$method_name());
-            RouterItemBuilder { filters, handler: Box::new(handler) }
+            RouterItemBuilder::new(Method::$method_const, filters, handler)
         }
     };
 }
 
-method_router_filter!(get, get_method);
-method_router_filter!(post, post_method);
-method_router_filter!(put, put_method);
-method_router_filter!(delete, delete_method);
-method_router_filter!(head, head_method);
-method_router_filter!(options, options_method);
-method_router_filter!(connect, connect_method);
-method_router_filter!(patch, patch_method);
-method_router_filter!(trace, trace_method);
+/// Routes `GET` requests, and `HEAD` requests which get the same response headers without the body
+///
+/// A [`head`] route for the same path must be added first to answer `HEAD` requests differently.
+pub fn get<H: RequestHandler + 'static>(handler: H) -> RouterItemBuilder {
+    let mut methods = filter::any_filter();
+    methods.or(filter::get_method()).or(filter::head_method());
+    let mut filters = filter::all_filter();
+    filters.and(methods);
+    RouterItemBuilder::new(Method::GET, filters, handler)
+}
+
+method_router_filter!(post, post_method, POST);
+method_router_filter!(put, put_method, PUT);
+method_router_filter!(delete, delete_method, DELETE);
+method_router_filter!(head, head_method, HEAD);
+method_router_filter!(options, options_method, OPTIONS);
+method_router_filter!(connect, connect_method, CONNECT);
+method_router_filter!(patch, patch_method, PATCH);
+method_router_filter!(trace, trace_method, TRACE);
 
 pub struct RouterItemBuilder {
+    method: Method,
     filters: AllFilter,
     handler: Box<dyn RequestHandler>,
+    handler_name: &'static str,
+    /// The type names of the group wrappers, in the order they run
+    wrappers: Vec<&'static str>,
 }
 
 impl RouterItemBuilder {
+    fn new<H: RequestHandler + 'static>(method: Method, filters: AllFilter, handler: H) -> Self {
+        Self { method, filters, handler: Box::new(handler), handler_name: type_name::<H>(), wrappers: vec![] }
+    }
+
     pub fn with<F: Filter + Send + Sync + 'static>(mut self, filter: F) -> Self {
         self.filters.and(filter);
         self
@@ -209,13 +549,104 @@ impl RouterItemBuilder {
     }
 }
 
+type WrapFn = dyn Fn(Box<dyn RequestHandler>) -> Box<dyn RequestHandler>;
+
+/// A type-erased wrapper of a [`RouteGroup`]
+struct GroupWrapper {
+    name: &'static str,
+    priority: i32,
+    wrap: Box<WrapFn>,
+}
+
+/// Routes sharing a path prefix and wrappers, added to a router with [`RouterBuilder::group`]
+///
+/// # Examples
+///
+/// ```no_run
+/// use micro_web::router::{get, RouteGroup, Router};
+/// use micro_web::wrapper::{BearerAuthWrapper, HmacSha256Validator};
+/// use micro_web::handler_fn;
+///
+/// async fn users() -> &'static str {
+///     "[]"
+/// }
+///
+/// async fn health() -> &'static str {
+///     "ok"
+/// }
+///
+/// let api = RouteGroup::new("/api/v1")
+///     .wrap(BearerAuthWrapper::new(HmacSha256Validator::new(b"secret")))
+///     .get("/users", handler_fn(users));
+///
+/// // `/health` is not authenticated
+/// let router = Router::builder().route("/health", get(handler_fn(health))).group(api).build();
+/// ```
+pub struct RouteGroup {
+    prefix: String,
+    routes: Vec<(String, RouterItemBuilder)>,
+    wrappers: Vec<GroupWrapper>,
+}
+
+macro_rules! group_method_route {
+    ($method:ident) => {
+        #[doc = concat!("Adds a `", stringify!($method), "` route, see [`", stringify!($method), "`]")]
+        pub fn $method<H: RequestHandler + 'static>(self, route: &str, handler: H) -> Self {
+            self.route(route, $method(handler))
+        }
+    };
+}
+
+impl RouteGroup {
+    /// Creates an empty group whose routes start with `prefix`, such as `/api/v1`
+    pub fn new(prefix: &str) -> Self {
+        Self { prefix: prefix.trim_end_matches('/').to_string(), routes: vec![], wrappers: vec![] }
+    }
+
+    /// Adds a route, `route` is appended to the prefix of the group
+    pub fn route(mut self, route: &str, item_builder: RouterItemBuilder) -> Self {
+        let route = match route {
+            "" | "/" if !self.prefix.is_empty() => self.prefix.clone(),
+            _ => format!("{}{}", self.prefix, route),
+        };
+        self.routes.push((route, item_builder));
+        self
+    }
+
+    group_method_route!(get);
+    group_method_route!(post);
+    group_method_route!(put);
+    group_method_route!(delete);
+    group_method_route!(patch);
+
+    /// Adds a wrapper applied only to the routes of this group
+    ///
+    /// Wrappers run by their [`priority`](Wrapper::priority), lower first. Among the same priority, like
+    /// [`RouterBuilder::wrap`], the last added wrapper runs first.
+    pub fn wrap<W>(mut self, wrapper: W) -> Self
+    where
+        W: Wrapper<Box<dyn RequestHandler>> + 'static,
+        W::Out: RequestHandler + 'static,
+    {
+        let (name, priority) = (type_name::<W>(), wrapper.priority());
+        let wrap = Box::new(move |handler| Box::new(wrapper.wrap(handler)) as Box<dyn RequestHandler>);
+        self.wrappers.push(GroupWrapper { name, priority, wrap });
+        self
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::filter::header;
-    use crate::router::{get, post, Router};
-    use crate::{handler_fn, PathParams, RequestContext};
-    use http::{HeaderValue, Method, Request};
+    use crate::handler::RequestHandler;
+    use crate::router::{delete, get, post, put, RouteGroup, Router, RouterBuildError, RoutingError};
+    use crate::testing::TestClient;
+    use crate::wrapper::{priority, Wrapper};
+    use crate::{handler_fn, OptionReqBody, PathParams, RequestContext, ResponseBody};
+    use async_trait::async_trait;
+    use http::{HeaderValue, Method, Request, Response, StatusCode};
     use micro_http::protocol::RequestHeader;
+    use std::sync::{Arc, Mutex};
 
     async fn simple_get_1(_method: &Method) -> String {
         "hello world".into()
@@ -300,4 +731,342 @@ mod tests {
         assert!(items[1].filter.matches(&req_ctx));
         assert!(items[2].filter.matches(&req_ctx));
     }
+
+    #[test]
+    fn test_route_head() {
+        let router = router();
+        let items = router.at("/").router_item;
+
+        let header: RequestHeader = Request::builder().method(Method::HEAD).body(()).unwrap().into_parts().0.into();
+        let req_ctx = RequestContext::new(&header, PathParams::empty());
+
+        // answered by the GET route
+        assert!(items[0].filter.matches(&req_ctx));
+        assert!(!items[2].filter.matches(&req_ctx));
+    }
+
+    #[test]
+    fn test_matched_route() {
+        let router = router();
+        assert_eq!(router.at("/2").route().unwrap().as_str(), "/2");
+        assert!(router.at("/3").route().is_none());
+    }
+
+    struct Record {
+        name: &'static str,
+        calls: Arc<Mutex<Vec<&'static str>>>,
+    }
+
+    struct RecordHandler<H> {
+        name: &'static str,
+        calls: Arc<Mutex<Vec<&'static str>>>,
+        handler: H,
+    }
+
+    impl<H: RequestHandler> Wrapper<H> for Record {
+        type Out = RecordHandler<H>;
+
+        fn wrap(&self, handler: H) -> Self::Out {
+            RecordHandler { name: self.name, calls: Arc::clone(&self.calls), handler }
+        }
+    }
+
+    #[async_trait]
+    impl<H: RequestHandler> RequestHandler for RecordHandler<H> {
+        async fn invoke<'server, 'req>(
+            &self,
+            req: &mut RequestContext<'server, 'req>,
+            req_body: OptionReqBody,
+        ) -> Response<ResponseBody> {
+            self.calls.lock().unwrap().push(self.name);
+            self.handler.invoke(req, req_body).await
+        }
+    }
+
+    #[test]
+    fn test_route_group() {
+        let calls = Arc::new(Mutex::new(vec![]));
+        let record = |name| Record { name, calls: Arc::clone(&calls) };
+
+        let api = RouteGroup::new("/api/v1/")
+            .wrap(record("api"))
+            .get("/", handler_fn(simple_get_1))
+            .get("/users", handler_fn(simple_get_2));
+        let admin = RouteGroup::new("/admin").wrap(record("admin")).post("/users", handler_fn(simple_get_1));
+        let router = Router::builder().group(api).group(admin).wrap(record("global")).build();
+
+        assert_eq!(router.at("/api/v1").route().unwrap().as_str(), "/api/v1");
+        assert_eq!(router.at("/api/v1/users").route().unwrap().as_str(), "/api/v1/users");
+        assert!(router.at("/users").route().is_none());
+
+        let client = TestClient::from_router(router);
+        client.get("/api/v1/users").send().status(StatusCode::OK);
+        assert_eq!(*calls.lock().unwrap(), vec!["global", "api"]);
+
+        calls.lock().unwrap().clear();
+        client.post("/admin/users", "").send().status(StatusCode::OK);
+        assert_eq!(*calls.lock().unwrap(), vec!["global", "admin"]);
+    }
+
+    struct Ordered {
+        name: &'static str,
+        priority: i32,
+        calls: Arc<Mutex<Vec<String>>>,
+    }
+
+    struct OrderedHandler<H> {
+        name: &'static str,
+        calls: Arc<Mutex<Vec<String>>>,
+        handler: H,
+    }
+
+    impl<H: RequestHandler> Wrapper<H> for Ordered {
+        type Out = OrderedHandler<H>;
+
+        fn wrap(&self, handler: H) -> Self::Out {
+            OrderedHandler { name: self.name, calls: Arc::clone(&self.calls), handler }
+        }
+
+        fn priority(&self) -> i32 {
+            self.priority
+        }
+    }
+
+    #[async_trait]
+    impl<H: RequestHandler> RequestHandler for OrderedHandler<H> {
+        async fn invoke<'server, 'req>(
+            &self,
+            req: &mut RequestContext<'server, 'req>,
+            req_body: OptionReqBody,
+        ) -> Response<ResponseBody> {
+            self.calls.lock().unwrap().push(format!("{} request", self.name));
+            let resp = self.handler.invoke(req, req_body).await;
+            self.calls.lock().unwrap().push(format!("{} response", self.name));
+            resp
+        }
+    }
+
+    #[test]
+    fn test_group_wrapper_priority() {
+        let calls = Arc::new(Mutex::new(vec![]));
+        let ordered = |name, priority| Ordered { name, priority, calls: Arc::clone(&calls) };
+
+        let api = RouteGroup::new("/api")
+            .wrap(ordered("encoding", priority::ENCODING))
+            .wrap(ordered("logging", priority::LOGGING))
+            .wrap(ordered("auth", priority::AUTH))
+            .get("/users", handler_fn(simple_get_1));
+        let client = TestClient::from_router(Router::builder().group(api).build());
+        client.get("/api/users").send().status(StatusCode::OK);
+
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec![
+                "logging request",
+                "auth request",
+                "encoding request",
+                "encoding response",
+                "auth response",
+                "logging response"
+            ]
+        );
+    }
+
+    async fn not_found() -> (StatusCode, &'static str) {
+        (StatusCode::NOT_FOUND, "custom not found")
+    }
+
+    async fn method_not_allowed() -> (StatusCode, &'static str) {
+        (StatusCode::METHOD_NOT_ALLOWED, "custom method not allowed")
+    }
+
+    #[test]
+    fn test_not_found_handler() {
+        let calls = Arc::new(Mutex::new(vec![]));
+        let router = Router::builder()
+            .route("/", get(handler_fn(simple_get_1)))
+            .not_found_handler(handler_fn(not_found))
+            .wrap(Record { name: "router", calls: Arc::clone(&calls) })
+            .build();
+        let client = TestClient::from_router(router);
+
+        client.get("/missing").send().status(StatusCode::NOT_FOUND).body("custom not found");
+        assert_eq!(*calls.lock().unwrap(), vec!["router"]);
+
+        // without a not found handler, the default handler of the server answers
+        let client = TestClient::from_router(Router::builder().route("/", get(handler_fn(simple_get_1))).build());
+        client.get("/missing").send().status(StatusCode::NOT_FOUND).body("404 Not Found");
+    }
+
+    #[test]
+    fn test_method_not_allowed() {
+        let router = || {
+            Router::builder()
+                .route("/users", get(handler_fn(simple_get_1)))
+                .route("/users", post(handler_fn(simple_get_1)))
+                .route("/users/{id}", delete(handler_fn(simple_get_2)))
+                .route("/users/{id}", put(handler_fn(simple_get_2)))
+                .route(
+                    "/form",
+                    post(handler_fn(simple_get_1)).with(header(
+                        http::header::CONTENT_TYPE,
+                        HeaderValue::from_static("application/x-www-form-urlencoded"),
+                    )),
+                )
+        };
+
+        let client = TestClient::from_router(router().build());
+        let req = Request::builder().method(Method::PATCH).uri("/users/1").body(Default::default()).unwrap();
+        let resp = client.request(req);
+        assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(resp.headers()[http::header::ALLOW], "DELETE, PUT");
+
+        client.post("/users/1", "").send().status(StatusCode::METHOD_NOT_ALLOWED).header("allow", "DELETE, PUT");
+        let req = Request::builder().method(Method::DELETE).uri("/users").body(Default::default()).unwrap();
+        assert_eq!(client.request(req).headers()[http::header::ALLOW], "GET, HEAD, POST");
+        // the method is allowed, but another filter of the route rejected the request
+        client.post("/form", "").send().status(StatusCode::NOT_FOUND);
+
+        let calls = Arc::new(Mutex::new(vec![]));
+        let router = router()
+            .method_not_allowed_handler(handler_fn(method_not_allowed))
+            .wrap(Record { name: "router", calls: Arc::clone(&calls) })
+            .build();
+        let client = TestClient::from_router(router);
+        client
+            .post("/users/1", "")
+            .send()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .header("allow", "DELETE, PUT")
+            .body("custom method not allowed");
+        assert_eq!(*calls.lock().unwrap(), vec!["router"]);
+    }
+
+    #[test]
+    fn test_routing_error() {
+        let router = router();
+        let request = |method: Method, uri: &str| -> RequestHeader {
+            Request::builder().method(method).uri(uri).body(()).unwrap().into()
+        };
+
+        let header = request(Method::POST, "/");
+        let req = RequestContext::new(&header, PathParams::empty());
+        assert_eq!(router.at("/").find(&req).unwrap().route().as_str(), "/");
+
+        let header = request(Method::GET, "/missing");
+        let req = RequestContext::new(&header, PathParams::empty());
+        assert_eq!(router.at("/missing").find(&req).err(), Some(RoutingError::NotFound));
+        assert_eq!(RoutingError::NotFound.allow(), None);
+
+        let header = request(Method::DELETE, "/2");
+        let req = RequestContext::new(&header, PathParams::empty());
+        let error = router.at("/2").find(&req).err().unwrap();
+        assert_eq!(error, RoutingError::MethodNotAllowed { allowed_methods: vec![Method::GET, Method::HEAD] });
+        assert_eq!(error.allow().unwrap(), "GET, HEAD");
+
+        // the error is passed to the handler
+        struct ReportError;
+
+        #[async_trait]
+        impl RequestHandler for ReportError {
+            async fn invoke<'server, 'req>(
+                &self,
+                req: &mut RequestContext<'server, 'req>,
+                _req_body: OptionReqBody,
+            ) -> Response<ResponseBody> {
+                Response::new(format!("{:?}", req.extensions().get::<RoutingError>()).into())
+            }
+        }
+
+        let router = Router::builder()
+            .route("/", get(handler_fn(simple_get_1)))
+            .not_found_handler(ReportError)
+            .method_not_allowed_handler(ReportError)
+            .build();
+        let client = TestClient::from_router(router);
+        client.get("/missing").send().body("Some(NotFound)");
+        client.post("/", "").send().body("Some(MethodNotAllowed { allowed_methods: [GET, HEAD] })");
+    }
+
+    #[test]
+    fn test_routes() {
+        let calls = Arc::new(Mutex::new(vec![]));
+        let api = RouteGroup::new("/api")
+            .wrap(Record { name: "group", calls: Arc::clone(&calls) })
+            .post("/users", handler_fn(simple_get_2));
+        let router = Router::builder()
+            .route("/", get(handler_fn(simple_get_1)))
+            .route("/", post(handler_fn(simple_get_1)))
+            .route("/users/{id}", delete(handler_fn(simple_get_2)))
+            .route("/users/{id}", put(handler_fn(simple_get_2)))
+            .group(api)
+            .wrap(Record { name: "router", calls })
+            .build();
+
+        let routes: Vec<_> = router.routes().map(|route| (route.method.as_str(), route.pattern.as_str())).collect();
+        assert_eq!(
+            routes,
+            vec![("GET", "/"), ("POST", "/"), ("POST", "/api/users"), ("DELETE", "/users/{id}"), ("PUT", "/users/{id}")]
+        );
+
+        let route = router.routes().find(|route| route.pattern == "/api/users").unwrap();
+        assert!(route.handler.contains("simple_get_2"), "{}", route.handler);
+        let record = std::any::type_name::<Record>();
+        assert_eq!(route.wrappers, vec![record, record]);
+
+        let display = router.to_string();
+        assert!(display.starts_with("METHOD  PATTERN      HANDLER\n"), "{display}");
+        assert!(display.contains("\nDELETE  /users/{id}  micro_web::handler::FnHandler<"), "{display}");
+        assert_eq!(display.lines().count(), 6);
+
+        let debug = format!("{router:?}");
+        for (method, pattern) in routes {
+            assert!(debug.contains(&format!("method: {method}, pattern: \"{pattern}\"")), "{debug}");
+        }
+    }
+
+    #[test]
+    fn test_conflicting_params() {
+        let result = Router::builder()
+            .route("/users/{id}", get(handler_fn(simple_get_1)))
+            .route("/users/{name}/posts", get(handler_fn(simple_get_1)))
+            .route("/users/{id}/comments/{comment}", get(handler_fn(simple_get_1)))
+            .route("/teams/{id}", get(handler_fn(simple_get_1)))
+            .try_build();
+        match result {
+            Err(RouterBuildError::ConflictingParams { routes }) => assert_eq!(
+                routes,
+                vec!["/users/{id}", "/users/{id}/comments/{comment}", "/users/{name}/posts"]
+            ),
+            Err(e) => panic!("unexpected error: {e}"),
+            Ok(_) => panic!("conflicting params are accepted"),
+        }
+
+        // after a parameter, the positions are the same whatever its name
+        let result = Router::builder()
+            .route("/users/{id}/posts/{post}", get(handler_fn(simple_get_1)))
+            .route("/users/{user}/posts/{id}", get(handler_fn(simple_get_1)))
+            .try_build();
+        assert!(matches!(result, Err(RouterBuildError::ConflictingParams { .. })));
+
+        let router = Router::builder()
+            .route("/users/{id}", get(handler_fn(simple_get_1)))
+            .route("/users/{id}/posts/{post}", get(handler_fn(simple_get_1)))
+            .route("/users/me", get(handler_fn(simple_get_1)))
+            .route("/files/{*path}", get(handler_fn(simple_get_1)))
+            .route("/{{literal}}/{id}", get(handler_fn(simple_get_1)))
+            .try_build()
+            .unwrap();
+        assert_eq!(router.at("/users/1/posts/2").params().get("post"), Some("2"));
+    }
+
+    #[test]
+    fn test_invalid_route() {
+        let result = Router::builder().route("/users/{id", get(handler_fn(simple_get_1))).try_build();
+        match result {
+            Err(RouterBuildError::InvalidRoute { route, .. }) => assert_eq!(route, "/users/{id"),
+            Err(e) => panic!("unexpected error: {e}"),
+            Ok(_) => panic!("an invalid route is accepted"),
+        }
+    }
 }
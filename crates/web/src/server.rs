@@ -31,43 +31,147 @@
 //! }
 //! ```
 
+use crate::acceptor::Acceptor;
 use crate::handler::RequestHandler;
-use crate::router::Router;
+use crate::router::{Router, RoutingError};
+use crate::stats::{ConnectionCounters, ConnectionStats, Counted};
 use crate::{handler_fn, OptionReqBody, RequestContext, ResponseBody};
-use http::{Request, Response, StatusCode};
-use micro_http::connection::HttpConnection;
+use http::request::Parts;
+use http::{Extensions, Request, Response, StatusCode};
+use micro_http::connection::{HttpConnection, OnUpgrade, ServerConfig};
 use micro_http::handler::Handler;
 use micro_http::protocol::body::ReqBody;
 use micro_http::protocol::RequestHeader;
 use std::error::Error;
 use std::future::Future;
+use std::io;
 use std::net::{SocketAddr, ToSocketAddrs};
+#[cfg(feature = "unix")]
+use std::os::unix::net::SocketAddr as UnixSocketAddr;
+#[cfg(feature = "unix")]
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
 use std::sync::Arc;
+use std::time::Duration;
 use thiserror::Error;
-use tokio::net::TcpListener;
-use tracing::{error, info, warn, Level};
+use tokio::io::{AsyncRead, AsyncWrite};
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::{TcpListener, TcpStream};
+#[cfg(feature = "unix")]
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinSet;
+use tracing::{error, info, trace, warn, Level};
 use tracing_subscriber::FmtSubscriber;
 
 /// Builder for configuring and constructing a [`Server`] instance.
 /// 
 /// The builder provides a fluent API for setting server options including:
-/// - Binding address
+/// - Binding addresses, with their accept queue size, and Unix domain sockets with the `unix` feature
+/// - The TCP socket options: `SO_REUSEPORT` and `TCP_NODELAY`
 /// - Request router
 /// - Default request handler
+/// - Whether to trust proxy headers for the client IP
+/// - How long a shutdown waits for in-flight requests
+/// - The read and write timeouts of connections
+/// - An [`Acceptor`] preparing the connections, e.g. for TLS
 pub struct ServerBuilder {
     router: Option<Router>,
     default_handler: Option<Box<dyn RequestHandler>>,
-    address: Option<Vec<SocketAddr>>,
+    binds: Vec<Bind>,
+    #[cfg(feature = "unix")]
+    unix_binds: Vec<PathBuf>,
+    acceptor: Option<Box<dyn Acceptor>>,
+    trust_proxy: bool,
+    drain_timeout: Duration,
+    config: ServerConfig,
+    so_reuseport: bool,
+    tcp_nodelay: bool,
+    tcp_backlog: i32,
 }
 
+/// An address the server listens on.
+struct Bind {
+    /// The resolved addresses, the first one that can be bound is used
+    addresses: Vec<SocketAddr>,
+    /// The size of the accept queue, [`ServerBuilder::tcp_backlog`] if not set
+    backlog: Option<i32>,
+}
+
+/// The default size of the accept queue, see [`ServerBuilder::tcp_backlog`].
+const DEFAULT_BACKLOG: i32 = 1024;
+
+/// The default of [`ServerBuilder::drain_timeout`].
+const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
 impl ServerBuilder {
     fn new() -> Self {
-        Self { router: None, default_handler: None, address: None }
+        Self {
+            router: None,
+            default_handler: None,
+            binds: vec![],
+            #[cfg(feature = "unix")]
+            unix_binds: vec![],
+            acceptor: None,
+            trust_proxy: false,
+            drain_timeout: DEFAULT_DRAIN_TIMEOUT,
+            config: ServerConfig::default(),
+            so_reuseport: false,
+            tcp_nodelay: true,
+            tcp_backlog: DEFAULT_BACKLOG,
+        }
     }
 
+    /// Adds an address to listen on, it can be called several times to listen on several addresses.
+    ///
+    /// When `address` resolves to several addresses, the first one that can be bound is used. An IPv6
+    /// address only accepts IPv6 connections when the server also listens on an IPv4 address, so both
+    /// `0.0.0.0:8080` and `[::]:8080` can be bound.
     pub fn bind<A: ToSocketAddrs>(mut self, address: A) -> Self {
-        self.address = Some(address.to_socket_addrs().unwrap().collect::<Vec<_>>());
+        let addresses = address.to_socket_addrs().unwrap().collect::<Vec<_>>();
+        self.binds.push(Bind { addresses, backlog: None });
+        self
+    }
+
+    /// Like [`bind`](Self::bind), with the maximum number of connections waiting to be accepted instead of
+    /// the [`tcp_backlog`](Self::tcp_backlog).
+    pub fn bind_with_backlog<A: ToSocketAddrs>(mut self, address: A, backlog: i32) -> Self {
+        let addresses = address.to_socket_addrs().unwrap().collect::<Vec<_>>();
+        self.binds.push(Bind { addresses, backlog: Some(backlog) });
+        self
+    }
+
+    /// Sets the maximum number of connections waiting to be accepted on the addresses bound with
+    /// [`bind`](Self::bind), 1024 by default. The system may cap it, e.g. to `net.core.somaxconn` on Linux.
+    pub fn tcp_backlog(mut self, backlog: i32) -> Self {
+        self.tcp_backlog = backlog;
+        self
+    }
+
+    /// Sets `SO_REUSEPORT` on the listening sockets, so several processes can listen on the same port and
+    /// the system distributes the connections between them. Defaults to `false`.
+    ///
+    /// It's only supported on Unix systems, [`build`](Self::build) fails with
+    /// [`ServerBuildError::ReusePortUnsupported`] on the others.
+    pub fn so_reuseport(mut self, so_reuseport: bool) -> Self {
+        self.so_reuseport = so_reuseport;
+        self
+    }
+
+    /// Sets `TCP_NODELAY` on the accepted connections, so small responses are sent without waiting for
+    /// more data. Defaults to `true`.
+    pub fn tcp_nodelay(mut self, tcp_nodelay: bool) -> Self {
+        self.tcp_nodelay = tcp_nodelay;
+        self
+    }
+
+    /// Adds a Unix domain socket to listen on, created at `path`, it can be called several times.
+    ///
+    /// The socket file is removed once the server stopped. Binding fails if the file already exists. The
+    /// [`acceptor`](Self::acceptor) only prepares TCP connections, Unix connections are served as is.
+    #[cfg(feature = "unix")]
+    pub fn bind_unix(mut self, path: impl AsRef<Path>) -> Self {
+        self.unix_binds.push(path.as_ref().to_path_buf());
         self
     }
 
@@ -81,14 +185,69 @@ impl ServerBuilder {
         self
     }
 
+    /// Sets whether [`RequestContext::client_ip`] trusts the `X-Forwarded-For` and `X-Real-IP` headers.
+    ///
+    /// Only enable this when the server runs behind a proxy that sets these headers, otherwise clients
+    /// can spoof their address. Defaults to `false`.
+    pub fn trust_proxy(mut self, trust_proxy: bool) -> Self {
+        self.trust_proxy = trust_proxy;
+        self
+    }
+
+    /// Sets how long a shutdown waits for in-flight requests before closing the remaining connections.
+    /// Defaults to 30 seconds.
+    pub fn drain_timeout(mut self, drain_timeout: Duration) -> Self {
+        self.drain_timeout = drain_timeout;
+        self
+    }
+
+    /// Sets the read and write timeouts of connections, see [`ServerConfig`].
+    pub fn config(mut self, config: ServerConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Sets the [`Acceptor`] preparing the accepted connections before HTTP is served on them, such as
+    /// a TLS handshake. Connections are served as plain TCP by default.
+    ///
+    /// The acceptor must be done within the [`read_header_timeout`](ServerConfig::read_header_timeout)
+    /// of the [config](Self::config), otherwise the connection is closed.
+    pub fn acceptor(mut self, acceptor: impl Acceptor + 'static) -> Self {
+        self.acceptor = Some(Box::new(acceptor));
+        self
+    }
+
     pub fn build(self) -> Result<Server, ServerBuildError> {
         let new_builder =
-            if let None = self.default_handler { self.default_handler(handler_fn(default_handler)) } else { self };
+            if self.default_handler.is_none() { self.default_handler(handler_fn(default_handler)) } else { self };
         let router = new_builder.router.ok_or(ServerBuildError::MissingRouter)?;
-        let address = new_builder.address.ok_or(ServerBuildError::MissingAddress)?;
+        #[cfg(not(feature = "unix"))]
+        let no_address = new_builder.binds.is_empty();
+        #[cfg(feature = "unix")]
+        let no_address = new_builder.binds.is_empty() && new_builder.unix_binds.is_empty();
+        if no_address {
+            return Err(ServerBuildError::MissingAddress);
+        }
+        if new_builder.so_reuseport && cfg!(not(unix)) {
+            return Err(ServerBuildError::ReusePortUnsupported);
+        }
 
         // unwrap is safe here because we set it in the new_builder
-        Ok(Server { router, default_handler: new_builder.default_handler.unwrap(), address })
+        Ok(Server {
+            router,
+            default_handler: new_builder.default_handler.unwrap(),
+            binds: new_builder.binds,
+            #[cfg(feature = "unix")]
+            unix_binds: new_builder.unix_binds,
+            acceptor: new_builder.acceptor,
+            trust_proxy: new_builder.trust_proxy,
+            drain_timeout: new_builder.drain_timeout,
+            config: new_builder.config,
+            so_reuseport: new_builder.so_reuseport,
+            tcp_nodelay: new_builder.tcp_nodelay,
+            tcp_backlog: new_builder.tcp_backlog,
+            stats_sender: None,
+        })
     }
 }
 
@@ -107,9 +266,22 @@ async fn default_handler() -> (StatusCode, &'static str) {
 pub struct Server {
     router: Router,
     default_handler: Box<dyn RequestHandler>,
-    address: Vec<SocketAddr>,
+    binds: Vec<Bind>,
+    #[cfg(feature = "unix")]
+    unix_binds: Vec<PathBuf>,
+    acceptor: Option<Box<dyn Acceptor>>,
+    trust_proxy: bool,
+    drain_timeout: Duration,
+    config: ServerConfig,
+    so_reuseport: bool,
+    tcp_nodelay: bool,
+    tcp_backlog: i32,
+    stats_sender: Option<mpsc::Sender<ConnectionStats>>,
 }
 
+/// The number of [`ConnectionStats`] waiting to be received, the next ones are dropped.
+const STATS_CAPACITY: usize = 1024;
+
 /// Errors that can occur during server construction.
 #[derive(Error, Debug)]
 pub enum ServerBuildError {
@@ -120,6 +292,10 @@ pub enum ServerBuildError {
     /// Bind address was not configured
     #[error("address must be set")]
     MissingAddress,
+
+    /// `SO_REUSEPORT` was enabled on a system that doesn't support it
+    #[error("SO_REUSEPORT is only supported on Unix systems")]
+    ReusePortUnsupported,
 }
 
 impl Server {
@@ -127,44 +303,212 @@ impl Server {
         ServerBuilder::new()
     }
 
+    /// Returns a receiver of the [`ConnectionStats`] of each connection, sent once it's closed.
+    ///
+    /// It must be called before the server is started. Up to 1024 stats wait to be received, the stats of
+    /// the connections closed while the receiver is full are dropped. Calling it again replaces the
+    /// previous receiver, which receives no more stats.
+    pub fn stats_receiver(&mut self) -> mpsc::Receiver<ConnectionStats> {
+        let (stats_sender, stats_receiver) = mpsc::channel(STATS_CAPACITY);
+        self.stats_sender = Some(stats_sender);
+        stats_receiver
+    }
+
     pub async fn start(self) {
         let subscriber = FmtSubscriber::builder().with_max_level(Level::INFO).finish();
         tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
 
-        info!("start listening at {:?}", self.address);
-        let tcp_listener = match TcpListener::bind(self.address.as_slice()).await {
-            Ok(tcp_listener) => tcp_listener,
-            Err(e) => {
-                error!(cause = %e, "bind server error");
-                return;
-            }
-        };
+        match self.serve().await {
+            Ok(shutdown_handle) => shutdown_handle.stopped().await,
+            Err(e) => error!(cause = %e, "bind server error"),
+        }
+    }
 
-        let handler = Arc::new(self);
-        loop {
-            let (tcp_stream, _remote_addr) = match tcp_listener.accept().await {
-                Ok(stream_and_addr) => stream_and_addr,
-                Err(e) => {
-                    warn!(cause = %e, "failed to accept");
-                    continue;
-                }
-            };
+    /// Binds the addresses and accepts connections in background tasks, one per address.
+    ///
+    /// Unlike [`start`](Self::start), it doesn't install a tracing subscriber, and it returns a
+    /// [`ShutdownHandle`] to stop the server.
+    pub async fn serve(self) -> io::Result<ShutdownHandle> {
+        let dual_stack = self.binds.iter().flat_map(|bind| &bind.addresses).any(SocketAddr::is_ipv4);
+        let tcp_listeners =
+            self.binds.iter().map(|bind| self.bind_listener(bind, dual_stack)).collect::<io::Result<Vec<_>>>()?;
+        let local_addrs = tcp_listeners.iter().map(TcpListener::local_addr).collect::<io::Result<Vec<_>>>()?;
+        info!("start listening at {:?}", local_addrs);
+
+        #[allow(unused_mut)]
+        let mut listeners: Vec<_> = tcp_listeners.into_iter().map(Listener::Tcp).collect();
+        #[cfg(feature = "unix")]
+        for path in &self.unix_binds {
+            let listener = UnixListener::bind(path)?;
+            info!("start listening at {}", path.display());
+            listeners.push(Listener::Unix(UnixSocketListener { listener, path: path.clone() }));
+        }
 
-            let handler = handler.clone();
+        let (shutdown_sender, shutdown_receiver) = watch::channel(false);
+        let (stopped_sender, stopped_receiver) = watch::channel(false);
+        tokio::spawn(async move {
+            let server = Arc::new(self);
+            let accept_loops =
+                listeners.into_iter().map(|listener| server.clone().accept_loop(listener, shutdown_receiver.clone()));
+            futures::future::join_all(accept_loops).await;
+            info!("server stopped");
+            let _ = stopped_sender.send(true);
+        });
 
-            tokio::spawn(async move {
-                let (reader, writer) = tcp_stream.into_split();
-                let connection = HttpConnection::new(reader, writer);
-                match connection.process(handler).await {
-                    Ok(_) => {
-                        info!("finished process, connection shutdown");
-                    }
+        Ok(ShutdownHandle { local_addrs, shutdown_sender, stopped_receiver })
+    }
+
+    /// Accepts connections until the shutdown is signaled, then drains them.
+    async fn accept_loop(self: Arc<Self>, listener: Listener, mut shutdown: watch::Receiver<bool>) {
+        let mut connections = JoinSet::new();
+        loop {
+            let connection = tokio::select! {
+                Ok(()) = shutdown.changed() => break,
+                // reaps the finished connections
+                Some(_) = connections.join_next(), if !connections.is_empty() => continue,
+                accepted = listener.accept() => match accepted {
+                    Ok(connection) => connection,
                     Err(e) => {
-                        error!("service has error, cause {}, connection shutdown", e);
+                        warn!(cause = %e, "failed to accept");
+                        continue;
                     }
-                }
-            });
+                },
+            };
+
+            connections.spawn(self.clone().handle_connection(connection, shutdown.clone()));
+        }
+
+        drop(listener);
+        info!("stop accepting connections, wait for {} connections to finish", connections.len());
+        let drain = async { while connections.join_next().await.is_some() {} };
+        if tokio::time::timeout(self.drain_timeout, drain).await.is_err() {
+            warn!("drain timeout elapsed, close the remaining {} connections", connections.len());
+            connections.shutdown().await;
+        }
+    }
+
+    async fn handle_connection(self: Arc<Self>, connection: Connection, shutdown: watch::Receiver<bool>) {
+        let (tcp_stream, remote_addr) = match connection {
+            Connection::Tcp(tcp_stream, remote_addr) => (tcp_stream, remote_addr),
+            #[cfg(feature = "unix")]
+            Connection::Unix(unix_stream, remote_addr) => {
+                let (reader, writer) = unix_stream.into_split();
+                let remote_addr = RemoteAddr::Unix(remote_addr);
+                let handler = ConnectionHandler::new(self.clone(), remote_addr, None);
+                return self.serve_connection(reader, writer, handler, shutdown).await;
+            }
+        };
+
+        if self.tcp_nodelay {
+            if let Err(e) = tcp_stream.set_nodelay(true) {
+                warn!(cause = %e, %remote_addr, "failed to set TCP_NODELAY");
+            }
+        }
+
+        let Some(acceptor) = &self.acceptor else {
+            let (reader, writer) = tcp_stream.into_split();
+            let remote_addr = RemoteAddr::Tcp(remote_addr);
+            let handler = ConnectionHandler::new(self.clone(), remote_addr, None);
+            return self.serve_connection(reader, writer, handler, shutdown).await;
+        };
+
+        // the handshake is part of receiving the first request head, a client stalling it would hold the
+        // connection forever
+        let accept = acceptor.accept(tcp_stream, remote_addr);
+        let accepted = match self.config.read_header_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, accept)
+                .await
+                .unwrap_or_else(|_| Err(io::Error::new(io::ErrorKind::TimedOut, "the acceptor timed out"))),
+            None => accept.await,
+        };
+
+        match accepted {
+            Ok(accepted) => {
+                let (stream, extensions) = accepted.into_parts();
+                let (reader, writer) = tokio::io::split(stream);
+                let (remote_addr, extensions) = (RemoteAddr::Tcp(remote_addr), Some(ConnectionExtensions(extensions)));
+                let handler = ConnectionHandler::new(self.clone(), remote_addr, extensions);
+                self.serve_connection(reader, writer, handler, shutdown).await;
+            }
+            Err(e) => warn!(cause = %e, %remote_addr, "failed to accept the connection"),
+        }
+    }
+
+    async fn serve_connection<R, W>(
+        &self,
+        reader: R,
+        writer: W,
+        handler: ConnectionHandler,
+        shutdown: watch::Receiver<bool>,
+    ) where
+        R: AsyncRead + Send + Unpin + 'static,
+        W: AsyncWrite + Send + Unpin + 'static,
+    {
+        let counters = handler.counters.clone();
+        let (reader, writer) = (Counted::new(reader, counters.clone()), Counted::new(writer, counters.clone()));
+        let connection = HttpConnection::new(reader, writer).with_shutdown(shutdown).with_config(self.config.clone());
+        match connection.process(Arc::new(handler)).await {
+            Ok(_) => {
+                info!("finished process, connection shutdown");
+            }
+            Err(e) => {
+                counters.error();
+                error!("service has error, cause {}, connection shutdown", e);
+            }
         }
+
+        if let Some(stats_sender) = &self.stats_sender {
+            if stats_sender.try_send(counters.stats()).is_err() {
+                trace!("the stats receiver is full or closed, the stats of the connection are dropped");
+            }
+        }
+    }
+}
+
+/// A handle to stop a server started by [`Server::serve`].
+///
+/// Dropping the handle doesn't stop the server.
+pub struct ShutdownHandle {
+    local_addrs: Vec<SocketAddr>,
+    shutdown_sender: watch::Sender<bool>,
+    stopped_receiver: watch::Receiver<bool>,
+}
+
+impl ShutdownHandle {
+    /// Returns the first address the server listens on, useful when it was bound to port 0.
+    ///
+    /// # Panics
+    /// Panics if the server only listens on Unix domain sockets.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addrs[0]
+    }
+
+    /// Returns the addresses the server listens on, in the order they were added to the builder, Unix domain
+    /// sockets excluded.
+    pub fn local_addrs(&self) -> &[SocketAddr] {
+        &self.local_addrs
+    }
+
+    /// Stops the server and waits until it stopped.
+    ///
+    /// The server stops accepting connections, waits for in-flight requests to complete for up to the
+    /// [drain timeout](ServerBuilder::drain_timeout), then closes the remaining connections.
+    pub async fn shutdown(&self) {
+        let _ = self.shutdown_sender.send(true);
+        self.stopped().await;
+    }
+
+    /// Waits for `duration`, then stops the server like [`shutdown`](Self::shutdown).
+    pub async fn shutdown_after(&self, duration: Duration) {
+        tokio::time::sleep(duration).await;
+        self.shutdown().await;
+    }
+
+    /// Waits until the server stopped.
+    async fn stopped(&self) {
+        let mut stopped_receiver = self.stopped_receiver.clone();
+        // the sender is only dropped once the server stopped
+        let _ = stopped_receiver.wait_for(|stopped| *stopped).await;
     }
 }
 
@@ -176,26 +520,632 @@ impl Handler for Server {
     fn call(&self, req: Request<ReqBody>) -> Self::Fut<'_> {
         Box::pin(async {
             let (parts, body) = req.into_parts();
-            let header = RequestHeader::from(parts);
-            let req_body = OptionReqBody::from(body);
-
-            let path = header.uri().path();
-            let route_result = self.router.at(path);
-
-            let mut request_context = RequestContext::new(&header, route_result.params());
-
-            let handler = route_result
-                .router_items()
-                .iter()
-                .filter(|item| item.filter().matches(&request_context))
-                .map(|item| item.handler())
-                .take(1)
-                .next()
-                .unwrap_or(self.default_handler.as_ref());
-
-            let response = handler.invoke(&mut request_context, req_body).await;
-            Ok(response) 
-            
+            Ok(self.handle(parts, OptionReqBody::from(body)).await)
         })
     }
 }
+
+impl Server {
+    /// Routes the request to its handler and invokes it.
+    pub(crate) async fn handle(&self, mut parts: Parts, req_body: OptionReqBody) -> Response<ResponseBody> {
+        let remote_addr = parts.extensions.get::<SocketAddr>().copied();
+        #[cfg(feature = "unix")]
+        let remote_addr_unix = parts.extensions.remove::<UnixSocketAddr>();
+        let on_upgrade = parts.extensions.remove::<OnUpgrade>();
+        let connection_extensions = parts.extensions.remove::<ConnectionExtensions>();
+        let header = RequestHeader::from(parts);
+
+        let path = header.uri().path();
+        let route_result = self.router.at(path);
+
+        let mut request_context = RequestContext::new(&header, route_result.params())
+            .with_remote_addr(remote_addr)
+            .with_trust_proxy(self.trust_proxy)
+            .with_body(req_body.clone());
+        #[cfg(feature = "unix")]
+        {
+            request_context = request_context.with_remote_addr_unix(remote_addr_unix);
+        }
+        if let Some(on_upgrade) = on_upgrade {
+            request_context.extensions_mut().insert(on_upgrade);
+        }
+        if let Some(ConnectionExtensions(extensions)) = connection_extensions {
+            request_context.extensions_mut().extend(extensions);
+        }
+        if let Some(route) = route_result.route() {
+            request_context.extensions_mut().insert(route.clone());
+        }
+
+        let (handler, allow) = match route_result.find(&request_context) {
+            Ok(route_match) => (route_match.handler(), None),
+            Err(e) => {
+                let handler = match e {
+                    RoutingError::NotFound => {
+                        self.router.not_found_handler().unwrap_or(self.default_handler.as_ref())
+                    }
+                    RoutingError::MethodNotAllowed { .. } => self.router.method_not_allowed_handler(),
+                };
+                let allow = e.allow();
+                // for the handler to tell why the request wasn't routed
+                request_context.extensions_mut().insert(e);
+                (handler, allow)
+            }
+        };
+
+        let mut response = handler.invoke(&mut request_context, req_body).await;
+        if let Some(allow) = allow {
+            response.headers_mut().entry(http::header::ALLOW).or_insert(allow);
+        }
+        response
+    }
+}
+
+/// A bound listener.
+enum Listener {
+    Tcp(TcpListener),
+    #[cfg(feature = "unix")]
+    Unix(UnixSocketListener),
+}
+
+/// An accepted connection, with the address of the peer.
+enum Connection {
+    Tcp(TcpStream, SocketAddr),
+    #[cfg(feature = "unix")]
+    Unix(UnixStream, UnixSocketAddr),
+}
+
+impl Listener {
+    async fn accept(&self) -> io::Result<Connection> {
+        match self {
+            Listener::Tcp(tcp_listener) => {
+                tcp_listener.accept().await.map(|(tcp_stream, remote_addr)| Connection::Tcp(tcp_stream, remote_addr))
+            }
+            #[cfg(feature = "unix")]
+            Listener::Unix(UnixSocketListener { listener, .. }) => listener
+                .accept()
+                .await
+                .map(|(unix_stream, remote_addr)| Connection::Unix(unix_stream, remote_addr.into())),
+        }
+    }
+}
+
+/// A listener of a Unix domain socket, its file is removed on drop.
+#[cfg(feature = "unix")]
+struct UnixSocketListener {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+#[cfg(feature = "unix")]
+impl Drop for UnixSocketListener {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            warn!(cause = %e, "failed to remove the socket file {}", self.path.display());
+        }
+    }
+}
+
+impl Server {
+    /// Binds the first address of `bind` that can be bound.
+    ///
+    /// IPv6 addresses only accept IPv6 connections if `dual_stack` is set, so an IPv4 address of the same
+    /// port can be bound too.
+    fn bind_listener(&self, bind: &Bind, dual_stack: bool) -> io::Result<TcpListener> {
+        let backlog = bind.backlog.unwrap_or(self.tcp_backlog);
+        let mut last_error = None;
+        for address in &bind.addresses {
+            match bind_socket(*address, backlog, dual_stack, self.so_reuseport) {
+                Ok(tcp_listener) => return Ok(tcp_listener),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error
+            .unwrap_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "could not resolve to any address")))
+    }
+}
+
+fn bind_socket(address: SocketAddr, backlog: i32, dual_stack: bool, so_reuseport: bool) -> io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(address), Type::STREAM, Some(Protocol::TCP))?;
+    if address.is_ipv6() && dual_stack {
+        socket.set_only_v6(true)?;
+    }
+    // like `TcpListener::bind`, so a restarted server can bind the address while old connections are in TIME_WAIT
+    #[cfg(not(windows))]
+    socket.set_reuse_address(true)?;
+    // rejected by `ServerBuilder::build` on the other systems
+    #[cfg(unix)]
+    if so_reuseport {
+        socket.set_reuse_port(true)?;
+    }
+    #[cfg(not(unix))]
+    let _ = so_reuseport;
+    socket.set_nonblocking(true)?;
+    socket.bind(&address.into())?;
+    socket.listen(backlog)?;
+    TcpListener::from_std(socket.into())
+}
+
+/// Handles the requests of a single connection, attaching the peer address to every request.
+///
+/// The address is passed to [`Server`] as a [`SocketAddr`] request extension, or a Unix `SocketAddr` for
+/// Unix domain sockets, and the extensions of the [`Accepted`](crate::acceptor::Accepted) connection as
+/// [`ConnectionExtensions`].
+struct ConnectionHandler {
+    server: Arc<Server>,
+    remote_addr: RemoteAddr,
+    extensions: Option<ConnectionExtensions>,
+    counters: Arc<ConnectionCounters>,
+}
+
+impl ConnectionHandler {
+    fn new(server: Arc<Server>, remote_addr: RemoteAddr, extensions: Option<ConnectionExtensions>) -> Self {
+        Self { server, remote_addr, extensions, counters: Arc::default() }
+    }
+}
+
+/// The address of the peer of a connection.
+enum RemoteAddr {
+    Tcp(SocketAddr),
+    #[cfg(feature = "unix")]
+    Unix(UnixSocketAddr),
+}
+
+/// The extensions added by the [`Acceptor`] to every request of a connection.
+#[derive(Clone)]
+struct ConnectionExtensions(Extensions);
+
+impl Handler for ConnectionHandler {
+    type RespBody = ResponseBody;
+    type Error = Box<dyn Error + Send + Sync>;
+    type Fut<'fut> = Pin<Box<dyn Future<Output = Result<Response<Self::RespBody>, Self::Error>> + Send + 'fut>>;
+
+    fn call(&self, mut req: Request<ReqBody>) -> Self::Fut<'_> {
+        match &self.remote_addr {
+            RemoteAddr::Tcp(remote_addr) => {
+                req.extensions_mut().insert(*remote_addr);
+            }
+            #[cfg(feature = "unix")]
+            RemoteAddr::Unix(remote_addr) => {
+                req.extensions_mut().insert(remote_addr.clone());
+            }
+        }
+        if let Some(extensions) = &self.extensions {
+            req.extensions_mut().insert(extensions.clone());
+        }
+        self.counters.request_handled();
+        Box::pin(async move {
+            // the status of the responses is the business of `MetricsWrapper`, only failures are counted
+            let resp = self.server.call(req).await;
+            if resp.is_err() {
+                self.counters.error();
+            }
+            resp
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::acceptor::{Accepted, Secure};
+    use crate::router::get;
+    use async_trait::async_trait;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::task::{ready, Context, Poll};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt, ReadBuf};
+    use tokio::net::TcpStream;
+
+    /// Counts the started requests, then answers after `delay`.
+    struct SlowHandler {
+        started: Arc<AtomicUsize>,
+        delay: Duration,
+    }
+
+    #[async_trait]
+    impl RequestHandler for SlowHandler {
+        async fn invoke<'server, 'req>(
+            &self,
+            _req: &mut RequestContext<'server, 'req>,
+            _req_body: OptionReqBody,
+        ) -> Response<ResponseBody> {
+            self.started.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(self.delay).await;
+            Response::new(ResponseBody::from("done"))
+        }
+    }
+
+    async fn serve(delay: Duration, drain_timeout: Duration) -> (ShutdownHandle, Arc<AtomicUsize>) {
+        let started = Arc::new(AtomicUsize::new(0));
+        let handler = SlowHandler { started: started.clone(), delay };
+        let router = Router::builder().route("/", get(handler)).build();
+        let server =
+            Server::builder().router(router).bind("127.0.0.1:0").drain_timeout(drain_timeout).build().unwrap();
+        (server.serve().await.unwrap(), started)
+    }
+
+    async fn wait_started(started: &AtomicUsize, count: usize) {
+        while started.load(Ordering::SeqCst) < count {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_drains_in_flight_requests() {
+        let (shutdown_handle, started) = serve(Duration::from_millis(200), Duration::from_secs(5)).await;
+        let addr = shutdown_handle.local_addr();
+
+        let clients: Vec<_> = (0..8)
+            .map(|_| {
+                tokio::spawn(async move {
+                    let mut stream = TcpStream::connect(addr).await.unwrap();
+                    stream.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+                    let mut response = String::new();
+                    // the connection is closed after the response, without a reset
+                    stream.read_to_string(&mut response).await.unwrap();
+                    response
+                })
+            })
+            .collect();
+        wait_started(&started, 8).await;
+
+        shutdown_handle.shutdown().await;
+
+        for client in clients {
+            let response = client.await.unwrap();
+            assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{response}");
+            assert!(response.ends_with("\r\n\r\ndone"), "{response}");
+        }
+        assert!(TcpStream::connect(addr).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_closes_connections_after_drain_timeout() {
+        let (shutdown_handle, started) = serve(Duration::from_secs(60), Duration::from_millis(50)).await;
+
+        let mut stream = TcpStream::connect(shutdown_handle.local_addr()).await.unwrap();
+        stream.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+        wait_started(&started, 1).await;
+
+        tokio::time::timeout(Duration::from_secs(5), shutdown_handle.shutdown()).await.unwrap();
+
+        let mut response = vec![];
+        let _ = stream.read_to_end(&mut response).await;
+        assert!(response.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_bind_multiple_addresses() {
+        let handler = SlowHandler { started: Arc::new(AtomicUsize::new(0)), delay: Duration::ZERO };
+        let router = Router::builder().route("/", get(handler)).build();
+        let server =
+            Server::builder().router(router).bind("127.0.0.1:0").bind_with_backlog("127.0.0.1:0", 16).build().unwrap();
+        let shutdown_handle = server.serve().await.unwrap();
+
+        let addrs = shutdown_handle.local_addrs().to_vec();
+        assert_eq!(addrs.len(), 2);
+        assert_ne!(addrs[0], addrs[1]);
+        assert_eq!(shutdown_handle.local_addr(), addrs[0]);
+
+        for addr in &addrs {
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            stream.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+            let mut response = vec![];
+            while !response.ends_with(b"done") {
+                let mut buf = [0u8; 256];
+                let n = stream.read(&mut buf).await.unwrap();
+                assert!(n > 0);
+                response.extend_from_slice(&buf[..n]);
+            }
+        }
+
+        shutdown_handle.shutdown().await;
+        for addr in &addrs {
+            assert!(TcpStream::connect(addr).await.is_err());
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_so_reuseport() {
+        let build = |bind: SocketAddr| {
+            let handler = SlowHandler { started: Arc::new(AtomicUsize::new(0)), delay: Duration::ZERO };
+            let router = Router::builder().route("/", get(handler)).build();
+            Server::builder().router(router).bind(bind).so_reuseport(true).tcp_backlog(16).build().unwrap()
+        };
+        let first = build("127.0.0.1:0".parse().unwrap()).serve().await.unwrap();
+        let second = build(first.local_addr()).serve().await.unwrap();
+        assert_eq!(first.local_addr(), second.local_addr());
+
+        first.shutdown().await;
+        let mut stream = TcpStream::connect(second.local_addr()).await.unwrap();
+        stream.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+        let mut response = vec![];
+        while !response.ends_with(b"done") {
+            let mut buf = [0u8; 256];
+            let n = stream.read(&mut buf).await.unwrap();
+            assert!(n > 0);
+            response.extend_from_slice(&buf[..n]);
+        }
+        second.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_so_reuseport_required_to_share_a_port() {
+        let router = Router::builder().route("/", get(handler_fn(default_handler))).build();
+        let first = Server::builder().router(router).bind("127.0.0.1:0").build().unwrap().serve().await.unwrap();
+        let router = Router::builder().route("/", get(handler_fn(default_handler))).build();
+        let second = Server::builder().router(router).bind(first.local_addr()).build().unwrap().serve().await;
+        assert!(second.is_err());
+        first.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_stats_receiver() {
+        let handler = SlowHandler { started: Arc::new(AtomicUsize::new(0)), delay: Duration::ZERO };
+        async fn fail() -> (StatusCode, &'static str) {
+            (StatusCode::INTERNAL_SERVER_ERROR, "failed")
+        }
+
+        let router = Router::builder().route("/", get(handler)).route("/fail", get(handler_fn(fail))).build();
+        let mut server = Server::builder().router(router).bind("127.0.0.1:0").build().unwrap();
+        let mut stats_receiver = server.stats_receiver();
+        let shutdown_handle = server.serve().await.unwrap();
+
+        // a server error of the application is not an error of the connection
+        let requests = b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\nGET /fail HTTP/1.1\r\nHost: localhost\r\n\r\n";
+        let mut stream = TcpStream::connect(shutdown_handle.local_addr()).await.unwrap();
+        stream.write_all(requests).await.unwrap();
+        stream.shutdown().await.unwrap();
+        let mut response = vec![];
+        stream.read_to_end(&mut response).await.unwrap();
+
+        let stats = stats_receiver.recv().await.unwrap();
+        let expected = ConnectionStats {
+            requests_handled: 2,
+            errors: 0,
+            bytes_read: requests.len() as u64,
+            bytes_written: response.len() as u64,
+        };
+        assert_eq!(stats, expected);
+
+        let mut stream = TcpStream::connect(shutdown_handle.local_addr()).await.unwrap();
+        stream.write_all(b"GET / HTTP/9.9\r\n\r\n").await.unwrap();
+        let mut response = vec![];
+        stream.read_to_end(&mut response).await.unwrap();
+
+        let stats = stats_receiver.recv().await.unwrap();
+        assert_eq!((stats.requests_handled, stats.errors), (0, 1));
+        assert_eq!(stats.bytes_written, response.len() as u64);
+
+        shutdown_handle.shutdown().await;
+    }
+
+    #[cfg(feature = "unix")]
+    struct PeerKindHandler;
+
+    #[cfg(feature = "unix")]
+    #[async_trait]
+    impl RequestHandler for PeerKindHandler {
+        async fn invoke<'server, 'req>(
+            &self,
+            req: &mut RequestContext<'server, 'req>,
+            _req_body: OptionReqBody,
+        ) -> Response<ResponseBody> {
+            let kind = match (req.remote_addr(), req.remote_addr_unix()) {
+                (None, Some(_)) => "unix",
+                (Some(_), None) => "tcp",
+                _ => "unknown",
+            };
+            Response::new(ResponseBody::from(kind))
+        }
+    }
+
+    #[cfg(feature = "unix")]
+    #[tokio::test]
+    async fn test_bind_unix() {
+        let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos();
+        let path = std::env::temp_dir().join(format!("micro-web-{}-{nanos}.sock", std::process::id()));
+        let router = Router::builder().route("/", get(PeerKindHandler)).build();
+        let server = Server::builder().router(router).bind("127.0.0.1:0").bind_unix(&path).build().unwrap();
+        let shutdown_handle = server.serve().await.unwrap();
+
+        for expected in ["unix", "tcp"] {
+            let mut stream: Box<dyn crate::acceptor::IoStream> = match expected {
+                "unix" => Box::new(tokio::net::UnixStream::connect(&path).await.unwrap()),
+                _ => Box::new(TcpStream::connect(shutdown_handle.local_addr()).await.unwrap()),
+            };
+            stream.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+            let body = format!("\r\n\r\n{expected}");
+            let mut response = vec![];
+            while !response.ends_with(body.as_bytes()) {
+                let mut buf = [0u8; 256];
+                let n = stream.read(&mut buf).await.unwrap();
+                assert!(n > 0, "{}", String::from_utf8_lossy(&response));
+                response.extend_from_slice(&buf[..n]);
+            }
+        }
+
+        shutdown_handle.shutdown().await;
+        assert!(!path.exists());
+    }
+
+    #[derive(Clone)]
+    struct PeerName(String);
+
+    /// Names the peer of every connection after its port.
+    struct NamingAcceptor;
+
+    #[async_trait]
+    impl Acceptor for NamingAcceptor {
+        async fn accept(&self, stream: TcpStream, remote_addr: SocketAddr) -> io::Result<Accepted> {
+            Ok(Accepted::new(stream).with_extension(PeerName(format!("peer-{}", remote_addr.port()))))
+        }
+    }
+
+    struct PeerNameHandler;
+
+    #[async_trait]
+    impl RequestHandler for PeerNameHandler {
+        async fn invoke<'server, 'req>(
+            &self,
+            req: &mut RequestContext<'server, 'req>,
+            _req_body: OptionReqBody,
+        ) -> Response<ResponseBody> {
+            let name = req.extensions().get::<PeerName>().map(|name| name.0.clone()).unwrap_or_default();
+            Response::new(ResponseBody::from(name))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_acceptor() {
+        let router = Router::builder().route("/", get(PeerNameHandler)).build();
+        let server =
+            Server::builder().router(router).bind("127.0.0.1:0").acceptor(NamingAcceptor).build().unwrap();
+        let shutdown_handle = server.serve().await.unwrap();
+
+        let mut stream = TcpStream::connect(shutdown_handle.local_addr()).await.unwrap();
+        let port = stream.local_addr().unwrap().port();
+        stream.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+        let expected = format!("\r\n\r\npeer-{port}");
+        let mut response = vec![];
+        while !response.ends_with(expected.as_bytes()) {
+            let mut buf = [0u8; 256];
+            let n = stream.read(&mut buf).await.unwrap();
+            assert!(n > 0, "{}", String::from_utf8_lossy(&response));
+            response.extend_from_slice(&buf[..n]);
+        }
+        assert!(response.starts_with(b"HTTP/1.1 200 OK\r\n"));
+
+        shutdown_handle.shutdown().await;
+    }
+
+    /// Inverts every byte on the wire, standing in for an encrypted stream.
+    struct InvertedStream<S>(S);
+
+    impl<S: AsyncRead + Unpin> AsyncRead for InvertedStream<S> {
+        fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+            let filled = buf.filled().len();
+            ready!(Pin::new(&mut self.0).poll_read(cx, buf))?;
+            buf.filled_mut()[filled..].iter_mut().for_each(|byte| *byte = !*byte);
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    impl<S: AsyncWrite + Unpin> AsyncWrite for InvertedStream<S> {
+        fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, data: &[u8]) -> Poll<io::Result<usize>> {
+            let inverted: Vec<u8> = data.iter().map(|byte| !byte).collect();
+            Pin::new(&mut self.0).poll_write(cx, &inverted)
+        }
+
+        fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.0).poll_flush(cx)
+        }
+
+        fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.0).poll_shutdown(cx)
+        }
+    }
+
+    /// Serves HTTP on an [`InvertedStream`], marking the connections as secure.
+    struct InvertingAcceptor;
+
+    #[async_trait]
+    impl Acceptor for InvertingAcceptor {
+        async fn accept(&self, stream: TcpStream, remote_addr: SocketAddr) -> io::Result<Accepted> {
+            Ok(Accepted::new(InvertedStream(stream))
+                .with_extension(PeerName(format!("peer-{}", remote_addr.port())))
+                .with_extension(Secure))
+        }
+    }
+
+    struct SecurePeerHandler;
+
+    #[async_trait]
+    impl RequestHandler for SecurePeerHandler {
+        async fn invoke<'server, 'req>(
+            &self,
+            req: &mut RequestContext<'server, 'req>,
+            _req_body: OptionReqBody,
+        ) -> Response<ResponseBody> {
+            let name = req.extensions().get::<PeerName>().map(|name| name.0.clone()).unwrap_or_default();
+            Response::new(ResponseBody::from(format!("{name} secure={}", req.is_secure())))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_acceptor_stream() {
+        let router = Router::builder().route("/", get(SecurePeerHandler)).build();
+        let server =
+            Server::builder().router(router).bind("127.0.0.1:0").acceptor(InvertingAcceptor).build().unwrap();
+        let shutdown_handle = server.serve().await.unwrap();
+
+        let stream = TcpStream::connect(shutdown_handle.local_addr()).await.unwrap();
+        let port = stream.local_addr().unwrap().port();
+        let mut stream = InvertedStream(stream);
+        stream.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+        let expected = format!("\r\n\r\npeer-{port} secure=true");
+        let mut response = vec![];
+        while !response.ends_with(expected.as_bytes()) {
+            let mut buf = [0u8; 256];
+            let n = stream.read(&mut buf).await.unwrap();
+            assert!(n > 0, "{}", String::from_utf8_lossy(&response));
+            response.extend_from_slice(&buf[..n]);
+        }
+        assert!(response.starts_with(b"HTTP/1.1 200 OK\r\n"));
+
+        shutdown_handle.shutdown().await;
+    }
+
+    /// Never finishes its handshake.
+    struct StalledAcceptor;
+
+    #[async_trait]
+    impl Acceptor for StalledAcceptor {
+        async fn accept(&self, _stream: TcpStream, _remote_addr: SocketAddr) -> io::Result<Accepted> {
+            std::future::pending().await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_acceptor_timeout() {
+        let router = Router::builder().route("/", get(PeerNameHandler)).build();
+        let config = ServerConfig { read_header_timeout: Some(Duration::from_millis(100)), ..ServerConfig::default() };
+        let server = Server::builder()
+            .router(router)
+            .bind("127.0.0.1:0")
+            .acceptor(StalledAcceptor)
+            .config(config)
+            .build()
+            .unwrap();
+        let shutdown_handle = server.serve().await.unwrap();
+
+        let mut stream = TcpStream::connect(shutdown_handle.local_addr()).await.unwrap();
+        let mut response = vec![];
+        tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut response)).await.unwrap().unwrap();
+        assert!(response.is_empty());
+
+        shutdown_handle.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_closes_idle_connections() {
+        let (shutdown_handle, _started) = serve(Duration::ZERO, Duration::from_secs(5)).await;
+
+        // a first request makes sure the connection was accepted, then it waits for the next one
+        let mut stream = TcpStream::connect(shutdown_handle.local_addr()).await.unwrap();
+        stream.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+        let mut response = vec![];
+        while !response.ends_with(b"done") {
+            let mut buf = [0u8; 256];
+            let n = stream.read(&mut buf).await.unwrap();
+            assert!(n > 0);
+            response.extend_from_slice(&buf[..n]);
+        }
+
+        tokio::time::timeout(Duration::from_secs(1), shutdown_handle.shutdown()).await.unwrap();
+
+        let mut response = vec![];
+        stream.read_to_end(&mut response).await.unwrap();
+        assert!(response.is_empty());
+    }
+}
@@ -0,0 +1,196 @@
+//! Server-Sent Events responses.
+//!
+//! [`SseBody`] turns a stream of [`SseEvent`]s into a response body using the
+//! [event stream format](https://html.spec.whatwg.org/multipage/server-sent-events.html#event-stream-interpretation).
+//! The body ends when the stream ends, and while the stream is idle a `: ping` comment is sent every
+//! `heartbeat_interval`, so proxies don't close the connection.
+//!
+//! Use [`ResponseBuilder::sse`](crate::ResponseBuilder::sse) to build a response with the headers
+//! expected by browsers:
+//!
+//! ```
+//! use micro_web::{ResponseBuilder, SseEvent};
+//! use futures::stream;
+//!
+//! async fn events() -> ResponseBuilder {
+//!     let events = stream::iter(vec![SseEvent::new("hello").event("greeting"), SseEvent::new("world")]);
+//!     ResponseBuilder::sse(events)
+//! }
+//! ```
+
+use bytes::{BufMut, Bytes, BytesMut};
+use futures::Stream;
+use http_body::{Body, Frame};
+use micro_http::protocol::HttpError;
+use pin_project_lite::pin_project;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::time::{Instant, Sleep};
+
+/// The default heartbeat interval of [`SseBody`].
+const DEFAULT_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+
+/// A single server-sent event.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SseEvent {
+    /// The event id, the browser sends the last one back in `Last-Event-ID` when reconnecting
+    pub id: Option<String>,
+    /// The event type, `message` if not set
+    pub event: Option<String>,
+    /// The event data, it may span several lines
+    pub data: String,
+    /// The reconnection time in milliseconds
+    pub retry: Option<u64>,
+}
+
+impl SseEvent {
+    /// Creates an event carrying `data`.
+    pub fn new(data: impl Into<String>) -> Self {
+        Self { data: data.into(), ..Self::default() }
+    }
+
+    /// Sets the event id.
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    /// Sets the event type.
+    pub fn event(mut self, event: impl Into<String>) -> Self {
+        self.event = Some(event.into());
+        self
+    }
+
+    /// Sets the reconnection time in milliseconds.
+    pub fn retry(mut self, retry: u64) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    /// Encodes the event in the event stream format.
+    ///
+    /// Line breaks in `id` and `event` would start a new field, they are removed.
+    fn encode(&self) -> Bytes {
+        let mut buf = BytesMut::with_capacity(self.data.len() + 16);
+        if let Some(id) = &self.id {
+            put_field(&mut buf, "id", &id.replace(['\r', '\n'], ""));
+        }
+        if let Some(event) = &self.event {
+            put_field(&mut buf, "event", &event.replace(['\r', '\n'], ""));
+        }
+        if let Some(retry) = self.retry {
+            put_field(&mut buf, "retry", &retry.to_string());
+        }
+        for line in self.data.split('\n') {
+            put_field(&mut buf, "data", line.strip_suffix('\r').unwrap_or(line));
+        }
+        buf.put_u8(b'\n');
+        buf.freeze()
+    }
+}
+
+fn put_field(buf: &mut BytesMut, name: &str, value: &str) {
+    buf.put_slice(name.as_bytes());
+    buf.put_slice(b": ");
+    buf.put_slice(value.as_bytes());
+    buf.put_u8(b'\n');
+}
+
+pin_project! {
+    /// A response body sending the events of a stream.
+    ///
+    /// Each `poll_frame` yields at most one event.
+    pub struct SseBody<S> {
+        #[pin]
+        stream: S,
+        #[pin]
+        heartbeat: Sleep,
+        heartbeat_interval: Duration,
+    }
+}
+
+impl<S> SseBody<S> {
+    /// Creates a body sending the events of `stream`, with a heartbeat every 15 seconds.
+    pub fn new(stream: S) -> Self {
+        Self::with_heartbeat(stream, DEFAULT_HEARTBEAT_INTERVAL)
+    }
+
+    /// Creates a body sending the events of `stream`, with a heartbeat after `heartbeat_interval`
+    /// without events.
+    pub fn with_heartbeat(stream: S, heartbeat_interval: Duration) -> Self {
+        let heartbeat = tokio::time::sleep(heartbeat_interval);
+        Self { stream, heartbeat, heartbeat_interval }
+    }
+}
+
+impl<S> Body for SseBody<S>
+where
+    S: Stream<Item = SseEvent>,
+{
+    type Data = Bytes;
+    type Error = HttpError;
+
+    fn poll_frame(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let mut this = self.project();
+
+        match this.stream.poll_next(cx) {
+            Poll::Ready(Some(event)) => {
+                this.heartbeat.reset(Instant::now() + *this.heartbeat_interval);
+                Poll::Ready(Some(Ok(Frame::data(event.encode()))))
+            }
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => {
+                if this.heartbeat.as_mut().poll(cx).is_pending() {
+                    return Poll::Pending;
+                }
+                this.heartbeat.reset(Instant::now() + *this.heartbeat_interval);
+                Poll::Ready(Some(Ok(Frame::data(Bytes::from_static(b": ping\n\n")))))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream;
+    use http_body_util::BodyExt;
+
+    #[test]
+    fn test_encode() {
+        let event = SseEvent::new("hello\nworld").id("1").event("greeting").retry(3000);
+        assert_eq!(event.encode(), "id: 1\nevent: greeting\nretry: 3000\ndata: hello\ndata: world\n\n");
+
+        assert_eq!(SseEvent::new("").encode(), "data: \n\n");
+        assert_eq!(SseEvent::new("a").event("x\ny").encode(), "event: xy\ndata: a\n\n");
+    }
+
+    #[tokio::test]
+    async fn test_one_event_per_frame() {
+        let events = stream::iter(vec![SseEvent::new("a"), SseEvent::new("b")]);
+        let mut body = Box::pin(SseBody::new(events));
+
+        assert_eq!(body.frame().await.unwrap().unwrap().into_data().unwrap(), "data: a\n\n");
+        assert_eq!(body.frame().await.unwrap().unwrap().into_data().unwrap(), "data: b\n\n");
+        assert!(body.frame().await.is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_heartbeat() {
+        let events = stream::once(async {
+            tokio::time::sleep(Duration::from_secs(25)).await;
+            SseEvent::new("late")
+        });
+        let mut body = Box::pin(SseBody::with_heartbeat(events, Duration::from_secs(10)));
+
+        let start = Instant::now();
+        assert_eq!(body.frame().await.unwrap().unwrap().into_data().unwrap(), ": ping\n\n");
+        assert_eq!(start.elapsed(), Duration::from_secs(10));
+        assert_eq!(body.frame().await.unwrap().unwrap().into_data().unwrap(), ": ping\n\n");
+        assert_eq!(body.frame().await.unwrap().unwrap().into_data().unwrap(), "data: late\n\n");
+        assert_eq!(start.elapsed(), Duration::from_secs(25));
+        assert!(body.frame().await.is_none());
+    }
+}
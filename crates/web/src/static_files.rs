@@ -0,0 +1,425 @@
+//! Serving files from a directory.
+//!
+//! [`StaticFileHandler`] serves the files under a root directory. The file path is taken from the
+//! last path parameter of the route, so the handler is usually registered with a catch-all route:
+//!
+//! ```no_run
+//! use micro_web::router::{get, Router};
+//! use micro_web::StaticFileHandler;
+//!
+//! let router = Router::builder().route("/static/{*path}", get(StaticFileHandler::new("./public".into()))).build();
+//! ```
+//!
+//! Responses carry `Content-Type`, `Content-Length`, `Last-Modified`, `ETag`, `Cache-Control` and
+//! `Accept-Ranges: bytes`, so [`RangeWrapper`](crate::wrapper::RangeWrapper) and
+//! [`ETagWrapper`](crate::wrapper::ETagWrapper) can answer range and conditional requests.
+//!
+//! A file compressed ahead of time next to the original, such as `app.js.br` for `app.js`, is served
+//! instead of it to the clients accepting its encoding, with `Content-Encoding` set, so
+//! [`EncodeWrapper`](struct@crate::wrapper::EncodeWrapper) doesn't compress it again, see
+//! [`StaticFileHandler::precompressed`].
+
+use crate::handler::RequestHandler;
+use crate::responder::Responder;
+use crate::wrapper::AcceptEncoding;
+use crate::{OptionReqBody, RequestContext, ResponseBody};
+use async_trait::async_trait;
+use http::header::{
+    ACCEPT_ENCODING, ACCEPT_RANGES, CACHE_CONTROL, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, ETAG, LAST_MODIFIED,
+    VARY,
+};
+use http::{HeaderValue, Response, StatusCode};
+use percent_encoding::percent_decode_str;
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fs::Metadata;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+use tokio::fs::File;
+use tracing::trace;
+
+/// The default `Cache-Control` of served files.
+const DEFAULT_CACHE_CONTROL: &str = "public, max-age=3600";
+
+/// The file served for a directory.
+const INDEX_FILE: &str = "index.html";
+
+/// The encodings of the precompressed files, with the extension of their files, in the order they are
+/// tried.
+const PRECOMPRESSED_ENCODINGS: &[(&str, &str)] = &[("zstd", "zst"), ("br", "br"), ("gzip", "gz")];
+
+/// A request handler serving the files under a root directory.
+///
+/// - a path escaping the root, with `..`, an absolute path or a symlink, gets `403 Forbidden`
+/// - a missing file gets `404 Not Found`
+/// - a directory is answered with its `index.html`, or `404 Not Found` if it has none
+pub struct StaticFileHandler {
+    root: PathBuf,
+    cache_control: HeaderValue,
+    precompressed: bool,
+    /// The precompressed files found missing, so they are looked up only once
+    missing: Mutex<HashSet<PathBuf>>,
+}
+
+impl StaticFileHandler {
+    /// Creates a handler serving the files under `root`.
+    pub fn new(root: PathBuf) -> Self {
+        Self {
+            root,
+            cache_control: HeaderValue::from_static(DEFAULT_CACHE_CONTROL),
+            precompressed: true,
+            missing: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Sets whether precompressed files are served, enabled by default.
+    ///
+    /// The `.zst`, `.br` and `.gz` files are tried in this order, skipping the encodings the client
+    /// doesn't accept. They are served with the `Content-Type`, `Last-Modified` and `ETag` of the
+    /// original file, the `ETag` suffixed with the encoding.
+    ///
+    /// A precompressed file is looked up once, one added while the server is running is not served if
+    /// it was found missing before.
+    pub fn precompressed(mut self, enabled: bool) -> Self {
+        self.precompressed = enabled;
+        self
+    }
+
+    /// Sets the `Cache-Control` of served files, `public, max-age=3600` by default.
+    pub fn cache_control(mut self, cache_control: HeaderValue) -> Self {
+        self.cache_control = cache_control;
+        self
+    }
+
+    async fn serve(&self, path: &str, accept_encoding: Option<&str>) -> Result<Response<ResponseBody>, ServeError> {
+        let path = self.root.join(relative_path(path)?);
+
+        let mut metadata = tokio::fs::metadata(&path).await.map_err(|_| ServeError::NotFound)?;
+        let path = if metadata.is_dir() {
+            let index = path.join(INDEX_FILE);
+            metadata = tokio::fs::metadata(&index).await.map_err(|_| ServeError::NotFound)?;
+            index
+        } else {
+            path
+        };
+        if !metadata.is_file() {
+            return Err(ServeError::NotFound);
+        }
+
+        // symlinks may point outside of the root
+        let root = tokio::fs::canonicalize(&self.root).await.map_err(|_| ServeError::NotFound)?;
+        let canonical = tokio::fs::canonicalize(&path).await.map_err(|_| ServeError::NotFound)?;
+        if !canonical.starts_with(&root) {
+            return Err(ServeError::Forbidden);
+        }
+
+        let precompressed = match accept_encoding {
+            Some(accept_encoding) if self.precompressed => {
+                self.find_precompressed(&canonical, &root, accept_encoding).await
+            }
+            _ => None,
+        };
+        let (file, len, encoding) = match precompressed {
+            Some((file, len, encoding)) => (file, len, Some(encoding)),
+            None => {
+                let file = File::open(&canonical).await.map_err(|e| {
+                    trace!("failed to open {}: {}", canonical.display(), e);
+                    ServeError::NotFound
+                })?;
+                (file, metadata.len(), None)
+            }
+        };
+
+        let mut resp = Response::new(ResponseBody::from_file(file, len));
+        let headers = resp.headers_mut();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static(content_type(&canonical)));
+        headers.insert(CONTENT_LENGTH, HeaderValue::from(len));
+        if let Ok(modified) = metadata.modified() {
+            // an http date is always a valid header value
+            headers.insert(LAST_MODIFIED, HeaderValue::from_str(&httpdate::fmt_http_date(modified)).unwrap());
+        }
+        if let Some(etag) = etag(&metadata, encoding) {
+            headers.insert(ETAG, etag);
+        }
+        if let Some(encoding) = encoding {
+            headers.insert(CONTENT_ENCODING, HeaderValue::from_static(encoding));
+            headers.insert(VARY, HeaderValue::from_static("accept-encoding"));
+        }
+        headers.insert(CACHE_CONTROL, self.cache_control.clone());
+        headers.insert(ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+        Ok(resp)
+    }
+
+    /// Opens the first precompressed file of `path` the client accepts the encoding of, returns it with
+    /// its length and encoding.
+    async fn find_precompressed(
+        &self,
+        path: &Path,
+        root: &Path,
+        accept_encoding: &str,
+    ) -> Option<(File, u64, &'static str)> {
+        let accept_encoding: AcceptEncoding = match accept_encoding.parse() {
+            Ok(accept_encoding) => accept_encoding,
+            Err(infallible) => match infallible {},
+        };
+
+        for (encoding, extension) in PRECOMPRESSED_ENCODINGS {
+            if !accept_encoding.quality(encoding).is_some_and(|quality| quality > 0.0) {
+                continue;
+            }
+
+            let mut compressed = OsString::from(path.as_os_str());
+            compressed.push(".");
+            compressed.push(extension);
+            let compressed = PathBuf::from(compressed);
+            if self.missing.lock().unwrap().contains(&compressed) {
+                continue;
+            }
+
+            match open_file(&compressed, root).await {
+                Some((file, metadata)) => return Some((file, metadata.len(), encoding)),
+                None => {
+                    self.missing.lock().unwrap().insert(compressed);
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Opens the regular file at `path`, if it's under `root` once symlinks are resolved.
+async fn open_file(path: &Path, root: &Path) -> Option<(File, Metadata)> {
+    let canonical = tokio::fs::canonicalize(path).await.ok()?;
+    if !canonical.starts_with(root) {
+        return None;
+    }
+    let file = File::open(&canonical).await.ok()?;
+    let metadata = file.metadata().await.ok()?;
+    metadata.is_file().then_some((file, metadata))
+}
+
+#[async_trait]
+impl RequestHandler for StaticFileHandler {
+    async fn invoke<'server, 'req>(
+        &self,
+        req: &mut RequestContext<'server, 'req>,
+        _req_body: OptionReqBody,
+    ) -> Response<ResponseBody> {
+        let path = match req.path_params().iter().last() {
+            Some((_, path)) => path,
+            None => req.uri().path().trim_start_matches('/'),
+        };
+
+        let accept_encoding = req.headers().get(ACCEPT_ENCODING).and_then(|value| value.to_str().ok());
+        match self.serve(path, accept_encoding).await {
+            Ok(resp) => resp,
+            Err(ServeError::NotFound) => (StatusCode::NOT_FOUND, "not found").response_to(req),
+            // the message must not tell where the root is
+            Err(ServeError::Forbidden) => (StatusCode::FORBIDDEN, "forbidden").response_to(req),
+        }
+    }
+}
+
+enum ServeError {
+    NotFound,
+    Forbidden,
+}
+
+/// Decodes the request path into a path relative to the root, rejecting any component that could
+/// leave it.
+fn relative_path(path: &str) -> Result<PathBuf, ServeError> {
+    let path = percent_decode_str(path).decode_utf8().map_err(|_| ServeError::NotFound)?;
+    if path.contains(['\0', '\\']) {
+        return Err(ServeError::Forbidden);
+    }
+
+    let mut relative = PathBuf::new();
+    for component in Path::new(path.as_ref()).components() {
+        match component {
+            Component::Normal(name) => relative.push(name),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return Err(ServeError::Forbidden),
+        }
+    }
+    Ok(relative)
+}
+
+/// Returns the content type of a file from its extension.
+fn content_type(path: &Path) -> &'static str {
+    let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or_default();
+    match extension.to_ascii_lowercase().as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "json" => "application/json",
+        "txt" => "text/plain; charset=utf-8",
+        "xml" => "application/xml",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "wasm" => "application/wasm",
+        "pdf" => "application/pdf",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "mp4" => "video/mp4",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Computes the tag of a file from its inode and modification time, which change whenever the file
+/// is replaced or written.
+///
+/// A precompressed file is tagged from its original file, suffixed with its `encoding` since a strong
+/// tag must differ between the encodings of a resource.
+fn etag(metadata: &Metadata, encoding: Option<&str>) -> Option<HeaderValue> {
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    let etag = format!("{:x}-{:x}.{:x}", file_id(metadata), modified.as_secs(), modified.subsec_nanos());
+    let etag = match encoding {
+        Some(encoding) => format!("\"{etag}-{encoding}\""),
+        None => format!("\"{etag}\""),
+    };
+    // only contains hex digits and encoding names
+    Some(HeaderValue::from_str(&etag).unwrap())
+}
+
+#[cfg(unix)]
+fn file_id(metadata: &Metadata) -> u64 {
+    std::os::unix::fs::MetadataExt::ino(metadata)
+}
+
+#[cfg(not(unix))]
+fn file_id(metadata: &Metadata) -> u64 {
+    metadata.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PathParams, RequestBody};
+    use bytes::Bytes;
+    use http_body::Body;
+    use http_body_util::BodyExt;
+    use micro_http::protocol::RequestHeader;
+
+    /// A directory under the system temp dir, removed on drop.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("micro_web_static_{}_{}", name, std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(dir.join("docs")).unwrap();
+            std::fs::write(dir.join("hello.txt"), "hello").unwrap();
+            std::fs::write(dir.join("docs").join("index.html"), "<p>docs</p>").unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    async fn get(handler: &StaticFileHandler, path: &str) -> Response<ResponseBody> {
+        get_encoded(handler, path, None).await
+    }
+
+    async fn get_encoded(handler: &StaticFileHandler, path: &str, accept: Option<&str>) -> Response<ResponseBody> {
+        let mut builder = http::Request::builder().uri(path);
+        if let Some(accept) = accept {
+            builder = builder.header(ACCEPT_ENCODING, accept);
+        }
+        let header: RequestHeader = builder.body(()).unwrap().into();
+        let mut req = RequestContext::new(&header, PathParams::empty());
+        handler.invoke(&mut req, OptionReqBody::from(RequestBody::empty())).await
+    }
+
+    async fn body_of(resp: Response<ResponseBody>) -> Bytes {
+        resp.into_body().collect().await.unwrap().to_bytes()
+    }
+
+    #[tokio::test]
+    async fn test_serve_file() {
+        let dir = TempDir::new("serve_file");
+        let handler = StaticFileHandler::new(dir.0.clone());
+
+        let resp = get(&handler, "/hello.txt").await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let headers = resp.headers();
+        assert_eq!(headers.get(CONTENT_TYPE).unwrap(), "text/plain; charset=utf-8");
+        assert_eq!(headers.get(CONTENT_LENGTH).unwrap(), "5");
+        assert_eq!(headers.get(CACHE_CONTROL).unwrap(), "public, max-age=3600");
+        assert_eq!(headers.get(ACCEPT_RANGES).unwrap(), "bytes");
+        assert!(headers.get(LAST_MODIFIED).is_some());
+        assert!(headers.get(ETAG).is_some());
+        assert_eq!(resp.body().size_hint().exact(), Some(5));
+        assert_eq!(body_of(resp).await, "hello");
+
+        let resp = get(&handler, "/docs/").await;
+        assert_eq!(resp.headers().get(CONTENT_TYPE).unwrap(), "text/html; charset=utf-8");
+        assert_eq!(body_of(resp).await, "<p>docs</p>");
+
+        assert_eq!(get(&handler, "/missing.txt").await.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_precompressed() {
+        let dir = TempDir::new("precompressed");
+        std::fs::write(dir.0.join("hello.txt.br"), "br!").unwrap();
+        std::fs::write(dir.0.join("hello.txt.gz"), "gzip!").unwrap();
+        let handler = StaticFileHandler::new(dir.0.clone());
+        let identity = get(&handler, "/hello.txt").await;
+        let identity_etag = identity.headers().get(ETAG).unwrap().to_str().unwrap().trim_matches('"').to_string();
+
+        for (accept, encoding, body) in [("gzip, br, zstd", "br", "br!"), ("gzip, br;q=0", "gzip", "gzip!")] {
+            let resp = get_encoded(&handler, "/hello.txt", Some(accept)).await;
+            let headers = resp.headers();
+            assert_eq!(headers.get(CONTENT_ENCODING).unwrap(), encoding, "{accept}");
+            assert_eq!(headers.get(CONTENT_TYPE).unwrap(), "text/plain; charset=utf-8");
+            assert_eq!(headers.get(CONTENT_LENGTH).unwrap(), body.len().to_string().as_str());
+            assert_eq!(headers.get(VARY).unwrap(), "accept-encoding");
+            assert_eq!(headers.get(LAST_MODIFIED), identity.headers().get(LAST_MODIFIED));
+            assert_eq!(headers.get(ETAG).unwrap(), format!("\"{identity_etag}-{encoding}\"").as_str());
+            assert_eq!(body_of(resp).await, body);
+        }
+
+        // the missing zstd file is only looked up once
+        assert!(handler.missing.lock().unwrap().contains(&dir.0.canonicalize().unwrap().join("hello.txt.zst")));
+        for accept in ["zstd", "identity"] {
+            let resp = get_encoded(&handler, "/hello.txt", Some(accept)).await;
+            assert!(resp.headers().get(CONTENT_ENCODING).is_none());
+            assert_eq!(body_of(resp).await, "hello");
+        }
+
+        let handler = StaticFileHandler::new(dir.0.clone()).precompressed(false);
+        let resp = get_encoded(&handler, "/hello.txt", Some("br")).await;
+        assert!(resp.headers().get(CONTENT_ENCODING).is_none());
+        assert_eq!(body_of(resp).await, "hello");
+    }
+
+    #[tokio::test]
+    async fn test_path_traversal() {
+        let dir = TempDir::new("path_traversal");
+        let handler = StaticFileHandler::new(dir.0.join("docs"));
+
+        for path in ["/../hello.txt", "/docs/%2e%2e/%2e%2e/hello.txt", "/%2Fetc%2Fpasswd", "/..%5Chello.txt"] {
+            let resp = get(&handler, path).await;
+            assert_eq!(resp.status(), StatusCode::FORBIDDEN, "{path}");
+            let body = body_of(resp).await;
+            assert!(!String::from_utf8_lossy(&body).contains(dir.0.to_str().unwrap()));
+        }
+    }
+
+    #[test]
+    fn test_content_type() {
+        assert_eq!(content_type(Path::new("app.JS")), "text/javascript; charset=utf-8");
+        assert_eq!(content_type(Path::new("module.wasm")), "application/wasm");
+        assert_eq!(content_type(Path::new("logo.svg")), "image/svg+xml");
+        assert_eq!(content_type(Path::new("README")), "application/octet-stream");
+    }
+}
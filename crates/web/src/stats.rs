@@ -0,0 +1,110 @@
+//! Statistics of the connections served by a [`Server`](crate::Server).
+//!
+//! Each connection counts its requests, errors and bytes while it's open, and reports them as a
+//! [`ConnectionStats`] once it's closed, see [`Server::stats_receiver`](crate::Server::stats_receiver).
+
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// The statistics of a closed connection.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConnectionStats {
+    /// The requests the handlers were invoked for
+    pub requests_handled: u64,
+    /// The errors of the connection, such as an invalid request or a failed write, see
+    /// `MetricsWrapper` with the `metrics` feature for the status of the responses
+    pub errors: u64,
+    /// The bytes read from the connection, after TLS decryption if any
+    pub bytes_read: u64,
+    /// The bytes written to the connection, before TLS encryption if any
+    pub bytes_written: u64,
+}
+
+/// The counters of an open connection, shared by its reader, its writer and its handler.
+#[derive(Debug, Default)]
+pub(crate) struct ConnectionCounters {
+    requests_handled: AtomicU64,
+    errors: AtomicU64,
+    bytes_read: AtomicU64,
+    bytes_written: AtomicU64,
+}
+
+impl ConnectionCounters {
+    pub(crate) fn request_handled(&self) {
+        self.requests_handled.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn error(&self) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn stats(&self) -> ConnectionStats {
+        ConnectionStats {
+            requests_handled: self.requests_handled.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            bytes_read: self.bytes_read.load(Ordering::Relaxed),
+            bytes_written: self.bytes_written.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// A stream adding the bytes read from and written to `inner` to the counters.
+pub(crate) struct Counted<T> {
+    inner: T,
+    counters: Arc<ConnectionCounters>,
+}
+
+impl<T> Counted<T> {
+    pub(crate) fn new(inner: T, counters: Arc<ConnectionCounters>) -> Self {
+        Self { inner, counters }
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for Counted<T> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = poll {
+            self.counters.bytes_read.fetch_add((buf.filled().len() - filled) as u64, Ordering::Relaxed);
+        }
+        poll
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for Counted<T> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(written)) = poll {
+            self.counters.bytes_written.fetch_add(written as u64, Ordering::Relaxed);
+        }
+        poll
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write_vectored(cx, bufs);
+        if let Poll::Ready(Ok(written)) = poll {
+            self.counters.bytes_written.fetch_add(written as u64, Ordering::Relaxed);
+        }
+        poll
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
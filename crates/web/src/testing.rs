@@ -0,0 +1,227 @@
+//! In-process client to test handlers without binding a port.
+//!
+//! [`TestClient`] routes requests through a [`Server`] the same way its connections do, running the
+//! wrappers and the handler, and reads the whole response body before returning it:
+//!
+//! ```
+//! use http::StatusCode;
+//! use micro_web::router::{get, Router};
+//! use micro_web::testing::TestClient;
+//! use micro_web::handler_fn;
+//!
+//! async fn hello() -> &'static str {
+//!     "hello"
+//! }
+//!
+//! let router = Router::builder().route("/hello", get(handler_fn(hello))).build();
+//! let client = TestClient::from_router(router);
+//!
+//! client.get("/hello").send().status(StatusCode::OK).body("hello");
+//! client.get("/missing").send().status(StatusCode::NOT_FOUND);
+//! ```
+//!
+//! Requests don't go through the HTTP/1 codec: TCP framing, `Content-Length` and `Transfer-Encoding`
+//! handling, `Expect: 100-continue`, keep-alive and TLS are not tested. The client blocks on its own
+//! runtime, so it can't be used from within an async test.
+
+use crate::router::Router;
+use crate::{OptionReqBody, RequestBody, Server};
+use bytes::Bytes;
+use http::{HeaderName, HeaderValue, Method, Request, Response, StatusCode};
+use http_body_util::{BodyExt, Full};
+use serde::de::DeserializeOwned;
+use tokio::runtime::Runtime;
+
+/// A client sending requests to a [`Server`] in-process.
+pub struct TestClient {
+    server: Server,
+    runtime: Runtime,
+}
+
+impl TestClient {
+    /// Creates a client for `server`, its bind address is not used.
+    ///
+    /// # Panics
+    /// Panics if the runtime can't be created.
+    pub fn new(server: Server) -> Self {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        Self { server, runtime }
+    }
+
+    /// Creates a client for a server with `router` and the default handler.
+    pub fn from_router(router: Router) -> Self {
+        // the address is resolved but never bound
+        Self::new(Server::builder().router(router).bind("127.0.0.1:0").build().unwrap())
+    }
+
+    /// Sends `req` and waits for the whole response.
+    ///
+    /// # Panics
+    /// Panics if the response body fails.
+    pub fn request(&self, req: Request<Bytes>) -> Response<Bytes> {
+        let (parts, body) = req.into_parts();
+        let req_body = RequestBody::boxed(Full::new(body).map_err(|never| match never {}));
+
+        self.runtime.block_on(async {
+            let resp = self.server.handle(parts, OptionReqBody::from(req_body)).await;
+            let (parts, body) = resp.into_parts();
+            let body = body.collect().await.expect("failed to read the response body").to_bytes();
+            Response::from_parts(parts, body)
+        })
+    }
+
+    /// Starts a `GET` request to `path`.
+    pub fn get(&self, path: &str) -> TestRequest<'_> {
+        TestRequest::new(self, Method::GET, path, Bytes::new())
+    }
+
+    /// Starts a `POST` request to `path` with `body`.
+    pub fn post(&self, path: &str, body: impl Into<Bytes>) -> TestRequest<'_> {
+        TestRequest::new(self, Method::POST, path, body.into())
+    }
+}
+
+/// A request being built by [`TestClient::get`] or [`TestClient::post`].
+pub struct TestRequest<'client> {
+    client: &'client TestClient,
+    request: Request<Bytes>,
+}
+
+impl<'client> TestRequest<'client> {
+    fn new(client: &'client TestClient, method: Method, path: &str, body: Bytes) -> Self {
+        let mut request = Request::new(body);
+        *request.method_mut() = method;
+        *request.uri_mut() = path.parse().unwrap_or_else(|e| panic!("invalid path {path:?}: {e}"));
+        Self { client, request }
+    }
+
+    /// Appends the header `name`.
+    ///
+    /// # Panics
+    /// Panics if `name` or `value` are not valid header names or values.
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        let name = HeaderName::try_from(name).unwrap_or_else(|e| panic!("invalid header name {name:?}: {e}"));
+        let value = HeaderValue::try_from(value).unwrap_or_else(|e| panic!("invalid header value {value:?}: {e}"));
+        self.request.headers_mut().append(name, value);
+        self
+    }
+
+    /// Sends the request, see [`TestClient::request`].
+    pub fn send(self) -> ResponseAssert {
+        ResponseAssert::new(self.client.request(self.request))
+    }
+}
+
+/// Assertions on a response, panicking with the mismatch on failure.
+#[derive(Debug)]
+pub struct ResponseAssert {
+    response: Response<Bytes>,
+}
+
+impl ResponseAssert {
+    pub fn new(response: Response<Bytes>) -> Self {
+        Self { response }
+    }
+
+    /// Asserts the status is `status`.
+    #[track_caller]
+    pub fn status(&self, status: StatusCode) -> &Self {
+        assert_eq!(self.response.status(), status, "unexpected status, body: {:?}", self.response.body());
+        self
+    }
+
+    /// Asserts the header `name` is present with `value`.
+    #[track_caller]
+    pub fn header(&self, name: &str, value: &str) -> &Self {
+        match self.response.headers().get(name) {
+            Some(actual) => assert_eq!(actual, value, "unexpected value of header {name}"),
+            None => panic!("missing header {name}, headers: {:?}", self.response.headers()),
+        }
+        self
+    }
+
+    /// Asserts the body is `body`.
+    #[track_caller]
+    pub fn body(&self, body: impl AsRef<[u8]>) -> &Self {
+        assert_eq!(self.response.body().as_ref(), body.as_ref(), "unexpected body");
+        self
+    }
+
+    /// Deserializes the body as JSON.
+    #[track_caller]
+    pub fn body_json<T: DeserializeOwned>(&self) -> T {
+        serde_json::from_slice(self.response.body())
+            .unwrap_or_else(|e| panic!("body is not the expected json: {e}, body: {:?}", self.response.body()))
+    }
+
+    /// Returns the response.
+    pub fn response(&self) -> &Response<Bytes> {
+        &self.response
+    }
+
+    /// Returns the response, to make other assertions.
+    pub fn into_response(self) -> Response<Bytes> {
+        self.response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extract::Json;
+    use crate::handler_fn;
+    use crate::router::{get, post};
+    use crate::wrapper::SecurityHeadersWrapper;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct User {
+        name: String,
+    }
+
+    async fn echo(Json(user): Json<User>) -> crate::ResponseBuilder {
+        crate::ResponseBuilder::json(&user)
+    }
+
+    async fn hello() -> &'static str {
+        "hello"
+    }
+
+    fn client() -> TestClient {
+        let router = Router::builder()
+            .route("/hello", get(handler_fn(hello)))
+            .route("/users", post(handler_fn(echo)))
+            .wrap(SecurityHeadersWrapper::strict_defaults())
+            .build();
+        TestClient::from_router(router)
+    }
+
+    #[test]
+    fn test_request() {
+        let client = client();
+
+        let resp = client.get("/hello").send();
+        resp.status(StatusCode::OK).header("content-type", "text/plain; charset=utf-8").body("hello");
+        // the wrappers are executed
+        resp.header("x-content-type-options", "nosniff");
+
+        client.get("/missing").send().status(StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn test_body_json() {
+        let user: User = client()
+            .post("/users", r#"{"name":"alice"}"#)
+            .with_header("content-type", "application/json")
+            .send()
+            .status(StatusCode::OK)
+            .body_json();
+        assert_eq!(user, User { name: "alice".to_string() });
+    }
+
+    #[test]
+    #[should_panic(expected = "unexpected status")]
+    fn test_status_mismatch() {
+        client().get("/missing").send().status(StatusCode::OK);
+    }
+}
@@ -0,0 +1,640 @@
+//! WebSocket support.
+//!
+//! A handler accepts a WebSocket connection in two steps:
+//! 1. [`WebSocketUpgrade::from_request`] validates the opening handshake described in
+//!    [RFC 6455 Section 4.2.1](https://tools.ietf.org/html/rfc6455#section-4.2.1), invalid requests are
+//!    rejected with `400 Bad Request`
+//! 2. [`WebSocketUpgrade::on_upgrade`] builds the `101 Switching Protocols` response and spawns a task
+//!    which runs the callback with a [`WebSocketStream`] once the response has been sent
+//!
+//! `WebSocketUpgrade` is also an extractor, which runs the first step:
+//!
+//! ```no_run
+//! use micro_web::websocket::{WebSocketStream, WebSocketUpgrade, WsFrame};
+//! use micro_web::ResponseBody;
+//!
+//! async fn echo(mut ws: WebSocketStream) {
+//!     while let Some(Ok(frame)) = ws.recv().await {
+//!         if let WsFrame::Text(_) | WsFrame::Binary(_) = frame {
+//!             if ws.send(frame).await.is_err() {
+//!                 break;
+//!             }
+//!         }
+//!     }
+//! }
+//!
+//! async fn handle(upgrade: WebSocketUpgrade) -> http::Response<ResponseBody> {
+//!     upgrade.on_upgrade(echo)
+//! }
+//! ```
+
+use crate::extract::FromRequest;
+use crate::responder::Responder;
+use crate::{OptionReqBody, RequestContext, ResponseBody};
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use bytes::{BufMut, Bytes, BytesMut};
+use http::header::{CONNECTION, SEC_WEBSOCKET_ACCEPT, SEC_WEBSOCKET_KEY, SEC_WEBSOCKET_VERSION, UPGRADE};
+use http::{HeaderMap, HeaderName, HeaderValue, Method, Response, StatusCode};
+use micro_http::connection::{OnUpgrade, Upgraded};
+use sha1::{Digest, Sha1};
+use std::future::Future;
+use std::io;
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tracing::{error, trace};
+
+/// The GUID appended to the key to compute `Sec-WebSocket-Accept`.
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// The default of [`WebSocketStream::max_message_size`], 16 MiB.
+const DEFAULT_MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+
+/// Errors of the opening handshake.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum HandshakeError {
+    /// The request method isn't `GET`
+    #[error("websocket handshake requires the GET method")]
+    MethodNotGet,
+
+    /// A required header is missing or has an invalid value
+    #[error("missing or invalid header: {0}")]
+    InvalidHeader(HeaderName),
+
+    /// `Sec-WebSocket-Version` isn't 13
+    #[error("unsupported websocket version")]
+    UnsupportedVersion,
+
+    /// The request didn't come from a connection that can switch protocols
+    #[error("connection can't be upgraded")]
+    NotUpgradable,
+}
+
+impl Responder for HandshakeError {
+    fn response_to(self, req: &RequestContext) -> Response<ResponseBody> {
+        trace!("reject websocket handshake: {}", self);
+        match self {
+            HandshakeError::UnsupportedVersion => {
+                let mut resp = (StatusCode::UPGRADE_REQUIRED, "unsupported websocket version").response_to(req);
+                resp.headers_mut().insert(SEC_WEBSOCKET_VERSION, HeaderValue::from_static("13"));
+                resp
+            }
+            _ => (StatusCode::BAD_REQUEST, "invalid websocket handshake").response_to(req),
+        }
+    }
+}
+
+/// A validated WebSocket opening handshake.
+pub struct WebSocketUpgrade {
+    accept: HeaderValue,
+    on_upgrade: OnUpgrade,
+    max_message_size: usize,
+}
+
+impl WebSocketUpgrade {
+    /// Validates the opening handshake of `req`.
+    pub fn from_request(req: &RequestContext) -> Result<Self, HandshakeError> {
+        if req.method() != Method::GET {
+            return Err(HandshakeError::MethodNotGet);
+        }
+
+        let headers = req.headers();
+        if !header_contains(headers, &UPGRADE, "websocket") {
+            return Err(HandshakeError::InvalidHeader(UPGRADE));
+        }
+        if !header_contains(headers, &CONNECTION, "upgrade") {
+            return Err(HandshakeError::InvalidHeader(CONNECTION));
+        }
+        if !matches!(headers.get(SEC_WEBSOCKET_VERSION), Some(version) if version == "13") {
+            return Err(HandshakeError::UnsupportedVersion);
+        }
+
+        // the key is a base64 encoded 16 bytes nonce
+        let key = headers.get(SEC_WEBSOCKET_KEY).ok_or(HandshakeError::InvalidHeader(SEC_WEBSOCKET_KEY))?;
+        match STANDARD.decode(key.as_bytes()) {
+            Ok(nonce) if nonce.len() == 16 => (),
+            _ => return Err(HandshakeError::InvalidHeader(SEC_WEBSOCKET_KEY)),
+        }
+
+        let on_upgrade = req.extensions().get::<OnUpgrade>().cloned().ok_or(HandshakeError::NotUpgradable)?;
+        Ok(Self { accept: accept_key(key.as_bytes()), on_upgrade, max_message_size: DEFAULT_MAX_MESSAGE_SIZE })
+    }
+
+    /// Sets the maximum size of a received message, in bytes, see [`WebSocketStream::max_message_size`].
+    pub fn max_message_size(mut self, max_message_size: usize) -> Self {
+        self.max_message_size = max_message_size;
+        self
+    }
+
+    /// Builds the `101 Switching Protocols` response, `callback` runs with the WebSocket connection
+    /// after the response has been sent.
+    pub fn on_upgrade<F, Fut>(self, callback: F) -> Response<ResponseBody>
+    where
+        F: FnOnce(WebSocketStream) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let Self { accept, on_upgrade, max_message_size } = self;
+        tokio::spawn(async move {
+            match on_upgrade.upgraded().await {
+                Ok(upgraded) => callback(WebSocketStream::new(upgraded).max_message_size(max_message_size)).await,
+                Err(e) => error!("websocket upgrade failed: {}", e),
+            }
+        });
+
+        let mut resp = Response::new(ResponseBody::empty());
+        *resp.status_mut() = StatusCode::SWITCHING_PROTOCOLS;
+        let headers = resp.headers_mut();
+        headers.insert(UPGRADE, HeaderValue::from_static("websocket"));
+        headers.insert(CONNECTION, HeaderValue::from_static("Upgrade"));
+        headers.insert(SEC_WEBSOCKET_ACCEPT, accept);
+        resp
+    }
+}
+
+#[async_trait]
+impl FromRequest for WebSocketUpgrade {
+    type Output<'r> = WebSocketUpgrade;
+    type Error = HandshakeError;
+
+    async fn from_request<'r>(req: &'r RequestContext, _body: OptionReqBody) -> Result<Self::Output<'r>, Self::Error> {
+        WebSocketUpgrade::from_request(req)
+    }
+}
+
+/// Returns true if one of the comma separated tokens of the header `name` is `token`, ignoring case.
+fn header_contains(headers: &HeaderMap, name: &HeaderName, token: &str) -> bool {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|value| value.trim().eq_ignore_ascii_case(token))
+}
+
+/// Computes `Sec-WebSocket-Accept` from `Sec-WebSocket-Key`.
+fn accept_key(key: &[u8]) -> HeaderValue {
+    let mut sha1 = Sha1::new();
+    sha1.update(key);
+    sha1.update(WEBSOCKET_GUID.as_bytes());
+    // base64 only contains valid header characters
+    HeaderValue::from_str(&STANDARD.encode(sha1.finalize())).unwrap()
+}
+
+/// The payload of a close frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CloseFrame {
+    /// The status code, see [RFC 6455 Section 7.4](https://tools.ietf.org/html/rfc6455#section-7.4)
+    pub code: u16,
+    /// The reason, for debugging
+    pub reason: String,
+}
+
+/// A WebSocket message or control frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WsFrame {
+    /// A text message
+    Text(String),
+    /// A binary message
+    Binary(Bytes),
+    /// A ping, the peer expects a pong with the same payload
+    Ping(Bytes),
+    /// A pong
+    Pong(Bytes),
+    /// The closing handshake
+    Close(Option<CloseFrame>),
+}
+
+/// Errors of a WebSocket connection.
+#[derive(Error, Debug)]
+pub enum WebSocketError {
+    /// The underlying IO failed
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+
+    /// The peer violated the protocol, e.g. by sending an unmasked frame
+    #[error("protocol error: {0}")]
+    Protocol(&'static str),
+
+    /// A text message isn't valid UTF-8
+    #[error("text message is not valid utf8")]
+    InvalidUtf8,
+
+    /// A message exceeds the maximum message size
+    #[error("message too large")]
+    MessageTooLarge,
+
+    /// The connection has been closed
+    #[error("connection closed")]
+    Closed,
+}
+
+impl WebSocketError {
+    /// The code of the close frame sent when this error is detected.
+    fn close_code(&self) -> Option<u16> {
+        match self {
+            WebSocketError::Protocol(_) => Some(1002),
+            WebSocketError::InvalidUtf8 => Some(1007),
+            WebSocketError::MessageTooLarge => Some(1009),
+            WebSocketError::Io(_) | WebSocketError::Closed => None,
+        }
+    }
+}
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_BINARY: u8 = 0x2;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+/// A WebSocket connection on the server side.
+///
+/// Received frames must be masked, as required from clients. Fragmented messages are reassembled,
+/// so [`recv`](Self::recv) always returns complete messages. Pings are not answered automatically,
+/// while a received close frame is echoed as required by the closing handshake.
+pub struct WebSocketStream<S = Upgraded> {
+    io: BufReader<S>,
+    max_message_size: usize,
+    /// The opcode and the payload received so far of a fragmented message
+    fragments: Option<(u8, BytesMut)>,
+    close_sent: bool,
+    close_received: bool,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> WebSocketStream<S> {
+    /// Wraps the IO of a connection which has completed the opening handshake.
+    pub fn new(io: S) -> Self {
+        Self {
+            io: BufReader::new(io),
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            fragments: None,
+            close_sent: false,
+            close_received: false,
+        }
+    }
+
+    /// Sets the maximum size of a received message, in bytes, larger messages fail with
+    /// [`WebSocketError::MessageTooLarge`]. Defaults to 16 MiB.
+    pub fn max_message_size(mut self, max_message_size: usize) -> Self {
+        self.max_message_size = max_message_size;
+        self
+    }
+
+    /// Sends a frame, nothing can be sent after a close frame.
+    pub async fn send(&mut self, frame: WsFrame) -> Result<(), WebSocketError> {
+        if self.close_sent {
+            return Err(WebSocketError::Closed);
+        }
+
+        let (opcode, payload) = match frame {
+            WsFrame::Text(text) => (OPCODE_TEXT, Bytes::from(text)),
+            WsFrame::Binary(data) => (OPCODE_BINARY, data),
+            WsFrame::Ping(data) => (OPCODE_PING, data),
+            WsFrame::Pong(data) => (OPCODE_PONG, data),
+            WsFrame::Close(close) => {
+                self.close_sent = true;
+                (OPCODE_CLOSE, encode_close(close))
+            }
+        };
+
+        let io = self.io.get_mut();
+        io.write_all(&encode_frame_head(opcode, payload.len())).await?;
+        io.write_all(&payload).await?;
+        io.flush().await?;
+        Ok(())
+    }
+
+    /// Receives the next message or control frame.
+    ///
+    /// Returns `None` when the connection is closed. When the peer violates the protocol, a close
+    /// frame with the matching status code is sent before the error is returned.
+    pub async fn recv(&mut self) -> Option<Result<WsFrame, WebSocketError>> {
+        if self.close_received {
+            return None;
+        }
+
+        match self.read_message().await {
+            Ok(Some(frame)) => {
+                if let WsFrame::Close(close) = &frame {
+                    self.close_received = true;
+                    if !self.close_sent {
+                        let code = close.as_ref().map(|close| close.code).unwrap_or(1000);
+                        let _ = self.send(WsFrame::Close(Some(CloseFrame { code, reason: String::new() }))).await;
+                    }
+                }
+                Some(Ok(frame))
+            }
+            Ok(None) => None,
+            Err(e) => {
+                if let Some(code) = e.close_code() {
+                    let _ = self.send(WsFrame::Close(Some(CloseFrame { code, reason: e.to_string() }))).await;
+                }
+                self.close_received = true;
+                Some(Err(e))
+            }
+        }
+    }
+
+    /// Reads frames until a complete message or a control frame is received.
+    async fn read_message(&mut self) -> Result<Option<WsFrame>, WebSocketError> {
+        loop {
+            let (fin, opcode, payload) = match self.read_frame().await? {
+                Some(frame) => frame,
+                None => return Ok(None),
+            };
+
+            match opcode {
+                OPCODE_CLOSE => return parse_close(payload).map(|close| Some(WsFrame::Close(close))),
+                OPCODE_PING => return Ok(Some(WsFrame::Ping(payload.freeze()))),
+                OPCODE_PONG => return Ok(Some(WsFrame::Pong(payload.freeze()))),
+                OPCODE_TEXT | OPCODE_BINARY if self.fragments.is_some() => {
+                    return Err(WebSocketError::Protocol("expect a continuation frame"));
+                }
+                OPCODE_TEXT | OPCODE_BINARY if fin => return to_message(opcode, payload).map(Some),
+                OPCODE_TEXT | OPCODE_BINARY => self.fragments = Some((opcode, payload)),
+                OPCODE_CONTINUATION => {
+                    let (_, buf) =
+                        self.fragments.as_mut().ok_or(WebSocketError::Protocol("unexpected continuation"))?;
+                    if buf.len() + payload.len() > self.max_message_size {
+                        return Err(WebSocketError::MessageTooLarge);
+                    }
+                    buf.extend_from_slice(&payload);
+                    if fin {
+                        let (opcode, buf) = self.fragments.take().unwrap();
+                        return to_message(opcode, buf).map(Some);
+                    }
+                }
+                _ => return Err(WebSocketError::Protocol("unknown opcode")),
+            }
+        }
+    }
+
+    /// Reads a single frame, returns `None` if the connection is closed before its first byte.
+    async fn read_frame(&mut self) -> Result<Option<(bool, u8, BytesMut)>, WebSocketError> {
+        let mut head = [0u8; 2];
+        match self.io.read_exact(&mut head).await {
+            Ok(_) => (),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+
+        let fin = head[0] & 0x80 != 0;
+        let opcode = head[0] & 0x0F;
+        if head[0] & 0x70 != 0 {
+            return Err(WebSocketError::Protocol("reserved bits must be zero"));
+        }
+        if head[1] & 0x80 == 0 {
+            return Err(WebSocketError::Protocol("client frames must be masked"));
+        }
+
+        let len = match head[1] & 0x7F {
+            126 => self.io.read_u16().await? as u64,
+            127 => self.io.read_u64().await?,
+            len => len as u64,
+        };
+
+        let is_control = opcode & 0x08 != 0;
+        if is_control && (!fin || len > 125) {
+            return Err(WebSocketError::Protocol("control frames must not be fragmented or longer than 125 bytes"));
+        }
+        if len > self.max_message_size as u64 {
+            return Err(WebSocketError::MessageTooLarge);
+        }
+
+        let mut mask = [0u8; 4];
+        self.io.read_exact(&mut mask).await?;
+
+        let mut payload = BytesMut::zeroed(len as usize);
+        self.io.read_exact(&mut payload).await?;
+        for (i, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[i % 4];
+        }
+
+        Ok(Some((fin, opcode, payload)))
+    }
+}
+
+fn to_message(opcode: u8, payload: BytesMut) -> Result<WsFrame, WebSocketError> {
+    match opcode {
+        OPCODE_TEXT => String::from_utf8(payload.to_vec()).map(WsFrame::Text).map_err(|_| WebSocketError::InvalidUtf8),
+        _ => Ok(WsFrame::Binary(payload.freeze())),
+    }
+}
+
+fn parse_close(payload: BytesMut) -> Result<Option<CloseFrame>, WebSocketError> {
+    match payload.len() {
+        0 => Ok(None),
+        1 => Err(WebSocketError::Protocol("close frame payload too short")),
+        _ => {
+            let code = u16::from_be_bytes([payload[0], payload[1]]);
+            let reason = std::str::from_utf8(&payload[2..]).map_err(|_| WebSocketError::InvalidUtf8)?;
+            Ok(Some(CloseFrame { code, reason: reason.to_string() }))
+        }
+    }
+}
+
+fn encode_close(close: Option<CloseFrame>) -> Bytes {
+    let close = match close {
+        Some(close) => close,
+        None => return Bytes::new(),
+    };
+
+    // control frame payloads are limited to 125 bytes
+    let mut reason_len = close.reason.len().min(123);
+    while !close.reason.is_char_boundary(reason_len) {
+        reason_len -= 1;
+    }
+
+    let mut buf = BytesMut::with_capacity(2 + reason_len);
+    buf.put_u16(close.code);
+    buf.put_slice(&close.reason.as_bytes()[..reason_len]);
+    buf.freeze()
+}
+
+/// Encodes the head of an unmasked, final frame.
+fn encode_frame_head(opcode: u8, len: usize) -> BytesMut {
+    let mut head = BytesMut::with_capacity(10);
+    head.put_u8(0x80 | opcode);
+    match len {
+        0..=125 => head.put_u8(len as u8),
+        126..=0xFFFF => {
+            head.put_u8(126);
+            head.put_u16(len as u16);
+        }
+        _ => {
+            head.put_u8(127);
+            head.put_u64(len as u64);
+        }
+    }
+    head
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::{get, Router};
+    use crate::{handler_fn, PathParams, Server};
+    use micro_http::connection::HttpConnection;
+    use micro_http::protocol::RequestHeader;
+    use std::sync::Arc;
+    use tokio::io::DuplexStream;
+    use tokio::net::{TcpListener, TcpStream};
+
+    /// Encodes a masked client frame.
+    fn client_frame(first_byte: u8, payload: &[u8]) -> Vec<u8> {
+        let mask = [1u8, 2, 3, 4];
+        let mut frame = vec![first_byte];
+        match payload.len() {
+            len @ 0..=125 => frame.push(0x80 | len as u8),
+            len => {
+                frame.push(0x80 | 126);
+                frame.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+        }
+        frame.extend_from_slice(&mask);
+        frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+        frame
+    }
+
+    fn stream() -> (WebSocketStream<DuplexStream>, DuplexStream) {
+        let (server, client) = tokio::io::duplex(1024);
+        (WebSocketStream::new(server), client)
+    }
+
+    async fn read_all(mut client: DuplexStream) -> Vec<u8> {
+        let mut buf = vec![];
+        client.read_to_end(&mut buf).await.unwrap();
+        buf
+    }
+
+    #[test]
+    fn test_accept_key() {
+        // the example of RFC 6455 Section 1.3
+        assert_eq!(accept_key(b"dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    }
+
+    #[test]
+    fn test_handshake_validation() {
+        fn validate(method: Method, headers: &[(&str, &str)]) -> Result<(), HandshakeError> {
+            let mut builder = http::Request::builder().method(method);
+            for (name, value) in headers {
+                builder = builder.header(*name, *value);
+            }
+            let header: RequestHeader = builder.body(()).unwrap().into();
+            let req = RequestContext::new(&header, PathParams::empty());
+            WebSocketUpgrade::from_request(&req).map(|_| ())
+        }
+
+        let valid = [
+            ("upgrade", "websocket"),
+            ("connection", "keep-alive, Upgrade"),
+            ("sec-websocket-version", "13"),
+            ("sec-websocket-key", "dGhlIHNhbXBsZSBub25jZQ=="),
+        ];
+        assert_eq!(validate(Method::POST, &valid), Err(HandshakeError::MethodNotGet));
+        assert_eq!(validate(Method::GET, &valid[1..]), Err(HandshakeError::InvalidHeader(UPGRADE)));
+        assert_eq!(
+            validate(Method::GET, &[valid[0], valid[2], valid[3]]),
+            Err(HandshakeError::InvalidHeader(CONNECTION))
+        );
+        assert_eq!(validate(Method::GET, &[valid[0], valid[1], valid[3]]), Err(HandshakeError::UnsupportedVersion));
+        assert_eq!(
+            validate(Method::GET, &[valid[0], valid[1], valid[2], ("sec-websocket-key", "c2hvcnQ=")]),
+            Err(HandshakeError::InvalidHeader(SEC_WEBSOCKET_KEY))
+        );
+        // not served by a connection
+        assert_eq!(validate(Method::GET, &valid), Err(HandshakeError::NotUpgradable));
+    }
+
+    #[tokio::test]
+    async fn test_fragmented_message() {
+        let (mut ws, mut client) = stream();
+        client.write_all(&client_frame(OPCODE_TEXT, b"hel")).await.unwrap();
+        client.write_all(&client_frame(0x80 | OPCODE_PING, b"p")).await.unwrap();
+        client.write_all(&client_frame(0x80 | OPCODE_CONTINUATION, b"lo")).await.unwrap();
+
+        assert_eq!(ws.recv().await.unwrap().unwrap(), WsFrame::Ping(Bytes::from_static(b"p")));
+        assert_eq!(ws.recv().await.unwrap().unwrap(), WsFrame::Text("hello".into()));
+    }
+
+    #[tokio::test]
+    async fn test_unmasked_frame() {
+        let (mut ws, mut client) = stream();
+        client.write_all(&[0x80 | OPCODE_TEXT, 0x02, b'h', b'i']).await.unwrap();
+
+        assert!(matches!(ws.recv().await, Some(Err(WebSocketError::Protocol(_)))));
+        assert!(ws.recv().await.is_none());
+
+        drop(ws);
+        // answered with a close frame with the protocol error status
+        let sent = read_all(client).await;
+        assert_eq!(&sent[..4], &[0x80 | OPCODE_CLOSE, sent[1], 0x03, 0xEA]);
+    }
+
+    #[tokio::test]
+    async fn test_closing_handshake() {
+        let (mut ws, mut client) = stream();
+        client.write_all(&client_frame(0x80 | OPCODE_CLOSE, &[0x03, 0xE8])).await.unwrap();
+
+        let close = ws.recv().await.unwrap().unwrap();
+        assert_eq!(close, WsFrame::Close(Some(CloseFrame { code: 1000, reason: String::new() })));
+        assert!(ws.send(WsFrame::Text("late".into())).await.is_err());
+
+        drop(ws);
+        assert_eq!(read_all(client).await, vec![0x80 | OPCODE_CLOSE, 0x02, 0x03, 0xE8]);
+    }
+
+    #[test]
+    fn test_frame_head() {
+        assert_eq!(&encode_frame_head(OPCODE_TEXT, 5)[..], &[0x81, 5]);
+        assert_eq!(&encode_frame_head(OPCODE_BINARY, 300)[..], &[0x82, 126, 0x01, 0x2C]);
+        assert_eq!(&encode_frame_head(OPCODE_BINARY, 70_000)[..], &[0x82, 127, 0, 0, 0, 0, 0, 0x01, 0x11, 0x70]);
+    }
+
+    async fn echo(mut ws: WebSocketStream) {
+        while let Some(Ok(frame)) = ws.recv().await {
+            if let WsFrame::Text(_) = frame {
+                ws.send(frame).await.unwrap();
+            }
+        }
+    }
+
+    async fn upgrade(upgrade: WebSocketUpgrade) -> Response<ResponseBody> {
+        upgrade.on_upgrade(echo)
+    }
+
+    #[tokio::test]
+    async fn test_upgrade_through_connection() {
+        let router = Router::builder().route("/ws", get(handler_fn(upgrade))).build();
+        let server = Arc::new(Server::builder().router(router).bind("127.0.0.1:0").build().unwrap());
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, writer) = stream.into_split();
+            HttpConnection::new(reader, writer).process(server).await.unwrap();
+        });
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let handshake = "GET /ws HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+            Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n";
+        // the first frame is sent together with the handshake
+        let mut request = handshake.as_bytes().to_vec();
+        request.extend(client_frame(0x80 | OPCODE_TEXT, b"hello"));
+        client.write_all(&request).await.unwrap();
+
+        let mut response = vec![];
+        while !response.ends_with(b"\r\n\r\n") {
+            response.push(client.read_u8().await.unwrap());
+        }
+        let response = String::from_utf8(response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 101 Switching Protocols\r\n"));
+        assert!(response.contains("sec-websocket-accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+
+        let mut frame = [0u8; 7];
+        client.read_exact(&mut frame).await.unwrap();
+        assert_eq!(&frame, b"\x81\x05hello");
+    }
+}
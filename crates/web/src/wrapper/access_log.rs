@@ -0,0 +1,319 @@
+//! Module for logging completed requests.
+//!
+//! [`AccessLogWrapper`] emits one `tracing` event at `INFO` level per request, with the target
+//! `micro_web::access_log` so it can be routed separately from the application logs.
+//!
+//! The line is built from a [`LogRecord`] according to the configured [`LogFormat`]:
+//! - [`LogFormat::Combined`]: the [Apache combined log format](https://httpd.apache.org/docs/current/logs.html#combined)
+//! - [`LogFormat::Json`]: a JSON object with one field per record field
+//! - [`LogFormat::Custom`]: any format produced by a closure
+//!
+//! The request and response headers are logged at `DEBUG` level with the same target, credentials
+//! redacted, see [`HeadersDisplay`].
+
+use crate::handler::RequestHandler;
+use crate::wrapper::{priority, Wrapper};
+use crate::{OptionReqBody, RequestContext, ResponseBody};
+use async_trait::async_trait;
+use http::{Method, Response, StatusCode, Version};
+use http_body::Body;
+use micro_http::protocol::HeadersDisplay;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{debug, info};
+
+/// The information logged for a completed request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogRecord {
+    /// The time the request was received
+    pub time: SystemTime,
+    /// The request method
+    pub method: Method,
+    /// The request path, including the query
+    pub path: String,
+    /// The request version
+    pub version: Version,
+    /// The response status
+    pub status: StatusCode,
+    /// The size of the response body, `None` if it's streamed with an unknown size
+    pub body_size: Option<u64>,
+    /// The time spent handling the request, until the response head was ready
+    pub duration: Duration,
+    /// The client ip, see [`RequestContext::client_ip`]
+    pub client_ip: Option<IpAddr>,
+    /// The `Referer` request header
+    pub referer: Option<String>,
+    /// The `User-Agent` request header
+    pub user_agent: Option<String>,
+}
+
+/// The format of the access log lines.
+pub enum LogFormat {
+    /// `127.0.0.1 - - [10/Oct/2000:13:55:36 +0000] "GET /index.html HTTP/1.1" 200 2326 "-" "curl/8.0"`
+    Combined,
+    /// `{"time":"...","method":"GET","path":"/index.html",...,"duration_ms":1.25,...}`
+    Json,
+    /// Formats the record with the given closure
+    Custom(Box<dyn Fn(&LogRecord) -> String + Send + Sync>),
+}
+
+impl LogFormat {
+    /// Formats `record` as a single log line.
+    pub fn format(&self, record: &LogRecord) -> String {
+        match self {
+            LogFormat::Combined => format_combined(record),
+            LogFormat::Json => format_json(record),
+            LogFormat::Custom(f) => f(record),
+        }
+    }
+}
+
+fn format_combined(record: &LogRecord) -> String {
+    let client_ip = record.client_ip.map(|ip| ip.to_string()).unwrap_or_else(|| "-".into());
+    let body_size = record.body_size.map(|size| size.to_string()).unwrap_or_else(|| "-".into());
+    format!(
+        "{} - - [{}] \"{} {} {:?}\" {} {} \"{}\" \"{}\"",
+        client_ip,
+        format_clf_time(record.time),
+        record.method,
+        record.path,
+        record.version,
+        record.status.as_u16(),
+        body_size,
+        record.referer.as_deref().unwrap_or("-"),
+        record.user_agent.as_deref().unwrap_or("-"),
+    )
+}
+
+fn format_json(record: &LogRecord) -> String {
+    serde_json::json!({
+        "time": httpdate::fmt_http_date(record.time),
+        "method": record.method.as_str(),
+        "path": record.path,
+        "version": format!("{:?}", record.version),
+        "status": record.status.as_u16(),
+        "body_size": record.body_size,
+        "duration_ms": record.duration.as_secs_f64() * 1000.0,
+        "client_ip": record.client_ip.map(|ip| ip.to_string()),
+        "referer": record.referer,
+        "user_agent": record.user_agent,
+    })
+    .to_string()
+}
+
+/// Formats `time` as `10/Oct/2000:13:55:36 +0000`, in UTC.
+fn format_clf_time(time: SystemTime) -> String {
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+    let secs = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let (days, secs_of_day) = (secs / 86400, secs % 86400);
+
+    // converts days since epoch to a civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+
+    format!(
+        "{:02}/{}/{}:{:02}:{:02}:{:02} +0000",
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60
+    )
+}
+
+/// The time a request was received, stored in the request extensions by [`AccessLogWrapper`].
+#[derive(Debug, Clone, Copy)]
+struct RequestStart {
+    time: SystemTime,
+    instant: Instant,
+}
+
+/// A wrapper that creates `AccessLogRequestHandler`.
+///
+/// # Example
+/// ```
+/// use micro_web::wrapper::{AccessLogWrapper, LogFormat};
+///
+/// let wrapper = AccessLogWrapper::new(LogFormat::Json);
+/// ```
+pub struct AccessLogWrapper {
+    format: Arc<LogFormat>,
+}
+
+impl AccessLogWrapper {
+    /// Creates an `AccessLogWrapper` using the given format.
+    pub fn new(format: LogFormat) -> Self {
+        Self { format: Arc::new(format) }
+    }
+}
+
+impl Default for AccessLogWrapper {
+    /// Logs in the Apache combined log format.
+    fn default() -> Self {
+        Self::new(LogFormat::Combined)
+    }
+}
+
+/// A request handler that logs each completed request.
+pub struct AccessLogRequestHandler<H: RequestHandler> {
+    handler: H,
+    format: Arc<LogFormat>,
+}
+
+impl<H: RequestHandler> Wrapper<H> for AccessLogWrapper {
+    type Out = AccessLogRequestHandler<H>;
+
+    fn wrap(&self, handler: H) -> Self::Out {
+        AccessLogRequestHandler { handler, format: Arc::clone(&self.format) }
+    }
+
+    fn priority(&self) -> i32 {
+        priority::LOGGING
+    }
+}
+
+#[async_trait]
+impl<H: RequestHandler> RequestHandler for AccessLogRequestHandler<H> {
+    async fn invoke<'server, 'req>(
+        &self,
+        req: &mut RequestContext<'server, 'req>,
+        req_body: OptionReqBody,
+    ) -> Response<ResponseBody> {
+        req.extensions_mut().insert(RequestStart { time: SystemTime::now(), instant: Instant::now() });
+
+        let resp = self.handler.invoke(req, req_body).await;
+
+        // the handler may have replaced the extensions, the start is unknown then
+        let start = req.extensions().get::<RequestStart>().copied();
+        let record = LogRecord {
+            time: start.map(|start| start.time).unwrap_or_else(SystemTime::now),
+            method: req.method().clone(),
+            path: req.uri().path_and_query().map(|p| p.as_str()).unwrap_or("/").to_string(),
+            version: req.version(),
+            status: resp.status(),
+            body_size: body_size(&resp),
+            duration: start.map(|start| start.instant.elapsed()).unwrap_or_default(),
+            client_ip: req.client_ip(),
+            referer: header_string(req, http::header::REFERER),
+            user_agent: header_string(req, http::header::USER_AGENT),
+        };
+
+        info!(target: "micro_web::access_log", "{}", self.format.format(&record));
+        debug!(
+            target: "micro_web::access_log",
+            "request headers:\n{}response headers:\n{}",
+            HeadersDisplay::new(req.headers()),
+            HeadersDisplay::new(resp.headers())
+        );
+        resp
+    }
+}
+
+/// Returns the body size from the `Content-Length` header, or from the body if its size is known.
+fn body_size(resp: &Response<ResponseBody>) -> Option<u64> {
+    resp.headers()
+        .get(http::header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+        .or_else(|| resp.body().size_hint().exact())
+}
+
+fn header_string(req: &RequestContext, name: http::HeaderName) -> Option<String> {
+    req.headers().get(name).and_then(|value| value.to_str().ok()).map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{handler_fn, PathParams, RequestBody};
+    use micro_http::protocol::RequestHeader;
+    use std::io;
+    use std::net::SocketAddr;
+    use std::sync::Mutex;
+
+    /// Collects the output of a tracing subscriber
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    async fn hello() -> &'static str {
+        "hello"
+    }
+
+    async fn invoke_logged(format: LogFormat) -> String {
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::fmt().with_writer(move || writer.clone()).with_ansi(false).finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let handler = AccessLogWrapper::new(format).wrap(handler_fn(hello));
+        let header: RequestHeader = http::Request::builder()
+            .uri("/index.html?a=1")
+            .header(http::header::USER_AGENT, "curl/8.0")
+            .body(())
+            .unwrap()
+            .into();
+        let remote_addr: SocketAddr = "10.0.0.1:5000".parse().unwrap();
+        let mut req = RequestContext::new(&header, PathParams::empty()).with_remote_addr(Some(remote_addr));
+        handler.invoke(&mut req, OptionReqBody::from(RequestBody::empty())).await;
+
+        let output = captured.0.lock().unwrap().clone();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_combined() {
+        let output = invoke_logged(LogFormat::Combined).await;
+        assert!(output.contains("micro_web::access_log"));
+        assert!(output.contains("10.0.0.1 - - ["));
+        assert!(output.contains("\"GET /index.html?a=1 HTTP/1.1\" 200 5 \"-\" \"curl/8.0\""));
+    }
+
+    #[tokio::test]
+    async fn test_json() {
+        let output = invoke_logged(LogFormat::Json).await;
+        let json = &output[output.find('{').unwrap()..output.rfind('}').unwrap() + 1];
+        let value: serde_json::Value = serde_json::from_str(json).unwrap();
+
+        assert_eq!(value["method"], "GET");
+        assert_eq!(value["path"], "/index.html?a=1");
+        assert_eq!(value["status"], 200);
+        assert_eq!(value["body_size"], 5);
+        assert_eq!(value["client_ip"], "10.0.0.1");
+        assert_eq!(value["user_agent"], "curl/8.0");
+        assert!(value["duration_ms"].is_number());
+    }
+
+    #[tokio::test]
+    async fn test_custom() {
+        let output = invoke_logged(LogFormat::Custom(Box::new(|record| format!("status={}", record.status)))).await;
+        assert!(output.contains("status=200 OK"));
+    }
+
+    #[test]
+    fn test_clf_time() {
+        let time = UNIX_EPOCH + Duration::from_secs(971_186_136);
+        assert_eq!(format_clf_time(time), "10/Oct/2000:13:55:36 +0000");
+        assert_eq!(format_clf_time(UNIX_EPOCH + Duration::from_secs(951_782_400)), "29/Feb/2000:00:00:00 +0000");
+    }
+}
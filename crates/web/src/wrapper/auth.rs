@@ -0,0 +1,325 @@
+//! Module for bearer token authentication.
+//!
+//! [`BearerAuthWrapper`] reads the token from the `Authorization: Bearer <token>` header as described in
+//! [RFC 6750 Section 2.1](https://tools.ietf.org/html/rfc6750#section-2.1) and checks it with a
+//! [`TokenValidator`]:
+//! - On success the validated claims are stored in the request extensions, handlers can read them
+//!   with `req.extensions().get::<Claims>()`
+//! - On failure the request is rejected with `401 Unauthorized` and a `WWW-Authenticate` challenge
+//!
+//! Paths under one of the configured public prefixes skip authentication, so the wrapper can be
+//! installed for the whole router. Prefixes match whole path segments: `/public` covers `/public` and
+//! `/public/index.html`, but not `/public-admin`.
+
+use crate::handler::RequestHandler;
+use crate::responder::Responder;
+use crate::wrapper::{priority, Wrapper};
+use crate::{OptionReqBody, RequestContext, ResponseBody};
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use http::{HeaderValue, Response, StatusCode};
+use sha2::Sha256;
+use std::sync::Arc;
+use thiserror::Error;
+use tracing::trace;
+
+/// Errors returned by a [`TokenValidator`].
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum AuthError {
+    /// The request doesn't carry a bearer token
+    #[error("missing bearer token")]
+    MissingToken,
+
+    /// The token is malformed, expired or its signature doesn't match
+    #[error("invalid token: {reason}")]
+    InvalidToken { reason: String },
+}
+
+impl AuthError {
+    /// Creates a new InvalidToken error
+    pub fn invalid_token<S: ToString>(str: S) -> Self {
+        Self::InvalidToken { reason: str.to_string() }
+    }
+}
+
+/// Validates bearer tokens, e.g. against a JWKS endpoint, a database or a shared secret.
+///
+/// The trait is object safe, so validators can be used as `Box<dyn TokenValidator<Claims = C>>`.
+#[async_trait]
+pub trait TokenValidator: Send + Sync {
+    /// The claims extracted from a valid token, stored in the request extensions
+    type Claims: Clone + Send + Sync + 'static;
+
+    /// Validates `token` and returns its claims
+    async fn validate(&self, token: &str) -> Result<Self::Claims, AuthError>;
+}
+
+#[async_trait]
+impl<V: TokenValidator + ?Sized> TokenValidator for Box<V> {
+    type Claims = V::Claims;
+
+    async fn validate(&self, token: &str) -> Result<Self::Claims, AuthError> {
+        (**self).validate(token).await
+    }
+}
+
+/// The claims of a token validated by [`HmacSha256Validator`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HmacClaims {
+    /// The subject the token was issued to
+    pub subject: String,
+}
+
+/// A validator for tokens of the form `<subject>.<signature>`, where the signature is the hex encoded
+/// HMAC-SHA256 of the subject.
+///
+/// It has no expiration or revocation, so it's mostly useful for tests and internal services.
+///
+/// # Example
+/// ```
+/// use micro_web::wrapper::{BearerAuthWrapper, HmacSha256Validator};
+///
+/// let validator = HmacSha256Validator::new(b"secret");
+/// let token = validator.sign("alice");
+/// let wrapper = BearerAuthWrapper::new(validator).public_prefix("/health");
+/// ```
+#[derive(Clone)]
+pub struct HmacSha256Validator {
+    mac: Hmac<Sha256>,
+}
+
+impl HmacSha256Validator {
+    /// Creates a validator using the given secret
+    pub fn new(secret: &[u8]) -> Self {
+        // hmac accepts keys of any length
+        Self { mac: Hmac::new_from_slice(secret).unwrap() }
+    }
+
+    /// Issues a token for `subject`
+    pub fn sign(&self, subject: &str) -> String {
+        let mut mac = self.mac.clone();
+        mac.update(subject.as_bytes());
+        let signature: String = mac.finalize().into_bytes().iter().map(|b| format!("{b:02x}")).collect();
+        format!("{subject}.{signature}")
+    }
+}
+
+#[async_trait]
+impl TokenValidator for HmacSha256Validator {
+    type Claims = HmacClaims;
+
+    async fn validate(&self, token: &str) -> Result<Self::Claims, AuthError> {
+        let (subject, signature) = token.rsplit_once('.').ok_or(AuthError::invalid_token("missing signature"))?;
+        let signature = decode_hex(signature).ok_or(AuthError::invalid_token("signature is not hex encoded"))?;
+
+        let mut mac = self.mac.clone();
+        mac.update(subject.as_bytes());
+        // compares in constant time
+        mac.verify_slice(&signature).map_err(|_| AuthError::invalid_token("signature mismatch"))?;
+
+        Ok(HmacClaims { subject: subject.to_string() })
+    }
+}
+
+/// Decodes a hex string, returns `None` if it has an odd length or a non hex digit.
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    // `get` returns None for the dangling digit of an odd length string
+    (0..s.len()).step_by(2).map(|i| s.get(i..i + 2).and_then(|b| u8::from_str_radix(b, 16).ok())).collect()
+}
+
+/// A wrapper that creates `BearerAuthRequestHandler`.
+pub struct BearerAuthWrapper<V> {
+    validator: Arc<V>,
+    challenge: Arc<HeaderValue>,
+    public_prefixes: Arc<Vec<String>>,
+}
+
+impl<V: TokenValidator> BearerAuthWrapper<V> {
+    /// Creates a `BearerAuthWrapper` using the given validator, with the realm `micro-web`.
+    pub fn new(validator: V) -> Self {
+        Self {
+            validator: Arc::new(validator),
+            challenge: Arc::new(HeaderValue::from_static("Bearer realm=\"micro-web\"")),
+            public_prefixes: Arc::new(vec![]),
+        }
+    }
+
+    /// Sets the realm sent in the `WWW-Authenticate` header.
+    ///
+    /// # Panics
+    /// Panics if the realm contains characters that are not allowed in a header value.
+    pub fn realm(mut self, realm: &str) -> Self {
+        let realm = realm.replace('\\', "\\\\").replace('"', "\\\"");
+        self.challenge = Arc::new(HeaderValue::from_str(&format!("Bearer realm=\"{realm}\"")).unwrap());
+        self
+    }
+
+    /// Skips authentication for the request paths under `prefix`, matching whole path segments.
+    pub fn public_prefix(mut self, prefix: impl Into<String>) -> Self {
+        let prefix = prefix.into().trim_end_matches('/').to_string();
+        Arc::make_mut(&mut self.public_prefixes).push(prefix);
+        self
+    }
+}
+
+/// A request handler that rejects requests without a valid bearer token.
+pub struct BearerAuthRequestHandler<H: RequestHandler, V> {
+    handler: H,
+    validator: Arc<V>,
+    challenge: Arc<HeaderValue>,
+    public_prefixes: Arc<Vec<String>>,
+}
+
+impl<H: RequestHandler, V: TokenValidator> Wrapper<H> for BearerAuthWrapper<V> {
+    type Out = BearerAuthRequestHandler<H, V>;
+
+    fn wrap(&self, handler: H) -> Self::Out {
+        BearerAuthRequestHandler {
+            handler,
+            validator: Arc::clone(&self.validator),
+            challenge: Arc::clone(&self.challenge),
+            public_prefixes: Arc::clone(&self.public_prefixes),
+        }
+    }
+
+    fn priority(&self) -> i32 {
+        priority::AUTH
+    }
+}
+
+#[async_trait]
+impl<H: RequestHandler, V: TokenValidator> RequestHandler for BearerAuthRequestHandler<H, V> {
+    async fn invoke<'server, 'req>(
+        &self,
+        req: &mut RequestContext<'server, 'req>,
+        req_body: OptionReqBody,
+    ) -> Response<ResponseBody> {
+        let path = req.uri().path();
+        if self.public_prefixes.iter().any(|prefix| is_under(path, prefix)) {
+            return self.handler.invoke(req, req_body).await;
+        }
+
+        let result = match bearer_token(req) {
+            Some(token) => self.validator.validate(token).await,
+            None => Err(AuthError::MissingToken),
+        };
+
+        match result {
+            Ok(claims) => {
+                req.extensions_mut().insert(claims);
+                self.handler.invoke(req, req_body).await
+            }
+            Err(e) => {
+                trace!("reject unauthorized request: {}", e);
+                let mut resp = (StatusCode::UNAUTHORIZED, "unauthorized").response_to(req);
+                resp.headers_mut().insert(http::header::WWW_AUTHENTICATE, self.challenge.as_ref().clone());
+                resp
+            }
+        }
+    }
+}
+
+/// Returns true if `path` is `prefix` or one of its sub-paths, `prefix` has no trailing `/`.
+fn is_under(path: &str, prefix: &str) -> bool {
+    path.strip_prefix(prefix).is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// Extracts the token from the `Authorization: Bearer <token>` header, the scheme is case insensitive.
+fn bearer_token<'a>(req: &'a RequestContext) -> Option<&'a str> {
+    let value = req.headers().get(http::header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.split_once(' ')?;
+    let token = token.trim();
+    (scheme.eq_ignore_ascii_case("bearer") && !token.is_empty()).then_some(token)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PathParams, RequestBody};
+    use micro_http::protocol::RequestHeader;
+
+    /// Responds with the subject of the validated claims
+    struct WhoAmI;
+
+    #[async_trait]
+    impl RequestHandler for WhoAmI {
+        async fn invoke<'server, 'req>(
+            &self,
+            req: &mut RequestContext<'server, 'req>,
+            _req_body: OptionReqBody,
+        ) -> Response<ResponseBody> {
+            let subject = req.extensions().get::<HmacClaims>().map(|claims| claims.subject.clone());
+            subject.unwrap_or_default().response_to(req)
+        }
+    }
+
+    async fn invoke<H: RequestHandler>(handler: &H, path: &str, authorization: Option<&str>) -> Response<ResponseBody> {
+        let mut builder = http::Request::builder().uri(path);
+        if let Some(authorization) = authorization {
+            builder = builder.header(http::header::AUTHORIZATION, authorization);
+        }
+        let header: RequestHeader = builder.body(()).unwrap().into();
+        let mut req = RequestContext::new(&header, PathParams::empty());
+        handler.invoke(&mut req, OptionReqBody::from(RequestBody::empty())).await
+    }
+
+    fn wrapper() -> BearerAuthWrapper<HmacSha256Validator> {
+        BearerAuthWrapper::new(HmacSha256Validator::new(b"secret")).realm("api").public_prefix("/public")
+    }
+
+    #[tokio::test]
+    async fn test_hmac_validator() {
+        let validator = HmacSha256Validator::new(b"secret");
+        let token = validator.sign("alice");
+
+        assert_eq!(validator.validate(&token).await, Ok(HmacClaims { subject: "alice".into() }));
+        assert!(validator.validate("alice.00").await.is_err());
+        assert!(validator.validate("alice").await.is_err());
+        assert!(HmacSha256Validator::new(b"other").validate(&token).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_valid_token() {
+        let token = HmacSha256Validator::new(b"secret").sign("alice");
+        let handler = wrapper().wrap(WhoAmI);
+
+        let resp = invoke(&handler, "/users", Some(&format!("Bearer {token}"))).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = http_body_util::BodyExt::collect(resp.into_body()).await.unwrap().to_bytes();
+        assert_eq!(&body[..], b"alice");
+    }
+
+    #[tokio::test]
+    async fn test_rejected() {
+        let handler = wrapper().wrap(WhoAmI);
+
+        for authorization in [None, Some("Bearer alice.00"), Some("Basic YWxpY2U6c2VjcmV0")] {
+            let resp = invoke(&handler, "/users", authorization).await;
+            assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+            assert_eq!(resp.headers().get(http::header::WWW_AUTHENTICATE).unwrap(), "Bearer realm=\"api\"");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_public_prefix() {
+        let handler = wrapper().wrap(WhoAmI);
+
+        let resp = invoke(&handler, "/public/index.html", None).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let resp = invoke(&handler, "/public", None).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        // only whole segments match
+        let resp = invoke(&handler, "/public-admin", None).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        let resp = invoke(&handler, "/publicity/index.html", None).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        // a trailing `/` is ignored, and `/` makes every path public
+        let handler = BearerAuthWrapper::new(HmacSha256Validator::new(b"secret")).public_prefix("/docs/").wrap(WhoAmI);
+        assert_eq!(invoke(&handler, "/docs", None).await.status(), StatusCode::OK);
+        assert_eq!(invoke(&handler, "/docs-private", None).await.status(), StatusCode::UNAUTHORIZED);
+        let handler = BearerAuthWrapper::new(HmacSha256Validator::new(b"secret")).public_prefix("/").wrap(WhoAmI);
+        assert_eq!(invoke(&handler, "/admin", None).await.status(), StatusCode::OK);
+    }
+}
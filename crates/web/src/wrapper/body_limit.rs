@@ -0,0 +1,215 @@
+//! Module for limiting the size of request bodies.
+//!
+//! [`BodyLimitWrapper`] protects handlers from request bodies that are too large to buffer:
+//! - Requests declaring a larger `Content-Length` are rejected with `413 Content Too Large`
+//!   before the handler runs
+//! - Other bodies, such as chunked ones, are counted while they are read, and reading fails with
+//!   [`ParseError::TooLargeBody`] once the limit is exceeded, which extractors turn into a `413` response
+//!
+//! The limit applies to each request separately. A wrapper running before this one can override it
+//! for a request by inserting a [`BodyLimit`] into the request extensions.
+
+use crate::handler::RequestHandler;
+use crate::responder::Responder;
+use crate::wrapper::Wrapper;
+use crate::{OptionReqBody, RequestBody, RequestContext, ResponseBody};
+use async_trait::async_trait;
+use bytes::Bytes;
+use http::{Response, StatusCode};
+use http_body::{Body, Frame, SizeHint};
+use micro_http::protocol::ParseError;
+use pin_project_lite::pin_project;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+/// Overrides the limit of [`BodyLimitWrapper`] for a single request, in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BodyLimit(pub u64);
+
+pin_project! {
+    /// A wrapper around a `Body` that fails once more than `max_bytes` have been read.
+    pub struct LimitedBody<B> {
+        #[pin]
+        inner: B,
+        read_bytes: u64,
+        max_bytes: u64,
+    }
+}
+
+impl<B> LimitedBody<B> {
+    /// Creates a new `LimitedBody`.
+    pub fn new(inner: B, max_bytes: u64) -> Self {
+        Self { inner, read_bytes: 0, max_bytes }
+    }
+}
+
+impl<B> Body for LimitedBody<B>
+where
+    B: Body<Data = Bytes, Error = ParseError>,
+{
+    type Data = Bytes;
+    type Error = ParseError;
+
+    fn poll_frame(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+
+        if *this.read_bytes > *this.max_bytes {
+            return Poll::Ready(Some(Err(ParseError::too_large_body(*this.read_bytes, *this.max_bytes))));
+        }
+
+        let frame = match ready!(this.inner.poll_frame(cx)) {
+            Some(Ok(frame)) => frame,
+            other => return Poll::Ready(other),
+        };
+
+        if let Some(data) = frame.data_ref() {
+            *this.read_bytes += data.len() as u64;
+            if *this.read_bytes > *this.max_bytes {
+                return Poll::Ready(Some(Err(ParseError::too_large_body(*this.read_bytes, *this.max_bytes))));
+            }
+        }
+
+        Poll::Ready(Some(Ok(frame)))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// A wrapper that creates `BodyLimitRequestHandler`.
+///
+/// # Example
+/// ```
+/// use micro_web::wrapper::BodyLimitWrapper;
+///
+/// // at most 1 MiB per request
+/// let wrapper = BodyLimitWrapper::new(1024 * 1024);
+/// ```
+pub struct BodyLimitWrapper {
+    max_bytes: u64,
+}
+
+impl BodyLimitWrapper {
+    /// Creates a `BodyLimitWrapper` allowing at most `max_bytes` per request body.
+    pub fn new(max_bytes: u64) -> Self {
+        Self { max_bytes }
+    }
+}
+
+/// A request handler that limits the size of the request body.
+pub struct BodyLimitRequestHandler<H: RequestHandler> {
+    handler: H,
+    max_bytes: u64,
+}
+
+impl<H: RequestHandler> Wrapper<H> for BodyLimitWrapper {
+    type Out = BodyLimitRequestHandler<H>;
+
+    fn wrap(&self, handler: H) -> Self::Out {
+        BodyLimitRequestHandler { handler, max_bytes: self.max_bytes }
+    }
+}
+
+#[async_trait]
+impl<H: RequestHandler> RequestHandler for BodyLimitRequestHandler<H> {
+    async fn invoke<'server, 'req>(
+        &self,
+        req: &mut RequestContext<'server, 'req>,
+        req_body: OptionReqBody,
+    ) -> Response<ResponseBody> {
+        let max_bytes = req.extensions().get::<BodyLimit>().map(|limit| limit.0).unwrap_or(self.max_bytes);
+
+        let content_length = req
+            .headers()
+            .get(http::header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok());
+        if content_length.is_some_and(|content_length| content_length > max_bytes) {
+            return (StatusCode::PAYLOAD_TOO_LARGE, "payload too large").response_to(req);
+        }
+
+        // an error means the body has been consumed, so there is nothing left to limit
+        let _ = req_body.map(|body| RequestBody::boxed(LimitedBody::new(body, max_bytes))).await;
+
+        self.handler.invoke(req, req_body).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{handler_fn, PathParams};
+    use futures::stream;
+    use http_body_util::{BodyExt, StreamBody};
+
+    fn chunked_body(chunks: &[&'static [u8]]) -> RequestBody {
+        let frames = chunks.iter().map(|chunk| Ok::<_, ParseError>(Frame::data(Bytes::from_static(chunk))));
+        RequestBody::boxed(StreamBody::new(stream::iter(frames.collect::<Vec<_>>())))
+    }
+
+    async fn collect(body: RequestBody, max_bytes: u64) -> Result<Bytes, ParseError> {
+        LimitedBody::new(body, max_bytes).collect().await.map(|collected| collected.to_bytes())
+    }
+
+    #[tokio::test]
+    async fn test_exact_boundary() {
+        let bytes = collect(chunked_body(&[b"hello", b"world"]), 10).await.unwrap();
+        assert_eq!(&bytes[..], b"helloworld");
+    }
+
+    #[tokio::test]
+    async fn test_one_over() {
+        let result = collect(chunked_body(&[b"hello", b"world!"]), 10).await;
+        assert!(matches!(result, Err(ParseError::TooLargeBody { current_size: 11, max_size: 10 })));
+    }
+
+    #[tokio::test]
+    async fn test_chunk_crosses_boundary() {
+        let mut body = LimitedBody::new(chunked_body(&[b"hel", b"lowor", b"ld", b"more"]), 9);
+
+        assert_eq!(body.frame().await.unwrap().unwrap().into_data().unwrap(), "hel");
+        assert_eq!(body.frame().await.unwrap().unwrap().into_data().unwrap(), "lowor");
+        assert!(body.frame().await.unwrap().is_err());
+        // keeps failing, the rest of the body is never handed out
+        assert!(body.frame().await.unwrap().is_err());
+    }
+
+    async fn echo(body: String) -> String {
+        body
+    }
+
+    async fn invoke(headers: &[(&str, &str)], body: RequestBody, limit: Option<u64>) -> Response<ResponseBody> {
+        let handler = BodyLimitWrapper::new(10).wrap(handler_fn(echo));
+        let mut builder = http::Request::builder().method("POST");
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        let header = builder.body(()).unwrap().into();
+        let mut req = RequestContext::new(&header, PathParams::empty());
+        if let Some(limit) = limit {
+            req.extensions_mut().insert(BodyLimit(limit));
+        }
+        handler.invoke(&mut req, OptionReqBody::from(body)).await
+    }
+
+    #[tokio::test]
+    async fn test_wrapper() {
+        let resp = invoke(&[("content-length", "11")], chunked_body(&[b"hello world"]), None).await;
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let resp = invoke(&[], chunked_body(&[b"hello", b" world"]), None).await;
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let resp = invoke(&[], chunked_body(&[b"hello"]), None).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        // overridden per request
+        let resp = invoke(&[("content-length", "11")], chunked_body(&[b"hello world"]), Some(20)).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+}
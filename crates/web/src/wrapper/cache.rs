@@ -0,0 +1,402 @@
+//! Module for caching responses in memory.
+//!
+//! [`CacheWrapper`] keeps the `200 OK` responses to `GET` and `HEAD` requests that are marked
+//! `Cache-Control: public`, and answers the same requests with them without invoking the handler,
+//! until they expire after the configured time to live.
+//!
+//! The responses are stored by method, `Host` and URI. The request headers named by the `Vary` header
+//! of a response must be the same for it to be reused, as required by
+//! [RFC 9111 Section 4.1](https://www.rfc-editor.org/rfc/rfc9111#section-4.1): a response compressed
+//! for `Accept-Encoding: gzip` isn't sent to a client asking for `br`.
+//!
+//! These responses are never stored:
+//! - the ones with `Vary: *`, they can't be matched with another request
+//! - the ones with `Cache-Control: private`, `no-store` or `no-cache`
+//! - the ones setting a cookie, it belongs to one client
+//! - the ones whose body is larger than [`CacheWrapper::max_body`], or has trailers
+//!
+//! When the cache is full, the least recently used response is evicted.
+
+use crate::handler::RequestHandler;
+use crate::wrapper::etag::buffer;
+use crate::wrapper::{priority, Wrapper};
+use crate::{OptionReqBody, RequestContext, ResponseBody};
+use async_trait::async_trait;
+use bytes::Bytes;
+use http::header::{AGE, CACHE_CONTROL, HOST, SET_COOKIE, VARY};
+use http::{HeaderMap, HeaderName, HeaderValue, Method, Response, StatusCode};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::trace;
+
+/// The default of [`CacheWrapper::max_body`], 1 MiB.
+const DEFAULT_MAX_BODY: usize = 1024 * 1024;
+
+/// A wrapper that creates `CacheRequestHandler`.
+///
+/// It runs after the authentication and rate limiting wrappers of a route group, and before the
+/// encoding, so the encoded responses are cached.
+///
+/// # Example
+/// ```
+/// use micro_web::wrapper::CacheWrapper;
+/// use std::time::Duration;
+///
+/// let wrapper = CacheWrapper::new(1000, Duration::from_secs(60)).max_body(64 * 1024);
+/// ```
+pub struct CacheWrapper {
+    cache: Arc<Mutex<Cache>>,
+    ttl: Duration,
+    max_body: usize,
+}
+
+impl CacheWrapper {
+    /// Creates a `CacheWrapper` keeping at most `max_entries` responses, each for `ttl`.
+    pub fn new(max_entries: usize, ttl: Duration) -> Self {
+        Self { cache: Arc::new(Mutex::new(Cache::new(max_entries))), ttl, max_body: DEFAULT_MAX_BODY }
+    }
+
+    /// Sets the maximum body size, in bytes, of the cached responses.
+    pub fn max_body(mut self, max_body: usize) -> Self {
+        self.max_body = max_body;
+        self
+    }
+}
+
+/// A request handler that answers requests with the cached responses.
+pub struct CacheRequestHandler<H: RequestHandler> {
+    handler: H,
+    cache: Arc<Mutex<Cache>>,
+    ttl: Duration,
+    max_body: usize,
+}
+
+impl<H: RequestHandler> Wrapper<H> for CacheWrapper {
+    type Out = CacheRequestHandler<H>;
+
+    fn wrap(&self, handler: H) -> Self::Out {
+        CacheRequestHandler { handler, cache: Arc::clone(&self.cache), ttl: self.ttl, max_body: self.max_body }
+    }
+
+    fn priority(&self) -> i32 {
+        priority::CACHE
+    }
+}
+
+#[async_trait]
+impl<H: RequestHandler> RequestHandler for CacheRequestHandler<H> {
+    async fn invoke<'server, 'req>(
+        &self,
+        req: &mut RequestContext<'server, 'req>,
+        req_body: OptionReqBody,
+    ) -> Response<ResponseBody> {
+        if !matches!(*req.method(), Method::GET | Method::HEAD) {
+            return self.handler.invoke(req, req_body).await;
+        }
+
+        let key = CacheKey::of(req);
+        if let Some(resp) = self.cache.lock().unwrap().get(&key, req.headers(), Instant::now()) {
+            trace!(uri = %req.uri(), "serve cached response");
+            return resp;
+        }
+
+        let mut resp = self.handler.invoke(req, req_body).await;
+        let Some(vary) = cacheable(&resp).then(|| vary(req.headers(), resp.headers())).flatten() else {
+            return resp;
+        };
+
+        let body = resp.body_mut().take();
+        match buffer(body, self.max_body).await {
+            Ok(bytes) => {
+                *resp.body_mut() = ResponseBody::once(bytes.clone());
+                let cached = CachedResponse {
+                    status: resp.status(),
+                    headers: resp.headers().clone(),
+                    body: bytes,
+                    vary,
+                    stored: Instant::now(),
+                    expires: Instant::now() + self.ttl,
+                    last_used: 0,
+                };
+                self.cache.lock().unwrap().insert(key, cached);
+            }
+            Err(body) => *resp.body_mut() = body,
+        }
+        resp
+    }
+}
+
+/// Returns true if `resp` may be stored, its `Vary` header is checked by [`vary`].
+fn cacheable(resp: &Response<ResponseBody>) -> bool {
+    if resp.status() != StatusCode::OK || resp.headers().contains_key(SET_COOKIE) {
+        return false;
+    }
+
+    let mut public = false;
+    for directive in resp.headers().get_all(CACHE_CONTROL).iter().filter_map(|value| value.to_str().ok()) {
+        for directive in directive.split(',').map(|directive| directive.trim()) {
+            // the directives with arguments, such as `private="set-cookie"`, are restrictive too
+            let name = directive.split('=').next().unwrap_or_default();
+            if ["private", "no-store", "no-cache"].iter().any(|no| name.eq_ignore_ascii_case(no)) {
+                return false;
+            }
+            public |= name.eq_ignore_ascii_case("public");
+        }
+    }
+    public
+}
+
+/// Returns the request headers named by the `Vary` header of the response, with their values.
+///
+/// Returns `None` for `Vary: *`, such a response matches no other request.
+fn vary(req_headers: &HeaderMap, resp_headers: &HeaderMap) -> Option<Vec<(HeaderName, Option<HeaderValue>)>> {
+    let mut vary = vec![];
+    for value in resp_headers.get_all(VARY).iter().filter_map(|value| value.to_str().ok()) {
+        for name in value.split(',').map(|name| name.trim()).filter(|name| !name.is_empty()) {
+            if name == "*" {
+                return None;
+            }
+            let Ok(name) = HeaderName::try_from(name) else {
+                continue;
+            };
+            let value = req_headers.get(&name).cloned();
+            vary.push((name, value));
+        }
+    }
+    Some(vary)
+}
+
+/// Identifies the resource a response was sent for, its variants are told apart by their `Vary` headers.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct CacheKey {
+    method: Method,
+    host: Option<HeaderValue>,
+    uri: String,
+}
+
+impl CacheKey {
+    fn of(req: &RequestContext) -> Self {
+        Self { method: req.method().clone(), host: req.headers().get(HOST).cloned(), uri: req.uri().to_string() }
+    }
+}
+
+/// A stored response.
+struct CachedResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+    /// The request headers named by the `Vary` header, with the values of the request it answered
+    vary: Vec<(HeaderName, Option<HeaderValue>)>,
+    stored: Instant,
+    expires: Instant,
+    /// The tick of the cache it was last used at, the smallest one is evicted first
+    last_used: u64,
+}
+
+impl CachedResponse {
+    /// Returns true if this response can answer a request with `req_headers`.
+    fn matches(&self, req_headers: &HeaderMap) -> bool {
+        self.vary.iter().all(|(name, value)| req_headers.get(name) == value.as_ref())
+    }
+
+    fn to_response(&self, now: Instant) -> Response<ResponseBody> {
+        let mut resp = Response::new(ResponseBody::once(self.body.clone()));
+        *resp.status_mut() = self.status;
+        *resp.headers_mut() = self.headers.clone();
+        // how long it has been stored, RFC 9111 Section 5.1
+        resp.headers_mut().insert(AGE, HeaderValue::from(now.duration_since(self.stored).as_secs()));
+        resp
+    }
+}
+
+/// The stored responses, the variants of a resource are kept together under its key.
+///
+/// Finding the least recently used response scans all of them, which is only done when the cache is
+/// full and a response is stored.
+struct Cache {
+    entries: HashMap<CacheKey, Vec<CachedResponse>>,
+    len: usize,
+    max_entries: usize,
+    /// Incremented on each use of a response, to order them
+    tick: u64,
+}
+
+impl Cache {
+    fn new(max_entries: usize) -> Self {
+        Self { entries: HashMap::new(), len: 0, max_entries, tick: 0 }
+    }
+
+    /// Returns the response stored for `key` that matches `req_headers`, the expired ones are removed.
+    fn get(&mut self, key: &CacheKey, req_headers: &HeaderMap, now: Instant) -> Option<Response<ResponseBody>> {
+        let variants = self.entries.get_mut(key)?;
+
+        let before = variants.len();
+        variants.retain(|cached| cached.expires > now);
+        self.len -= before - variants.len();
+        if variants.is_empty() {
+            self.entries.remove(key);
+            return None;
+        }
+
+        let cached = variants.iter_mut().find(|cached| cached.matches(req_headers))?;
+        self.tick += 1;
+        cached.last_used = self.tick;
+        Some(cached.to_response(now))
+    }
+
+    /// Stores `cached`, replacing the variant matching the same request headers.
+    fn insert(&mut self, key: CacheKey, mut cached: CachedResponse) {
+        if self.max_entries == 0 {
+            return;
+        }
+
+        self.tick += 1;
+        cached.last_used = self.tick;
+
+        let variants = self.entries.entry(key.clone()).or_default();
+        if let Some(existing) = variants.iter_mut().find(|existing| existing.vary == cached.vary) {
+            *existing = cached;
+            return;
+        }
+
+        if self.len == self.max_entries {
+            self.evict();
+        }
+        self.entries.entry(key).or_default().push(cached);
+        self.len += 1;
+    }
+
+    /// Removes the least recently used response.
+    fn evict(&mut self) {
+        let oldest = self
+            .entries
+            .iter()
+            .flat_map(|(key, variants)| variants.iter().enumerate().map(move |(i, cached)| (cached.last_used, key, i)))
+            .min_by_key(|(last_used, _, _)| *last_used)
+            .map(|(_, key, i)| (key.clone(), i));
+
+        if let Some((key, i)) = oldest {
+            let variants = self.entries.get_mut(&key).unwrap();
+            variants.remove(i);
+            if variants.is_empty() {
+                self.entries.remove(&key);
+            }
+            self.len -= 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PathParams, RequestBody};
+    use http::header::ACCEPT_ENCODING;
+    use http_body_util::BodyExt;
+    use micro_http::protocol::RequestHeader;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Answers with the request URI, and the `Cache-Control` and `Vary` headers it's created with.
+    struct Counting {
+        calls: AtomicUsize,
+        cache_control: &'static str,
+        vary: Option<&'static str>,
+    }
+
+    #[async_trait]
+    impl RequestHandler for Counting {
+        async fn invoke<'server, 'req>(
+            &self,
+            req: &mut RequestContext<'server, 'req>,
+            _req_body: OptionReqBody,
+        ) -> Response<ResponseBody> {
+            let calls = self.calls.fetch_add(1, Ordering::SeqCst) + 1;
+            let mut resp = Response::new(ResponseBody::from(format!("{} #{calls}", req.uri())));
+            resp.headers_mut().insert(CACHE_CONTROL, HeaderValue::from_static(self.cache_control));
+            if let Some(vary) = self.vary {
+                resp.headers_mut().insert(VARY, HeaderValue::from_static(vary));
+            }
+            resp
+        }
+    }
+
+    fn counting(cache_control: &'static str, vary: Option<&'static str>) -> Counting {
+        Counting { calls: AtomicUsize::new(0), cache_control, vary }
+    }
+
+    async fn get<H: RequestHandler>(handler: &H, uri: &str, accept_encoding: &str) -> (Response<()>, Bytes) {
+        let header: RequestHeader =
+            http::Request::get(uri).header(ACCEPT_ENCODING, accept_encoding).body(()).unwrap().into();
+        let mut req = RequestContext::new(&header, PathParams::empty());
+        let resp = handler.invoke(&mut req, OptionReqBody::from(RequestBody::empty())).await;
+        let (parts, body) = resp.into_parts();
+        (Response::from_parts(parts, ()), body.collect().await.unwrap().to_bytes())
+    }
+
+    #[tokio::test]
+    async fn test_cache_hit() {
+        let handler = CacheWrapper::new(10, Duration::from_secs(60)).wrap(counting("public, max-age=60", None));
+
+        let (resp, body) = get(&handler, "/a", "gzip").await;
+        assert_eq!(body, "/a #1");
+        assert!(resp.headers().get(AGE).is_none());
+
+        let (resp, body) = get(&handler, "/a", "gzip").await;
+        assert_eq!(body, "/a #1");
+        assert_eq!(resp.headers().get(AGE).unwrap(), "0");
+        assert_eq!(resp.headers().get(CACHE_CONTROL).unwrap(), "public, max-age=60");
+
+        assert_eq!(get(&handler, "/b", "gzip").await.1, "/b #2");
+        assert_eq!(handler.handler.calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_vary() {
+        let handler = CacheWrapper::new(10, Duration::from_secs(60)).wrap(counting("public", Some("Accept-Encoding")));
+
+        assert_eq!(get(&handler, "/a", "gzip").await.1, "/a #1");
+        assert_eq!(get(&handler, "/a", "br").await.1, "/a #2");
+        assert_eq!(get(&handler, "/a", "gzip").await.1, "/a #1");
+        assert_eq!(get(&handler, "/a", "br").await.1, "/a #2");
+
+        let handler = CacheWrapper::new(10, Duration::from_secs(60)).wrap(counting("public", Some("*")));
+        assert_eq!(get(&handler, "/a", "gzip").await.1, "/a #1");
+        assert_eq!(get(&handler, "/a", "gzip").await.1, "/a #2");
+    }
+
+    #[tokio::test]
+    async fn test_not_cacheable() {
+        for cache_control in ["max-age=60", "public, no-store", "public, private=\"x-user\"", "no-cache"] {
+            let handler = CacheWrapper::new(10, Duration::from_secs(60)).wrap(counting(cache_control, None));
+            assert_eq!(get(&handler, "/a", "gzip").await.1, "/a #1");
+            assert_eq!(get(&handler, "/a", "gzip").await.1, "/a #2", "{cache_control}");
+        }
+
+        let handler = CacheWrapper::new(10, Duration::from_secs(60)).max_body(3).wrap(counting("public", None));
+        assert_eq!(get(&handler, "/a", "gzip").await.1, "/a #1");
+        assert_eq!(get(&handler, "/a", "gzip").await.1, "/a #2");
+    }
+
+    #[tokio::test]
+    async fn test_expiration() {
+        let handler = CacheWrapper::new(10, Duration::ZERO).wrap(counting("public", None));
+        assert_eq!(get(&handler, "/a", "gzip").await.1, "/a #1");
+        assert_eq!(get(&handler, "/a", "gzip").await.1, "/a #2");
+        assert_eq!(handler.cache.lock().unwrap().len, 1);
+    }
+
+    #[tokio::test]
+    async fn test_evicts_least_recently_used() {
+        let handler = CacheWrapper::new(2, Duration::from_secs(60)).wrap(counting("public", None));
+        assert_eq!(get(&handler, "/a", "gzip").await.1, "/a #1");
+        assert_eq!(get(&handler, "/b", "gzip").await.1, "/b #2");
+        // `/a` is used again, `/b` becomes the least recently used one
+        assert_eq!(get(&handler, "/a", "gzip").await.1, "/a #1");
+        assert_eq!(get(&handler, "/c", "gzip").await.1, "/c #3");
+
+        assert_eq!(get(&handler, "/a", "gzip").await.1, "/a #1");
+        assert_eq!(get(&handler, "/c", "gzip").await.1, "/c #3");
+        assert_eq!(get(&handler, "/b", "gzip").await.1, "/b #4");
+        assert_eq!(handler.cache.lock().unwrap().len, 2);
+    }
+}
@@ -0,0 +1,312 @@
+//! Module for handling Cross-Origin Resource Sharing (CORS).
+//!
+//! This module implements the server side of the CORS protocol described in the
+//! [Fetch Standard](https://fetch.spec.whatwg.org/#http-cors-protocol):
+//! - Preflight requests (`OPTIONS` with `Access-Control-Request-Method`) are answered directly
+//!   with `204 No Content`, the wrapped handler is not invoked
+//! - Other requests carrying an `Origin` header are passed to the wrapped handler, and the CORS
+//!   headers are added to its response
+//!
+//! Requests from origins that are not allowed get no CORS headers at all, so the browser blocks them.
+//!
+//! Note that wrappers only run for matched routes, so a path must have an `OPTIONS` route (the handler
+//! can be anything, it is never called for preflight requests) to answer preflight requests.
+
+use crate::handler::RequestHandler;
+use crate::wrapper::Wrapper;
+use crate::{OptionReqBody, RequestContext, ResponseBody};
+use async_trait::async_trait;
+use http::header::{
+    ACCESS_CONTROL_ALLOW_CREDENTIALS, ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS,
+    ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_EXPOSE_HEADERS, ACCESS_CONTROL_MAX_AGE, ACCESS_CONTROL_REQUEST_METHOD,
+    ORIGIN, VARY,
+};
+use http::{HeaderMap, HeaderName, HeaderValue, Method, Response, StatusCode};
+use std::sync::Arc;
+
+/// The origins allowed to access the resources.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AllowedOrigins {
+    /// Any origin is allowed, answered with `*`
+    ///
+    /// `*` is not allowed together with credentials, in that case the request's origin is mirrored.
+    Any,
+    /// Only the listed origins, such as `https://example.com`, are allowed
+    List(Vec<String>),
+    /// Any origin is allowed, answered with the request's own origin
+    Mirror,
+}
+
+/// Configuration of the [`CorsWrapper`].
+///
+/// # Example
+/// ```
+/// use micro_web::wrapper::{AllowedOrigins, CorsConfig, CorsWrapper};
+///
+/// let config = CorsConfig {
+///     allowed_origins: AllowedOrigins::List(vec!["https://example.com".into()]),
+///     allow_credentials: true,
+///     max_age: Some(3600),
+///     ..CorsConfig::default()
+/// };
+/// let wrapper = CorsWrapper::new(config);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorsConfig {
+    /// The origins allowed to access the resources
+    pub allowed_origins: AllowedOrigins,
+    /// The methods allowed in preflight requests
+    pub allowed_methods: Vec<Method>,
+    /// The request headers allowed in preflight requests
+    pub allowed_headers: Vec<HeaderName>,
+    /// The response headers the browser exposes to scripts
+    pub expose_headers: Vec<HeaderName>,
+    /// Whether the browser may send credentials such as cookies
+    pub allow_credentials: bool,
+    /// How long, in seconds, the browser may cache the preflight response
+    pub max_age: Option<u32>,
+}
+
+impl Default for CorsConfig {
+    /// Allows any origin to use the CORS-safelisted methods, without credentials.
+    fn default() -> Self {
+        Self {
+            allowed_origins: AllowedOrigins::Any,
+            allowed_methods: vec![Method::GET, Method::HEAD, Method::POST],
+            allowed_headers: vec![],
+            expose_headers: vec![],
+            allow_credentials: false,
+            max_age: None,
+        }
+    }
+}
+
+impl CorsConfig {
+    /// Returns the `Access-Control-Allow-Origin` value for `origin`, or `None` if it's not allowed.
+    fn allow_origin(&self, origin: &HeaderValue) -> Option<HeaderValue> {
+        match &self.allowed_origins {
+            AllowedOrigins::Any if !self.allow_credentials => Some(HeaderValue::from_static("*")),
+            AllowedOrigins::Any | AllowedOrigins::Mirror => Some(origin.clone()),
+            AllowedOrigins::List(origins) => {
+                let origin_str = origin.to_str().ok()?;
+                origins.iter().any(|allowed| allowed.eq_ignore_ascii_case(origin_str)).then(|| origin.clone())
+            }
+        }
+    }
+
+    /// Adds the headers shared by preflight and actual responses.
+    fn add_origin_headers(&self, headers: &mut HeaderMap, allow_origin: HeaderValue) {
+        // the response depends on the origin unless all origins get the same `*`
+        if allow_origin != "*" {
+            headers.append(VARY, HeaderValue::from_static("Origin"));
+        }
+        headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, allow_origin);
+        if self.allow_credentials {
+            headers.insert(ACCESS_CONTROL_ALLOW_CREDENTIALS, HeaderValue::from_static("true"));
+        }
+    }
+
+    /// Builds the response for a preflight request.
+    fn preflight(&self, headers: &HeaderMap, allow_origin: Option<HeaderValue>) -> Response<ResponseBody> {
+        let mut resp = Response::builder().status(StatusCode::NO_CONTENT).body(ResponseBody::empty()).unwrap();
+
+        let allow_origin = match allow_origin {
+            Some(allow_origin) => allow_origin,
+            None => return resp,
+        };
+
+        let method_allowed = headers
+            .get(ACCESS_CONTROL_REQUEST_METHOD)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<Method>().ok())
+            .is_some_and(|method| self.allowed_methods.contains(&method));
+        if !method_allowed {
+            return resp;
+        }
+
+        let resp_headers = resp.headers_mut();
+        self.add_origin_headers(resp_headers, allow_origin);
+        resp_headers.insert(ACCESS_CONTROL_ALLOW_METHODS, join(self.allowed_methods.iter().map(Method::as_str)));
+        if !self.allowed_headers.is_empty() {
+            resp_headers
+                .insert(ACCESS_CONTROL_ALLOW_HEADERS, join(self.allowed_headers.iter().map(HeaderName::as_str)));
+        }
+        if let Some(max_age) = self.max_age {
+            resp_headers.insert(ACCESS_CONTROL_MAX_AGE, max_age.into());
+        }
+        resp
+    }
+}
+
+/// Joins header tokens into a comma separated header value.
+fn join<'a>(values: impl Iterator<Item = &'a str>) -> HeaderValue {
+    // methods and header names are valid tokens, so the joined value is always valid
+    HeaderValue::from_str(&values.collect::<Vec<_>>().join(", ")).unwrap()
+}
+
+/// A wrapper that creates `CorsRequestHandler`.
+///
+/// Use [`CorsWrapper::default`] to allow any origin with the default [`CorsConfig`], or
+/// [`CorsWrapper::new`] to restrict it.
+#[derive(Default)]
+pub struct CorsWrapper {
+    config: Arc<CorsConfig>,
+}
+
+impl CorsWrapper {
+    /// Creates a `CorsWrapper` using the given config.
+    pub fn new(config: CorsConfig) -> Self {
+        Self { config: Arc::new(config) }
+    }
+
+    /// Returns the config used by this wrapper.
+    pub fn config(&self) -> &CorsConfig {
+        &self.config
+    }
+}
+
+/// A request handler that answers preflight requests and adds CORS headers to responses.
+pub struct CorsRequestHandler<H: RequestHandler> {
+    handler: H,
+    config: Arc<CorsConfig>,
+}
+
+impl<H: RequestHandler> Wrapper<H> for CorsWrapper {
+    type Out = CorsRequestHandler<H>;
+
+    fn wrap(&self, handler: H) -> Self::Out {
+        CorsRequestHandler { handler, config: Arc::clone(&self.config) }
+    }
+}
+
+#[async_trait]
+impl<H: RequestHandler> RequestHandler for CorsRequestHandler<H> {
+    async fn invoke<'server, 'req>(
+        &self,
+        req: &mut RequestContext<'server, 'req>,
+        req_body: OptionReqBody,
+    ) -> Response<ResponseBody> {
+        // not a cross-origin request
+        let allow_origin = match req.headers().get(ORIGIN) {
+            Some(origin) => self.config.allow_origin(origin),
+            None => return self.handler.invoke(req, req_body).await,
+        };
+
+        if req.method() == Method::OPTIONS && req.headers().contains_key(ACCESS_CONTROL_REQUEST_METHOD) {
+            return self.config.preflight(req.headers(), allow_origin);
+        }
+
+        let mut resp = self.handler.invoke(req, req_body).await;
+        if let Some(allow_origin) = allow_origin {
+            let headers = resp.headers_mut();
+            self.config.add_origin_headers(headers, allow_origin);
+            if !self.config.expose_headers.is_empty() {
+                headers.insert(
+                    ACCESS_CONTROL_EXPOSE_HEADERS,
+                    join(self.config.expose_headers.iter().map(HeaderName::as_str)),
+                );
+            }
+        }
+        resp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{handler_fn, PathParams};
+    use http::Request;
+    use micro_http::protocol::RequestHeader;
+
+    async fn hello() -> &'static str {
+        "hello"
+    }
+
+    async fn invoke(config: CorsConfig, request: Request<()>) -> Response<ResponseBody> {
+        let handler = CorsWrapper::new(config).wrap(handler_fn(hello));
+        let header: RequestHeader = request.into();
+        let mut req = RequestContext::new(&header, PathParams::empty());
+        handler.invoke(&mut req, OptionReqBody::from(crate::RequestBody::empty())).await
+    }
+
+    fn config() -> CorsConfig {
+        CorsConfig {
+            allowed_origins: AllowedOrigins::List(vec!["https://example.com".into()]),
+            allowed_methods: vec![Method::GET, Method::PUT],
+            allowed_headers: vec![http::header::CONTENT_TYPE],
+            expose_headers: vec![HeaderName::from_static("x-request-id")],
+            allow_credentials: true,
+            max_age: Some(600),
+        }
+    }
+
+    fn preflight_request(origin: &str, method: &str) -> Request<()> {
+        Request::builder()
+            .method(Method::OPTIONS)
+            .header(ORIGIN, origin)
+            .header(ACCESS_CONTROL_REQUEST_METHOD, method)
+            .body(())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_preflight() {
+        let resp = invoke(config(), preflight_request("https://example.com", "PUT")).await;
+
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        let headers = resp.headers();
+        assert_eq!(headers.get(ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(), "https://example.com");
+        assert_eq!(headers.get(ACCESS_CONTROL_ALLOW_METHODS).unwrap(), "GET, PUT");
+        assert_eq!(headers.get(ACCESS_CONTROL_ALLOW_HEADERS).unwrap(), "content-type");
+        assert_eq!(headers.get(ACCESS_CONTROL_ALLOW_CREDENTIALS).unwrap(), "true");
+        assert_eq!(headers.get(ACCESS_CONTROL_MAX_AGE).unwrap(), "600");
+        assert_eq!(headers.get(VARY).unwrap(), "Origin");
+    }
+
+    #[tokio::test]
+    async fn test_preflight_disallowed() {
+        let resp = invoke(config(), preflight_request("https://evil.com", "PUT")).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        assert!(resp.headers().get(ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+
+        let resp = invoke(config(), preflight_request("https://example.com", "DELETE")).await;
+        assert!(resp.headers().get(ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_simple_request() {
+        let request = Request::builder().header(ORIGIN, "https://example.com").body(()).unwrap();
+        let resp = invoke(config(), request).await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        let headers = resp.headers();
+        assert_eq!(headers.get(ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(), "https://example.com");
+        assert_eq!(headers.get(ACCESS_CONTROL_EXPOSE_HEADERS).unwrap(), "x-request-id");
+        assert!(headers.get(ACCESS_CONTROL_ALLOW_METHODS).is_none());
+
+        let request = Request::builder().header(ORIGIN, "https://evil.com").body(()).unwrap();
+        let resp = invoke(config(), request).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(resp.headers().get(ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_any_origin() {
+        let request = Request::builder().header(ORIGIN, "https://example.com").body(()).unwrap();
+        let resp = invoke(CorsConfig::default(), request).await;
+        assert_eq!(resp.headers().get(ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(), "*");
+        assert!(resp.headers().get(VARY).is_none());
+
+        // `*` can't be used with credentials
+        let config = CorsConfig { allow_credentials: true, ..CorsConfig::default() };
+        let request = Request::builder().header(ORIGIN, "https://example.com").body(()).unwrap();
+        let resp = invoke(config, request).await;
+        assert_eq!(resp.headers().get(ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(), "https://example.com");
+    }
+
+    #[tokio::test]
+    async fn test_same_origin_request() {
+        let resp = invoke(config(), Request::builder().body(()).unwrap()).await;
+        assert!(resp.headers().get(ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+    }
+}
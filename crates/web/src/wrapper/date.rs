@@ -1,44 +1,76 @@
 //! Module for handling HTTP response date headers.
 //!
-//! This module provides functionality for automatically adding RFC 7231 compliant 
-//! date headers to HTTP responses. It implements a wrapper pattern that can be 
+//! This module provides functionality for automatically adding RFC 7231 compliant
+//! date headers to HTTP responses. It implements a wrapper pattern that can be
 //! composed with other wrappers in the request handling pipeline.
 //!
 //! The main components are:
 //! - `DateWrapper`: A wrapper that adds date handling capability
 //! - `DateResponseHandler`: The actual handler that adds the Date header to responses
 //!
-//! The Date header is added according to RFC 7231 Section 7.1.1.2
+//! The Date header is added according to RFC 7231 Section 7.1.1.2, unless the handler already set one.
 
 use crate::date::DateService;
 use crate::handler::RequestHandler;
 use crate::wrapper::Wrapper;
 use crate::{OptionReqBody, RequestContext, ResponseBody};
 use async_trait::async_trait;
-use http::{HeaderValue, Response};
+use http::header::DATE;
+use http::Response;
+use std::sync::Arc;
+use std::time::SystemTime;
 
 /// A wrapper that adds automatic date header handling to responses.
 ///
 /// This wrapper creates a `DateResponseHandler` that will add an RFC 7231 compliant
-/// Date header to all HTTP responses.
-pub struct DateWrapper;
+/// Date header to all HTTP responses. All the handlers it wraps share the same cached date.
+///
+/// # Example
+/// ```
+/// use micro_web::wrapper::DateWrapper;
+/// use std::time::{Duration, SystemTime, UNIX_EPOCH};
+///
+/// // a fixed clock, to test the responses
+/// let wrapper = DateWrapper::with_clock(|| UNIX_EPOCH + Duration::from_secs(784_111_777));
+/// ```
+#[derive(Clone)]
+pub struct DateWrapper {
+    date_service: Arc<DateService>,
+}
+
+impl DateWrapper {
+    /// Creates a wrapper reading the system clock.
+    pub fn new() -> Self {
+        Self { date_service: Arc::new(DateService::new()) }
+    }
+
+    /// Creates a wrapper reading the time from `clock`.
+    pub fn with_clock(clock: fn() -> SystemTime) -> Self {
+        Self { date_service: Arc::new(DateService::with_clock(clock)) }
+    }
+}
+
+impl Default for DateWrapper {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 /// A request handler that adds the Date header to responses.
 ///
 /// This handler wraps another handler and adds the Date header to its responses.
 /// The Date header is generated using a shared `DateService` instance to avoid
-/// unnecessary system calls.
+/// formatting the date on every response.
 pub struct DateResponseHandler<H: RequestHandler> {
     handler: H,
-    // todo: we need to ensure data_service is singleton
-    date_service: DateService,
+    date_service: Arc<DateService>,
 }
 
 impl<H: RequestHandler> Wrapper<H> for DateWrapper {
     type Out = DateResponseHandler<H>;
 
     fn wrap(&self, handler: H) -> Self::Out {
-        DateResponseHandler { handler, date_service: DateService::new() }
+        DateResponseHandler { handler, date_service: Arc::clone(&self.date_service) }
     }
 }
 
@@ -51,13 +83,60 @@ impl<H: RequestHandler> RequestHandler for DateResponseHandler<H> {
     ) -> Response<ResponseBody> {
         let mut resp = self.handler.invoke(req, req_body).await;
 
-        self.date_service.with_http_date(|date_str| {
-            resp.headers_mut().insert(
-                http::header::DATE,
-                HeaderValue::try_from(date_str).unwrap(),
-            );
-        });
+        if !resp.headers().contains_key(DATE) {
+            resp.headers_mut().insert(DATE, self.date_service.http_date());
+        }
 
         resp
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{handler_fn, PathParams, RequestBody};
+    use http::HeaderValue;
+    use micro_http::protocol::RequestHeader;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    static NOW_MILLIS: AtomicU64 = AtomicU64::new(784_111_777_000);
+
+    fn clock() -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(NOW_MILLIS.load(Ordering::Relaxed))
+    }
+
+    async fn invoke<H: RequestHandler>(handler: &H) -> Response<ResponseBody> {
+        let header: RequestHeader = http::Request::builder().body(()).unwrap().into();
+        let mut req = RequestContext::new(&header, PathParams::empty());
+        handler.invoke(&mut req, OptionReqBody::from(RequestBody::empty())).await
+    }
+
+    #[tokio::test]
+    async fn test_date_header() {
+        async fn hello() -> &'static str {
+            "hello"
+        }
+
+        let handler = DateWrapper::with_clock(clock).wrap(handler_fn(hello));
+        let resp = invoke(&handler).await;
+        assert_eq!(resp.headers().get(DATE).unwrap(), "Sun, 06 Nov 1994 08:49:37 GMT");
+
+        NOW_MILLIS.fetch_add(1_500, Ordering::Relaxed);
+        let resp = invoke(&handler).await;
+        assert_eq!(resp.headers().get(DATE).unwrap(), "Sun, 06 Nov 1994 08:49:38 GMT");
+    }
+
+    #[tokio::test]
+    async fn test_keep_handler_date() {
+        async fn dated() -> Response<ResponseBody> {
+            let mut resp = Response::new(ResponseBody::empty());
+            resp.headers_mut().insert(DATE, HeaderValue::from_static("Tue, 15 Nov 1994 08:12:31 GMT"));
+            resp
+        }
+
+        let handler = DateWrapper::with_clock(clock).wrap(handler_fn(dated));
+        let resp = invoke(&handler).await;
+        assert_eq!(resp.headers().get(DATE).unwrap(), "Tue, 15 Nov 1994 08:12:31 GMT");
+    }
+}
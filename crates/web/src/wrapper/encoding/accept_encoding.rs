@@ -0,0 +1,234 @@
+//! Parsing of the `Accept-Encoding` request header.
+//!
+//! This module implements content-coding negotiation as described in
+//! [RFC 7231 Section 5.3.4](https://tools.ietf.org/html/rfc7231#section-5.3.4):
+//! - Each coding may carry a quality value (`q`), defaulting to `1.0` when absent
+//! - `q=0` marks a coding as explicitly not acceptable
+//! - The `*` wildcard matches any coding not listed explicitly
+//! - If a coding is listed more than once, the highest quality value wins
+
+use std::convert::Infallible;
+use std::str::FromStr;
+
+/// Quality values are kept in thousandths so they can be compared exactly.
+const MAX_QUALITY: u16 = 1000;
+
+/// A parsed `Accept-Encoding` header value.
+///
+/// The codings are kept sorted from the highest to the lowest quality value.
+///
+/// # Example
+/// ```
+/// use micro_web::wrapper::AcceptEncoding;
+///
+/// let accept_encoding: AcceptEncoding = "gzip;q=0.1, zstd;q=0.9".parse().unwrap();
+/// assert_eq!(accept_encoding.best_match(&["br", "gzip", "zstd"]), Some("zstd"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct AcceptEncoding {
+    /// Lowercase coding names with their quality values in thousandths
+    codings: Vec<(String, u16)>,
+}
+
+impl AcceptEncoding {
+    /// Returns the quality value of `coding` in thousandths, taking the `*` wildcard into account.
+    ///
+    /// Returns `None` if the coding is neither listed nor covered by a wildcard.
+    fn quality_of(&self, coding: &str) -> Option<u16> {
+        let explicit = self.codings.iter().find(|(name, _)| name.eq_ignore_ascii_case(coding));
+        let wildcard = self.codings.iter().find(|(name, _)| name == "*");
+        explicit.or(wildcard).map(|(_, quality)| *quality)
+    }
+
+    /// Returns the quality value of `coding` in the range `0.0..=1.0`.
+    ///
+    /// Returns `None` if the coding is neither listed nor covered by a wildcard.
+    pub fn quality(&self, coding: &str) -> Option<f32> {
+        self.quality_of(coding).map(|quality| quality as f32 / MAX_QUALITY as f32)
+    }
+
+    /// Selects the best coding from `supported`, which is ordered by the server's preference.
+    ///
+    /// Codings with a higher quality value always win, the server's preference only breaks ties.
+    /// Codings with `q=0` are never selected.
+    pub fn best_match<'a>(&self, supported: &[&'a str]) -> Option<&'a str> {
+        supported
+            .iter()
+            .enumerate()
+            .filter_map(|(index, coding)| match self.quality_of(coding) {
+                Some(quality) if quality > 0 => Some((quality, index, *coding)),
+                _ => None,
+            })
+            // prefer higher quality first, then the lower index in `supported`
+            .max_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)))
+            .map(|(_, _, coding)| coding)
+    }
+
+    /// Returns true if the header doesn't contain any valid coding
+    pub fn is_empty(&self) -> bool {
+        self.codings.is_empty()
+    }
+}
+
+/// Parses a quality value such as `1`, `0.5` or `0.125` into thousandths.
+///
+/// Returns `None` if the value is not a valid `qvalue` according to the RFC.
+fn parse_quality(value: &str) -> Option<u16> {
+    let (integer, fraction) = match value.split_once('.') {
+        Some((integer, fraction)) => (integer, fraction),
+        None => (value, ""),
+    };
+
+    if fraction.len() > 3 || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    let fraction_value =
+        fraction.bytes().chain(std::iter::repeat(b'0')).take(3).fold(0u16, |acc, b| acc * 10 + (b - b'0') as u16);
+
+    match integer {
+        "0" => Some(fraction_value),
+        "1" if fraction_value == 0 => Some(MAX_QUALITY),
+        _ => None,
+    }
+}
+
+impl FromStr for AcceptEncoding {
+    type Err = Infallible;
+
+    /// Parses the header value leniently: malformed entries are skipped instead of failing the whole header.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut codings: Vec<(String, u16)> = Vec::new();
+
+        for item in s.split(',') {
+            let mut parts = item.split(';');
+            let name = parts.next().unwrap_or_default().trim();
+            if name.is_empty() {
+                continue;
+            }
+
+            let mut quality = Some(MAX_QUALITY);
+            for param in parts {
+                if let Some((key, value)) = param.split_once('=') {
+                    if key.trim().eq_ignore_ascii_case("q") {
+                        quality = parse_quality(value.trim());
+                    }
+                }
+            }
+
+            let quality = match quality {
+                Some(quality) => quality,
+                // an invalid quality value makes the whole entry invalid
+                None => continue,
+            };
+
+            let name = name.to_ascii_lowercase();
+            match codings.iter_mut().find(|(existing, _)| *existing == name) {
+                Some((_, existing_quality)) => *existing_quality = (*existing_quality).max(quality),
+                None => codings.push((name, quality)),
+            }
+        }
+
+        // stable sort, so codings with the same quality keep the client's order
+        codings.sort_by_key(|(_, quality)| std::cmp::Reverse(*quality));
+        Ok(Self { codings })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SUPPORTED: [&str; 4] = ["zstd", "br", "gzip", "deflate"];
+
+    fn parse(s: &str) -> AcceptEncoding {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_quality() {
+        assert_eq!(parse_quality("1"), Some(1000));
+        assert_eq!(parse_quality("1.000"), Some(1000));
+        assert_eq!(parse_quality("0"), Some(0));
+        assert_eq!(parse_quality("0.5"), Some(500));
+        assert_eq!(parse_quality("0.125"), Some(125));
+        assert_eq!(parse_quality("1.5"), None);
+        assert_eq!(parse_quality("0.1234"), None);
+        assert_eq!(parse_quality("abc"), None);
+        assert_eq!(parse_quality("2"), None);
+    }
+
+    #[test]
+    fn test_client_preference_wins() {
+        let accept_encoding = parse("gzip;q=0.1, zstd;q=0.9");
+        assert_eq!(accept_encoding.best_match(&SUPPORTED), Some("zstd"));
+
+        let accept_encoding = parse("zstd;q=0.1, gzip;q=0.9");
+        assert_eq!(accept_encoding.best_match(&SUPPORTED), Some("gzip"));
+    }
+
+    #[test]
+    fn test_missing_quality_defaults_to_one() {
+        let accept_encoding = parse("deflate, br;q=0.9");
+        assert_eq!(accept_encoding.quality("deflate"), Some(1.0));
+        assert_eq!(accept_encoding.best_match(&SUPPORTED), Some("deflate"));
+    }
+
+    #[test]
+    fn test_server_preference_breaks_ties() {
+        let accept_encoding = parse("gzip, deflate, br, zstd");
+        assert_eq!(accept_encoding.best_match(&SUPPORTED), Some("zstd"));
+    }
+
+    #[test]
+    fn test_zero_quality_is_rejected() {
+        let accept_encoding = parse("zstd;q=0, gzip;q=0");
+        assert_eq!(accept_encoding.best_match(&SUPPORTED), None);
+
+        let accept_encoding = parse("zstd;q=0, gzip;q=0.2");
+        assert_eq!(accept_encoding.best_match(&SUPPORTED), Some("gzip"));
+    }
+
+    #[test]
+    fn test_wildcard() {
+        let accept_encoding = parse("*");
+        assert_eq!(accept_encoding.best_match(&SUPPORTED), Some("zstd"));
+
+        let accept_encoding = parse("*;q=0.5, zstd;q=0, br;q=0.2");
+        assert_eq!(accept_encoding.best_match(&SUPPORTED), Some("gzip"));
+
+        let accept_encoding = parse("*;q=0");
+        assert_eq!(accept_encoding.best_match(&SUPPORTED), None);
+    }
+
+    #[test]
+    fn test_duplicate_uses_highest_quality() {
+        let accept_encoding = parse("gzip;q=0.1, br;q=0.5, gzip;q=0.8");
+        assert_eq!(accept_encoding.quality("gzip"), Some(0.8));
+        assert_eq!(accept_encoding.best_match(&SUPPORTED), Some("gzip"));
+    }
+
+    #[test]
+    fn test_invalid_entries_are_skipped() {
+        let accept_encoding = parse("zstd;q=abc, , gzip;q=0.3, br;q=1.5");
+        assert_eq!(accept_encoding.quality("zstd"), None);
+        assert_eq!(accept_encoding.quality("br"), None);
+        assert_eq!(accept_encoding.best_match(&SUPPORTED), Some("gzip"));
+    }
+
+    #[test]
+    fn test_empty_and_unknown() {
+        let accept_encoding = parse("");
+        assert!(accept_encoding.is_empty());
+        assert_eq!(accept_encoding.best_match(&SUPPORTED), None);
+
+        let accept_encoding = parse("identity, compress");
+        assert_eq!(accept_encoding.best_match(&SUPPORTED), None);
+    }
+
+    #[test]
+    fn test_case_insensitive() {
+        let accept_encoding = parse("GZip;Q=0.5");
+        assert_eq!(accept_encoding.best_match(&SUPPORTED), Some("gzip"));
+    }
+}
@@ -0,0 +1,316 @@
+//! Configuration for response body compression.
+//!
+//! [`CompressionConfig`] holds the per-algorithm compression levels used by the
+//! [`EncodeWrapper`](struct@crate::wrapper::EncodeWrapper). Higher levels produce smaller
+//! payloads at the cost of more CPU time per response.
+//!
+//! The levels can be tuned per content type, e.g. the best level for HTML pages that are
+//! cached, and the fastest one for API responses where latency matters more than a few bytes.
+//!
+//! Zstd can be given a dictionary per content type, trained from samples of the responses with
+//! [`CompressionConfig::train_dict_from_samples`]. Clients must hold the same dictionary to decode
+//! them, so it's meant for the APIs between services rather than for browsers.
+//!
+//! It also holds the content types that are never compressed, since compressing already
+//! compressed formats such as JPEG or ZIP wastes CPU and may even inflate the payload.
+
+use bytes::Bytes;
+use mime::Mime;
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Content types skipped by default, all of them are already compressed.
+const DEFAULT_SKIP_CONTENT_TYPES: [&str; 11] = [
+    "image/jpeg",
+    "image/png",
+    "image/gif",
+    "image/webp",
+    "video/*",
+    "audio/*",
+    "font/woff2",
+    "application/pdf",
+    "application/zip",
+    "application/gzip",
+    "application/zstd",
+];
+
+/// The compression level of each algorithm, for the content types of
+/// [`CompressionConfig::compression_levels`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompressionLevel {
+    /// Gzip compression level, in the range `0..=9`
+    pub gzip: u32,
+    /// Deflate compression level, in the range `0..=9`
+    pub deflate: u32,
+    /// Zstd compression level, in the range `1..=22`
+    pub zstd: i32,
+    /// Brotli quality, in the range `0..=11`
+    pub brotli: u32,
+}
+
+impl CompressionLevel {
+    /// Favors speed over compression ratio.
+    pub fn fast() -> Self {
+        Self { gzip: 1, deflate: 1, zstd: 1, brotli: 1 }
+    }
+
+    /// Favors compression ratio over speed.
+    pub fn best() -> Self {
+        Self { gzip: 9, deflate: 9, zstd: 19, brotli: 11 }
+    }
+}
+
+/// A zstd dictionary responses are compressed with, see [`CompressionConfig::zstd_dictionaries`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZstdDictionary {
+    id: Option<u32>,
+    data: Arc<[u8]>,
+}
+
+impl ZstdDictionary {
+    /// Creates a dictionary from its content, either trained or raw content.
+    pub fn new(data: impl Into<Arc<[u8]>>) -> Self {
+        let data = data.into();
+        let id = zstd::zstd_safe::get_dict_id_from_dict(&data).map(|id| id.get());
+        Self { id, data }
+    }
+
+    /// Reads a dictionary from a file, such as one written by `zstd --train`.
+    pub fn from_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::new(std::fs::read(path)?))
+    }
+
+    /// Returns the ID of a trained dictionary, raw content has none.
+    pub fn id(&self) -> Option<u32> {
+        self.id
+    }
+
+    /// Returns the content of the dictionary.
+    pub fn data(&self) -> &Arc<[u8]> {
+        &self.data
+    }
+}
+
+/// Compression levels used when encoding response bodies.
+///
+/// # Example
+/// ```
+/// use micro_web::wrapper::{CompressionConfig, CompressionLevel, EncodeWrapper};
+///
+/// let mut config = CompressionConfig { zstd_level: 3, ..CompressionConfig::default() };
+/// config.compression_levels.insert("text/html".to_string(), CompressionLevel::best());
+/// config.compression_levels.insert("application/json".to_string(), CompressionLevel::fast());
+/// let wrapper = EncodeWrapper::with_config(config);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompressionConfig {
+    /// Gzip compression level, in the range `0..=9`
+    pub gzip_level: u32,
+    /// Deflate compression level, in the range `0..=9`
+    pub deflate_level: u32,
+    /// Zstd compression level, in the range `1..=22`
+    pub zstd_level: i32,
+    /// Brotli quality, in the range `0..=11`
+    pub brotli_quality: u32,
+    /// Brotli window size as a power of two, in the range `10..=24`
+    pub brotli_lgwin: u32,
+    /// LZ4 block size in bytes, rounded up to 64KB, 256KB, 1MB, 4MB or 8MB, detected from the first
+    /// write if `None`. Only used with the `lz4` feature
+    pub lz4_block_size: Option<usize>,
+    /// Compression levels by content type prefix, such as `text/` or `application/json`, in lowercase.
+    ///
+    /// The longest prefix of the response content type wins, the levels above are used for the
+    /// content types without any match, see [`default_level`](Self::default_level).
+    pub compression_levels: HashMap<String, CompressionLevel>,
+    /// Zstd dictionaries by content type prefix, in lowercase, the longest prefix wins as for
+    /// [`compression_levels`](Self::compression_levels).
+    ///
+    /// The ID of the dictionary is sent in the `zstd-dict-id` response header.
+    pub zstd_dictionaries: HashMap<String, ZstdDictionary>,
+    /// Responses smaller than this many bytes are not compressed, the default is 1 KiB.
+    ///
+    /// A small body barely shrinks, or even grows with the headers of the format, while it still
+    /// costs the setup of an encoder. A body of unknown size, such as a stream, is always compressed.
+    /// `u64::MAX` disables the compression of the bodies of a known size.
+    pub min_size: u64,
+    /// Responses with one of these content types are not compressed.
+    ///
+    /// A `*` subtype matches every subtype, e.g. `image/*` matches `image/png`.
+    pub skip_content_types: Vec<Mime>,
+}
+
+impl CompressionConfig {
+    /// Favors speed over compression ratio.
+    pub fn fast() -> Self {
+        Self { gzip_level: 1, deflate_level: 1, zstd_level: 1, brotli_quality: 1, ..Self::default() }
+    }
+
+    /// Favors compression ratio over speed.
+    pub fn best() -> Self {
+        Self { gzip_level: 9, deflate_level: 9, zstd_level: 19, brotli_quality: 11, ..Self::default() }
+    }
+
+    /// Returns the levels used for the content types without a match in
+    /// [`compression_levels`](Self::compression_levels).
+    pub fn default_level(&self) -> CompressionLevel {
+        CompressionLevel {
+            gzip: self.gzip_level,
+            deflate: self.deflate_level,
+            zstd: self.zstd_level,
+            brotli: self.brotli_quality,
+        }
+    }
+
+    /// Returns the levels used for responses with `content_type`, or the default ones if it's `None`.
+    ///
+    /// Parameters such as `charset` are ignored, and the content type is compared case-insensitively.
+    pub fn level_for(&self, content_type: Option<&str>) -> CompressionLevel {
+        content_type
+            .and_then(|content_type| longest_prefix_match(&self.compression_levels, content_type))
+            .map_or_else(|| self.default_level(), |level| *level)
+    }
+
+    /// Returns the zstd dictionary used for responses with `content_type`, if any.
+    pub fn zstd_dictionary_for(&self, content_type: Option<&str>) -> Option<&ZstdDictionary> {
+        longest_prefix_match(&self.zstd_dictionaries, content_type?)
+    }
+
+    /// Reads the zstd dictionary of the content types starting with `prefix` from `path`.
+    pub fn add_zstd_dictionary(&mut self, prefix: &str, path: impl AsRef<Path>) -> io::Result<()> {
+        let dictionary = ZstdDictionary::from_file(path)?;
+        self.zstd_dictionaries.insert(prefix.to_ascii_lowercase(), dictionary);
+        Ok(())
+    }
+
+    /// Trains a zstd dictionary of at most `max_size` bytes from samples of the responses.
+    ///
+    /// A few hundred samples are needed for a useful dictionary, too few of them is an error.
+    pub fn train_dict_from_samples(samples: &[Bytes], max_size: usize) -> io::Result<Vec<u8>> {
+        zstd::dict::from_samples(samples, max_size)
+    }
+
+    /// Adds a content type that should not be compressed, such as `image/*` or `application/pdf`.
+    pub fn add_skip_type(&mut self, mime: &str) -> Result<(), mime::FromStrError> {
+        let mime: Mime = mime.parse()?;
+        if !self.skip_content_types.contains(&mime) {
+            self.skip_content_types.push(mime);
+        }
+        Ok(())
+    }
+
+    /// Removes a content type from the skip list, returns true if it was present.
+    pub fn remove_skip_type(&mut self, mime: &str) -> bool {
+        let mime: Mime = match mime.parse() {
+            Ok(mime) => mime,
+            Err(_) => return false,
+        };
+        let len = self.skip_content_types.len();
+        self.skip_content_types.retain(|skip_type| *skip_type != mime);
+        self.skip_content_types.len() != len
+    }
+
+    /// Returns true if responses with `content_type` should not be compressed.
+    ///
+    /// Parameters such as `charset` are ignored, and an unparsable content type is never skipped.
+    pub fn should_skip(&self, content_type: &str) -> bool {
+        let content_type: Mime = match content_type.parse() {
+            Ok(mime) => mime,
+            Err(_) => return false,
+        };
+
+        self.skip_content_types.iter().any(|skip_type| {
+            skip_type.type_() == content_type.type_()
+                && (skip_type.subtype() == mime::STAR || skip_type.subtype() == content_type.subtype())
+        })
+    }
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            gzip_level: 9,
+            deflate_level: 9,
+            zstd_level: 6,
+            brotli_quality: 3,
+            brotli_lgwin: 22,
+            lz4_block_size: None,
+            compression_levels: HashMap::new(),
+            zstd_dictionaries: HashMap::new(),
+            min_size: 1024,
+            skip_content_types: DEFAULT_SKIP_CONTENT_TYPES.iter().map(|mime| mime.parse().unwrap()).collect(),
+        }
+    }
+}
+
+/// Returns the value of the longest key `content_type` starts with, ignoring its parameters and case.
+fn longest_prefix_match<'a, T>(map: &'a HashMap<String, T>, content_type: &str) -> Option<&'a T> {
+    let content_type = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+    map.iter()
+        .filter(|(prefix, _)| content_type.starts_with(prefix.as_str()))
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map(|(_, value)| value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_skip_types() {
+        let config = CompressionConfig::default();
+        assert!(config.should_skip("image/jpeg"));
+        assert!(config.should_skip("video/mp4"));
+        assert!(config.should_skip("application/pdf"));
+        assert!(!config.should_skip("text/html; charset=utf-8"));
+        assert!(!config.should_skip("image/svg+xml"));
+        assert!(!config.should_skip("not a mime"));
+    }
+
+    #[test]
+    fn test_level_for() {
+        let mut config = CompressionConfig::fast();
+        config.compression_levels.insert("text/".to_string(), CompressionLevel::best());
+        config.compression_levels.insert("text/event-stream".to_string(), CompressionLevel::fast());
+
+        assert_eq!(config.level_for(Some("text/html; charset=utf-8")), CompressionLevel::best());
+        assert_eq!(config.level_for(Some("Text/CSS")), CompressionLevel::best());
+        assert_eq!(config.level_for(Some("text/event-stream")), CompressionLevel::fast());
+        assert_eq!(config.level_for(Some("application/octet-stream")), CompressionLevel::fast());
+        assert_eq!(config.level_for(None), config.default_level());
+    }
+
+    #[test]
+    fn test_zstd_dictionary() {
+        // raw content works as a dictionary too, without an id
+        let dictionary = br#"{"id":1,"name":"user","email":"user@example.com","active":true}"#;
+        let path = std::env::temp_dir().join(format!("micro-web-test-{}.dict", std::process::id()));
+        std::fs::write(&path, dictionary).unwrap();
+
+        let mut config = CompressionConfig::default();
+        config.add_zstd_dictionary("Application/JSON", &path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let found = config.zstd_dictionary_for(Some("application/json; charset=utf-8")).unwrap();
+        assert_eq!(&found.data()[..], &dictionary[..]);
+        assert_eq!(found.id(), None);
+        assert!(config.zstd_dictionary_for(Some("text/html")).is_none());
+        assert!(config.zstd_dictionary_for(None).is_none());
+
+        assert!(config.add_zstd_dictionary("text/", path).is_err());
+        assert!(CompressionConfig::train_dict_from_samples(&[Bytes::from_static(b"{}")], 1024).is_err());
+    }
+
+    #[test]
+    fn test_add_and_remove_skip_type() {
+        let mut config = CompressionConfig::default();
+        config.add_skip_type("image/*").unwrap();
+        assert!(config.should_skip("image/svg+xml"));
+        assert!(config.add_skip_type("invalid").is_err());
+
+        assert!(config.remove_skip_type("image/*"));
+        assert!(!config.remove_skip_type("image/*"));
+        assert!(!config.should_skip("image/svg+xml"));
+    }
+}
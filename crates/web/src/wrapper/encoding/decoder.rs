@@ -0,0 +1,498 @@
+//! Decompression of request bodies sent with a `Content-Encoding` header.
+//!
+//! [`DecodeWrapper`] is the counterpart of [`EncodeWrapper`](struct@crate::wrapper::EncodeWrapper): before the
+//! request reaches the handler, the body is wrapped in a `DecodedBody` for every coding listed in
+//! `Content-Encoding`, so handlers and extractors always see the plain payload.
+//!
+//! Codings are applied by the client in the order they are listed, so they are removed in reverse order.
+//! Requests using a coding we can't decode are rejected with `415 Unsupported Media Type`.
+//!
+//! A small encoded body can expand to a huge one, so reading the decoded body fails with
+//! [`ParseError::TooLargeBody`] once it's larger than the [`BodyLimit`] of the request, or 16 MiB if the
+//! request has none.
+
+use crate::handler::RequestHandler;
+use crate::responder::Responder;
+use crate::wrapper::encoding::Writer;
+use crate::wrapper::{BodyLimit, Wrapper};
+use crate::{OptionReqBody, RequestBody, RequestContext, ResponseBody};
+use async_trait::async_trait;
+use bytes::{Buf, Bytes};
+use flate2::write::{GzDecoder, ZlibDecoder};
+use http::{Response, StatusCode};
+use http_body::{Body, Frame};
+use micro_http::protocol::{ParseError, RequestHeader};
+use pin_project_lite::pin_project;
+use std::io;
+use std::io::Write;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use tracing::trace;
+use zstd::stream::raw::{Decoder as RawZstdDecoder, InBuffer, Operation, OutBuffer};
+
+/// The decoded size allowed when the request has no [`BodyLimit`], 16 MiB.
+const DEFAULT_MAX_DECODED_SIZE: u64 = 16 * 1024 * 1024;
+
+/// A zstd decoder writing to a `Writer`.
+///
+/// Unlike `zstd::stream::write::Decoder`, it keeps track of the end of the frames, so a truncated body
+/// is detected when it's finished.
+pub(crate) struct ZstdDecoder {
+    decoder: RawZstdDecoder<'static>,
+    writer: Writer,
+    /// The output of the decoder, before it's written to `writer`
+    buf: Vec<u8>,
+    /// Whether the data written so far ends with a complete frame
+    frame_complete: bool,
+}
+
+impl ZstdDecoder {
+    fn new(writer: Writer) -> io::Result<Self> {
+        let buf = Vec::with_capacity(zstd::zstd_safe::DCtx::out_size());
+        Ok(Self { decoder: RawZstdDecoder::new()?, writer, buf, frame_complete: false })
+    }
+
+    fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        let mut input = InBuffer::around(data);
+        loop {
+            self.buf.clear();
+            let mut output = OutBuffer::around(&mut self.buf);
+            let hint = self.decoder.run(&mut input, &mut output)?;
+            let output_full = output.pos() == output.capacity();
+            self.writer.write_all(&self.buf)?;
+
+            // zstd only returns 0 once the frame is decoded and all of its output has been flushed
+            self.frame_complete = hint == 0;
+            if input.pos() == data.len() && (self.frame_complete || !output_full) {
+                return Ok(());
+            }
+        }
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if self.frame_complete {
+            Ok(())
+        } else {
+            Err(io::Error::new(io::ErrorKind::UnexpectedEof, "incomplete zstd frame"))
+        }
+    }
+}
+
+/// Represents different types of content decoding.
+pub(crate) enum Decoder {
+    /// Gzip decoding.
+    Gzip(GzDecoder<Writer>),
+    /// Deflate decoding.
+    Deflate(ZlibDecoder<Writer>),
+    /// Zstd decoding.
+    Zstd(ZstdDecoder),
+    /// Brotli decoding.
+    Br(Box<brotli::DecompressorWriter<Writer>>),
+}
+
+impl Decoder {
+    /// Creates a decoder for the given coding name, returns `None` if the coding is not supported.
+    ///
+    /// The decoder fails once it has decoded more than `max_len` bytes.
+    fn from_name(name: &str, max_len: u64) -> Option<Self> {
+        let writer = Writer::with_max_len(max_len);
+        match name {
+            "gzip" | "x-gzip" => Some(Self::Gzip(GzDecoder::new(writer))),
+            "deflate" => Some(Self::Deflate(ZlibDecoder::new(writer))),
+            // creating the zstd decoder only fails when the context can't be allocated
+            "zstd" => ZstdDecoder::new(writer).ok().map(Self::Zstd),
+            "br" => Some(Self::Br(Box::new(brotli::DecompressorWriter::new(
+                writer,
+                32 * 1024, // 32 KiB buffer
+            )))),
+            _ => None,
+        }
+    }
+
+    /// Writes encoded data to the decoder.
+    fn write(&mut self, data: &[u8]) -> Result<(), io::Error> {
+        let result = match self {
+            Self::Gzip(ref mut decoder) => decoder.write_all(data),
+            Self::Deflate(ref mut decoder) => decoder.write_all(data),
+            Self::Zstd(ref mut decoder) => decoder.write(data),
+            Self::Br(ref mut decoder) => decoder.write_all(data),
+        };
+
+        if let Err(ref err) = result {
+            trace!("Error decoding {} encoding: {}", self.name(), err);
+        }
+        result
+    }
+
+    /// Returns the name of the encoding.
+    fn name(&self) -> &'static str {
+        match self {
+            Decoder::Gzip(_) => "gzip",
+            Decoder::Deflate(_) => "deflate",
+            Decoder::Zstd(_) => "zstd",
+            Decoder::Br(_) => "br",
+        }
+    }
+
+    /// Returns the writer receiving the decoded data.
+    fn writer(&self) -> &Writer {
+        match self {
+            Self::Gzip(decoder) => decoder.get_ref(),
+            Self::Deflate(decoder) => decoder.get_ref(),
+            Self::Zstd(decoder) => &decoder.writer,
+            Self::Br(decoder) => decoder.get_ref(),
+        }
+    }
+
+    /// Takes the decoded data from the decoder.
+    fn take(&mut self) -> Bytes {
+        match *self {
+            Self::Gzip(ref mut decoder) => decoder.get_mut().take(),
+            Self::Deflate(ref mut decoder) => decoder.get_mut().take(),
+            Self::Zstd(ref mut decoder) => decoder.writer.take(),
+            Self::Br(ref mut decoder) => decoder.get_mut().take(),
+        }
+    }
+
+    /// Finishes the decoding process and returns the remaining decoded data.
+    ///
+    /// Fails if the encoded stream was truncated.
+    fn finish(&mut self) -> Result<Bytes, io::Error> {
+        match self {
+            Self::Gzip(decoder) => decoder.try_finish()?,
+            Self::Deflate(decoder) => decoder.try_finish()?,
+            Self::Zstd(decoder) => decoder.finish()?,
+            Self::Br(decoder) => decoder.close()?,
+        }
+        Ok(self.take())
+    }
+
+    /// Converts an error of [`write`](Self::write) or [`finish`](Self::finish) into a `ParseError`.
+    fn parse_error(&self, err: io::Error) -> ParseError {
+        let writer = self.writer();
+        if writer.is_full() {
+            ParseError::too_large_body(writer.written, writer.max_len)
+        } else {
+            ParseError::invalid_body(err.to_string())
+        }
+    }
+}
+
+pin_project! {
+    /// A wrapper around a `Body` that decodes the data.
+    struct DecodedBody<B: Body> {
+        #[pin]
+        inner: B,
+        decoder: Option<Decoder>,
+    }
+}
+
+impl<B: Body> DecodedBody<B> {
+    /// Creates a new `DecodedBody`.
+    fn new(b: B, decoder: Decoder) -> Self {
+        Self { inner: b, decoder: Some(decoder) }
+    }
+}
+
+impl<B> Body for DecodedBody<B>
+where
+    B: Body<Error = ParseError>,
+    B::Data: Buf,
+{
+    type Data = Bytes;
+    type Error = ParseError;
+
+    fn poll_frame(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let mut this = self.project();
+
+        // the decoder is only taken once the inner body is finished
+        if this.decoder.is_none() {
+            return Poll::Ready(None);
+        }
+
+        loop {
+            return match ready!(this.inner.as_mut().poll_frame(cx)) {
+                Some(Ok(frame)) => {
+                    let data = match frame.into_data() {
+                        Ok(data) => data,
+                        // trailers don't need decoding
+                        Err(frame) => return Poll::Ready(Some(Ok(frame.map_data(|_| Bytes::new())))),
+                    };
+
+                    let decoder = this.decoder.as_mut().unwrap();
+                    if let Err(e) = decoder.write(data.chunk()) {
+                        return Poll::Ready(Some(Err(decoder.parse_error(e))));
+                    }
+
+                    let bytes = decoder.take();
+                    if bytes.is_empty() {
+                        continue;
+                    }
+                    Poll::Ready(Some(Ok(Frame::data(bytes))))
+                }
+                Some(Err(e)) => Poll::Ready(Some(Err(e))),
+                None => {
+                    let mut decoder = this.decoder.take().unwrap();
+                    match decoder.finish() {
+                        Ok(bytes) if bytes.is_empty() => Poll::Ready(None),
+                        Ok(bytes) => Poll::Ready(Some(Ok(Frame::data(bytes)))),
+                        Err(e) => Poll::Ready(Some(Err(decoder.parse_error(e)))),
+                    }
+                }
+            };
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.decoder.is_none()
+    }
+}
+
+/// A request handler that decodes the request body.
+pub struct DecodeRequestHandler<H: RequestHandler> {
+    handler: H,
+}
+
+/// A wrapper that creates `DecodeRequestHandler`.
+///
+/// Supports the same codings as [`EncodeWrapper`](struct@crate::wrapper::EncodeWrapper): `gzip`, `deflate`,
+/// `zstd` and `br`. The `Content-Encoding` and `Content-Length` headers are removed from the request
+/// seen by the inner handler, since they describe the encoded body.
+pub struct DecodeWrapper;
+
+impl<H: RequestHandler> Wrapper<H> for DecodeWrapper {
+    type Out = DecodeRequestHandler<H>;
+
+    fn wrap(&self, handler: H) -> Self::Out {
+        DecodeRequestHandler { handler }
+    }
+}
+
+#[async_trait]
+impl<H: RequestHandler> RequestHandler for DecodeRequestHandler<H> {
+    async fn invoke<'server, 'req>(
+        &self,
+        req: &mut RequestContext<'server, 'req>,
+        req_body: OptionReqBody,
+    ) -> Response<ResponseBody> {
+        let max_len = req.extensions().get::<BodyLimit>().map_or(DEFAULT_MAX_DECODED_SIZE, |limit| limit.0);
+        let decoders = match decoders(req.headers(), max_len) {
+            Some(decoders) if decoders.is_empty() => return self.handler.invoke(req, req_body).await,
+            Some(decoders) => decoders,
+            None => return (StatusCode::UNSUPPORTED_MEDIA_TYPE, "unsupported content encoding").response_to(req),
+        };
+
+        let mapped = req_body
+            .map(|body| {
+                decoders.into_iter().fold(body, |body, decoder| RequestBody::boxed(DecodedBody::new(body, decoder)))
+            })
+            .await;
+        if mapped.is_err() {
+            // the body has been consumed by an outer handler, there is nothing left to decode
+            return self.handler.invoke(req, req_body).await;
+        }
+
+        let header = decoded_header(req.request_header());
+        let mut decoded_req = req.with_request_header(&header);
+        let resp = self.handler.invoke(&mut decoded_req, req_body).await;
+
+        // hand the extensions back, so outer wrappers can see what the inner handler attached
+        *req.extensions_mut() = std::mem::take(decoded_req.extensions_mut());
+        resp
+    }
+}
+
+/// Creates the decoders for the `Content-Encoding` header, in the order they need to be applied.
+///
+/// Returns `None` if any of the codings is not supported, `identity` codings are skipped. Each decoder
+/// fails once it has decoded more than `max_len` bytes.
+fn decoders(headers: &http::HeaderMap, max_len: u64) -> Option<Vec<Decoder>> {
+    let mut decoders = Vec::new();
+    for value in headers.get_all(http::header::CONTENT_ENCODING) {
+        let value = value.to_str().ok()?;
+        for coding in value.split(',').map(str::trim).filter(|coding| !coding.is_empty()) {
+            let coding = coding.to_ascii_lowercase();
+            if coding == "identity" {
+                continue;
+            }
+            decoders.push(Decoder::from_name(&coding, max_len)?);
+        }
+    }
+
+    // the last listed coding was applied last, so it must be removed first
+    decoders.reverse();
+    Some(decoders)
+}
+
+/// Copies the request header without the headers describing the encoded body.
+fn decoded_header(header: &RequestHeader) -> RequestHeader {
+    let mut inner = header.as_ref().clone();
+    inner.headers_mut().remove(http::header::CONTENT_ENCODING);
+    inner.headers_mut().remove(http::header::CONTENT_LENGTH);
+    inner.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{handler_fn, PathParams};
+    use flate2::write::{GzEncoder, ZlibEncoder};
+    use flate2::Compression;
+    use http::Request;
+    use http_body_util::{BodyExt, Full};
+
+    fn request_header(content_encoding: &str) -> RequestHeader {
+        Request::builder()
+            .header(http::header::CONTENT_ENCODING, content_encoding)
+            .header(http::header::CONTENT_LENGTH, "42")
+            .body(())
+            .unwrap()
+            .into_parts()
+            .0
+            .into()
+    }
+
+    fn request_body(bytes: Vec<u8>) -> OptionReqBody {
+        RequestBody::boxed(Full::new(Bytes::from(bytes)).map_err(|never| match never {})).into()
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn deflate(data: &[u8]) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    async fn echo(headers: &http::HeaderMap, body: String) -> String {
+        format!("{}|{}", headers.contains_key(http::header::CONTENT_ENCODING), body)
+    }
+
+    async fn invoke(content_encoding: &str, body: Vec<u8>) -> Response<ResponseBody> {
+        let handler = DecodeWrapper.wrap(handler_fn(echo));
+        let header = request_header(content_encoding);
+        let mut req = RequestContext::new(&header, PathParams::empty());
+        handler.invoke(&mut req, request_body(body)).await
+    }
+
+    async fn body_string(resp: Response<ResponseBody>) -> String {
+        let bytes = resp.into_body().collect().await.unwrap().to_bytes();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_decode_gzip() {
+        let resp = invoke("gzip", gzip(b"hello world")).await;
+
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(body_string(resp).await, "false|hello world");
+    }
+
+    #[tokio::test]
+    async fn test_decode_zstd_and_br() {
+        let encoded = zstd::encode_all(&b"hello zstd"[..], 3).unwrap();
+        assert_eq!(body_string(invoke("zstd", encoded).await).await, "false|hello zstd");
+
+        let mut encoded = Vec::new();
+        brotli::CompressorWriter::new(&mut encoded, 4096, 5, 22).write_all(b"hello br").unwrap();
+        assert_eq!(body_string(invoke("br", encoded).await).await, "false|hello br");
+    }
+
+    #[tokio::test]
+    async fn test_decode_stacked_encodings() {
+        // gzip is applied first, then deflate
+        let resp = invoke("gzip, deflate", deflate(&gzip(b"stacked"))).await;
+
+        assert_eq!(body_string(resp).await, "false|stacked");
+    }
+
+    #[tokio::test]
+    async fn test_identity_is_passed_through() {
+        let resp = invoke("identity", b"plain".to_vec()).await;
+
+        assert_eq!(body_string(resp).await, "true|plain");
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_encoding() {
+        let resp = invoke("gzip, compress", b"whatever".to_vec()).await;
+
+        assert_eq!(resp.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+
+    #[tokio::test]
+    async fn test_decode_large_zstd() {
+        // larger than the output buffer of the decoder, and made of two frames
+        let data = "hello zstd ".repeat(50_000);
+        let mut encoded = zstd::encode_all(data.as_bytes(), 3).unwrap();
+        encoded.extend(zstd::encode_all(&b"!"[..], 3).unwrap());
+
+        assert_eq!(body_string(invoke("zstd", encoded).await).await, format!("false|{data}!"));
+    }
+
+    async fn decode(content_encoding: &str, encoded: Vec<u8>, max_len: u64) -> Result<Bytes, ParseError> {
+        let header = request_header(content_encoding);
+        let decoder = decoders(header.headers(), max_len).unwrap().remove(0);
+        let req_body = request_body(encoded);
+        req_body.map(|body| RequestBody::boxed(DecodedBody::new(body, decoder))).await.unwrap();
+
+        req_body.apply(|body| async { body.collect().await.map(|c| c.to_bytes()) }).await
+    }
+
+    #[tokio::test]
+    async fn test_truncated_zstd() {
+        let mut encoded = zstd::encode_all(&b"hello zstd"[..], 3).unwrap();
+        encoded.truncate(encoded.len() - 4);
+        assert!(matches!(decode("zstd", encoded, u64::MAX).await, Err(ParseError::InvalidBody { .. })));
+
+        // no frame at all
+        assert!(decode("zstd", vec![], u64::MAX).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_decoded_size_limit() {
+        let data = vec![0; 1024 * 1024];
+        let encoded = [
+            ("gzip", gzip(&data)),
+            ("deflate", deflate(&data)),
+            ("zstd", zstd::encode_all(&data[..], 3).unwrap()),
+        ];
+        for (coding, encoded) in encoded {
+            assert_eq!(decode(coding, encoded.clone(), data.len() as u64).await.unwrap().len(), data.len());
+            let result = decode(coding, encoded, 1000).await;
+            assert!(matches!(result, Err(ParseError::TooLargeBody { max_size: 1000, .. })), "{coding}");
+        }
+
+        let mut encoded = Vec::new();
+        brotli::CompressorWriter::new(&mut encoded, 4096, 5, 22).write_all(&data).unwrap();
+        let result = decode("br", encoded, 1000).await;
+        assert!(matches!(result, Err(ParseError::TooLargeBody { max_size: 1000, .. })));
+    }
+
+    #[tokio::test]
+    async fn test_body_limit_extension() {
+        let handler = DecodeWrapper.wrap(handler_fn(echo));
+        let header = request_header("gzip");
+        let mut req = RequestContext::new(&header, PathParams::empty());
+        req.extensions_mut().insert(BodyLimit(4));
+        let resp = handler.invoke(&mut req, request_body(gzip(b"hello world"))).await;
+
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn test_truncated_body() {
+        let mut encoded = gzip(b"hello world");
+        encoded.truncate(encoded.len() - 4);
+
+        assert!(decode("gzip", encoded, u64::MAX).await.is_err());
+    }
+}
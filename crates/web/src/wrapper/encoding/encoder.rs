@@ -1,12 +1,13 @@
 use crate::handler::RequestHandler;
-use crate::wrapper::encoding::Writer;
-use crate::wrapper::Wrapper;
+use crate::wrapper::encoding::{AcceptEncoding, CompressionConfig, Writer};
+use crate::wrapper::etag::buffer;
+use crate::wrapper::{priority, Wrapper};
 use crate::{OptionReqBody, RequestContext, ResponseBody};
 use async_trait::async_trait;
 use bytes::{Buf, Bytes};
 use flate2::write::{GzEncoder, ZlibEncoder};
 use flate2::Compression;
-use http::{Response, StatusCode};
+use http::{HeaderMap, HeaderValue, Response, StatusCode};
 use http_body::{Body, Frame};
 use http_body_util::combinators::UnsyncBoxBody;
 use micro_http::protocol::{HttpError, SendError};
@@ -14,13 +15,35 @@ use pin_project_lite::pin_project;
 use std::fmt::Debug;
 use std::io;
 use std::io::Write;
+use std::mem;
 use std::pin::Pin;
+use std::sync::{Arc, OnceLock};
 use std::task::{ready, Context, Poll};
 use tracing::{error, trace};
 use zstd::stream::write::Encoder as ZstdEncoder;
 
 // (almost thanks and) copy from actix-http: https://github.com/actix/actix-web/blob/master/actix-http/src/encoding/encoder.rs
 
+/// The encodings supported by [`Encoder`], ordered by the server's preference.
+const SUPPORTED_ENCODINGS: &[&str] = &[
+    "zstd",
+    "br",
+    "gzip",
+    "deflate",
+    // the name used by proxies and gRPC, lz4 has no registered content coding
+    #[cfg(feature = "lz4")]
+    "x-lz4",
+];
+
+/// A response header opting out of compression when set to `true`, it's removed from the response.
+///
+/// Handlers that already encoded the body should set `Content-Encoding` instead.
+pub const X_NO_ENCODE: &str = "x-no-encode";
+
+/// The response header naming the zstd dictionary the body is compressed with, see
+/// [`CompressionConfig::zstd_dictionaries`].
+pub const ZSTD_DICT_ID: &str = "zstd-dict-id";
+
 /// Represents different types of content encoding.
 pub(crate) enum Encoder {
     /// Gzip encoding.
@@ -31,47 +54,86 @@ pub(crate) enum Encoder {
     Zstd(ZstdEncoder<'static, Writer>),
     /// Brotli encoding.
     Br(Box<brotli::CompressorWriter<Writer>>),
+    /// LZ4 frame encoding.
+    #[cfg(feature = "lz4")]
+    Lz4(lz4_flex::frame::FrameEncoder<Writer>),
 }
 
 impl Encoder {
-    /// Creates a new Gzip encoder.
-    fn gzip() -> Self {
-        Self::Gzip(GzEncoder::new(Writer::new(), Compression::best()))
+    /// Creates a new Gzip encoder with the given level, clamped to `0..=9`.
+    ///
+    /// `capacity` is the expected size of the encoded data, see [`writer_capacity`].
+    fn gzip(level: u32, capacity: Option<usize>) -> Self {
+        Self::Gzip(GzEncoder::new(writer(capacity), Compression::new(level.min(9))))
+    }
+
+    /// Creates a new Deflate encoder with the given level, clamped to `0..=9`.
+    fn deflate(level: u32, capacity: Option<usize>) -> Self {
+        Self::Deflate(ZlibEncoder::new(writer(capacity), Compression::new(level.min(9))))
     }
 
-    /// Creates a new Deflate encoder.
-    fn deflate() -> Self {
-        Self::Deflate(ZlibEncoder::new(Writer::new(), Compression::best()))
+    /// Creates a new Zstd encoder with the given level.
+    fn zstd(level: i32, capacity: Option<usize>) -> Self {
+        // todo: remove the unwrap
+        Self::Zstd(ZstdEncoder::new(writer(capacity), level).unwrap())
     }
 
-    /// Creates a new Zstd encoder.
-    fn zstd() -> Self {
+    /// Creates a new Zstd encoder with the given level, compressing with `dict`.
+    fn zstd_with_dict(dict: Arc<[u8]>, level: i32, capacity: Option<usize>) -> Self {
         // todo: remove the unwrap
-        Self::Zstd(ZstdEncoder::new(Writer::new(), 6).unwrap())
+        Self::Zstd(ZstdEncoder::with_dictionary(writer(capacity), level, &dict).unwrap())
     }
 
-    /// Creates a new Brotli encoder.
-    fn br() -> Self {
+    /// Creates a new Brotli encoder with the given quality and window size.
+    fn br(quality: u32, lgwin: u32, capacity: Option<usize>) -> Self {
         Self::Br(Box::new(brotli::CompressorWriter::new(
-            Writer::new(),
+            writer(capacity),
             32 * 1024, // 32 KiB buffer
-            3,         // BROTLI_PARAM_QUALITY
-            22,        // BROTLI_PARAM_LGWIN
+            quality,   // BROTLI_PARAM_QUALITY
+            lgwin,     // BROTLI_PARAM_LGWIN
         )))
     }
 
-    /// Selects an encoder based on the `Accept-Encoding` header.
-    fn select(accept_encodings: &str) -> Option<Self> {
-        if accept_encodings.contains("zstd") {
-            Some(Self::zstd())
-        } else if accept_encodings.contains("br") {
-            Some(Self::br())
-        } else if accept_encodings.contains("gzip") {
-            Some(Self::gzip())
-        } else if accept_encodings.contains("deflate") {
-            Some(Self::deflate())
-        } else {
-            None
+    /// Creates a new LZ4 frame encoder, with the smallest block size holding `block_size` bytes.
+    ///
+    /// The block size is detected from the first write when `block_size` is `None`.
+    #[cfg(feature = "lz4")]
+    fn lz4(block_size: Option<usize>) -> Self {
+        use lz4_flex::frame::{BlockSize, FrameEncoder, FrameInfo};
+
+        let block_size = match block_size {
+            None => BlockSize::Auto,
+            Some(size) if size <= 64 * 1024 => BlockSize::Max64KB,
+            Some(size) if size <= 256 * 1024 => BlockSize::Max256KB,
+            Some(size) if size <= 1024 * 1024 => BlockSize::Max1MB,
+            Some(size) if size <= 4 * 1024 * 1024 => BlockSize::Max4MB,
+            Some(_) => BlockSize::Max8MB,
+        };
+        Self::Lz4(FrameEncoder::with_frame_info(FrameInfo::new().block_size(block_size), Writer::new()))
+    }
+
+    /// Creates the encoder of the `encoding` returned by [`negotiate`].
+    ///
+    /// The compression levels and the zstd dictionary are taken from `config` for the `content_type`
+    /// of the response, `capacity` is the expected size of the encoded data.
+    fn new(
+        encoding: &str,
+        config: &CompressionConfig,
+        content_type: Option<&str>,
+        capacity: Option<usize>,
+    ) -> Option<Self> {
+        let level = config.level_for(content_type);
+        match encoding {
+            "zstd" => match config.zstd_dictionary_for(content_type) {
+                Some(dictionary) => Some(Self::zstd_with_dict(Arc::clone(dictionary.data()), level.zstd, capacity)),
+                None => Some(Self::zstd(level.zstd, capacity)),
+            },
+            "br" => Some(Self::br(level.brotli, config.brotli_lgwin, capacity)),
+            "gzip" => Some(Self::gzip(level.gzip, capacity)),
+            "deflate" => Some(Self::deflate(level.deflate, capacity)),
+            #[cfg(feature = "lz4")]
+            "x-lz4" => Some(Self::lz4(config.lz4_block_size)),
+            _ => None,
         }
     }
 
@@ -82,6 +144,8 @@ impl Encoder {
             Encoder::Deflate(_) => "deflate",
             Encoder::Zstd(_) => "zstd",
             Encoder::Br(_) => "br",
+            #[cfg(feature = "lz4")]
+            Encoder::Lz4(_) => "x-lz4",
         }
     }
 
@@ -119,6 +183,15 @@ impl Encoder {
                     Err(err)
                 }
             },
+
+            #[cfg(feature = "lz4")]
+            Self::Lz4(ref mut encoder) => match encoder.write_all(data) {
+                Ok(_) => Ok(()),
+                Err(err) => {
+                    trace!("Error encoding lz4 encoding: {}", err);
+                    Err(err)
+                }
+            },
         }
     }
 
@@ -129,6 +202,8 @@ impl Encoder {
             Self::Deflate(ref mut encoder) => encoder.get_mut().take(),
             Self::Zstd(ref mut encoder) => encoder.get_mut().take(),
             Self::Br(ref mut encoder) => encoder.get_mut().take(),
+            #[cfg(feature = "lz4")]
+            Self::Lz4(ref mut encoder) => encoder.get_mut().take(),
         }
     }
 
@@ -150,28 +225,64 @@ impl Encoder {
                 Err(err) => Err(err),
             },
 
+            // `into_inner` finishes the stream with its last meta-block, but ignores the errors, so
+            // the pending data is flushed first to report them
             Self::Br(mut encoder) => match encoder.flush() {
                 Ok(()) => Ok(encoder.into_inner().buf.freeze()),
                 Err(err) => Err(err),
             },
+
+            #[cfg(feature = "lz4")]
+            Self::Lz4(encoder) => match encoder.finish() {
+                Ok(writer) => Ok(writer.buf.freeze()),
+                Err(err) => Err(err.into()),
+            },
         }
     }
 }
 
-/// A wrapper around a `Body` that encodes the data.
 pin_project! {
+    /// A wrapper around a `Body` that encodes the data.
     struct EncodedBody<B: Body> {
         #[pin]
         inner: B,
-        encoder: Option<Encoder>,
-        state: Option<bool>,
+        state: EncoderState,
+    }
+}
+
+/// The state of an [`EncodedBody`].
+enum EncoderState {
+    /// Encoding the data of the inner body
+    Writing(Encoder),
+    /// The encoded data is complete, the trailers of the inner body are left to yield
+    Finishing(HeaderMap),
+    /// The body is complete, or failed
+    Done,
+}
+
+impl EncoderState {
+    /// Finishes the encoder and returns its last bytes, the state moves on to the `trailers` if any.
+    ///
+    /// An encoder already finished returns no bytes.
+    fn finish(&mut self, trailers: Option<HeaderMap>) -> io::Result<Bytes> {
+        let next = trailers.map_or(EncoderState::Done, EncoderState::Finishing);
+        let EncoderState::Writing(encoder) = mem::replace(self, next) else {
+            return Ok(Bytes::new());
+        };
+        match encoder.finish() {
+            Ok(bytes) => Ok(bytes),
+            Err(e) => {
+                *self = EncoderState::Done;
+                Err(e)
+            }
+        }
     }
 }
 
 impl<B: Body> EncodedBody<B> {
     /// Creates a new `EncodedBody`.
     fn new(b: B, encoder: Encoder) -> Self {
-        Self { inner: b, encoder: Some(encoder), state: Some(true) }
+        Self { inner: b, state: EncoderState::Writing(encoder) }
     }
 }
 
@@ -179,7 +290,7 @@ impl<B> Body for EncodedBody<B>
 where
     B: Body + Unpin,
     B::Data: Buf + Debug,
-    B::Error: ToString,
+    B::Error: Into<HttpError>,
 {
     type Data = Bytes;
     type Error = HttpError;
@@ -187,83 +298,137 @@ where
     fn poll_frame(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
         let mut this = self.project();
 
-        if this.state.is_none() {
-            return Poll::Ready(None);
-        }
-
         loop {
+            let encoder = match this.state {
+                EncoderState::Writing(encoder) => encoder,
+                _ => {
+                    return Poll::Ready(match mem::replace(this.state, EncoderState::Done) {
+                        EncoderState::Finishing(trailers) => Some(Ok(Frame::trailers(trailers))),
+                        _ => None,
+                    })
+                }
+            };
+
             return match ready!(this.inner.as_mut().poll_frame(cx)) {
                 Some(Ok(frame)) => {
                     let data = match frame.into_data() {
                         Ok(data) => data,
-                        Err(mut frame) => {
-                            let debug_info = frame.trailers_mut();
-                            error!("want to data from body, but receive trailer header: {:?}", debug_info);
-                            return Poll::Ready(Some(Err(SendError::invalid_body(format!(
-                                "invalid body frame : {:?}",
-                                debug_info
-                            ))
-                            .into())));
+                        Err(frame) => {
+                            let trailers = match frame.into_trailers() {
+                                Ok(trailers) => trailers,
+                                Err(frame) => {
+                                    error!("want data or trailers from body, but receive: {:?}", frame);
+                                    return Poll::Ready(Some(Err(SendError::invalid_body(format!(
+                                        "invalid body frame : {:?}",
+                                        frame
+                                    ))
+                                    .into())));
+                                }
+                            };
+
+                            // the trailers end the body, they follow the end of the encoded data
+                            return match this.state.finish(Some(trailers)) {
+                                Ok(bytes) if bytes.is_empty() => continue,
+                                Ok(bytes) => Poll::Ready(Some(Ok(Frame::data(bytes)))),
+                                Err(e) => Poll::Ready(Some(Err(SendError::from(e).into()))),
+                            };
                         }
                     };
 
-                    match this.encoder.as_mut().unwrap().write(data.chunk()) {
-                        Ok(_) => (),
-                        Err(e) => {
-                            return Poll::Ready(Some(Err(SendError::from(e).into())));
-                        }
+                    if let Err(e) = encoder.write(data.chunk()) {
+                        // the encoder can't be finished after a failed write, so the body ends here
+                        *this.state = EncoderState::Done;
+                        return Poll::Ready(Some(Err(SendError::from(e).into())));
                     }
-                    // use wrap here is safe, because we only take it when receive None
-                    let bytes = this.encoder.as_mut().unwrap().take();
+                    let bytes = encoder.take();
                     if bytes.is_empty() {
                         continue;
                     }
                     Poll::Ready(Some(Ok(Frame::data(bytes))))
                 }
-                Some(Err(e)) => Poll::Ready(Some(Err(SendError::invalid_body(e.to_string()).into()))),
-                None => {
-                    if this.state.is_some() {
-                        // will only run below  code once
-                        this.state.take();
-
-                        // unwrap here is safe, because we only take once
-                        let bytes = match this.encoder.take().unwrap().finish() {
-                            Ok(bytes) => bytes,
-                            Err(e) => {
-                                return Poll::Ready(Some(Err(SendError::from(e).into())));
-                            }
-                        };
-                        if !bytes.is_empty() {
-                            Poll::Ready(Some(Ok(Frame::data(bytes))))
-                        } else {
-                            Poll::Ready(None)
-                        }
-                    } else {
-                        Poll::Ready(None)
-                    }
-                }
+                // passed through as is, to keep its source chain
+                Some(Err(e)) => Poll::Ready(Some(Err(e.into()))),
+                None => match this.state.finish(None) {
+                    Ok(bytes) if bytes.is_empty() => Poll::Ready(None),
+                    Ok(bytes) => Poll::Ready(Some(Ok(Frame::data(bytes)))),
+                    Err(e) => Poll::Ready(Some(Err(SendError::from(e).into()))),
+                },
             };
         }
     }
 
     fn is_end_stream(&self) -> bool {
-        self.inner.is_end_stream()
+        // the encoder may still hold data, or the trailers wait for it to be sent
+        matches!(self.state, EncoderState::Done)
     }
 }
 
 /// A request handler that encodes the response body.
 pub struct EncodeRequestHandler<H: RequestHandler> {
     handler: H,
+    config: Arc<CompressionConfig>,
+    buffer_limit: Option<usize>,
 }
 
 /// A wrapper that creates `EncodeRequestHandler`.
-pub struct EncodeWrapper;
+///
+/// Use the [`EncodeWrapper`](const@EncodeWrapper) constant, or [`EncodeWrapper::default`], for the
+/// default compression levels, or [`EncodeWrapper::with_config`] to tune them.
+///
+/// Responses with a `Content-Encoding` header, an [`X-No-Encode: true`](X_NO_ENCODE) header, or
+/// `Cache-Control: no-transform`, are left as is. So are the responses framing their body with their own
+/// `Transfer-Encoding`, the `206 Partial Content` ones, and the responses without a body, see `encode` for
+/// the full list.
+///
+/// Encoded responses are sent chunked, unless a buffer limit is set with
+/// [`with_buffer_limit`](EncodeWrapper::with_buffer_limit).
+#[derive(Default)]
+pub struct EncodeWrapper {
+    /// `None` for the default config, which allows the `EncodeWrapper` constant
+    config: Option<Arc<CompressionConfig>>,
+    buffer_limit: Option<usize>,
+}
+
+/// An [`EncodeWrapper`](struct@EncodeWrapper) with the default compression levels, so it can be used
+/// as a value: `.wrap(EncodeWrapper)`.
+#[allow(non_upper_case_globals)]
+pub const EncodeWrapper: EncodeWrapper = EncodeWrapper { config: None, buffer_limit: None };
+
+impl EncodeWrapper {
+    /// Creates an `EncodeWrapper` using the given compression config.
+    pub fn with_config(config: CompressionConfig) -> Self {
+        Self { config: Some(Arc::new(config)), buffer_limit: None }
+    }
+
+    /// Buffers the encoded bodies of at most `limit` bytes, so they are sent with a `Content-Length`.
+    ///
+    /// It helps caching proxies and saves the chunked framing of moderate-sized responses. Only the
+    /// bodies of a known size are buffered, larger or streaming ones are still sent chunked.
+    pub fn with_buffer_limit(mut self, limit: usize) -> Self {
+        self.buffer_limit = Some(limit);
+        self
+    }
+
+    /// Returns the compression config used by this wrapper.
+    pub fn config(&self) -> &CompressionConfig {
+        self.shared_config()
+    }
+
+    fn shared_config(&self) -> &Arc<CompressionConfig> {
+        static DEFAULT_CONFIG: OnceLock<Arc<CompressionConfig>> = OnceLock::new();
+        self.config.as_ref().unwrap_or_else(|| DEFAULT_CONFIG.get_or_init(Default::default))
+    }
+}
 
 impl<H: RequestHandler> Wrapper<H> for EncodeWrapper {
     type Out = EncodeRequestHandler<H>;
 
     fn wrap(&self, handler: H) -> Self::Out {
-        EncodeRequestHandler { handler }
+        EncodeRequestHandler { handler, config: Arc::clone(self.shared_config()), buffer_limit: self.buffer_limit }
+    }
+
+    fn priority(&self) -> i32 {
+        priority::ENCODING
     }
 }
 
@@ -275,24 +440,91 @@ impl<H: RequestHandler> RequestHandler for EncodeRequestHandler<H> {
         req_body: OptionReqBody,
     ) -> Response<ResponseBody> {
         let mut resp = self.handler.invoke(req, req_body).await;
-        encode(req, &mut resp);
+        let sized = resp.body().size_hint().upper().is_some();
+        let encoded = resp.headers().contains_key(http::header::CONTENT_ENCODING);
+
+        encode(req, &mut resp, &self.config);
+
+        let encoded = !encoded && resp.headers().contains_key(http::header::CONTENT_ENCODING);
+        if let (true, true, Some(limit)) = (sized, encoded, self.buffer_limit) {
+            buffer_encoded(&mut resp, limit).await;
+        }
         resp
     }
 }
 
+/// Reads the encoded body if it has at most `limit` bytes, and sets its `Content-Length`.
+async fn buffer_encoded(resp: &mut Response<ResponseBody>, limit: usize) {
+    let body = resp.body_mut().take();
+    match buffer(body, limit).await {
+        Ok(bytes) => {
+            resp.headers_mut().insert(http::header::CONTENT_LENGTH, HeaderValue::from(bytes.len()));
+            *resp.body_mut() = ResponseBody::once(bytes);
+        }
+        Err(body) => *resp.body_mut() = body,
+    }
+}
+
+/// Selects the encoding based on the `Accept-Encoding` header.
+///
+/// The client's quality values decide first, [`SUPPORTED_ENCODINGS`] order only breaks ties.
+fn negotiate(accept_encodings: &str) -> Option<&'static str> {
+    let accept_encoding: AcceptEncoding = match accept_encodings.parse() {
+        Ok(accept_encoding) => accept_encoding,
+        Err(infallible) => match infallible {},
+    };
+    accept_encoding.best_match(SUPPORTED_ENCODINGS)
+}
+
 /// Encodes the response body based on the `Accept-Encoding` header.
-fn encode(req: &RequestContext, resp: &mut Response<ResponseBody>) {
+///
+/// The response is left as is when:
+/// - it opts out with [`X_NO_ENCODE`]
+/// - it has no body to encode: `204`, `304`, or `101` whose body belongs to the next protocol
+/// - it's a `206`, its ranges are offsets in the unencoded body
+/// - it already has a `Content-Encoding`, or sets its own `Transfer-Encoding`
+/// - the client accepts no supported encoding, or sent no `Accept-Encoding` at all
+/// - its content type is compressed already, see [`CompressionConfig::should_skip`]
+/// - it has `Cache-Control: no-transform`, RFC 9111 Section 5.2.2.6
+/// - its body is empty, or smaller than [`CompressionConfig::min_size`]
+///
+/// A `304` gets `Vary: Accept-Encoding` when the other rules, short of the body size, would encode
+/// the full response, so caches see the same `Vary` for both.
+///
+/// `Cache-Control: no-store` and `Pragma: no-cache` only restrict how the response is cached, such
+/// responses are still encoded.
+fn encode(req: &RequestContext, resp: &mut Response<ResponseBody>, config: &CompressionConfig) {
+    // the opt-out is meant for this wrapper only, so it's not sent to the client
+    let no_encode = resp.headers_mut().remove(X_NO_ENCODE);
+    if no_encode.is_some_and(|value| value.as_bytes().eq_ignore_ascii_case(b"true")) {
+        return;
+    }
+
+    // no content, RFC 9110 Section 15.3.5, and the data after a 101 isn't HTTP, Section 15.2.2
     let status_code = resp.status();
     if status_code == StatusCode::NO_CONTENT || status_code == StatusCode::SWITCHING_PROTOCOLS {
         return;
     }
 
-    // response has already encoded
-    if req.headers().contains_key(http::header::CONTENT_ENCODING) {
+    // the Content-Range of a 206 refers to the representation the handler selected, an encoded
+    // slice of it is neither that range nor a range of the encoded representation, RFC 9110 Section 14.4
+    if status_code == StatusCode::PARTIAL_CONTENT {
         return;
     }
 
-    // request doesn't have any accept encodings
+    // response has already encoded, another coding would have to be listed too, RFC 9110 Section 8.4
+    if resp.headers().contains_key(http::header::CONTENT_ENCODING) {
+        return;
+    }
+
+    // the handler manages the framing of its body, it may be transfer coded already and can't be
+    // told apart from raw data, RFC 9112 Section 6.1
+    if resp.headers().contains_key(http::header::TRANSFER_ENCODING) {
+        return;
+    }
+
+    // request doesn't have any accept encodings, RFC 9110 Section 12.5.3 allows any coding then, but
+    // the clients leaving it out usually can't decode any
     let possible_encodings = req.headers().get(http::header::ACCEPT_ENCODING);
     if possible_encodings.is_none() {
         return;
@@ -306,25 +538,53 @@ fn encode(req: &RequestContext, resp: &mut Response<ResponseBody>) {
         }
     };
 
-    let encoder = match Encoder::select(accept_encodings) {
+    let encoding = match negotiate(accept_encodings) {
+        Some(encoding) => encoding,
+        None => {
+            return;
+        }
+    };
+
+    // the origin asked intermediaries not to change the representation, and this wrapper acts like one
+    if has_no_transform(resp.headers()) {
+        return;
+    }
+
+    // compressing already compressed content is a waste of CPU
+    let content_type = resp.headers().get(http::header::CONTENT_TYPE).and_then(|value| value.to_str().ok());
+    if content_type.is_some_and(|content_type| config.should_skip(content_type)) {
+        return;
+    }
+
+    // a 304 has no body, but caches need the same `Vary` as the full response, RFC 9110 Section 15.4.5
+    if status_code == StatusCode::NOT_MODIFIED {
+        add_vary_accept_encoding(resp);
+        return;
+    }
+
+    let capacity = writer_capacity(resp.body().size_hint().upper(), content_type);
+    let encoder = match Encoder::new(encoding, config, content_type, capacity) {
         Some(encoder) => encoder,
         None => {
             return;
         }
     };
 
+    // clients need to know which dictionary to decode the body with
+    let dictionary_id = match encoder {
+        Encoder::Zstd(_) => config.zstd_dictionary_for(content_type).and_then(|dictionary| dictionary.id()),
+        _ => None,
+    };
+
     let body = resp.body_mut();
 
     if body.is_empty() {
         return;
     }
 
-    match body.size_hint().upper() {
-        Some(upper) if upper <= 1024 => {
-            // less then 1k, we needn't compress
-            return;
-        }
-        _ => (),
+    // a body of unknown size is always compressed
+    if body.size_hint().upper().unwrap_or(u64::MAX) < config.min_size {
+        return;
     }
 
     let encoder_name = encoder.name();
@@ -333,4 +593,673 @@ fn encode(req: &RequestContext, resp: &mut Response<ResponseBody>) {
 
     resp.headers_mut().remove(http::header::CONTENT_LENGTH);
     resp.headers_mut().append(http::header::CONTENT_ENCODING, encoder_name.parse().unwrap());
+    if let Some(id) = dictionary_id {
+        resp.headers_mut().insert(ZSTD_DICT_ID, HeaderValue::from(id));
+    }
+    add_vary_accept_encoding(resp);
+}
+
+/// Returns true if the `Cache-Control` header has the `no-transform` directive.
+fn has_no_transform(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(http::header::CACHE_CONTROL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|directive| directive.trim().eq_ignore_ascii_case("no-transform"))
+}
+
+/// The largest buffer allocated up front for the encoded data, larger bodies grow it as needed.
+const MAX_WRITER_CAPACITY: usize = 1024 * 1024;
+
+/// Estimates the size of the encoded data of a body of at most `upper` bytes, `None` if it's unknown.
+///
+/// Text usually compresses to about a third of its size, other content is assumed to be compressed
+/// already.
+fn writer_capacity(upper: Option<u64>, content_type: Option<&str>) -> Option<usize> {
+    let ratio = match content_type {
+        Some(content_type) if is_text(content_type) => 3,
+        _ => 1,
+    };
+    upper.map(|upper| (upper / ratio).min(MAX_WRITER_CAPACITY as u64) as usize)
+}
+
+/// Returns true for the textual content types, which compress well.
+fn is_text(content_type: &str) -> bool {
+    let mime = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+    // `+json` and `+xml` structured syntax suffixes included
+    mime.starts_with("text/") || ["json", "javascript", "xml"].iter().any(|suffix| mime.ends_with(suffix))
+}
+
+fn writer(capacity: Option<usize>) -> Writer {
+    capacity.map_or_else(Writer::new, Writer::with_capacity)
+}
+
+/// Adds `Accept-Encoding` to the `Vary` header, unless it's already listed or `Vary` is `*`.
+fn add_vary_accept_encoding(resp: &mut Response<ResponseBody>) {
+    let headers = resp.headers_mut();
+    let mut fields = vec![];
+    for value in headers.get_all(http::header::VARY) {
+        match value.to_str() {
+            Ok(value) => fields.extend(value.split(',').map(str::trim).filter(|field| !field.is_empty())),
+            // keep a value we can't parse as is
+            Err(_) => return,
+        }
+    }
+
+    if fields.iter().any(|field| *field == "*" || field.eq_ignore_ascii_case("accept-encoding")) {
+        return;
+    }
+
+    fields.push("Accept-Encoding");
+    // made of valid header values joined with commas
+    let vary = HeaderValue::from_str(&fields.join(", ")).unwrap();
+    headers.insert(http::header::VARY, vary);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sse::SseEvent;
+    use crate::wrapper::encoding::{CompressionLevel, ZstdDictionary};
+    use crate::ResponseBuilder;
+    use crate::{handler_fn, PathParams};
+    use http::Request;
+    use http_body_util::BodyExt;
+    use micro_http::protocol::RequestHeader;
+    use std::io::Read;
+
+    fn request_header(accept_encoding: &str) -> RequestHeader {
+        Request::builder()
+            .header(http::header::ACCEPT_ENCODING, accept_encoding)
+            .body(())
+            .unwrap()
+            .into_parts()
+            .0
+            .into()
+    }
+
+    fn text_response(len: usize) -> Response<ResponseBody> {
+        Response::new(ResponseBody::from("hello world ".repeat(len / 12 + 1)))
+    }
+
+    async fn encoded_bytes(resp: Response<ResponseBody>) -> Bytes {
+        resp.into_body().collect().await.unwrap().to_bytes()
+    }
+
+    #[tokio::test]
+    async fn test_encode_with_negotiated_encoding() {
+        let header = request_header("br;q=0.5, gzip");
+        let req = RequestContext::new(&header, PathParams::empty());
+        let mut resp = text_response(4096);
+
+        encode(&req, &mut resp, &CompressionConfig::default());
+
+        assert_eq!(resp.headers().get(http::header::CONTENT_ENCODING).unwrap(), "gzip");
+        let bytes = encoded_bytes(resp).await;
+        let mut decoded = String::new();
+        flate2::read::GzDecoder::new(&bytes[..]).read_to_string(&mut decoded).unwrap();
+        assert!(decoded.starts_with("hello world hello world"));
+    }
+
+    #[tokio::test]
+    async fn test_encode_uses_configured_level() {
+        let header = request_header("gzip");
+        let req = RequestContext::new(&header, PathParams::empty());
+
+        // the gzip header's XFL byte is 4 for the fastest level and 2 for the best level
+        let mut resp = text_response(4096);
+        encode(&req, &mut resp, &CompressionConfig::fast());
+        assert_eq!(encoded_bytes(resp).await[8], 4);
+
+        let mut resp = text_response(4096);
+        encode(&req, &mut resp, &CompressionConfig::best());
+        assert_eq!(encoded_bytes(resp).await[8], 2);
+    }
+
+    #[tokio::test]
+    async fn test_encode_uses_content_type_level() {
+        let header = request_header("gzip");
+        let req = RequestContext::new(&header, PathParams::empty());
+        let mut config = CompressionConfig::fast();
+        config.compression_levels.insert("text/".to_string(), CompressionLevel::best());
+
+        let mut resp = text_response(4096);
+        resp.headers_mut().insert(http::header::CONTENT_TYPE, HeaderValue::from_static("text/html; charset=utf-8"));
+        encode(&req, &mut resp, &config);
+        assert_eq!(encoded_bytes(resp).await[8], 2);
+
+        let mut resp = text_response(4096);
+        resp.headers_mut().insert(http::header::CONTENT_TYPE, HeaderValue::from_static("application/octet-stream"));
+        encode(&req, &mut resp, &config);
+        assert_eq!(encoded_bytes(resp).await[8], 4);
+    }
+
+    /// JSON responses of an API, similar enough for a dictionary to help.
+    fn json_samples() -> Vec<Bytes> {
+        (0..1000)
+            .map(|i| {
+                let body = format!(
+                    r#"{{"id":{i},"name":"user-{}","email":"user{i}@example.com","active":{},"roles":["reader"]}}"#,
+                    i * 7 % 13,
+                    i % 2 == 0
+                );
+                Bytes::from(body)
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_encode_with_zstd_dictionary() {
+        let header = request_header("zstd");
+        let req = RequestContext::new(&header, PathParams::empty());
+        let samples = json_samples();
+        let dictionary = ZstdDictionary::new(CompressionConfig::train_dict_from_samples(&samples, 4 * 1024).unwrap());
+        let mut config = CompressionConfig::default();
+        config.zstd_dictionaries.insert("application/json".to_string(), dictionary.clone());
+
+        let json = samples[..20].iter().map(|sample| std::str::from_utf8(sample).unwrap()).collect::<Vec<_>>();
+        let json = format!("[{}]", json.join(","));
+        let mut resp = Response::new(ResponseBody::from(json.clone()));
+        resp.headers_mut().insert(http::header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        encode(&req, &mut resp, &config);
+
+        assert_eq!(resp.headers().get(http::header::CONTENT_ENCODING).unwrap(), "zstd");
+        let id = resp.headers().get(ZSTD_DICT_ID).unwrap().to_str().unwrap().parse::<u32>().unwrap();
+        assert_eq!(Some(id), dictionary.id());
+
+        let bytes = encoded_bytes(resp).await;
+        let mut decoder = zstd::stream::read::Decoder::with_dictionary(&bytes[..], dictionary.data()).unwrap();
+        let mut decoded = String::new();
+        decoder.read_to_string(&mut decoded).unwrap();
+        assert_eq!(decoded, json);
+
+        // other content types are compressed without it
+        let mut resp = text_response(4096);
+        encode(&req, &mut resp, &config);
+        assert!(resp.headers().get(ZSTD_DICT_ID).is_none());
+        zstd::decode_all(&encoded_bytes(resp).await[..]).unwrap();
+    }
+
+    #[test]
+    fn test_br_round_trip() {
+        // 1MB of text made of pseudo-random words, which doesn't compress to almost nothing
+        let words = ["hello", "world", "micro", "web", "brotli", "stream", "finish", "flush"];
+        let mut seed = 42u32;
+        let mut text = String::with_capacity(1024 * 1024);
+        while text.len() < 1024 * 1024 {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            text.push_str(words[(seed >> 16) as usize % words.len()]);
+            text.push(if seed % 7 == 1 { '\n' } else { ' ' });
+        }
+
+        let mut encoder = Encoder::br(3, 22, None);
+        let mut encoded = vec![];
+        for chunk in text.as_bytes().chunks(16 * 1024) {
+            encoder.write(chunk).unwrap();
+            encoded.extend_from_slice(&encoder.take());
+        }
+        encoded.extend_from_slice(&encoder.finish().unwrap());
+
+        let mut decoded = vec![];
+        brotli::Decompressor::new(&encoded[..], 4096).read_to_end(&mut decoded).unwrap();
+        assert_eq!(decoded, text.as_bytes());
+
+        // the stream is terminated, a truncated one is not
+        let mut decoded = vec![];
+        assert!(brotli::Decompressor::new(&encoded[..encoded.len() - 1], 4096).read_to_end(&mut decoded).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_buffer_limit() {
+        async fn text() -> String {
+            "hello world ".repeat(400)
+        }
+
+        async fn streaming() -> Response<ResponseBody> {
+            let chunks: Vec<Result<Frame<Bytes>, HttpError>> = vec![Ok(Frame::data(Bytes::from("hello ".repeat(400))))];
+            Response::new(ResponseBody::stream(http_body_util::StreamBody::new(futures::stream::iter(chunks))))
+        }
+
+        async fn invoke<H: RequestHandler>(handler: &H) -> Response<ResponseBody> {
+            let header = request_header("gzip");
+            let mut req = RequestContext::new(&header, PathParams::empty());
+            handler.invoke(&mut req, OptionReqBody::from(crate::RequestBody::empty())).await
+        }
+
+        let resp = invoke(&EncodeWrapper::default().with_buffer_limit(1024).wrap(handler_fn(text))).await;
+        let length = resp.headers().get(http::header::CONTENT_LENGTH).unwrap().to_str().unwrap();
+        let length: usize = length.parse().unwrap();
+        assert_eq!(resp.body().size_hint().exact(), Some(length as u64));
+        let bytes = encoded_bytes(resp).await;
+        assert_eq!(bytes.len(), length);
+        let mut decoded = String::new();
+        flate2::read::GzDecoder::new(&bytes[..]).read_to_string(&mut decoded).unwrap();
+        assert_eq!(decoded, text().await);
+
+        // larger than the limit
+        let resp = invoke(&EncodeWrapper::default().with_buffer_limit(8).wrap(handler_fn(text))).await;
+        assert!(resp.headers().get(http::header::CONTENT_LENGTH).is_none());
+        assert_eq!(resp.body().size_hint().exact(), None);
+        let mut decoded = String::new();
+        flate2::read::GzDecoder::new(&encoded_bytes(resp).await[..]).read_to_string(&mut decoded).unwrap();
+        assert_eq!(decoded, text().await);
+
+        // streams of unknown size are never buffered
+        let resp = invoke(&EncodeWrapper::default().with_buffer_limit(1024).wrap(handler_fn(streaming))).await;
+        assert_eq!(resp.headers().get(http::header::CONTENT_ENCODING).unwrap(), "gzip");
+        assert!(resp.headers().get(http::header::CONTENT_LENGTH).is_none());
+
+        // the constant uses the default config, without a buffer limit
+        assert_eq!(EncodeWrapper.config(), &CompressionConfig::default());
+        let resp = invoke(&EncodeWrapper.wrap(handler_fn(text))).await;
+        assert_eq!(resp.headers().get(http::header::CONTENT_ENCODING).unwrap(), "gzip");
+        assert!(resp.headers().get(http::header::CONTENT_LENGTH).is_none());
+    }
+
+    /// Polls all the frames of `body`, returns the data and the error that ended it.
+    async fn poll_all<B>(mut body: EncodedBody<B>) -> (Vec<Bytes>, Option<HttpError>)
+    where
+        B: Body + Unpin,
+        B::Data: Buf + Debug,
+        B::Error: Into<HttpError>,
+    {
+        let mut data = vec![];
+        let mut error = None;
+        while let Some(frame) = body.frame().await {
+            match frame {
+                Ok(frame) => data.push(frame.into_data().unwrap()),
+                Err(e) => {
+                    assert!(error.is_none(), "the body goes on after an error");
+                    error = Some(e);
+                }
+            }
+        }
+        (data, error)
+    }
+
+    #[tokio::test]
+    async fn test_trailers() {
+        let mut trailers = HeaderMap::new();
+        trailers.insert("grpc-status", HeaderValue::from_static("0"));
+        let frames: Vec<Result<Frame<Bytes>, HttpError>> = vec![
+            Ok(Frame::data(Bytes::from("hello world ".repeat(100)))),
+            Ok(Frame::data(Bytes::from("bye"))),
+            Ok(Frame::trailers(trailers.clone())),
+        ];
+        let inner = http_body_util::StreamBody::new(futures::stream::iter(frames));
+        let mut body = EncodedBody::new(inner, Encoder::gzip(6, None));
+
+        let mut encoded = vec![];
+        let mut received = None;
+        while let Some(frame) = body.frame().await {
+            let frame = frame.unwrap();
+            assert!(received.is_none(), "a frame follows the trailers");
+            match frame.into_data() {
+                Ok(data) => encoded.extend_from_slice(&data),
+                Err(frame) => received = Some(frame.into_trailers().unwrap()),
+            }
+        }
+        assert_eq!(received, Some(trailers));
+
+        let mut decoded = String::new();
+        flate2::read::GzDecoder::new(&encoded[..]).read_to_string(&mut decoded).unwrap();
+        assert_eq!(decoded, "hello world ".repeat(100) + "bye");
+    }
+
+    #[tokio::test]
+    async fn test_poll_after_end() {
+        let mut trailers = HeaderMap::new();
+        trailers.insert("grpc-status", HeaderValue::from_static("0"));
+        let frames: Vec<Result<Frame<Bytes>, HttpError>> =
+            vec![Ok(Frame::data(Bytes::from("hello"))), Ok(Frame::trailers(trailers))];
+        let inner = http_body_util::StreamBody::new(futures::stream::iter(frames));
+        let mut body = EncodedBody::new(inner, Encoder::gzip(6, None));
+
+        let mut frames = vec![];
+        while let Some(frame) = body.frame().await {
+            assert!(!body.is_end_stream() || frame.as_ref().unwrap().is_trailers());
+            frames.push(frame.unwrap());
+        }
+        assert!(frames.last().unwrap().is_trailers());
+        assert!(frames[..frames.len() - 1].iter().all(Frame::is_data));
+        for _ in 0..3 {
+            assert!(body.frame().await.is_none());
+        }
+
+        let mut body = EncodedBody::new(ResponseBody::from("hello"), Encoder::gzip(6, None));
+        while body.frame().await.is_some() {}
+        assert!(body.is_end_stream());
+        assert!(body.frame().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_inner_error_source() {
+        let frames: Vec<Result<Frame<Bytes>, HttpError>> = vec![
+            Ok(Frame::data(Bytes::from("hello world ".repeat(100)))),
+            Err(SendError::io(io::Error::new(io::ErrorKind::BrokenPipe, "upstream gone")).into()),
+        ];
+        let inner = http_body_util::StreamBody::new(futures::stream::iter(frames));
+        let (_, error) = poll_all(EncodedBody::new(inner, Encoder::gzip(6, None))).await;
+
+        let HttpError::ResponseError { source } = error.unwrap() else {
+            panic!("expect a response error");
+        };
+        assert_eq!(source.into_io_error().unwrap().kind(), io::ErrorKind::BrokenPipe);
+    }
+
+    #[tokio::test]
+    async fn test_zstd_finish_error() {
+        // the frame header fits, but the compressed block and the checksum don't
+        let encoder = Encoder::Zstd(ZstdEncoder::new(Writer::failing_after(8), 3).unwrap());
+        let body = EncodedBody::new(ResponseBody::from("hello world ".repeat(100)), encoder);
+
+        let (data, error) = poll_all(body).await;
+        assert!(data.iter().map(Bytes::len).sum::<usize>() <= 8);
+        assert!(error.unwrap().to_string().contains("writer failed"));
+    }
+
+    #[tokio::test]
+    async fn test_zstd_write_error() {
+        // a block is flushed by the writes once zstd has buffered 128KB
+        let encoder = Encoder::Zstd(ZstdEncoder::new(Writer::failing_after(0), 3).unwrap());
+        let chunks: Vec<Result<Frame<Bytes>, HttpError>> =
+            (0..64).map(|i| Ok(Frame::data(Bytes::from(format!("{i:04} ").repeat(1024))))).collect();
+        let body = EncodedBody::new(http_body_util::StreamBody::new(futures::stream::iter(chunks)), encoder);
+
+        let (data, error) = poll_all(body).await;
+        assert!(data.is_empty());
+        assert!(error.is_some());
+    }
+
+    #[test]
+    fn test_flate_finish_error() {
+        // the gzip header is 10 bytes, and the zlib one is 2 bytes
+        let encoders = [
+            Encoder::Gzip(GzEncoder::new(Writer::failing_after(10), Compression::default())),
+            Encoder::Deflate(ZlibEncoder::new(Writer::failing_after(2), Compression::default())),
+        ];
+
+        for mut encoder in encoders {
+            let name = encoder.name();
+            encoder.write(b"hello world").unwrap();
+            let err = encoder.finish().unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::BrokenPipe, "{name}");
+        }
+
+        let mut encoder = Encoder::Gzip(GzEncoder::new(Writer::failing_after(4), Compression::default()));
+        assert!(encoder.write(b"hello world").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_encode_skips_small_body() {
+        let header = request_header("gzip");
+        let req = RequestContext::new(&header, PathParams::empty());
+        let mut resp = text_response(100);
+
+        encode(&req, &mut resp, &CompressionConfig::default());
+
+        assert!(resp.headers().get(http::header::CONTENT_ENCODING).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_encode_unknown_size_stream() {
+        let header = request_header("gzip");
+        let req = RequestContext::new(&header, PathParams::empty());
+
+        // nothing is known about the stream until it's polled, so it's encoded
+        let chunks: Vec<Result<Frame<Bytes>, HttpError>> =
+            vec![Ok(Frame::data(Bytes::from_static(b"hello "))), Ok(Frame::data(Bytes::from_static(b"world")))];
+        let body = http_body_util::StreamBody::new(futures::stream::iter(chunks));
+        let mut resp = Response::new(ResponseBody::stream(body));
+        assert_eq!(resp.body().size_hint().upper(), None);
+        encode(&req, &mut resp, &CompressionConfig::default());
+        assert_eq!(resp.headers().get(http::header::CONTENT_ENCODING).unwrap(), "gzip");
+
+        let bytes = encoded_bytes(resp).await;
+        let mut decoded = String::new();
+        flate2::read::GzDecoder::new(&bytes[..]).read_to_string(&mut decoded).unwrap();
+        assert_eq!(decoded, "hello world");
+
+        let mut resp = Response::new(ResponseBody::empty());
+        encode(&req, &mut resp, &CompressionConfig { min_size: 0, ..CompressionConfig::default() });
+        assert!(resp.headers().get(http::header::CONTENT_ENCODING).is_none());
+    }
+
+    #[test]
+    fn test_writer_capacity() {
+        assert_eq!(writer_capacity(Some(3000), Some("text/html; charset=utf-8")), Some(1000));
+        assert_eq!(writer_capacity(Some(3000), Some("application/problem+json")), Some(1000));
+        assert_eq!(writer_capacity(Some(3000), Some("Application/JavaScript")), Some(1000));
+        assert_eq!(writer_capacity(Some(3000), Some("image/png")), Some(3000));
+        assert_eq!(writer_capacity(Some(3000), None), Some(3000));
+        assert_eq!(writer_capacity(Some(u64::MAX), Some("text/plain")), Some(MAX_WRITER_CAPACITY));
+        assert_eq!(writer_capacity(None, Some("text/plain")), None);
+    }
+
+    #[tokio::test]
+    async fn test_encode_min_size() {
+        let header = request_header("gzip");
+        let req = RequestContext::new(&header, PathParams::empty());
+
+        let config = CompressionConfig { min_size: 100, ..CompressionConfig::default() };
+        let mut resp = text_response(100);
+        encode(&req, &mut resp, &config);
+        assert_eq!(resp.headers().get(http::header::CONTENT_ENCODING).unwrap(), "gzip");
+
+        let config = CompressionConfig { min_size: u64::MAX, ..CompressionConfig::default() };
+        let mut resp = text_response(1024 * 1024);
+        encode(&req, &mut resp, &config);
+        assert!(resp.headers().get(http::header::CONTENT_ENCODING).is_none());
+
+        // the size of a stream is unknown
+        let chunks: Vec<Result<Frame<Bytes>, HttpError>> = vec![Ok(Frame::data(Bytes::from_static(b"hello")))];
+        let body = http_body_util::StreamBody::new(futures::stream::iter(chunks));
+        let mut resp = Response::new(ResponseBody::stream(body));
+        encode(&req, &mut resp, &CompressionConfig::default());
+        assert_eq!(resp.headers().get(http::header::CONTENT_ENCODING).unwrap(), "gzip");
+    }
+
+    #[tokio::test]
+    async fn test_encode_sized_stream() {
+        let header = request_header("gzip");
+        let req = RequestContext::new(&header, PathParams::empty());
+        let text = Bytes::from("hello world ".repeat(4096 / 12 + 1));
+        let len = text.len() as u64;
+        let body = http_body_util::Full::new(text).map_err(|never| match never {});
+        let mut resp = Response::new(ResponseBody::stream_with_size(body, len));
+        resp.headers_mut().insert(http::header::CONTENT_LENGTH, len.into());
+
+        encode(&req, &mut resp, &CompressionConfig::default());
+
+        // the encoded size isn't known, so the response is sent chunked
+        assert_eq!(resp.headers().get(http::header::CONTENT_ENCODING).unwrap(), "gzip");
+        assert!(resp.headers().get(http::header::CONTENT_LENGTH).is_none());
+        assert_eq!(resp.body().size_hint().exact(), None);
+    }
+
+    #[tokio::test]
+    async fn test_encode_skips_compressed_content_type() {
+        let header = request_header("gzip");
+        let req = RequestContext::new(&header, PathParams::empty());
+        let mut resp = text_response(4096);
+        resp.headers_mut().insert(http::header::CONTENT_TYPE, "image/jpeg".parse().unwrap());
+        let expected = "hello world ".repeat(4096 / 12 + 1);
+
+        encode(&req, &mut resp, &CompressionConfig::default());
+
+        assert!(resp.headers().get(http::header::CONTENT_ENCODING).is_none());
+        assert_eq!(encoded_bytes(resp).await, Bytes::from(expected));
+    }
+
+    #[tokio::test]
+    async fn test_encode_adds_vary() {
+        let header = request_header("gzip");
+        let req = RequestContext::new(&header, PathParams::empty());
+
+        let mut resp = text_response(4096);
+        encode(&req, &mut resp, &CompressionConfig::default());
+        assert_eq!(resp.headers().get(http::header::CONTENT_ENCODING).unwrap(), "gzip");
+        assert_eq!(resp.headers().get(http::header::VARY).unwrap(), "Accept-Encoding");
+
+        let mut resp = text_response(4096);
+        resp.headers_mut().insert(http::header::VARY, "Origin".parse().unwrap());
+        encode(&req, &mut resp, &CompressionConfig::default());
+        assert_eq!(resp.headers().get(http::header::VARY).unwrap(), "Origin, Accept-Encoding");
+
+        let mut resp = text_response(4096);
+        resp.headers_mut().insert(http::header::VARY, "origin, accept-encoding".parse().unwrap());
+        encode(&req, &mut resp, &CompressionConfig::default());
+        assert_eq!(resp.headers().get_all(http::header::VARY).iter().count(), 1);
+        assert_eq!(resp.headers().get(http::header::VARY).unwrap(), "origin, accept-encoding");
+    }
+
+    #[tokio::test]
+    async fn test_not_modified_keeps_vary() {
+        let header = request_header("gzip");
+        let req = RequestContext::new(&header, PathParams::empty());
+
+        let mut resp = Response::new(ResponseBody::empty());
+        *resp.status_mut() = StatusCode::NOT_MODIFIED;
+        resp.headers_mut().insert(http::header::VARY, "Origin".parse().unwrap());
+        encode(&req, &mut resp, &CompressionConfig::default());
+        assert!(resp.headers().get(http::header::CONTENT_ENCODING).is_none());
+        assert_eq!(resp.headers().get(http::header::VARY).unwrap(), "Origin, Accept-Encoding");
+    }
+
+    #[tokio::test]
+    async fn test_not_modified_vary_follows_skip_rules() {
+        let header = request_header("gzip");
+        let req = RequestContext::new(&header, PathParams::empty());
+
+        let not_modified = |name: &str, value: &str| {
+            let mut resp = Response::new(ResponseBody::empty());
+            *resp.status_mut() = StatusCode::NOT_MODIFIED;
+            resp.headers_mut().insert(http::HeaderName::from_bytes(name.as_bytes()).unwrap(), value.parse().unwrap());
+            resp
+        };
+
+        // the full responses of these wouldn't be encoded, so they don't vary on Accept-Encoding
+        for (name, value) in [("content-type", "image/png"), ("cache-control", "no-transform"), (X_NO_ENCODE, "true")] {
+            let mut resp = not_modified(name, value);
+            encode(&req, &mut resp, &CompressionConfig::default());
+            assert!(resp.headers().get(http::header::VARY).is_none(), "{name}: {value}");
+        }
+
+        let mut resp = not_modified("content-type", "text/html");
+        encode(&req, &mut resp, &CompressionConfig::default());
+        assert_eq!(resp.headers().get(http::header::VARY).unwrap(), "Accept-Encoding");
+
+        let header = request_header("identity");
+        let req = RequestContext::new(&header, PathParams::empty());
+        let mut resp = not_modified("content-type", "text/html");
+        encode(&req, &mut resp, &CompressionConfig::default());
+        assert!(resp.headers().get(http::header::VARY).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_encode_skips_encoded_response() {
+        let header = request_header("gzip");
+        let req = RequestContext::new(&header, PathParams::empty());
+        let mut resp = text_response(4096);
+        resp.headers_mut().insert(http::header::CONTENT_ENCODING, "br".parse().unwrap());
+
+        encode(&req, &mut resp, &CompressionConfig::default());
+
+        assert_eq!(resp.headers().get_all(http::header::CONTENT_ENCODING).iter().count(), 1);
+        assert_eq!(resp.headers().get(http::header::CONTENT_ENCODING).unwrap(), "br");
+        assert!(resp.headers().get(http::header::VARY).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_encode_skips_framed_and_partial_responses() {
+        let header = request_header("gzip");
+        let req = RequestContext::new(&header, PathParams::empty());
+
+        let mut resp = text_response(4096);
+        resp.headers_mut().insert(http::header::TRANSFER_ENCODING, "chunked".parse().unwrap());
+        encode(&req, &mut resp, &CompressionConfig::default());
+        assert!(resp.headers().get(http::header::CONTENT_ENCODING).is_none());
+        assert_eq!(resp.headers().get(http::header::TRANSFER_ENCODING).unwrap(), "chunked");
+
+        let mut resp = text_response(4096);
+        *resp.status_mut() = StatusCode::PARTIAL_CONTENT;
+        resp.headers_mut().insert(http::header::CONTENT_RANGE, "bytes 0-4095/8192".parse().unwrap());
+        encode(&req, &mut resp, &CompressionConfig::default());
+        assert!(resp.headers().get(http::header::CONTENT_ENCODING).is_none());
+        assert!(resp.headers().get(http::header::VARY).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_encode_skips_no_transform() {
+        let header = request_header("gzip");
+        let req = RequestContext::new(&header, PathParams::empty());
+
+        let mut resp = text_response(4096);
+        resp.headers_mut().insert(http::header::CACHE_CONTROL, "max-age=60, No-Transform".parse().unwrap());
+        let headers = resp.headers().clone();
+        encode(&req, &mut resp, &CompressionConfig::default());
+        assert_eq!(resp.headers(), &headers);
+        assert_eq!(encoded_bytes(resp).await, "hello world ".repeat(4096 / 12 + 1));
+
+        // the other directives only restrict caching
+        let mut resp = text_response(4096);
+        resp.headers_mut().insert(http::header::CACHE_CONTROL, "no-store".parse().unwrap());
+        resp.headers_mut().insert(http::header::PRAGMA, "no-cache".parse().unwrap());
+        encode(&req, &mut resp, &CompressionConfig::default());
+        assert_eq!(resp.headers().get(http::header::CONTENT_ENCODING).unwrap(), "gzip");
+    }
+
+    #[tokio::test]
+    async fn test_encode_skips_sse() {
+        async fn events() -> Response<ResponseBody> {
+            let events = vec![SseEvent::new("hello"), SseEvent::new("world")];
+            ResponseBuilder::sse(futures::stream::iter(events)).build()
+        }
+
+        let header = request_header("gzip");
+        let mut req = RequestContext::new(&header, PathParams::empty());
+        let handler = EncodeWrapper::default().wrap(handler_fn(events));
+        let resp = handler.invoke(&mut req, OptionReqBody::from(crate::RequestBody::empty())).await;
+        assert!(resp.headers().get(http::header::CONTENT_ENCODING).is_none());
+        assert!(resp.headers().get(http::header::VARY).is_none());
+        assert_eq!(encoded_bytes(resp).await, "data: hello\n\ndata: world\n\n");
+    }
+
+    #[tokio::test]
+    async fn test_encode_opt_out() {
+        let header = request_header("gzip");
+        let req = RequestContext::new(&header, PathParams::empty());
+
+        let mut resp = text_response(4096);
+        resp.headers_mut().insert(X_NO_ENCODE, "true".parse().unwrap());
+        encode(&req, &mut resp, &CompressionConfig::default());
+        assert!(resp.headers().get(http::header::CONTENT_ENCODING).is_none());
+        assert!(resp.headers().get(X_NO_ENCODE).is_none());
+
+        // any other value doesn't opt out, but isn't sent either
+        let mut resp = text_response(4096);
+        resp.headers_mut().insert(X_NO_ENCODE, "false".parse().unwrap());
+        encode(&req, &mut resp, &CompressionConfig::default());
+        assert_eq!(resp.headers().get(http::header::CONTENT_ENCODING).unwrap(), "gzip");
+        assert!(resp.headers().get(X_NO_ENCODE).is_none());
+    }
+
+    #[cfg(feature = "lz4")]
+    #[tokio::test]
+    async fn test_encode_lz4_round_trip() {
+        let header = request_header("x-lz4");
+        let req = RequestContext::new(&header, PathParams::empty());
+        let config = CompressionConfig { lz4_block_size: Some(64 * 1024), ..CompressionConfig::default() };
+        let expected = "hello world ".repeat(100_000 / 12 + 1);
+        let mut resp = Response::new(ResponseBody::from(expected.clone()));
+
+        encode(&req, &mut resp, &config);
+
+        assert_eq!(resp.headers().get(http::header::CONTENT_ENCODING).unwrap(), "x-lz4");
+        let bytes = encoded_bytes(resp).await;
+        let mut decoded = vec![];
+        lz4_flex::frame::FrameDecoder::new(&bytes[..]).read_to_end(&mut decoded).unwrap();
+        assert_eq!(decoded, expected.as_bytes());
+    }
 }
@@ -1,28 +1,73 @@
 //! Module for handling HTTP response body encoding.
 //! 
 //! This module provides functionality for encoding HTTP response bodies using different compression
-//! algorithms like gzip, deflate, zstd, and brotli. It works in conjunction with the encoder
-//! module to provide a complete encoding solution.
+//! algorithms like gzip, deflate, zstd, brotli, and lz4 with the `lz4` feature. It works in conjunction
+//! with the encoder module to provide a complete encoding solution.
 //!
 //! The main components are:
 //! - `Writer`: An internal buffer implementation for collecting encoded data
+//! - `AcceptEncoding`: A parsed `Accept-Encoding` header used to negotiate the encoding
+//! - `CompressionConfig`: The compression levels used by each algorithm, see `CompressionLevel`
 //! - `encoder`: A sub-module containing the encoding logic and request handler wrapper
+//! - `decoder`: A sub-module decoding request bodies sent with a `Content-Encoding` header
 //!
 //! The implementation is inspired by the actix-http crate's encoding functionality.
 
 use bytes::{Bytes, BytesMut};
 use std::io;
 
+mod accept_encoding;
+mod config;
+pub mod decoder;
 pub mod encoder;
 
+pub use accept_encoding::AcceptEncoding;
+pub use config::{CompressionConfig, CompressionLevel, ZstdDictionary};
+
 // inspired by from actix-http
 pub(crate) struct Writer {
     buf: BytesMut,
+    /// The number of bytes written so far, including the ones already taken
+    written: u64,
+    /// Writes fail once more than `max_len` bytes have been written, which bounds the output of the
+    /// decoders
+    max_len: u64,
+    /// The bytes that can still be written before failing, to test the error paths of the encoders
+    #[cfg(test)]
+    remaining: Option<usize>,
 }
 
 impl Writer {
     fn new() -> Self {
-        Self { buf: BytesMut::with_capacity(4096) }
+        Self::with_capacity(4096)
+    }
+
+    /// Creates a writer with room for `capacity` bytes of encoded data, to avoid growing the buffer of
+    /// large bodies.
+    fn with_capacity(capacity: usize) -> Self {
+        Self {
+            buf: BytesMut::with_capacity(capacity),
+            written: 0,
+            max_len: u64::MAX,
+            #[cfg(test)]
+            remaining: None,
+        }
+    }
+
+    /// Creates a writer failing once more than `max_len` bytes have been written.
+    fn with_max_len(max_len: u64) -> Self {
+        Self { max_len, ..Self::new() }
+    }
+
+    /// Returns `true` if more than `max_len` bytes have been written.
+    fn is_full(&self) -> bool {
+        self.written > self.max_len
+    }
+
+    /// Creates a writer failing on its `n`th byte.
+    #[cfg(test)]
+    fn failing_after(n: usize) -> Self {
+        Self { remaining: Some(n), ..Self::new() }
     }
 
     fn take(&mut self) -> Bytes {
@@ -32,6 +77,25 @@ impl Writer {
 
 impl io::Write for Writer {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.written.saturating_add(buf.len() as u64) > self.max_len {
+            // still counted, so the decoders can tell this error apart with `is_full`
+            self.written += buf.len() as u64;
+            return Err(io::Error::other("the output is too large"));
+        }
+
+        #[cfg(test)]
+        if let Some(remaining) = self.remaining.as_mut() {
+            if *remaining == 0 {
+                return Err(io::Error::new(io::ErrorKind::BrokenPipe, "writer failed"));
+            }
+            let len = buf.len().min(*remaining);
+            *remaining -= len;
+            self.written += len as u64;
+            self.buf.extend_from_slice(&buf[..len]);
+            return Ok(len);
+        }
+
+        self.written += buf.len() as u64;
         self.buf.extend_from_slice(buf);
         Ok(buf.len())
     }
@@ -0,0 +1,312 @@
+//! Module for entity tags and conditional requests.
+//!
+//! [`ETagWrapper`] adds an `ETag` header to successful responses and evaluates the preconditions of
+//! [RFC 9110 Section 13](https://www.rfc-editor.org/rfc/rfc9110#section-13):
+//! - `If-None-Match`: a `GET` or `HEAD` request whose tag matches gets `304 Not Modified`
+//! - `If-Match`: a request whose tag doesn't match gets `412 Precondition Failed`
+//!
+//! By default the tag is a weak one, `W/"<xxh3 hex>"`, computed from the response body. The body is
+//! buffered to compute it, bodies larger than `max_buffer` are streamed as is and get no tag.
+//!
+//! Strong tags such as a database version can be provided by [`ETagWrapper::with_strong`]. They are
+//! computed from the request before the handler runs, so `If-Match` protects unsafe requests: the
+//! handler isn't invoked when the precondition fails.
+//!
+//! Tags set by the handler are kept and used for the preconditions.
+
+use crate::handler::RequestHandler;
+use crate::responder::Responder;
+use crate::wrapper::Wrapper;
+use crate::{OptionReqBody, RequestContext, ResponseBody};
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use futures::{stream, StreamExt};
+use http::header::{CONTENT_LENGTH, ETAG, IF_MATCH, IF_NONE_MATCH};
+use http::{HeaderValue, Method, Response, StatusCode};
+use http_body::{Body, Frame};
+use http_body_util::{BodyExt, BodyStream, StreamBody};
+use micro_http::protocol::HttpError;
+use std::sync::Arc;
+use tracing::trace;
+use xxhash_rust::xxh3::xxh3_64;
+
+/// Computes the strong tag of the requested resource, without the surrounding double quotes.
+///
+/// Returning `None` falls back to the weak tag computed from the response body.
+pub type StrongETagFn = Box<dyn Fn(&RequestContext) -> Option<String> + Send + Sync>;
+
+/// The default of [`ETagWrapper::max_buffer`], 1 MiB.
+const DEFAULT_MAX_BUFFER: usize = 1024 * 1024;
+
+/// A wrapper that creates `ETagRequestHandler`.
+///
+/// # Example
+/// ```
+/// use micro_web::wrapper::ETagWrapper;
+///
+/// let wrapper = ETagWrapper::new().max_buffer(64 * 1024);
+/// ```
+pub struct ETagWrapper {
+    strong: Option<Arc<StrongETagFn>>,
+    max_buffer: usize,
+}
+
+impl ETagWrapper {
+    /// Creates an `ETagWrapper` computing weak tags from the response body.
+    pub fn new() -> Self {
+        Self { strong: None, max_buffer: DEFAULT_MAX_BUFFER }
+    }
+
+    /// Creates an `ETagWrapper` using the strong tags computed by `strong`.
+    pub fn with_strong(strong: StrongETagFn) -> Self {
+        Self { strong: Some(Arc::new(strong)), max_buffer: DEFAULT_MAX_BUFFER }
+    }
+
+    /// Sets the maximum body size, in bytes, buffered to compute a weak tag.
+    pub fn max_buffer(mut self, max_buffer: usize) -> Self {
+        self.max_buffer = max_buffer;
+        self
+    }
+}
+
+impl Default for ETagWrapper {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A request handler that tags responses and evaluates conditional requests.
+pub struct ETagRequestHandler<H: RequestHandler> {
+    handler: H,
+    strong: Option<Arc<StrongETagFn>>,
+    max_buffer: usize,
+}
+
+impl<H: RequestHandler> Wrapper<H> for ETagWrapper {
+    type Out = ETagRequestHandler<H>;
+
+    fn wrap(&self, handler: H) -> Self::Out {
+        ETagRequestHandler { handler, strong: self.strong.clone(), max_buffer: self.max_buffer }
+    }
+}
+
+#[async_trait]
+impl<H: RequestHandler> RequestHandler for ETagRequestHandler<H> {
+    async fn invoke<'server, 'req>(
+        &self,
+        req: &mut RequestContext<'server, 'req>,
+        req_body: OptionReqBody,
+    ) -> Response<ResponseBody> {
+        let strong = self.strong.as_ref().and_then(|f| f(req)).and_then(|tag| {
+            HeaderValue::from_str(&format!("\"{tag}\""))
+                .map_err(|e| trace!("skip strong etag which is not a valid header value: {}", e))
+                .ok()
+        });
+
+        // evaluated before the handler, so a failed precondition doesn't change the resource
+        if let Some(strong) = &strong {
+            if !if_match(req.headers().get(IF_MATCH), strong) {
+                return (StatusCode::PRECONDITION_FAILED, "precondition failed").response_to(req);
+            }
+        }
+
+        let mut resp = self.handler.invoke(req, req_body).await;
+        if !resp.status().is_success() {
+            return resp;
+        }
+
+        let safe = matches!(*req.method(), Method::GET | Method::HEAD);
+        let etag = match (resp.headers().get(ETAG), strong.as_ref()) {
+            (Some(etag), _) => Some(etag.clone()),
+            (None, Some(strong)) => Some(strong.clone()),
+            (None, None) if safe => weak_etag(&mut resp, self.max_buffer).await,
+            (None, None) => None,
+        };
+        let etag = match etag {
+            Some(etag) => etag,
+            None => return resp,
+        };
+        resp.headers_mut().insert(ETAG, etag.clone());
+
+        if strong.is_none() && !if_match(req.headers().get(IF_MATCH), &etag) {
+            return (StatusCode::PRECONDITION_FAILED, "precondition failed").response_to(req);
+        }
+
+        if safe && if_none_match(req.headers().get(IF_NONE_MATCH), &etag) {
+            return not_modified(resp);
+        }
+
+        resp
+    }
+}
+
+/// Computes the weak tag of the response body, the body is put back after it has been read.
+async fn weak_etag(resp: &mut Response<ResponseBody>, max_buffer: usize) -> Option<HeaderValue> {
+    let body = resp.body_mut().take();
+    match buffer(body, max_buffer).await {
+        Ok(bytes) => {
+            let etag = format!("W/\"{:016x}\"", xxh3_64(&bytes));
+            *resp.body_mut() = ResponseBody::once(bytes);
+            // only contains hex digits
+            Some(HeaderValue::from_str(&etag).unwrap())
+        }
+        Err(body) => {
+            *resp.body_mut() = body;
+            None
+        }
+    }
+}
+
+/// Reads the whole body if it has at most `max_buffer` bytes and no trailers.
+///
+/// Otherwise returns a body yielding the same frames as the original one.
+pub(super) async fn buffer(mut body: ResponseBody, max_buffer: usize) -> Result<Bytes, ResponseBody> {
+    if body.size_hint().lower() > max_buffer as u64 {
+        return Err(body);
+    }
+
+    let mut buf = BytesMut::new();
+    let pending = loop {
+        match body.frame().await {
+            None => return Ok(buf.freeze()),
+            Some(Ok(frame)) => match frame.into_data() {
+                Ok(data) if buf.len() + data.len() <= max_buffer => buf.extend_from_slice(&data),
+                Ok(data) => break Ok(Frame::data(data)),
+                Err(frame) => break Ok(frame),
+            },
+            Some(Err(e)) => break Err(e),
+        }
+    };
+
+    let buffered: Vec<Result<Frame<Bytes>, HttpError>> = vec![Ok(Frame::data(buf.freeze())), pending];
+    let frames = stream::iter(buffered).chain(BodyStream::new(body));
+    Err(ResponseBody::stream(StreamBody::new(frames)))
+}
+
+/// Turns `resp` into a `304 Not Modified` response with the same headers.
+fn not_modified(resp: Response<ResponseBody>) -> Response<ResponseBody> {
+    let (mut parts, _body) = resp.into_parts();
+    parts.status = StatusCode::NOT_MODIFIED;
+    parts.headers.remove(CONTENT_LENGTH);
+    Response::from_parts(parts, ResponseBody::empty())
+}
+
+/// Evaluates `If-Match` with the strong comparison, a missing header always matches.
+fn if_match(header: Option<&HeaderValue>, etag: &HeaderValue) -> bool {
+    match header {
+        Some(header) => any_tag(header, |tag| !is_weak(tag) && !is_weak(etag.as_bytes()) && tag == etag.as_bytes()),
+        None => true,
+    }
+}
+
+/// Evaluates `If-None-Match` with the weak comparison, returns true if one of the tags matches.
+fn if_none_match(header: Option<&HeaderValue>, etag: &HeaderValue) -> bool {
+    match header {
+        Some(header) => any_tag(header, |tag| opaque_tag(tag) == opaque_tag(etag.as_bytes())),
+        None => false,
+    }
+}
+
+/// Returns true if the header is `*` or if one of its comma separated tags satisfies `f`.
+fn any_tag(header: &HeaderValue, f: impl Fn(&[u8]) -> bool) -> bool {
+    let header = match header.to_str() {
+        Ok(header) => header.trim(),
+        Err(_) => return false,
+    };
+    header == "*" || header.split(',').map(|tag| tag.trim().as_bytes()).any(f)
+}
+
+fn is_weak(tag: &[u8]) -> bool {
+    tag.starts_with(b"W/")
+}
+
+fn opaque_tag(tag: &[u8]) -> &[u8] {
+    tag.strip_prefix(b"W/").unwrap_or(tag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{handler_fn, PathParams, RequestBody};
+    use micro_http::protocol::RequestHeader;
+
+    async fn hello() -> &'static str {
+        "hello"
+    }
+
+    async fn invoke<H: RequestHandler>(
+        handler: &H,
+        method: Method,
+        headers: &[(&str, &str)],
+    ) -> Response<ResponseBody> {
+        let mut builder = http::Request::builder().method(method);
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        let header: RequestHeader = builder.body(()).unwrap().into();
+        let mut req = RequestContext::new(&header, PathParams::empty());
+        handler.invoke(&mut req, OptionReqBody::from(RequestBody::empty())).await
+    }
+
+    async fn body_of(resp: Response<ResponseBody>) -> Bytes {
+        resp.into_body().collect().await.unwrap().to_bytes()
+    }
+
+    #[tokio::test]
+    async fn test_weak_etag() {
+        let handler = ETagWrapper::new().wrap(handler_fn(hello));
+
+        let resp = invoke(&handler, Method::GET, &[]).await;
+        let etag = resp.headers().get(ETAG).unwrap().to_str().unwrap().to_string();
+        assert_eq!(etag, format!("W/\"{:016x}\"", xxh3_64(b"hello")));
+        assert_eq!(body_of(resp).await, "hello");
+
+        let resp = invoke(&handler, Method::GET, &[("if-none-match", &format!("\"other\", {etag}"))]).await;
+        assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(resp.headers().get(ETAG).unwrap(), etag.as_str());
+        assert!(resp.headers().get(CONTENT_LENGTH).is_none());
+        assert!(body_of(resp).await.is_empty());
+
+        let resp = invoke(&handler, Method::GET, &[("if-none-match", "\"other\"")]).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_if_match() {
+        let strong: StrongETagFn = Box::new(|_| Some("v2".into()));
+        let handler = ETagWrapper::with_strong(strong).wrap(handler_fn(hello));
+
+        let resp = invoke(&handler, Method::PUT, &[("if-match", "\"v1\"")]).await;
+        assert_eq!(resp.status(), StatusCode::PRECONDITION_FAILED);
+
+        let resp = invoke(&handler, Method::PUT, &[("if-match", "\"v2\"")]).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers().get(ETAG).unwrap(), "\"v2\"");
+
+        // weak tags never match If-Match
+        let resp = invoke(&handler, Method::PUT, &[("if-match", "W/\"v2\"")]).await;
+        assert_eq!(resp.status(), StatusCode::PRECONDITION_FAILED);
+
+        let resp = invoke(&handler, Method::GET, &[("if-none-match", "W/\"v2\"")]).await;
+        assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+    }
+
+    #[tokio::test]
+    async fn test_streaming_over_max_buffer() {
+        async fn streaming() -> Response<ResponseBody> {
+            let chunks: Vec<Result<Frame<Bytes>, HttpError>> =
+                vec![Ok(Frame::data(Bytes::from_static(b"hel"))), Ok(Frame::data(Bytes::from_static(b"lo world")))];
+            Response::new(ResponseBody::stream(StreamBody::new(stream::iter(chunks))))
+        }
+
+        let handler = ETagWrapper::new().max_buffer(5).wrap(handler_fn(streaming));
+        let resp = invoke(&handler, Method::GET, &[]).await;
+        assert!(resp.headers().get(ETAG).is_none());
+        assert_eq!(body_of(resp).await, "hello world");
+
+        let handler = ETagWrapper::new().wrap(handler_fn(streaming));
+        let resp = invoke(&handler, Method::GET, &[]).await;
+        assert!(resp.headers().get(ETAG).is_some());
+        assert_eq!(body_of(resp).await, "hello world");
+    }
+}
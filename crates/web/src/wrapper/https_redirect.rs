@@ -0,0 +1,216 @@
+//! Module for redirecting plain HTTP requests to HTTPS.
+//!
+//! [`HttpsRedirectWrapper`] answers the requests received over plain HTTP with a redirect to the same
+//! URL with the `https` scheme, without invoking the handler. A request is over plain HTTP when:
+//! - its connection wasn't marked [`Secure`](crate::acceptor::Secure) by the
+//!   [`Acceptor`](crate::acceptor::Acceptor)
+//! - or, when the server trusts proxy headers, its `X-Forwarded-Proto` header is `http`, as set by a load
+//!   balancer terminating TLS
+//!
+//! See [`RequestContext::is_secure`].
+//!
+//! `GET` and `HEAD` requests are redirected with `301 Moved Permanently`, the other methods with
+//! `308 Permanent Redirect`, so clients resend them with the same method and body
+//! ([RFC 9110 Section 15.4.9](https://www.rfc-editor.org/rfc/rfc9110#section-15.4.9)).
+
+use crate::handler::RequestHandler;
+use crate::responder::Responder;
+use crate::wrapper::{priority, Wrapper};
+use crate::{OptionReqBody, RequestContext, ResponseBody};
+use async_trait::async_trait;
+use http::header::{HOST, LOCATION};
+use http::{HeaderValue, Method, Response, StatusCode};
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// A wrapper that creates `HttpsRedirectRequestHandler`.
+///
+/// # Example
+/// ```
+/// use micro_web::wrapper::HttpsRedirectWrapper;
+///
+/// // the load balancer health checks stay on plain HTTP
+/// let wrapper = HttpsRedirectWrapper::new(Some(8443)).with_excluded_paths(vec!["/health".to_string()]);
+/// ```
+#[derive(Clone)]
+pub struct HttpsRedirectWrapper {
+    config: Arc<RedirectConfig>,
+}
+
+struct RedirectConfig {
+    https_port: Option<u16>,
+    excluded_paths: HashSet<String>,
+}
+
+impl HttpsRedirectWrapper {
+    /// Creates a wrapper redirecting to `https_port`, or to the default port 443 if `None`.
+    ///
+    /// The port of the `Host` header is the one of plain HTTP, so it's always replaced.
+    pub fn new(https_port: Option<u16>) -> Self {
+        Self { config: Arc::new(RedirectConfig { https_port, excluded_paths: HashSet::new() }) }
+    }
+
+    /// Sets the paths still served over plain HTTP, such as health checks. They are matched exactly.
+    pub fn with_excluded_paths(self, paths: Vec<String>) -> Self {
+        let https_port = self.config.https_port;
+        Self { config: Arc::new(RedirectConfig { https_port, excluded_paths: paths.into_iter().collect() }) }
+    }
+}
+
+/// A request handler that redirects the requests over plain HTTP to HTTPS.
+pub struct HttpsRedirectRequestHandler<H: RequestHandler> {
+    handler: H,
+    config: Arc<RedirectConfig>,
+}
+
+impl<H: RequestHandler> Wrapper<H> for HttpsRedirectWrapper {
+    type Out = HttpsRedirectRequestHandler<H>;
+
+    fn wrap(&self, handler: H) -> Self::Out {
+        HttpsRedirectRequestHandler { handler, config: Arc::clone(&self.config) }
+    }
+
+    fn priority(&self) -> i32 {
+        priority::SECURITY
+    }
+}
+
+#[async_trait]
+impl<H: RequestHandler> RequestHandler for HttpsRedirectRequestHandler<H> {
+    async fn invoke<'server, 'req>(
+        &self,
+        req: &mut RequestContext<'server, 'req>,
+        req_body: OptionReqBody,
+    ) -> Response<ResponseBody> {
+        if req.is_secure() || self.config.excluded_paths.contains(req.uri().path()) {
+            return self.handler.invoke(req, req_body).await;
+        }
+
+        let Some(location) = self.location(req) else {
+            return (StatusCode::BAD_REQUEST, "missing host").response_to(req);
+        };
+
+        let status = match *req.method() {
+            Method::GET | Method::HEAD => StatusCode::MOVED_PERMANENTLY,
+            _ => StatusCode::PERMANENT_REDIRECT,
+        };
+        let mut resp = Response::new(ResponseBody::empty());
+        *resp.status_mut() = status;
+        resp.headers_mut().insert(LOCATION, location);
+        resp
+    }
+}
+
+impl<H: RequestHandler> HttpsRedirectRequestHandler<H> {
+    /// Returns the HTTPS URL of the request, `None` if it has no valid host.
+    fn location(&self, req: &RequestContext) -> Option<HeaderValue> {
+        let authority = match req.headers().get(HOST) {
+            Some(host) => host.to_str().ok()?.parse::<http::uri::Authority>().ok()?,
+            None => req.uri().authority()?.clone(),
+        };
+
+        let path = req.uri().path_and_query().map_or("/", |path| path.as_str());
+        let location = match self.config.https_port {
+            Some(port) if port != 443 => format!("https://{}:{port}{path}", authority.host()),
+            _ => format!("https://{}{path}", authority.host()),
+        };
+        HeaderValue::try_from(location).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::acceptor::Secure;
+    use crate::{handler_fn, PathParams, RequestBody};
+    use micro_http::protocol::RequestHeader;
+
+    async fn invoke(
+        wrapper: &HttpsRedirectWrapper,
+        request: http::Request<()>,
+        secure: bool,
+    ) -> Response<ResponseBody> {
+        invoke_with(wrapper, request, secure, false).await
+    }
+
+    async fn invoke_with(
+        wrapper: &HttpsRedirectWrapper,
+        request: http::Request<()>,
+        secure: bool,
+        trust_proxy: bool,
+    ) -> Response<ResponseBody> {
+        let handler = wrapper.wrap(handler_fn(|| async { "hello" }));
+        let header: RequestHeader = request.into();
+        let mut req = RequestContext::new(&header, PathParams::empty()).with_trust_proxy(trust_proxy);
+        if secure {
+            req.extensions_mut().insert(Secure);
+        }
+        handler.invoke(&mut req, OptionReqBody::from(RequestBody::empty())).await
+    }
+
+    fn get(uri: &str) -> http::request::Builder {
+        http::Request::get(uri).header(HOST, "example.com:8080")
+    }
+
+    #[tokio::test]
+    async fn test_redirect_to_default_port() {
+        let wrapper = HttpsRedirectWrapper::new(None);
+
+        let resp = invoke(&wrapper, get("/search?q=rust").body(()).unwrap(), false).await;
+        assert_eq!(resp.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(resp.headers()[LOCATION], "https://example.com/search?q=rust");
+
+        let resp = invoke(&wrapper, get("/").body(()).unwrap(), true).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let request = http::Request::post("/form").header(HOST, "[::1]:8080").body(()).unwrap();
+        let resp = invoke(&wrapper, request, false).await;
+        assert_eq!(resp.status(), StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(resp.headers()[LOCATION], "https://[::1]/form");
+
+        let resp = invoke(&wrapper, http::Request::get("/").body(()).unwrap(), false).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_redirect_to_https_port() {
+        let wrapper = HttpsRedirectWrapper::new(Some(8443));
+        let resp = invoke(&wrapper, get("/a").body(()).unwrap(), false).await;
+        assert_eq!(resp.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(resp.headers()[LOCATION], "https://example.com:8443/a");
+
+        let wrapper = HttpsRedirectWrapper::new(Some(443));
+        let resp = invoke(&wrapper, get("/a").body(()).unwrap(), false).await;
+        assert_eq!(resp.headers()[LOCATION], "https://example.com/a");
+    }
+
+    #[tokio::test]
+    async fn test_forwarded_proto() {
+        let wrapper = HttpsRedirectWrapper::new(None);
+
+        let forwarded = |proto| get("/").header("x-forwarded-proto", proto).body(()).unwrap();
+
+        let resp = invoke_with(&wrapper, forwarded("https"), false, true).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        // the load balancer received it over plain HTTP, even though its own connection is secure
+        let resp = invoke_with(&wrapper, forwarded("HTTP"), true, true).await;
+        assert_eq!(resp.status(), StatusCode::MOVED_PERMANENTLY);
+
+        // without a trusted proxy, clients can neither skip nor force the redirect
+        let resp = invoke_with(&wrapper, forwarded("https"), false, false).await;
+        assert_eq!(resp.status(), StatusCode::MOVED_PERMANENTLY);
+        let resp = invoke_with(&wrapper, forwarded("http"), true, false).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_excluded_paths() {
+        let wrapper = HttpsRedirectWrapper::new(None).with_excluded_paths(vec!["/health".to_string()]);
+
+        let resp = invoke(&wrapper, get("/health").body(()).unwrap(), false).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let resp = invoke(&wrapper, get("/health/deep").body(()).unwrap(), false).await;
+        assert_eq!(resp.status(), StatusCode::MOVED_PERMANENTLY);
+    }
+}
@@ -0,0 +1,183 @@
+//! Wrappers made of synchronous hooks.
+//!
+//! Many wrappers only look at the request before the handler and at the response after it, without
+//! awaiting anything, such as adding a header. [`InterceptorSync`] is enough for them: its hooks are
+//! plain methods, implemented without `#[async_trait]`, and every implementor is a [`Wrapper`], so it's
+//! added to a router or a route group like the other wrappers.
+//!
+//! The wrapped handler is still invoked through [`RequestHandler`], so its future is boxed as usual: the
+//! routes are stored as `Box<dyn RequestHandler>`, which can only return a boxed future.
+//!
+//! # Example
+//! ```
+//! use http::{HeaderValue, Response};
+//! use micro_web::router::{get, Router};
+//! use micro_web::wrapper::InterceptorSync;
+//! use micro_web::{handler_fn, RequestContext, ResponseBody};
+//!
+//! #[derive(Clone)]
+//! struct PoweredBy;
+//!
+//! impl InterceptorSync for PoweredBy {
+//!     fn on_response(&self, _req: &RequestContext, resp: &mut Response<ResponseBody>) {
+//!         resp.headers_mut().insert("x-powered-by", HeaderValue::from_static("micro-web"));
+//!     }
+//! }
+//!
+//! async fn hello() -> &'static str {
+//!     "hello"
+//! }
+//!
+//! let router = Router::builder().route("/", get(handler_fn(hello))).wrap(PoweredBy).build();
+//! ```
+
+use crate::handler::RequestHandler;
+use crate::wrapper::Wrapper;
+use crate::{OptionReqBody, RequestContext, ResponseBody};
+use async_trait::async_trait;
+use http::Response;
+
+/// A wrapper made of synchronous hooks run before and after the handler, see the [module](self) docs.
+///
+/// It must be `Clone`, as each wrapped handler gets its own copy, put the shared state in an `Arc`.
+pub trait InterceptorSync: Send + Sync {
+    /// Called before the handler, returning a response skips the handler and [`on_response`](Self::on_response)
+    fn on_request(&self, _req: &mut RequestContext<'_, '_>) -> Option<Response<ResponseBody>> {
+        None
+    }
+
+    /// Called with the response of the handler
+    fn on_response(&self, _req: &RequestContext<'_, '_>, _resp: &mut Response<ResponseBody>) {}
+
+    /// The position of the interceptor among the wrappers of a route group, see [`Wrapper::priority`]
+    fn priority(&self) -> i32 {
+        0
+    }
+}
+
+/// A request handler running the hooks of an [`InterceptorSync`] around another handler.
+pub struct InterceptorSyncHandler<I, H> {
+    interceptor: I,
+    handler: H,
+}
+
+impl<I, H> Wrapper<H> for I
+where
+    I: InterceptorSync + Clone,
+    H: RequestHandler,
+{
+    type Out = InterceptorSyncHandler<I, H>;
+
+    fn wrap(&self, handler: H) -> Self::Out {
+        InterceptorSyncHandler { interceptor: self.clone(), handler }
+    }
+
+    fn priority(&self) -> i32 {
+        InterceptorSync::priority(self)
+    }
+}
+
+#[async_trait]
+impl<I, H> RequestHandler for InterceptorSyncHandler<I, H>
+where
+    I: InterceptorSync,
+    H: RequestHandler,
+{
+    async fn invoke<'server, 'req>(
+        &self,
+        req: &mut RequestContext<'server, 'req>,
+        req_body: OptionReqBody,
+    ) -> Response<ResponseBody> {
+        if let Some(resp) = self.interceptor.on_request(req) {
+            return resp;
+        }
+
+        let mut resp = self.handler.invoke(req, req_body).await;
+        self.interceptor.on_response(req, &mut resp);
+        resp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::responder::Responder;
+    use crate::router::{get, RouteGroup, Router};
+    use crate::testing::TestClient;
+    use crate::wrapper::priority;
+    use crate::{handler_fn, PathParams, RequestBody};
+    use http::{HeaderValue, StatusCode};
+    use micro_http::protocol::RequestHeader;
+    use std::sync::{Arc, Mutex};
+
+    /// Rejects the requests without an `x-api-key` header, and records the calls of its hooks
+    #[derive(Clone)]
+    struct ApiKey {
+        calls: Arc<Mutex<Vec<&'static str>>>,
+    }
+
+    impl InterceptorSync for ApiKey {
+        fn on_request(&self, req: &mut RequestContext<'_, '_>) -> Option<Response<ResponseBody>> {
+            self.calls.lock().unwrap().push("request");
+            if req.headers().contains_key("x-api-key") {
+                None
+            } else {
+                Some((StatusCode::UNAUTHORIZED, "missing api key").response_to(req))
+            }
+        }
+
+        fn on_response(&self, _req: &RequestContext<'_, '_>, resp: &mut Response<ResponseBody>) {
+            self.calls.lock().unwrap().push("response");
+            resp.headers_mut().insert("x-checked", HeaderValue::from_static("true"));
+        }
+
+        fn priority(&self) -> i32 {
+            priority::AUTH
+        }
+    }
+
+    async fn hello() -> &'static str {
+        "hello"
+    }
+
+    #[tokio::test]
+    async fn test_hooks() {
+        let calls = Arc::new(Mutex::new(vec![]));
+        let interceptor = ApiKey { calls: Arc::clone(&calls) };
+        assert_eq!(Wrapper::<Box<dyn RequestHandler>>::priority(&interceptor), priority::AUTH);
+        let handler = interceptor.wrap(handler_fn(hello));
+
+        let header: RequestHeader = http::Request::builder().header("x-api-key", "key").body(()).unwrap().into();
+        let mut req = RequestContext::new(&header, PathParams::empty());
+        let resp = handler.invoke(&mut req, OptionReqBody::from(RequestBody::empty())).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers()["x-checked"], "true");
+        assert_eq!(*calls.lock().unwrap(), vec!["request", "response"]);
+
+        calls.lock().unwrap().clear();
+        let header: RequestHeader = http::Request::builder().body(()).unwrap().into();
+        let mut req = RequestContext::new(&header, PathParams::empty());
+        let resp = handler.invoke(&mut req, OptionReqBody::from(RequestBody::empty())).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        assert!(!resp.headers().contains_key("x-checked"));
+        assert_eq!(*calls.lock().unwrap(), vec!["request"]);
+    }
+
+    #[test]
+    fn test_route_group() {
+        let calls = Arc::new(Mutex::new(vec![]));
+        let api = RouteGroup::new("/api").wrap(ApiKey { calls: Arc::clone(&calls) }).get("/hello", handler_fn(hello));
+        let router = Router::builder().route("/hello", get(handler_fn(hello))).group(api).build();
+        let client = TestClient::from_router(router);
+
+        client.get("/hello").send().status(StatusCode::OK);
+        assert!(calls.lock().unwrap().is_empty());
+        client.get("/api/hello").send().status(StatusCode::UNAUTHORIZED).body("missing api key");
+        client
+            .get("/api/hello")
+            .with_header("x-api-key", "key")
+            .send()
+            .status(StatusCode::OK)
+            .header("x-checked", "true");
+    }
+}
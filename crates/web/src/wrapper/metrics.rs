@@ -0,0 +1,313 @@
+//! Module for recording request metrics.
+//!
+//! [`MetricsWrapper`] records, for each completed request:
+//! - [`REQUESTS_TOTAL`]: a counter labeled with `method`, `path_template` and `status`
+//! - [`REQUEST_DURATION_SECONDS`]: a histogram of the time spent handling the request, until the response
+//!   head was ready, labeled with `method` and `path_template`
+//! - [`RESPONSE_BODY_BYTES`]: a histogram of the response body sizes, labeled with `method` and
+//!   `path_template`. Bodies streamed with an unknown size are not recorded
+//!
+//! The path template is the [matched route](crate::router::MatchedRoute), such as `/users/{id}` rather than
+//! `/users/42`, so the number of series stays bounded.
+//!
+//! The metrics are sent to a [`MetricsRecorder`], the facade to plug any exporter in. [`PrometheusRecorder`]
+//! keeps them in memory and renders them in the Prometheus text format:
+//!
+//! ```
+//! use micro_web::router::{get, Router};
+//! use micro_web::wrapper::{MetricsWrapper, PrometheusRecorder, REQUEST_DURATION_SECONDS};
+//! use micro_web::handler_fn;
+//! use std::sync::Arc;
+//!
+//! let recorder = Arc::new(PrometheusRecorder::new().with_buckets(REQUEST_DURATION_SECONDS, vec![0.01, 0.1, 1.0]));
+//!
+//! let exporter = recorder.clone();
+//! let router = Router::builder()
+//!     .route("/metrics", get(handler_fn(move || {
+//!         let exporter = exporter.clone();
+//!         async move { exporter.render() }
+//!     })))
+//!     .wrap(MetricsWrapper::new(recorder))
+//!     .build();
+//! ```
+//!
+//! This module requires the `metrics` feature.
+
+use crate::handler::RequestHandler;
+use crate::router::MatchedRoute;
+use crate::wrapper::Wrapper;
+use crate::{OptionReqBody, RequestContext, ResponseBody};
+use async_trait::async_trait;
+use http::Response;
+use http_body::Body;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// The counter of completed requests.
+pub const REQUESTS_TOTAL: &str = "http_requests_total";
+/// The histogram of request durations, in seconds.
+pub const REQUEST_DURATION_SECONDS: &str = "http_request_duration_seconds";
+/// The histogram of response body sizes, in bytes.
+pub const RESPONSE_BODY_BYTES: &str = "http_response_body_bytes";
+
+/// The default buckets of [`REQUEST_DURATION_SECONDS`], the default buckets of the Prometheus clients.
+const DEFAULT_DURATION_BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// The default buckets of [`RESPONSE_BODY_BYTES`], from 100 bytes to 10MB.
+const DEFAULT_BODY_BYTES_BUCKETS: [f64; 6] = [100.0, 1_000.0, 10_000.0, 100_000.0, 1_000_000.0, 10_000_000.0];
+
+/// Receives the metrics of [`MetricsWrapper`], to export them.
+///
+/// The labels are `(name, value)` pairs, always in the same order for a given metric.
+pub trait MetricsRecorder: Send + Sync {
+    /// Increments the counter `name` by one.
+    fn increment_counter(&self, name: &'static str, labels: &[(&'static str, &str)]);
+
+    /// Records `value` in the histogram `name`.
+    fn record_histogram(&self, name: &'static str, labels: &[(&'static str, &str)], value: f64);
+}
+
+/// A series, the metric name and its labels.
+type SeriesKey = (&'static str, Vec<(&'static str, String)>);
+
+/// A histogram with cumulative bucket counts.
+#[derive(Debug)]
+struct Histogram {
+    buckets: Vec<(f64, u64)>,
+    sum: f64,
+    count: u64,
+}
+
+/// A [`MetricsRecorder`] keeping the metrics in memory, rendered in the Prometheus text format by
+/// [`render`](Self::render).
+#[derive(Debug)]
+pub struct PrometheusRecorder {
+    buckets: HashMap<&'static str, Vec<f64>>,
+    counters: Mutex<BTreeMap<SeriesKey, u64>>,
+    histograms: Mutex<BTreeMap<SeriesKey, Histogram>>,
+}
+
+impl PrometheusRecorder {
+    /// Creates a recorder with the default buckets.
+    pub fn new() -> Self {
+        let buckets = HashMap::from([
+            (REQUEST_DURATION_SECONDS, DEFAULT_DURATION_BUCKETS.to_vec()),
+            (RESPONSE_BODY_BYTES, DEFAULT_BODY_BYTES_BUCKETS.to_vec()),
+        ]);
+        Self { buckets, counters: Mutex::default(), histograms: Mutex::default() }
+    }
+
+    /// Sets the upper bounds of the buckets of the histogram `name`, the `+Inf` bucket is always added.
+    ///
+    /// Histograms without buckets use the default buckets of [`REQUEST_DURATION_SECONDS`].
+    pub fn with_buckets(mut self, name: &'static str, mut buckets: Vec<f64>) -> Self {
+        buckets.retain(|bound| bound.is_finite());
+        buckets.sort_by(f64::total_cmp);
+        buckets.dedup();
+        self.buckets.insert(name, buckets);
+        self
+    }
+
+    /// Renders the metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+
+        let counters = self.counters.lock().unwrap();
+        let mut last_name = None;
+        for ((name, labels), value) in counters.iter() {
+            if last_name != Some(*name) {
+                let _ = writeln!(output, "# TYPE {name} counter");
+                last_name = Some(*name);
+            }
+            let _ = writeln!(output, "{name}{} {value}", format_labels(labels, None));
+        }
+        drop(counters);
+
+        let histograms = self.histograms.lock().unwrap();
+        let mut last_name = None;
+        for ((name, labels), histogram) in histograms.iter() {
+            if last_name != Some(*name) {
+                let _ = writeln!(output, "# TYPE {name} histogram");
+                last_name = Some(*name);
+            }
+            for (bound, count) in &histogram.buckets {
+                let _ = writeln!(output, "{name}_bucket{} {count}", format_labels(labels, Some(&bound.to_string())));
+            }
+            let _ = writeln!(output, "{name}_bucket{} {}", format_labels(labels, Some("+Inf")), histogram.count);
+            let _ = writeln!(output, "{name}_sum{} {}", format_labels(labels, None), histogram.sum);
+            let _ = writeln!(output, "{name}_count{} {}", format_labels(labels, None), histogram.count);
+        }
+
+        output
+    }
+}
+
+impl Default for PrometheusRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MetricsRecorder for PrometheusRecorder {
+    fn increment_counter(&self, name: &'static str, labels: &[(&'static str, &str)]) {
+        *self.counters.lock().unwrap().entry(series_key(name, labels)).or_default() += 1;
+    }
+
+    fn record_histogram(&self, name: &'static str, labels: &[(&'static str, &str)], value: f64) {
+        let mut histograms = self.histograms.lock().unwrap();
+        let histogram = histograms.entry(series_key(name, labels)).or_insert_with(|| {
+            let bounds = self.buckets.get(name).map(Vec::as_slice).unwrap_or(&DEFAULT_DURATION_BUCKETS);
+            Histogram { buckets: bounds.iter().map(|bound| (*bound, 0)).collect(), sum: 0.0, count: 0 }
+        });
+
+        for (bound, count) in histogram.buckets.iter_mut() {
+            if value <= *bound {
+                *count += 1;
+            }
+        }
+        histogram.sum += value;
+        histogram.count += 1;
+    }
+}
+
+fn series_key(name: &'static str, labels: &[(&'static str, &str)]) -> SeriesKey {
+    (name, labels.iter().map(|(label, value)| (*label, value.to_string())).collect())
+}
+
+/// Formats `{name="value",...}`, with the `le` label of a bucket last.
+fn format_labels(labels: &[(&'static str, String)], le: Option<&str>) -> String {
+    let le = le.map(|le| ("le", le));
+    let pairs: Vec<String> = labels
+        .iter()
+        .map(|(name, value)| (*name, value.as_str()))
+        .chain(le)
+        .map(|(name, value)| format!("{name}=\"{}\"", escape_label_value(value)))
+        .collect();
+
+    if pairs.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", pairs.join(","))
+    }
+}
+
+fn escape_label_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// A wrapper that creates `MetricsRequestHandler`.
+pub struct MetricsWrapper {
+    recorder: Arc<dyn MetricsRecorder>,
+}
+
+impl MetricsWrapper {
+    /// Creates a `MetricsWrapper` sending the metrics to `recorder`.
+    pub fn new<R: MetricsRecorder + 'static>(recorder: Arc<R>) -> Self {
+        Self { recorder }
+    }
+}
+
+/// A request handler that records the metrics of each completed request.
+pub struct MetricsRequestHandler<H: RequestHandler> {
+    handler: H,
+    recorder: Arc<dyn MetricsRecorder>,
+}
+
+impl<H: RequestHandler> Wrapper<H> for MetricsWrapper {
+    type Out = MetricsRequestHandler<H>;
+
+    fn wrap(&self, handler: H) -> Self::Out {
+        MetricsRequestHandler { handler, recorder: Arc::clone(&self.recorder) }
+    }
+}
+
+#[async_trait]
+impl<H: RequestHandler> RequestHandler for MetricsRequestHandler<H> {
+    async fn invoke<'server, 'req>(
+        &self,
+        req: &mut RequestContext<'server, 'req>,
+        req_body: OptionReqBody,
+    ) -> Response<ResponseBody> {
+        let start = Instant::now();
+        // the handler may replace the extensions, so the route is read first
+        let route = req.extensions().get::<MatchedRoute>().cloned();
+
+        let resp = self.handler.invoke(req, req_body).await;
+
+        let duration = start.elapsed().as_secs_f64();
+        let method = req.method().as_str();
+        let path_template = route.as_ref().map(MatchedRoute::as_str).unwrap_or_default();
+        let labels = [("method", method), ("path_template", path_template)];
+
+        let status = resp.status();
+        let status = status.as_str();
+        self.recorder.increment_counter(REQUESTS_TOTAL, &[labels[0], labels[1], ("status", status)]);
+        self.recorder.record_histogram(REQUEST_DURATION_SECONDS, &labels, duration);
+        if let Some(size) = resp.body().size_hint().exact() {
+            self.recorder.record_histogram(RESPONSE_BODY_BYTES, &labels, size as f64);
+        }
+        resp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handler_fn;
+    use crate::router::{get, Router};
+    use crate::testing::TestClient;
+    use http::StatusCode;
+
+    async fn user() -> &'static str {
+        "alice"
+    }
+
+    #[test]
+    fn test_record_requests() {
+        let recorder = Arc::new(PrometheusRecorder::new().with_buckets(REQUEST_DURATION_SECONDS, vec![60.0]));
+        let router = Router::builder()
+            .route("/users/{id}", get(handler_fn(user)))
+            .wrap(MetricsWrapper::new(recorder.clone()))
+            .build();
+        let client = TestClient::from_router(router);
+
+        client.get("/users/1").send().status(StatusCode::OK);
+        client.get("/users/2").send().status(StatusCode::OK);
+
+        let rendered = recorder.render();
+        let expected = [
+            "# TYPE http_requests_total counter",
+            r#"http_requests_total{method="GET",path_template="/users/{id}",status="200"} 2"#,
+            "# TYPE http_request_duration_seconds histogram",
+            r#"http_request_duration_seconds_bucket{method="GET",path_template="/users/{id}",le="60"} 2"#,
+            r#"http_request_duration_seconds_bucket{method="GET",path_template="/users/{id}",le="+Inf"} 2"#,
+            r#"http_request_duration_seconds_count{method="GET",path_template="/users/{id}"} 2"#,
+            "# TYPE http_response_body_bytes histogram",
+            r#"http_response_body_bytes_bucket{method="GET",path_template="/users/{id}",le="100"} 2"#,
+            r#"http_response_body_bytes_sum{method="GET",path_template="/users/{id}"} 10"#,
+        ];
+        for line in expected {
+            assert!(rendered.lines().any(|rendered_line| rendered_line == line), "missing {line} in\n{rendered}");
+        }
+    }
+
+    #[test]
+    fn test_histogram_buckets() {
+        let recorder = PrometheusRecorder::new().with_buckets("latency", vec![1.0, 0.5, f64::NAN]);
+        recorder.record_histogram("latency", &[("path", "a\"b")], 0.25);
+        recorder.record_histogram("latency", &[("path", "a\"b")], 0.75);
+        recorder.record_histogram("latency", &[("path", "a\"b")], 3.0);
+
+        assert_eq!(
+            recorder.render(),
+            "# TYPE latency histogram\n\
+             latency_bucket{path=\"a\\\"b\",le=\"0.5\"} 1\n\
+             latency_bucket{path=\"a\\\"b\",le=\"1\"} 2\n\
+             latency_bucket{path=\"a\\\"b\",le=\"+Inf\"} 3\n\
+             latency_sum{path=\"a\\\"b\"} 4\n\
+             latency_count{path=\"a\\\"b\"} 3\n"
+        );
+    }
+}
@@ -9,13 +9,53 @@
 //! - [`Wrapper`]: Core trait for implementing wrappers
 //! - [`Wrappers`]: A composable list of wrappers that can be chained together
 //! - [`IdentityWrapper`]: A no-op wrapper that passes through the handler unchanged
+//! - [`InterceptorSync`]: Synchronous hooks run before and after the handler, usable as a wrapper
+mod access_log;
+mod auth;
+mod body_limit;
+#[cfg(feature = "cache")]
+mod cache;
+mod cors;
 mod date;
 mod encoding;
+mod etag;
+mod https_redirect;
+mod interceptor;
+#[cfg(feature = "metrics")]
+mod metrics;
+mod panic_recovery;
+mod range;
+mod rate_limit;
+mod security;
+#[cfg(feature = "otel")]
+mod telemetry;
 
 use std::marker::PhantomData;
 
+pub use access_log::{AccessLogWrapper, LogFormat, LogRecord};
+pub use auth::{AuthError, BearerAuthWrapper, HmacClaims, HmacSha256Validator, TokenValidator};
+pub use body_limit::{BodyLimit, BodyLimitWrapper, LimitedBody};
+#[cfg(feature = "cache")]
+pub use cache::CacheWrapper;
+pub use cors::{AllowedOrigins, CorsConfig, CorsWrapper};
 pub use date::DateWrapper;
-pub use encoding::encoder::EncodeWrapper;
+pub use encoding::decoder::DecodeWrapper;
+pub use encoding::encoder::{EncodeWrapper, X_NO_ENCODE, ZSTD_DICT_ID};
+pub use encoding::AcceptEncoding;
+pub use encoding::{CompressionConfig, CompressionLevel, ZstdDictionary};
+pub use etag::{ETagWrapper, StrongETagFn};
+pub use https_redirect::HttpsRedirectWrapper;
+pub use interceptor::{InterceptorSync, InterceptorSyncHandler};
+#[cfg(feature = "metrics")]
+pub use metrics::{
+    MetricsRecorder, MetricsWrapper, PrometheusRecorder, REQUESTS_TOTAL, REQUEST_DURATION_SECONDS, RESPONSE_BODY_BYTES,
+};
+pub use panic_recovery::{PanicHandler, PanicRecoveryWrapper};
+pub use range::RangeWrapper;
+pub use rate_limit::{KeyFn, RateLimitConfig, RateLimitWrapper};
+pub use security::{HstsConfig, SecurityHeadersConfig, SecurityHeadersWrapper, XFrameOptions};
+#[cfg(feature = "otel")]
+pub use telemetry::{OtelWrapper, TraceContext};
 
 /// A trait for transforming request handlers.
 ///
@@ -28,6 +68,32 @@ pub trait Wrapper<H> {
 
     /// Wraps the given handler with additional processing
     fn wrap(&self, handler: H) -> Self::Out;
+
+    /// The position of the wrapper among the wrappers of a [`RouteGroup`](crate::router::RouteGroup),
+    /// see [`priority`]
+    ///
+    /// Lower numbers run outer: first on the request, last on the response.
+    fn priority(&self) -> i32 {
+        0
+    }
+}
+
+/// Predefined [`Wrapper::priority`] values of the built-in wrappers.
+///
+/// Wrappers of the same priority keep the order they were added in.
+pub mod priority {
+    /// Access logging, the outermost wrapper
+    pub const LOGGING: i32 = -1000;
+    /// Security headers
+    pub const SECURITY: i32 = -900;
+    /// Authentication
+    pub const AUTH: i32 = -800;
+    /// Rate limiting
+    pub const RATE_LIMIT: i32 = -700;
+    /// Response caching, after the checks of the request and before the encoding
+    pub const CACHE: i32 = -600;
+    /// Response encoding, the innermost wrapper before the handler
+    pub const ENCODING: i32 = 900;
 }
 
 /// A composable list of wrappers that can transform a handler.
@@ -0,0 +1,199 @@
+//! Module for recovering from panics in request handlers.
+//!
+//! Without [`PanicRecoveryWrapper`] a panicking handler tears down the task serving the connection, and
+//! the client sees the connection closing without a response. With it the panic is caught, logged at
+//! `ERROR` level together with the request method and path, and answered with
+//! `500 Internal Server Error`, or with the response built by a custom [`PanicHandler`].
+//!
+//! The request headers are logged too, with their credentials redacted. The panic message is read from
+//! the caught payload, so by default the wrapper leaves the process-wide panic hook alone. The
+//! backtrace can only be captured by a panic hook, see [`PanicRecoveryWrapper::with_backtrace`] to opt
+//! in.
+
+use crate::handler::RequestHandler;
+use crate::responder::Responder;
+use crate::wrapper::Wrapper;
+use crate::{OptionReqBody, RequestContext, ResponseBody};
+use async_trait::async_trait;
+use futures::FutureExt;
+use http::{Response, StatusCode};
+use micro_http::protocol::HeadersDisplay;
+use std::any::Any;
+use std::backtrace::Backtrace;
+use std::cell::RefCell;
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, Once};
+use tracing::error;
+
+/// Builds the response for a caught panic from its payload.
+pub type PanicHandler = Box<dyn Fn(Box<dyn Any + Send>) -> Response<ResponseBody> + Send + Sync>;
+
+thread_local! {
+    /// The backtrace of the last panic on this thread.
+    static LAST_BACKTRACE: RefCell<Option<Backtrace>> = const { RefCell::new(None) };
+}
+
+/// Installs a panic hook recording the backtrace, keeping the previous hook.
+fn install_backtrace_hook() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            LAST_BACKTRACE.with(|backtrace| *backtrace.borrow_mut() = Some(Backtrace::capture()));
+            previous(info);
+        }));
+    });
+}
+
+/// Returns the message of a panic payload, if it's a string.
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&'static str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "Box<dyn Any>"
+    }
+}
+
+/// A wrapper that creates `PanicRecoveryRequestHandler`.
+///
+/// # Example
+/// ```
+/// use micro_web::wrapper::PanicRecoveryWrapper;
+///
+/// let wrapper = PanicRecoveryWrapper::new();
+/// ```
+pub struct PanicRecoveryWrapper {
+    panic_handler: Option<Arc<PanicHandler>>,
+}
+
+impl PanicRecoveryWrapper {
+    /// Creates a `PanicRecoveryWrapper` answering caught panics with `500 Internal Server Error`.
+    pub fn new() -> Self {
+        Self { panic_handler: None }
+    }
+
+    /// Creates a `PanicRecoveryWrapper` answering caught panics with the response built by `panic_handler`.
+    ///
+    /// `panic_handler` is called outside the recovery scope, if it panics itself the panic propagates.
+    pub fn with_handler(panic_handler: PanicHandler) -> Self {
+        Self { panic_handler: Some(Arc::new(panic_handler)) }
+    }
+
+    /// Logs the backtrace of the caught panics too.
+    ///
+    /// The first call installs a process-wide panic hook recording the backtrace, which honors
+    /// `RUST_BACKTRACE` like the default hook does, then calls the previously installed hook.
+    pub fn with_backtrace(self) -> Self {
+        install_backtrace_hook();
+        self
+    }
+}
+
+impl Default for PanicRecoveryWrapper {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A request handler that turns panics of the wrapped handler into error responses.
+pub struct PanicRecoveryRequestHandler<H: RequestHandler> {
+    handler: H,
+    panic_handler: Option<Arc<PanicHandler>>,
+}
+
+impl<H: RequestHandler> Wrapper<H> for PanicRecoveryWrapper {
+    type Out = PanicRecoveryRequestHandler<H>;
+
+    fn wrap(&self, handler: H) -> Self::Out {
+        PanicRecoveryRequestHandler { handler, panic_handler: self.panic_handler.clone() }
+    }
+}
+
+#[async_trait]
+impl<H: RequestHandler> RequestHandler for PanicRecoveryRequestHandler<H> {
+    async fn invoke<'server, 'req>(
+        &self,
+        req: &mut RequestContext<'server, 'req>,
+        req_body: OptionReqBody,
+    ) -> Response<ResponseBody> {
+        let result = AssertUnwindSafe(self.handler.invoke(req, req_body)).catch_unwind().await;
+
+        let payload = match result {
+            Ok(resp) => return resp,
+            Err(payload) => payload,
+        };
+
+        let backtrace = LAST_BACKTRACE.with(|backtrace| backtrace.borrow_mut().take());
+        error!(
+            "handler panicked while serving {} {}: {}\nrequest headers:\n{}{}",
+            req.method(),
+            req.uri().path(),
+            panic_message(payload.as_ref()),
+            HeadersDisplay::new(req.headers()),
+            backtrace.map(|backtrace| backtrace.to_string()).unwrap_or_default()
+        );
+
+        match &self.panic_handler {
+            Some(panic_handler) => panic_handler(payload),
+            None => (StatusCode::INTERNAL_SERVER_ERROR, "internal server error").response_to(req),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{handler_fn, PathParams, RequestBody};
+    use micro_http::protocol::RequestHeader;
+
+    async fn boom() -> &'static str {
+        panic!("boom")
+    }
+
+    async fn hello() -> &'static str {
+        "hello"
+    }
+
+    async fn invoke<H: RequestHandler>(handler: &H) -> Response<ResponseBody> {
+        let header: RequestHeader = http::Request::builder().uri("/boom").body(()).unwrap().into();
+        let mut req = RequestContext::new(&header, PathParams::empty());
+        handler.invoke(&mut req, OptionReqBody::from(RequestBody::empty())).await
+    }
+
+    #[tokio::test]
+    async fn test_recover() {
+        let handler = PanicRecoveryWrapper::new().wrap(handler_fn(boom));
+        assert_eq!(invoke(&handler).await.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        let handler = PanicRecoveryWrapper::new().wrap(handler_fn(hello));
+        assert_eq!(invoke(&handler).await.status(), StatusCode::OK);
+
+        let handler = PanicRecoveryWrapper::new().with_backtrace().wrap(handler_fn(boom));
+        assert_eq!(invoke(&handler).await.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(LAST_BACKTRACE.with(|backtrace| backtrace.borrow().is_none()));
+    }
+
+    #[tokio::test]
+    async fn test_custom_handler() {
+        let panic_handler: PanicHandler = Box::new(|payload| {
+            let body = format!("oops: {}", panic_message(payload.as_ref()));
+            Response::builder().status(StatusCode::SERVICE_UNAVAILABLE).body(body.into()).unwrap()
+        });
+        let handler = PanicRecoveryWrapper::with_handler(panic_handler).wrap(handler_fn(boom));
+
+        let resp = invoke(&handler).await;
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body = http_body_util::BodyExt::collect(resp.into_body()).await.unwrap().to_bytes();
+        assert_eq!(&body[..], b"oops: boom");
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "panic handler failed")]
+    async fn test_panic_handler_panics() {
+        let panic_handler: PanicHandler = Box::new(|_| panic!("panic handler failed"));
+        let handler = PanicRecoveryWrapper::with_handler(panic_handler).wrap(handler_fn(boom));
+        invoke(&handler).await;
+    }
+}
@@ -0,0 +1,240 @@
+//! Module for answering range requests.
+//!
+//! [`RangeWrapper`] answers the `Range` header of `GET` requests for responses that advertise
+//! `Accept-Ranges: bytes`, as done by handlers serving files:
+//! - a single range gets `206 Partial Content` with its `Content-Range`
+//! - several ranges get `206 Partial Content` with a `multipart/byteranges` body, its parts are read from
+//!   the response body as they are sent, in ascending order
+//! - more than 16 ranges are ignored, the full response is sent
+//! - an invalid, unsatisfiable or overlapping range gets `416 Range Not Satisfiable` with
+//!   `Content-Range: bytes */total`
+//!
+//! Only `200 OK` responses whose body size is known are sliced, other responses are returned as is.
+
+use crate::handler::RequestHandler;
+use crate::range::{parse_range, ByteRange, MultiRangeBody, RangeBody, RangeError};
+use crate::responder::Responder;
+use crate::wrapper::Wrapper;
+use crate::{OptionReqBody, RequestContext, ResponseBody};
+use async_trait::async_trait;
+use http::header::{ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, RANGE};
+use http::{HeaderValue, Method, Response, StatusCode};
+use http_body::Body;
+use tracing::trace;
+
+/// The most ranges answered with a `multipart/byteranges` body, RFC 9110 Section 14.2 allows ignoring
+/// a `Range` header with many small ranges, which cost more to send than the full response.
+const MAX_RANGES: usize = 16;
+
+/// A wrapper that creates `RangeRequestHandler`.
+pub struct RangeWrapper;
+
+/// A request handler that answers range requests with partial responses.
+pub struct RangeRequestHandler<H: RequestHandler> {
+    handler: H,
+}
+
+impl<H: RequestHandler> Wrapper<H> for RangeWrapper {
+    type Out = RangeRequestHandler<H>;
+
+    fn wrap(&self, handler: H) -> Self::Out {
+        RangeRequestHandler { handler }
+    }
+}
+
+#[async_trait]
+impl<H: RequestHandler> RequestHandler for RangeRequestHandler<H> {
+    async fn invoke<'server, 'req>(
+        &self,
+        req: &mut RequestContext<'server, 'req>,
+        req_body: OptionReqBody,
+    ) -> Response<ResponseBody> {
+        let resp = self.handler.invoke(req, req_body).await;
+
+        let range = match req.headers().get(RANGE) {
+            Some(range) if req.method() == Method::GET => range.clone(),
+            _ => return resp,
+        };
+        if resp.status() != StatusCode::OK || !accepts_ranges(&resp) {
+            return resp;
+        }
+        let total = match resp.body().size_hint().exact() {
+            Some(total) => total,
+            None => return resp,
+        };
+
+        let ranges = match range.to_str().map_err(|_| RangeError::Invalid).and_then(|value| parse_range(value, total)) {
+            Ok(ranges) => ranges,
+            Err(e) => {
+                trace!("range {:?} of {} bytes: {}", range, total, e);
+                let mut resp = (StatusCode::RANGE_NOT_SATISFIABLE, "range not satisfiable").response_to(req);
+                resp.headers_mut().insert(CONTENT_RANGE, RangeError::content_range(total));
+                return resp;
+            }
+        };
+
+        match ranges.as_slice() {
+            [range] => single_range(resp, *range, total),
+            _ if ranges.len() > MAX_RANGES => {
+                trace!("range {:?} has {} ranges, sending the full response", range, ranges.len());
+                resp
+            }
+            _ => multiple_ranges(resp, ranges, total),
+        }
+    }
+}
+
+fn accepts_ranges(resp: &Response<ResponseBody>) -> bool {
+    resp.headers().get_all(ACCEPT_RANGES).iter().any(|value| value.as_bytes().eq_ignore_ascii_case(b"bytes"))
+}
+
+fn single_range(resp: Response<ResponseBody>, range: ByteRange, total: u64) -> Response<ResponseBody> {
+    let (mut parts, body) = resp.into_parts();
+    parts.status = StatusCode::PARTIAL_CONTENT;
+    parts.headers.insert(CONTENT_RANGE, range.content_range(total));
+    parts.headers.insert(CONTENT_LENGTH, HeaderValue::from(range.len()));
+    Response::from_parts(parts, ResponseBody::stream(RangeBody::new(body, range)))
+}
+
+fn multiple_ranges(resp: Response<ResponseBody>, ranges: Vec<ByteRange>, total: u64) -> Response<ResponseBody> {
+    let (mut parts, body) = resp.into_parts();
+    let content_type = parts.headers.get(CONTENT_TYPE).and_then(|value| value.to_str().ok());
+    let multipart = MultiRangeBody::from_body(body, ranges, total, content_type);
+
+    parts.status = StatusCode::PARTIAL_CONTENT;
+    parts.headers.insert(CONTENT_TYPE, multipart.content_type());
+    parts.headers.insert(CONTENT_LENGTH, HeaderValue::from(multipart.size_hint().exact().unwrap_or_default()));
+    Response::from_parts(parts, ResponseBody::stream(multipart))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{handler_fn, PathParams, RequestBody};
+    use bytes::Bytes;
+    use http_body::Frame;
+    use http_body_util::{BodyExt, StreamBody};
+    use micro_http::protocol::RequestHeader;
+
+    async fn digits() -> Response<ResponseBody> {
+        let mut resp = Response::new(ResponseBody::from("0123456789"));
+        resp.headers_mut().insert(ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+        resp.headers_mut().insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+        resp
+    }
+
+    async fn invoke<H: RequestHandler>(handler: &H, method: Method, range: &str) -> Response<ResponseBody> {
+        let header: RequestHeader =
+            http::Request::builder().method(method).header(RANGE, range).body(()).unwrap().into();
+        let mut req = RequestContext::new(&header, PathParams::empty());
+        handler.invoke(&mut req, OptionReqBody::from(RequestBody::empty())).await
+    }
+
+    async fn body_of(resp: Response<ResponseBody>) -> Bytes {
+        resp.into_body().collect().await.unwrap().to_bytes()
+    }
+
+    #[tokio::test]
+    async fn test_single_range() {
+        let handler = RangeWrapper.wrap(handler_fn(digits));
+
+        let resp = invoke(&handler, Method::GET, "bytes=2-4").await;
+        assert_eq!(resp.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(resp.headers().get(CONTENT_RANGE).unwrap(), "bytes 2-4/10");
+        assert_eq!(resp.headers().get(CONTENT_LENGTH).unwrap(), "3");
+        assert_eq!(body_of(resp).await, "234");
+
+        let resp = invoke(&handler, Method::GET, "bytes=-3").await;
+        assert_eq!(resp.headers().get(CONTENT_RANGE).unwrap(), "bytes 7-9/10");
+        assert_eq!(body_of(resp).await, "789");
+
+        // ranges are only defined for GET
+        let resp = invoke(&handler, Method::POST, "bytes=2-4").await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(body_of(resp).await, "0123456789");
+    }
+
+    #[tokio::test]
+    async fn test_multiple_ranges() {
+        let handler = RangeWrapper.wrap(handler_fn(digits));
+
+        let resp = invoke(&handler, Method::GET, "bytes=0-1, 8-").await;
+        assert_eq!(resp.status(), StatusCode::PARTIAL_CONTENT);
+        let content_type = resp.headers().get(CONTENT_TYPE).unwrap().to_str().unwrap();
+        let boundary = content_type.strip_prefix("multipart/byteranges; boundary=").unwrap().to_string();
+        let expected = format!(
+            "--{boundary}\r\nContent-Type: text/plain\r\nContent-Range: bytes 0-1/10\r\n\r\n01\r\n\
+             --{boundary}\r\nContent-Type: text/plain\r\nContent-Range: bytes 8-9/10\r\n\r\n89\r\n--{boundary}--\r\n"
+        );
+        assert_eq!(resp.headers().get(CONTENT_LENGTH).unwrap(), expected.len().to_string().as_str());
+        assert_eq!(body_of(resp).await, expected);
+    }
+
+    #[tokio::test]
+    async fn test_multiple_ranges_streamed() {
+        async fn chunked_digits() -> Response<ResponseBody> {
+            let chunks = ["012", "3456", "789"].map(|chunk| Ok(Frame::data(Bytes::from_static(chunk.as_bytes()))));
+            let body = ResponseBody::stream_with_size(StreamBody::new(futures::stream::iter(chunks)), 10);
+            let mut resp = Response::new(body);
+            resp.headers_mut().insert(ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+            resp
+        }
+
+        let handler = RangeWrapper.wrap(handler_fn(chunked_digits));
+
+        // the parts are sent in ascending order, whatever the order of the header
+        let resp = invoke(&handler, Method::GET, "bytes=8-, 2-4, 0-0").await;
+        assert_eq!(resp.status(), StatusCode::PARTIAL_CONTENT);
+        let content_type = resp.headers().get(CONTENT_TYPE).unwrap().to_str().unwrap();
+        let boundary = content_type.strip_prefix("multipart/byteranges; boundary=").unwrap().to_string();
+        let expected = format!(
+            "--{boundary}\r\nContent-Range: bytes 0-0/10\r\n\r\n0\r\n\
+             --{boundary}\r\nContent-Range: bytes 2-4/10\r\n\r\n234\r\n\
+             --{boundary}\r\nContent-Range: bytes 8-9/10\r\n\r\n89\r\n--{boundary}--\r\n"
+        );
+        assert_eq!(resp.headers().get(CONTENT_LENGTH).unwrap(), expected.len().to_string().as_str());
+        assert_eq!(body_of(resp).await, expected);
+    }
+
+    #[tokio::test]
+    async fn test_too_many_ranges() {
+        let handler = RangeWrapper.wrap(handler_fn(digits));
+
+        let ranges = (0..10).map(|i| format!("{i}-{i}")).collect::<Vec<_>>().join(",");
+        let resp = invoke(&handler, Method::GET, &format!("bytes={ranges}")).await;
+        assert_eq!(resp.status(), StatusCode::PARTIAL_CONTENT);
+
+        let handler = RangeWrapper.wrap(handler_fn(|| async {
+            let mut resp = Response::new(ResponseBody::from("x".repeat(100)));
+            resp.headers_mut().insert(ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+            resp
+        }));
+        let ranges = (0..MAX_RANGES + 1).map(|i| format!("{}-{}", i * 2, i * 2)).collect::<Vec<_>>().join(",");
+        let resp = invoke(&handler, Method::GET, &format!("bytes={ranges}")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(body_of(resp).await, "x".repeat(100));
+    }
+
+    #[tokio::test]
+    async fn test_not_satisfiable() {
+        let handler = RangeWrapper.wrap(handler_fn(digits));
+
+        for range in ["bytes=10-", "bytes=5-1", "lines=1-2", "bytes=0-4, 3-5"] {
+            let resp = invoke(&handler, Method::GET, range).await;
+            assert_eq!(resp.status(), StatusCode::RANGE_NOT_SATISFIABLE, "{range}");
+            assert_eq!(resp.headers().get(CONTENT_RANGE).unwrap(), "bytes */10");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_without_accept_ranges() {
+        async fn hello() -> &'static str {
+            "hello"
+        }
+
+        let handler = RangeWrapper.wrap(handler_fn(hello));
+        let resp = invoke(&handler, Method::GET, "bytes=0-1").await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(body_of(resp).await, "hello");
+    }
+}
@@ -0,0 +1,336 @@
+//! Module for limiting the request rate of each client.
+//!
+//! Every client gets its own token bucket: each request takes one token, and tokens are refilled
+//! continuously at a fixed rate. When the bucket is empty the request is rejected with
+//! `429 Too Many Requests`, and the `Retry-After` header tells the client when the next token is available.
+//!
+//! Clients are identified by their IP address by default, see [`RateLimitWrapper::with_key`] to use
+//! another key such as the authenticated user. Buckets of clients that have been idle for a while are
+//! purged lazily while handling requests.
+
+use crate::handler::RequestHandler;
+use crate::responder::Responder;
+use crate::wrapper::{priority, Wrapper};
+use crate::{OptionReqBody, RequestContext, ResponseBody};
+use async_trait::async_trait;
+use dashmap::DashMap;
+use http::{HeaderValue, Response, StatusCode};
+use std::hash::Hash;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Extracts the rate limit key from a request, requests without a key are not limited.
+pub type KeyFn<K> = Box<dyn Fn(&RequestContext) -> Option<K> + Send + Sync>;
+
+/// Configuration of the [`RateLimitWrapper`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimitConfig {
+    /// The number of tokens a new client starts with
+    pub capacity: u32,
+    /// The number of tokens refilled per second, `0` to never refill them, in which case rejected
+    /// requests get no `Retry-After` header
+    pub refill_rate: f64,
+    /// Extra tokens a client may accumulate above `capacity` while idle
+    pub burst: u32,
+    /// Buckets of clients without requests for this long are purged
+    pub idle_timeout: Duration,
+}
+
+impl Default for RateLimitConfig {
+    /// Allows 100 requests per second per client.
+    fn default() -> Self {
+        Self { capacity: 100, refill_rate: 100.0, burst: 0, idle_timeout: Duration::from_secs(60) }
+    }
+}
+
+/// A token bucket of a single client.
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(config: &RateLimitConfig, now: Instant) -> Self {
+        Self { tokens: config.capacity as f64, last_refill: now }
+    }
+
+    /// Refills the bucket and takes one token.
+    ///
+    /// Returns the time until the next token is available if the bucket is empty.
+    fn try_acquire(&mut self, config: &RateLimitConfig, now: Instant) -> Result<(), Option<Duration>> {
+        let elapsed = now.saturating_duration_since(self.last_refill).as_secs_f64();
+        let max_tokens = config.capacity as f64 + config.burst as f64;
+        self.tokens = (self.tokens + elapsed * config.refill_rate).min(max_tokens);
+        self.last_refill = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return Ok(());
+        }
+
+        if config.refill_rate > 0.0 {
+            // a tiny rate can overflow a `Duration`
+            let wait = Duration::try_from_secs_f64((1.0 - self.tokens) / config.refill_rate).unwrap_or(Duration::MAX);
+            Err(Some(wait))
+        } else {
+            Err(None)
+        }
+    }
+}
+
+/// The buckets shared by all handlers created from one [`RateLimitWrapper`].
+struct RateLimiter<K> {
+    config: RateLimitConfig,
+    key_fn: KeyFn<K>,
+    buckets: DashMap<K, TokenBucket>,
+    last_purge: Mutex<Instant>,
+}
+
+impl<K: Hash + Eq> RateLimiter<K> {
+    fn acquire(&self, key: K) -> Result<(), Option<Duration>> {
+        let now = Instant::now();
+        self.purge_idle(now);
+
+        self.buckets.entry(key).or_insert_with(|| TokenBucket::new(&self.config, now)).try_acquire(&self.config, now)
+    }
+
+    /// Removes idle buckets, at most once per `idle_timeout`.
+    fn purge_idle(&self, now: Instant) {
+        let mut last_purge = match self.last_purge.try_lock() {
+            Ok(last_purge) => last_purge,
+            // another request is purging right now
+            Err(_) => return,
+        };
+
+        if now.saturating_duration_since(*last_purge) < self.config.idle_timeout {
+            return;
+        }
+        *last_purge = now;
+
+        let idle_timeout = self.config.idle_timeout;
+        self.buckets.retain(|_, bucket| now.saturating_duration_since(bucket.last_refill) < idle_timeout);
+    }
+}
+
+/// A wrapper that creates `RateLimitRequestHandler`.
+///
+/// All handlers wrapped by the same `RateLimitWrapper` share the same buckets, so a client's requests
+/// to every route count against one limit.
+///
+/// # Example
+/// ```
+/// use micro_web::wrapper::{RateLimitConfig, RateLimitWrapper};
+///
+/// let config = RateLimitConfig { capacity: 10, refill_rate: 1.0, ..RateLimitConfig::default() };
+/// let wrapper = RateLimitWrapper::new(config);
+/// ```
+pub struct RateLimitWrapper<K = IpAddr> {
+    limiter: Arc<RateLimiter<K>>,
+}
+
+impl RateLimitWrapper<IpAddr> {
+    /// Creates a `RateLimitWrapper` keyed by [`RequestContext::client_ip`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `config.refill_rate` is negative or not finite.
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self::with_key(config, Box::new(|req: &RequestContext| req.client_ip()))
+    }
+}
+
+impl<K: Hash + Eq> RateLimitWrapper<K> {
+    /// Creates a `RateLimitWrapper` keyed by the result of `key_fn`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `config.refill_rate` is negative or not finite.
+    pub fn with_key(config: RateLimitConfig, key_fn: KeyFn<K>) -> Self {
+        assert!(
+            config.refill_rate.is_finite() && config.refill_rate >= 0.0,
+            "invalid refill rate: {}",
+            config.refill_rate
+        );
+        let limiter = RateLimiter { config, key_fn, buckets: DashMap::new(), last_purge: Mutex::new(Instant::now()) };
+        Self { limiter: Arc::new(limiter) }
+    }
+
+    /// Returns the config used by this wrapper.
+    pub fn config(&self) -> &RateLimitConfig {
+        &self.limiter.config
+    }
+}
+
+/// A request handler that rejects requests of clients exceeding the rate limit.
+pub struct RateLimitRequestHandler<H: RequestHandler, K> {
+    handler: H,
+    limiter: Arc<RateLimiter<K>>,
+}
+
+impl<H, K> Wrapper<H> for RateLimitWrapper<K>
+where
+    H: RequestHandler,
+    K: Hash + Eq + Send + Sync + 'static,
+{
+    type Out = RateLimitRequestHandler<H, K>;
+
+    fn wrap(&self, handler: H) -> Self::Out {
+        RateLimitRequestHandler { handler, limiter: Arc::clone(&self.limiter) }
+    }
+
+    fn priority(&self) -> i32 {
+        priority::RATE_LIMIT
+    }
+}
+
+#[async_trait]
+impl<H, K> RequestHandler for RateLimitRequestHandler<H, K>
+where
+    H: RequestHandler,
+    K: Hash + Eq + Send + Sync + 'static,
+{
+    async fn invoke<'server, 'req>(
+        &self,
+        req: &mut RequestContext<'server, 'req>,
+        req_body: OptionReqBody,
+    ) -> Response<ResponseBody> {
+        let key = match (self.limiter.key_fn)(req) {
+            Some(key) => key,
+            None => return self.handler.invoke(req, req_body).await,
+        };
+
+        match self.limiter.acquire(key) {
+            Ok(()) => self.handler.invoke(req, req_body).await,
+            Err(retry_after) => {
+                let mut resp = (StatusCode::TOO_MANY_REQUESTS, "too many requests").response_to(req);
+                if let Some(retry_after) = retry_after {
+                    // round up, so the client doesn't retry before the token is available
+                    let seconds = retry_after.as_secs().saturating_add(u64::from(retry_after.subsec_nanos() > 0));
+                    resp.headers_mut().insert(http::header::RETRY_AFTER, HeaderValue::from(seconds));
+                }
+                resp
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{handler_fn, PathParams, RequestBody};
+    use micro_http::protocol::RequestHeader;
+    use std::net::SocketAddr;
+
+    async fn hello() -> &'static str {
+        "hello"
+    }
+
+    async fn invoke<H: RequestHandler>(handler: &H, remote_addr: &str) -> Response<ResponseBody> {
+        let header: RequestHeader = http::Request::builder().body(()).unwrap().into();
+        let remote_addr: SocketAddr = remote_addr.parse().unwrap();
+        let mut req = RequestContext::new(&header, PathParams::empty()).with_remote_addr(Some(remote_addr));
+        handler.invoke(&mut req, OptionReqBody::from(RequestBody::empty())).await
+    }
+
+    fn config(capacity: u32) -> RateLimitConfig {
+        RateLimitConfig { capacity, refill_rate: 0.001, burst: 0, idle_timeout: Duration::from_secs(60) }
+    }
+
+    #[tokio::test]
+    async fn test_rejects_over_limit() {
+        let handler = RateLimitWrapper::new(config(100)).wrap(handler_fn(hello));
+
+        let mut rejected = 0;
+        for _ in 0..110 {
+            let resp = invoke(&handler, "10.0.0.1:5000").await;
+            if resp.status() == StatusCode::TOO_MANY_REQUESTS {
+                assert!(resp.headers().get(http::header::RETRY_AFTER).is_some());
+                rejected += 1;
+            }
+        }
+        assert_eq!(rejected, 10);
+
+        // other clients have their own bucket
+        let resp = invoke(&handler, "10.0.0.2:5000").await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_custom_key() {
+        let key_fn: KeyFn<String> = Box::new(|req: &RequestContext| {
+            req.headers().get("x-user").and_then(|value| value.to_str().ok()).map(str::to_string)
+        });
+        let handler = RateLimitWrapper::with_key(config(1), key_fn).wrap(handler_fn(hello));
+
+        async fn invoke_as<H: RequestHandler>(handler: &H, user: &str) -> Response<ResponseBody> {
+            let header: RequestHeader = http::Request::builder().header("x-user", user).body(()).unwrap().into();
+            let mut req = RequestContext::new(&header, PathParams::empty());
+            handler.invoke(&mut req, OptionReqBody::from(RequestBody::empty())).await
+        }
+
+        // the same client address, but each user has their own bucket
+        assert_eq!(invoke_as(&handler, "alice").await.status(), StatusCode::OK);
+        assert_eq!(invoke_as(&handler, "alice").await.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(invoke_as(&handler, "bob").await.status(), StatusCode::OK);
+        assert_eq!(invoke_as(&handler, "bob").await.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        // requests without a key are not limited
+        assert_eq!(invoke(&handler, "10.0.0.1:5000").await.status(), StatusCode::OK);
+        assert_eq!(invoke(&handler, "10.0.0.1:5000").await.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_tiny_refill_rate() {
+        let config = RateLimitConfig { refill_rate: f64::MIN_POSITIVE, ..config(1) };
+        let handler = RateLimitWrapper::new(config).wrap(handler_fn(hello));
+
+        assert_eq!(invoke(&handler, "10.0.0.1:5000").await.status(), StatusCode::OK);
+        let resp = invoke(&handler, "10.0.0.1:5000").await;
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(resp.headers()[http::header::RETRY_AFTER], u64::MAX.to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid refill rate")]
+    fn test_invalid_refill_rate() {
+        RateLimitWrapper::new(RateLimitConfig { refill_rate: f64::NAN, ..config(1) });
+    }
+
+    #[test]
+    fn test_token_bucket_refill() {
+        let config = RateLimitConfig { capacity: 1, refill_rate: 2.0, burst: 1, idle_timeout: Duration::from_secs(60) };
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(&config, start);
+
+        assert!(bucket.try_acquire(&config, start).is_ok());
+        assert_eq!(bucket.try_acquire(&config, start), Err(Some(Duration::from_millis(500))));
+
+        // refilled up to capacity + burst
+        let later = start + Duration::from_secs(10);
+        assert!(bucket.try_acquire(&config, later).is_ok());
+        assert!(bucket.try_acquire(&config, later).is_ok());
+        assert!(bucket.try_acquire(&config, later).is_err());
+    }
+
+    #[test]
+    fn test_token_bucket_max_burst() {
+        let config = RateLimitConfig { burst: u32::MAX, ..config(u32::MAX) };
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(&config, start);
+
+        assert!(bucket.try_acquire(&config, start + Duration::from_secs(1)).is_ok());
+    }
+
+    #[test]
+    fn test_purge_idle() {
+        let config = RateLimitConfig { idle_timeout: Duration::from_millis(0), ..config(10) };
+        let wrapper = RateLimitWrapper::with_key(config, Box::new(|_: &RequestContext| Some(1)));
+
+        assert!(wrapper.limiter.acquire(1).is_ok());
+        assert!(wrapper.limiter.acquire(2).is_ok());
+        wrapper.limiter.purge_idle(Instant::now() + Duration::from_millis(1));
+        assert!(wrapper.limiter.buckets.is_empty());
+    }
+}
@@ -0,0 +1,279 @@
+//! Module for adding security related response headers.
+//!
+//! [`SecurityHeadersWrapper`] adds the headers recommended by the
+//! [OWASP Secure Headers Project](https://owasp.org/www-project-secure-headers/) to every response:
+//! - `Strict-Transport-Security`, only for requests received over HTTPS, see
+//!   [`RequestContext::is_secure`], so development servers running on plain HTTP are not pinned to HTTPS
+//!   by the browser
+//! - `X-Frame-Options`
+//! - `X-Content-Type-Options`
+//! - `Referrer-Policy`
+//! - `Content-Security-Policy`
+//!
+//! Headers already set by the handler are never overwritten, so a single route can relax the policy.
+
+use crate::handler::RequestHandler;
+use crate::wrapper::{priority, Wrapper};
+use crate::{OptionReqBody, RequestContext, ResponseBody};
+use async_trait::async_trait;
+use http::header::{
+    CONTENT_SECURITY_POLICY, REFERRER_POLICY, STRICT_TRANSPORT_SECURITY, X_CONTENT_TYPE_OPTIONS, X_FRAME_OPTIONS,
+};
+use http::{HeaderName, HeaderValue, Response};
+use std::sync::Arc;
+
+/// Configuration of the `Strict-Transport-Security` header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HstsConfig {
+    /// How long, in seconds, the browser should only use HTTPS
+    pub max_age: u64,
+    /// Whether the policy applies to all subdomains as well
+    pub include_subdomains: bool,
+    /// Whether the domain may be included in the browsers' preload lists
+    pub preload: bool,
+}
+
+impl HstsConfig {
+    fn header_value(&self) -> HeaderValue {
+        let mut value = format!("max-age={}", self.max_age);
+        if self.include_subdomains {
+            value.push_str("; includeSubDomains");
+        }
+        if self.preload {
+            value.push_str("; preload");
+        }
+        // only contains digits and ascii letters
+        HeaderValue::from_str(&value).unwrap()
+    }
+}
+
+/// The value of the `X-Frame-Options` header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XFrameOptions {
+    /// The page can't be displayed in a frame
+    Deny,
+    /// The page can only be displayed in a frame on the same origin
+    SameOrigin,
+}
+
+impl XFrameOptions {
+    fn header_value(&self) -> HeaderValue {
+        match self {
+            XFrameOptions::Deny => HeaderValue::from_static("DENY"),
+            XFrameOptions::SameOrigin => HeaderValue::from_static("SAMEORIGIN"),
+        }
+    }
+}
+
+/// Configuration of the [`SecurityHeadersWrapper`], headers set to `None` or `false` are not added.
+///
+/// # Example
+/// ```
+/// use micro_web::wrapper::{SecurityHeadersConfig, SecurityHeadersWrapper, XFrameOptions};
+///
+/// let config = SecurityHeadersConfig {
+///     x_frame_options: Some(XFrameOptions::SameOrigin),
+///     x_content_type_options: true,
+///     ..SecurityHeadersConfig::default()
+/// };
+/// let wrapper = SecurityHeadersWrapper::new(config);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SecurityHeadersConfig {
+    /// The `Strict-Transport-Security` header
+    pub hsts: Option<HstsConfig>,
+    /// The `X-Frame-Options` header
+    pub x_frame_options: Option<XFrameOptions>,
+    /// Whether to add `X-Content-Type-Options: nosniff`
+    pub x_content_type_options: bool,
+    /// The `Referrer-Policy` header
+    pub referrer_policy: Option<String>,
+    /// The `Content-Security-Policy` header
+    pub csp: Option<String>,
+}
+
+/// A wrapper that creates `SecurityHeadersRequestHandler`.
+pub struct SecurityHeadersWrapper {
+    headers: Arc<SecurityHeaders>,
+}
+
+/// The header values computed once from a [`SecurityHeadersConfig`].
+struct SecurityHeaders {
+    hsts: Option<HeaderValue>,
+    others: Vec<(HeaderName, HeaderValue)>,
+}
+
+impl SecurityHeadersWrapper {
+    /// Creates a `SecurityHeadersWrapper` using the given config.
+    ///
+    /// # Panics
+    /// Panics if `referrer_policy` or `csp` contain characters that are not allowed in a header value.
+    pub fn new(config: SecurityHeadersConfig) -> Self {
+        let mut others = vec![];
+        if let Some(x_frame_options) = config.x_frame_options {
+            others.push((X_FRAME_OPTIONS, x_frame_options.header_value()));
+        }
+        if config.x_content_type_options {
+            others.push((X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff")));
+        }
+        if let Some(referrer_policy) = &config.referrer_policy {
+            others.push((REFERRER_POLICY, HeaderValue::from_str(referrer_policy).unwrap()));
+        }
+        if let Some(csp) = &config.csp {
+            others.push((CONTENT_SECURITY_POLICY, HeaderValue::from_str(csp).unwrap()));
+        }
+
+        let hsts = config.hsts.as_ref().map(HstsConfig::header_value);
+        Self { headers: Arc::new(SecurityHeaders { hsts, others }) }
+    }
+
+    /// Creates a `SecurityHeadersWrapper` with the values recommended by OWASP:
+    /// - `Strict-Transport-Security: max-age=31536000; includeSubDomains`
+    /// - `X-Frame-Options: DENY`
+    /// - `X-Content-Type-Options: nosniff`
+    /// - `Referrer-Policy: no-referrer`
+    /// - `Content-Security-Policy: default-src 'self'; object-src 'none'; frame-ancestors 'none'`
+    pub fn strict_defaults() -> Self {
+        Self::new(SecurityHeadersConfig {
+            hsts: Some(HstsConfig { max_age: 31_536_000, include_subdomains: true, preload: false }),
+            x_frame_options: Some(XFrameOptions::Deny),
+            x_content_type_options: true,
+            referrer_policy: Some("no-referrer".into()),
+            csp: Some("default-src 'self'; object-src 'none'; frame-ancestors 'none'".into()),
+        })
+    }
+}
+
+/// A request handler that adds security headers to the response.
+pub struct SecurityHeadersRequestHandler<H: RequestHandler> {
+    handler: H,
+    headers: Arc<SecurityHeaders>,
+}
+
+impl<H: RequestHandler> Wrapper<H> for SecurityHeadersWrapper {
+    type Out = SecurityHeadersRequestHandler<H>;
+
+    fn wrap(&self, handler: H) -> Self::Out {
+        SecurityHeadersRequestHandler { handler, headers: Arc::clone(&self.headers) }
+    }
+
+    fn priority(&self) -> i32 {
+        priority::SECURITY
+    }
+}
+
+#[async_trait]
+impl<H: RequestHandler> RequestHandler for SecurityHeadersRequestHandler<H> {
+    async fn invoke<'server, 'req>(
+        &self,
+        req: &mut RequestContext<'server, 'req>,
+        req_body: OptionReqBody,
+    ) -> Response<ResponseBody> {
+        let https = req.is_secure();
+
+        let mut resp = self.handler.invoke(req, req_body).await;
+        let resp_headers = resp.headers_mut();
+
+        let hsts = self.headers.hsts.as_ref().filter(|_| https).map(|value| (&STRICT_TRANSPORT_SECURITY, value));
+        let others = self.headers.others.iter().map(|(name, value)| (name, value));
+        for (name, value) in hsts.into_iter().chain(others) {
+            if !resp_headers.contains_key(name) {
+                resp_headers.insert(name.clone(), value.clone());
+            }
+        }
+
+        resp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::acceptor::Secure;
+    use crate::responder::Responder;
+    use crate::{handler_fn, PathParams, RequestBody};
+    use micro_http::protocol::RequestHeader;
+
+    async fn hello() -> &'static str {
+        "hello"
+    }
+
+    /// Sets its own `X-Frame-Options` header
+    struct Framed;
+
+    #[async_trait]
+    impl RequestHandler for Framed {
+        async fn invoke<'server, 'req>(
+            &self,
+            req: &mut RequestContext<'server, 'req>,
+            _req_body: OptionReqBody,
+        ) -> Response<ResponseBody> {
+            let mut resp = "framed".response_to(req);
+            resp.headers_mut().insert(X_FRAME_OPTIONS, HeaderValue::from_static("SAMEORIGIN"));
+            resp
+        }
+    }
+
+    async fn invoke<H: RequestHandler>(handler: &H, uri: &str) -> Response<ResponseBody> {
+        let header: RequestHeader = http::Request::builder().uri(uri).body(()).unwrap().into();
+        let mut req = RequestContext::new(&header, PathParams::empty());
+        handler.invoke(&mut req, OptionReqBody::from(RequestBody::empty())).await
+    }
+
+    /// Invokes `handler` with a request of a connection the acceptor marked as TLS
+    async fn invoke_secure<H: RequestHandler>(handler: &H, uri: &str) -> Response<ResponseBody> {
+        let header: RequestHeader = http::Request::builder().uri(uri).body(()).unwrap().into();
+        let mut req = RequestContext::new(&header, PathParams::empty());
+        req.extensions_mut().insert(Secure);
+        handler.invoke(&mut req, OptionReqBody::from(RequestBody::empty())).await
+    }
+
+    #[tokio::test]
+    async fn test_strict_defaults() {
+        let handler = SecurityHeadersWrapper::strict_defaults().wrap(handler_fn(hello));
+
+        let resp = invoke_secure(&handler, "/").await;
+        let headers = resp.headers();
+        assert_eq!(headers.get(STRICT_TRANSPORT_SECURITY).unwrap(), "max-age=31536000; includeSubDomains");
+        assert_eq!(headers.get(X_FRAME_OPTIONS).unwrap(), "DENY");
+        assert_eq!(headers.get(X_CONTENT_TYPE_OPTIONS).unwrap(), "nosniff");
+        assert_eq!(headers.get(REFERRER_POLICY).unwrap(), "no-referrer");
+        assert!(headers.get(CONTENT_SECURITY_POLICY).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_no_hsts_for_plain_http() {
+        let handler = SecurityHeadersWrapper::strict_defaults().wrap(handler_fn(hello));
+
+        // the scheme of the request target is chosen by the client
+        for uri in ["/", "http://example.com/", "https://example.com/"] {
+            let resp = invoke(&handler, uri).await;
+            assert!(resp.headers().get(STRICT_TRANSPORT_SECURITY).is_none());
+            assert_eq!(resp.headers().get(X_FRAME_OPTIONS).unwrap(), "DENY");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_keep_handler_headers() {
+        let handler = SecurityHeadersWrapper::strict_defaults().wrap(Framed);
+
+        let resp = invoke(&handler, "/").await;
+        assert_eq!(resp.headers().get_all(X_FRAME_OPTIONS).iter().count(), 1);
+        assert_eq!(resp.headers().get(X_FRAME_OPTIONS).unwrap(), "SAMEORIGIN");
+    }
+
+    #[test]
+    fn test_hsts_value() {
+        let hsts = HstsConfig { max_age: 60, include_subdomains: false, preload: true };
+        assert_eq!(hsts.header_value(), "max-age=60; preload");
+    }
+
+    #[tokio::test]
+    async fn test_empty_config() {
+        let handler = SecurityHeadersWrapper::new(SecurityHeadersConfig::default()).wrap(handler_fn(hello));
+
+        let resp = invoke_secure(&handler, "/").await;
+        assert!(resp.headers().get(STRICT_TRANSPORT_SECURITY).is_none());
+        assert!(resp.headers().get(X_CONTENT_TYPE_OPTIONS).is_none());
+    }
+}
@@ -0,0 +1,328 @@
+//! Module for propagating the [W3C Trace Context](https://www.w3.org/TR/trace-context/) of requests.
+//!
+//! [`OtelWrapper`] continues the trace of the incoming `traceparent` and `tracestate` headers, or starts
+//! a new trace when they are absent or invalid:
+//! - a [`TraceContext`] with a new span id is added to the request extensions
+//! - the handler runs within a `tracing` span named `http.request`, so the events and spans of the handler
+//!   are nested in it
+//! - the `traceparent` of the request span is added to the response
+//!
+//! The span records the method, the [route template](crate::router::MatchedRoute), the status code and the
+//! duration following the OpenTelemetry semantic conventions, plus the trace, span and parent span ids, so a
+//! `tracing-opentelemetry` layer can export it. Requests whose `traceparent` isn't sampled get no span, their
+//! trace context is still propagated.
+//!
+//! This module requires the `otel` feature.
+
+use crate::handler::RequestHandler;
+use crate::router::MatchedRoute;
+use crate::wrapper::Wrapper;
+use crate::{OptionReqBody, RequestContext, ResponseBody};
+use async_trait::async_trait;
+use http::{HeaderMap, HeaderName, HeaderValue, Response};
+use std::collections::hash_map::RandomState;
+use std::fmt::Write;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Instant;
+use tracing::field::Empty;
+use tracing::{info_span, Instrument, Span};
+
+const TRACEPARENT: HeaderName = HeaderName::from_static("traceparent");
+const TRACESTATE: HeaderName = HeaderName::from_static("tracestate");
+
+/// The flag of sampled traces in `trace-flags`.
+const SAMPLED: u8 = 0x01;
+
+/// The trace context of a request, available in the request extensions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceContext {
+    trace_id: [u8; 16],
+    span_id: [u8; 8],
+    parent_span_id: Option<[u8; 8]>,
+    flags: u8,
+    trace_state: Option<HeaderValue>,
+}
+
+impl TraceContext {
+    /// Continues the trace of the `traceparent` and `tracestate` headers, or starts a new sampled trace.
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        match headers.get(TRACEPARENT).and_then(|value| parse_traceparent(value.as_bytes())) {
+            Some((trace_id, parent_span_id, flags)) => Self {
+                trace_id,
+                span_id: random_id(),
+                parent_span_id: Some(parent_span_id),
+                flags: flags & SAMPLED,
+                trace_state: trace_state(headers),
+            },
+            None => {
+                let (high, low): ([u8; 8], [u8; 8]) = (random_id(), random_id());
+                let mut trace_id = [0; 16];
+                trace_id[..8].copy_from_slice(&high);
+                trace_id[8..].copy_from_slice(&low);
+                Self { trace_id, span_id: random_id(), parent_span_id: None, flags: SAMPLED, trace_state: None }
+            }
+        }
+    }
+
+    /// The trace id, as 32 lowercase hex digits.
+    pub fn trace_id(&self) -> String {
+        hex(&self.trace_id)
+    }
+
+    /// The id of the request span, as 16 lowercase hex digits.
+    pub fn span_id(&self) -> String {
+        hex(&self.span_id)
+    }
+
+    /// The id of the caller span, `None` for a new trace.
+    pub fn parent_span_id(&self) -> Option<String> {
+        self.parent_span_id.as_ref().map(|id| hex(id))
+    }
+
+    /// Whether the caller decided to record the trace.
+    pub fn is_sampled(&self) -> bool {
+        self.flags & SAMPLED != 0
+    }
+
+    /// The vendor specific `tracestate` of the caller.
+    pub fn trace_state(&self) -> Option<&HeaderValue> {
+        self.trace_state.as_ref()
+    }
+
+    /// The `traceparent` header identifying the request span, to propagate to downstream calls.
+    pub fn traceparent(&self) -> HeaderValue {
+        let value = format!("00-{}-{}-{:02x}", self.trace_id(), self.span_id(), self.flags);
+        // only contains hex digits and dashes
+        HeaderValue::from_str(&value).unwrap()
+    }
+}
+
+/// Parses `version-trace_id-parent_id-trace_flags`, rejecting invalid and all zero ids.
+fn parse_traceparent(value: &[u8]) -> Option<([u8; 16], [u8; 8], u8)> {
+    // 2 + 1 + 32 + 1 + 16 + 1 + 2
+    const LEN: usize = 55;
+
+    if value.len() < LEN || value[2] != b'-' || value[35] != b'-' || value[52] != b'-' {
+        return None;
+    }
+    let [version] = decode_hex::<1>(&value[..2])?;
+    // version 00 has no other field, later versions may append fields
+    match version {
+        0xff => return None,
+        0x00 if value.len() != LEN => return None,
+        _ if value.len() > LEN && value[LEN] != b'-' => return None,
+        _ => {}
+    }
+
+    let trace_id = decode_hex::<16>(&value[3..35]).filter(|id| id.iter().any(|b| *b != 0))?;
+    let parent_id = decode_hex::<8>(&value[36..52]).filter(|id| id.iter().any(|b| *b != 0))?;
+    let [flags] = decode_hex::<1>(&value[53..55])?;
+    Some((trace_id, parent_id, flags))
+}
+
+/// Combines the `tracestate` headers, which may be split over several lines.
+fn trace_state(headers: &HeaderMap) -> Option<HeaderValue> {
+    let mut values = headers.get_all(TRACESTATE).iter();
+    let first = values.next()?.clone();
+    let mut combined = first.as_bytes().to_vec();
+    for value in values {
+        combined.push(b',');
+        combined.extend_from_slice(value.as_bytes());
+    }
+    HeaderValue::from_bytes(&combined).ok()
+}
+
+/// Decodes lowercase hex digits, as required by the trace context.
+fn decode_hex<const N: usize>(digits: &[u8]) -> Option<[u8; N]> {
+    fn digit(c: u8) -> Option<u8> {
+        match c {
+            b'0'..=b'9' => Some(c - b'0'),
+            b'a'..=b'f' => Some(c - b'a' + 10),
+            _ => None,
+        }
+    }
+
+    if digits.len() != N * 2 {
+        return None;
+    }
+    let mut bytes = [0; N];
+    for (byte, pair) in bytes.iter_mut().zip(digits.chunks(2)) {
+        *byte = (digit(pair[0])? << 4) | digit(pair[1])?;
+    }
+    Some(bytes)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::with_capacity(bytes.len() * 2), |mut hex, b| {
+        let _ = write!(hex, "{b:02x}");
+        hex
+    })
+}
+
+/// Generates a random non zero id.
+fn random_id() -> [u8; 8] {
+    loop {
+        let id = RandomState::new().build_hasher().finish();
+        if id != 0 {
+            return id.to_be_bytes();
+        }
+    }
+}
+
+/// A wrapper that creates `OtelRequestHandler`.
+pub struct OtelWrapper;
+
+/// A request handler that runs the handler within the span of the request trace.
+pub struct OtelRequestHandler<H: RequestHandler> {
+    handler: H,
+}
+
+impl<H: RequestHandler> Wrapper<H> for OtelWrapper {
+    type Out = OtelRequestHandler<H>;
+
+    fn wrap(&self, handler: H) -> Self::Out {
+        OtelRequestHandler { handler }
+    }
+}
+
+#[async_trait]
+impl<H: RequestHandler> RequestHandler for OtelRequestHandler<H> {
+    async fn invoke<'server, 'req>(
+        &self,
+        req: &mut RequestContext<'server, 'req>,
+        req_body: OptionReqBody,
+    ) -> Response<ResponseBody> {
+        let start = Instant::now();
+        let context = TraceContext::from_headers(req.headers());
+
+        let span = if context.is_sampled() {
+            let route = req.extensions().get::<MatchedRoute>().map(MatchedRoute::as_str).unwrap_or_default();
+            info_span!(
+                "http.request",
+                http.request.method = %req.method(),
+                http.route = route,
+                http.response.status_code = Empty,
+                http.server.duration_ms = Empty,
+                trace_id = context.trace_id(),
+                span_id = context.span_id(),
+                parent_span_id = context.parent_span_id(),
+            )
+        } else {
+            Span::none()
+        };
+
+        let traceparent = context.traceparent();
+        req.extensions_mut().insert(context);
+
+        let mut resp = self.handler.invoke(req, req_body).instrument(span.clone()).await;
+
+        span.record("http.response.status_code", resp.status().as_u16());
+        span.record("http.server.duration_ms", start.elapsed().as_secs_f64() * 1000.0);
+        resp.headers_mut().insert(TRACEPARENT, traceparent);
+        resp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PathParams, RequestBody};
+    use http_body_util::BodyExt;
+    use micro_http::protocol::RequestHeader;
+
+    const PARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(*name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    #[test]
+    fn test_continue_trace() {
+        let context = TraceContext::from_headers(&headers(&[
+            ("traceparent", PARENT),
+            ("tracestate", "a=1"),
+            ("tracestate", "b=2"),
+        ]));
+
+        assert_eq!(context.trace_id(), "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(context.parent_span_id().unwrap(), "00f067aa0ba902b7");
+        assert_ne!(context.span_id(), "00f067aa0ba902b7");
+        assert!(context.is_sampled());
+        assert_eq!(context.trace_state().unwrap(), "a=1,b=2");
+
+        let traceparent = context.traceparent();
+        let traceparent = traceparent.to_str().unwrap();
+        assert_eq!(traceparent, format!("00-4bf92f3577b34da6a3ce929d0e0e4736-{}-01", context.span_id()));
+        assert!(parse_traceparent(traceparent.as_bytes()).is_some());
+    }
+
+    #[test]
+    fn test_sampling_decision() {
+        let context = TraceContext::from_headers(&headers(&[(
+            "traceparent",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00",
+        )]));
+        assert!(!context.is_sampled());
+        assert!(context.traceparent().to_str().unwrap().ends_with("-00"));
+    }
+
+    #[test]
+    fn test_invalid_traceparent() {
+        let invalid = [
+            "",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+            "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+            "01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01extra",
+        ];
+        for traceparent in invalid {
+            assert!(parse_traceparent(traceparent.as_bytes()).is_none(), "{traceparent}");
+        }
+
+        // later versions may append fields
+        assert!(parse_traceparent(b"01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra").is_some());
+    }
+
+    #[test]
+    fn test_new_trace() {
+        let context = TraceContext::from_headers(&headers(&[("traceparent", "invalid"), ("tracestate", "a=1")]));
+        assert!(context.parent_span_id().is_none());
+        assert!(context.trace_state().is_none());
+        assert!(context.is_sampled());
+        assert_ne!(context.trace_id(), TraceContext::from_headers(&HeaderMap::new()).trace_id());
+    }
+
+    #[tokio::test]
+    async fn test_wrapper() {
+        struct TraceIdHandler;
+
+        #[async_trait]
+        impl RequestHandler for TraceIdHandler {
+            async fn invoke<'server, 'req>(
+                &self,
+                req: &mut RequestContext<'server, 'req>,
+                _req_body: OptionReqBody,
+            ) -> Response<ResponseBody> {
+                Response::new(ResponseBody::from(req.extensions().get::<TraceContext>().unwrap().trace_id()))
+            }
+        }
+
+        let handler = OtelWrapper.wrap(TraceIdHandler);
+        let header: RequestHeader = http::Request::builder().header("traceparent", PARENT).body(()).unwrap().into();
+        let mut req = RequestContext::new(&header, PathParams::empty());
+        let resp = handler.invoke(&mut req, OptionReqBody::from(RequestBody::empty())).await;
+
+        let traceparent = resp.headers().get(TRACEPARENT).unwrap().to_str().unwrap();
+        assert!(traceparent.starts_with("00-4bf92f3577b34da6a3ce929d0e0e4736-"), "{traceparent}");
+        assert!(!traceparent.contains("00f067aa0ba902b7"));
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "4bf92f3577b34da6a3ce929d0e0e4736");
+    }
+}
@@ -0,0 +1,97 @@
+//! Decodes the head of a request from arbitrary bytes, received in two reads.
+//!
+//! Most inputs are assembled from a request line and header fields close to valid HTTP, to get
+//! past the request line more often than random bytes do. The decoder must never panic, a decoded
+//! request has a method, and the errors are parsing errors, never I/O ones.
+
+#![no_main]
+
+use bytes::BytesMut;
+use libfuzzer_sys::arbitrary::{self, Arbitrary};
+use libfuzzer_sys::fuzz_target;
+use micro_http::codec::RequestDecoder;
+use micro_http::protocol::{Message, ParseError, RequestHeader};
+use tokio_util::codec::Decoder;
+
+const METHODS: &[&str] = &["GET", "HEAD", "POST", "PUT", "DELETE", "CONNECT", "OPTIONS", "TRACE", "PATCH", "PURGE"];
+
+const HEADER_NAMES: &[&str] =
+    &["host", "content-length", "transfer-encoding", "connection", "expect", "upgrade", "x-custom"];
+
+#[derive(Arbitrary, Debug)]
+enum Input {
+    /// Random bytes, for the error paths
+    Raw { bytes: Vec<u8>, split: usize },
+    /// A request built from mostly valid parts
+    Assembled {
+        method: u8,
+        target: String,
+        minor_version: u8,
+        headers: Vec<(u8, String)>,
+        /// Appended after the end of the headers, it may also be read as the body
+        tail: Vec<u8>,
+        split: usize,
+    },
+}
+
+impl Input {
+    fn into_bytes(self) -> (Vec<u8>, usize) {
+        match self {
+            Input::Raw { bytes, split } => (bytes, split),
+            Input::Assembled { method, target, minor_version, headers, tail, split } => {
+                let method = METHODS[method as usize % METHODS.len()];
+                let mut bytes = format!("{method} /{target} HTTP/1.{}\r\n", minor_version % 3).into_bytes();
+                for (name, value) in headers {
+                    let name = HEADER_NAMES[name as usize % HEADER_NAMES.len()];
+                    bytes.extend_from_slice(format!("{name}: {value}\r\n").as_bytes());
+                }
+                bytes.extend_from_slice(b"\r\n");
+                bytes.extend_from_slice(&tail);
+                (bytes, split)
+            }
+        }
+    }
+}
+
+/// Decodes the request head of `bytes`, the first read ends at `split`.
+fn decode(bytes: &[u8], split: usize) -> Result<Option<RequestHeader>, ParseError> {
+    let mut decoder = RequestDecoder::new();
+    let split = split % (bytes.len() + 1);
+
+    let mut buf = BytesMut::from(&bytes[..split]);
+    for _ in 0..2 {
+        match decoder.decode(&mut buf)? {
+            Some(Message::Header(header)) => return Ok(Some(header)),
+            Some(Message::Payload(_)) => panic!("a payload is decoded before the header"),
+            None => buf.extend_from_slice(&bytes[split..]),
+        }
+    }
+    Ok(None)
+}
+
+fuzz_target!(|input: Input| {
+    let (bytes, split) = input.into_bytes();
+
+    match decode(&bytes, split) {
+        Ok(Some(header)) => {
+            assert!(!header.method().as_str().is_empty());
+            assert!(header.version() == http::Version::HTTP_10 || header.version() == http::Version::HTTP_11);
+        }
+        Ok(None) => (),
+        Err(e) => match e {
+            ParseError::TooLargeHeader { .. }
+            | ParseError::TooManyHeaders { .. }
+            | ParseError::InvalidHeader { .. }
+            | ParseError::InvalidUtf8 { .. }
+            | ParseError::InvalidVersion(_)
+            | ParseError::InvalidMethod
+            | ParseError::InvalidUri
+            | ParseError::InvalidContentLength { .. }
+            | ParseError::InvalidBody { .. }
+            | ParseError::TooLargeBody { .. } => (),
+            ParseError::ReadTimeout { .. } | ParseError::Io { .. } => {
+                panic!("decoding a buffer can't fail with an I/O error: {e}")
+            }
+        },
+    }
+});
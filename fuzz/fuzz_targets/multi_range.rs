@@ -0,0 +1,49 @@
+//! Answers random range sets of a 10 KB body with a `MultiRangeBody`, and checks its length.
+//!
+//! Each pair of `u16` in the input is a range, parsed like a `Range: bytes=start-end` header.
+
+#![no_main]
+
+use bytes::Bytes;
+use http_body::Body;
+use http_body_util::BodyExt;
+use libfuzzer_sys::fuzz_target;
+use micro_web::range::{parse_range, ByteRange, MultiRangeBody};
+
+const TOTAL: usize = 10 * 1024;
+
+fuzz_target!(|specs: Vec<(u16, u16)>| {
+    if specs.is_empty() {
+        return;
+    }
+
+    let header = specs.iter().map(|(start, end)| format!("{start}-{end}")).collect::<Vec<_>>().join(",");
+    let ranges = match parse_range(&format!("bytes={header}"), TOTAL as u64) {
+        Ok(ranges) => ranges,
+        Err(_) => return,
+    };
+
+    let body = Bytes::from(vec![b'x'; TOTAL]);
+    let parts = ranges.iter().map(|range| (*range, body.slice(range.start as usize..=range.end as usize))).collect();
+    let multipart = MultiRangeBody::new(parts, TOTAL as u64, Some("text/plain"));
+    let boundary = multipart.boundary().to_string();
+    let size = multipart.size_hint().exact().expect("the size of a multipart body is known");
+
+    let collected = futures::executor::block_on(multipart.collect()).unwrap().to_bytes();
+    assert_eq!(collected.len() as u64, size);
+
+    // the data, plus the delimiter and headers of each part, plus the close delimiter
+    let data: u64 = ranges.iter().map(ByteRange::len).sum();
+    let headers: u64 = ranges
+        .iter()
+        .enumerate()
+        .map(|(i, range)| {
+            let delimiter = if i == 0 { 2 + boundary.len() + 2 } else { 4 + boundary.len() + 2 };
+            let content_type = "Content-Type: text/plain\r\n".len();
+            let content_range = format!("Content-Range: bytes {}-{}/{TOTAL}\r\n\r\n", range.start, range.end).len();
+            (delimiter + content_type + content_range) as u64
+        })
+        .sum();
+    let close = (2 + 2 + boundary.len() + 4) as u64;
+    assert_eq!(size, data + headers + close);
+});
@@ -0,0 +1,88 @@
+//! Encodes an arbitrary request head with `RequestHeaderEncoder` and decodes it back.
+//!
+//! The decoded method, URI, version and header fields must be the ones encoded, apart from the
+//! `Content-Length: 0` the encoder adds to the methods expecting a body.
+
+#![no_main]
+
+use bytes::BytesMut;
+use http::header::{CONTENT_LENGTH, HOST};
+use http::{HeaderName, HeaderValue, Method, Request, Version};
+use libfuzzer_sys::arbitrary::{self, Arbitrary};
+use libfuzzer_sys::fuzz_target;
+use micro_http::codec::{RequestDecoder, RequestHeaderEncoder};
+use micro_http::protocol::{Message, PayloadSize};
+use tokio_util::codec::{Decoder, Encoder};
+
+// CONNECT is left out, its request target is an authority instead of a path
+const METHODS: &[&str] = &["GET", "HEAD", "POST", "PUT", "DELETE", "OPTIONS", "TRACE", "PATCH", "PURGE"];
+
+const HEADER_NAMES: &[&str] = &["accept", "user-agent", "cookie", "cache-control", "x-request-id", "x-custom"];
+
+const PATH_CHARS: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789-._~/";
+
+const MAX_HEADERS: usize = 32;
+
+const MAX_LEN: usize = 128;
+
+#[derive(Arbitrary, Debug)]
+struct Head {
+    method: u8,
+    path: Vec<u8>,
+    query: Option<Vec<u8>>,
+    http_10: bool,
+    headers: Vec<(u8, Vec<u8>)>,
+}
+
+/// Maps `bytes` onto `chars`, up to [`MAX_LEN`] of them.
+fn map(bytes: &[u8], chars: &[u8]) -> String {
+    bytes.iter().take(MAX_LEN).map(|b| chars[*b as usize % chars.len()] as char).collect()
+}
+
+impl Head {
+    fn build(&self) -> Request<()> {
+        let method = Method::from_bytes(METHODS[self.method as usize % METHODS.len()].as_bytes()).unwrap();
+        let mut uri = format!("/{}", map(&self.path, PATH_CHARS));
+        if let Some(query) = &self.query {
+            uri.push('?');
+            uri.push_str(&map(query, PATH_CHARS));
+        }
+        let version = if self.http_10 { Version::HTTP_10 } else { Version::HTTP_11 };
+
+        let mut request = Request::builder().method(method).uri(uri).version(version).body(()).unwrap();
+        let headers = request.headers_mut();
+        headers.insert(HOST, HeaderValue::from_static("example.com"));
+        for (name, value) in self.headers.iter().take(MAX_HEADERS) {
+            let name = HeaderName::from_static(HEADER_NAMES[*name as usize % HEADER_NAMES.len()]);
+            // visible ASCII with inner spaces, the spaces around a field value aren't part of it
+            let value = value.iter().take(MAX_LEN).map(|b| (b' ' + b % 95) as char).collect::<String>();
+            let value = HeaderValue::from_str(value.trim()).unwrap();
+            headers.append(name, value);
+        }
+        request
+    }
+}
+
+fuzz_target!(|head: Head| {
+    let request = head.build();
+
+    let mut buf = BytesMut::new();
+    RequestHeaderEncoder::new().encode((request.clone(), PayloadSize::Empty), &mut buf).unwrap();
+
+    let decoded = match RequestDecoder::new().decode(&mut buf) {
+        Ok(Some(Message::Header(header))) => header.into_inner(),
+        Ok(_) => panic!("the encoded request head isn't decoded: {request:?}"),
+        Err(e) => panic!("the encoded request head can't be decoded: {e}, {request:?}"),
+    };
+    assert!(buf.is_empty(), "bytes left after the request head: {buf:?}");
+
+    assert_eq!(decoded.method(), request.method());
+    assert_eq!(decoded.uri(), request.uri());
+    assert_eq!(decoded.version(), request.version());
+
+    let mut headers = decoded.headers().clone();
+    if let Some(length) = headers.remove(CONTENT_LENGTH) {
+        assert_eq!(length, "0");
+    }
+    assert_eq!(&headers, request.headers());
+});